@@ -1,13 +1,14 @@
 // Auxiliary functions
 
 use crate::api::*;
+use crate::chunk::binary::{Chunk, LUA_SIGNATURE};
 use std::cell::RefCell;
 use std::rc::Rc;
 
 #[allow(non_camel_case_types)]
 pub struct luaL_Reg {
-    pub(crate) name: &'static str,
-    pub(crate) func: lua_CFunction,
+    pub name: &'static str,
+    pub func: lua_CFunction,
 }
 
 #[allow(non_snake_case)]
@@ -22,10 +23,549 @@ pub fn luaL_loadfile(l: lua_State, filename: &str) {
     l.borrow_mut().load(proto);
 }
 
+// 还没有编译器，只能加载 luac 生成的二进制 chunk。成功时把函数压栈并返回 LUA_OK，
+// 否则压入错误信息并返回 LUA_ERRSYNTAX
 #[allow(non_snake_case)]
-pub fn luaL_setfuncs(l: lua_State, regs: &[luaL_Reg]) {
+pub fn luaL_loadbuffer(l: lua_State, buff: &[u8], name: &str) -> isize {
+    if buff.first() != Some(&LUA_SIGNATURE[0]) {
+        lua_pushstring(
+            l,
+            &format!("{}: attempt to load a text chunk (mode is 'b')", name),
+        );
+        return LUA_ERRSYNTAX;
+    }
+    match Chunk::parse(buff) {
+        Ok((_, chunk)) => {
+            l.borrow_mut().load(chunk.main);
+            LUA_OK
+        }
+        Err(_) => {
+            lua_pushstring(l, &format!("{}: bad binary format", name));
+            LUA_ERRSYNTAX
+        }
+    }
+}
+
+// 把函数注册到栈顶 nup 个上值下面的表中，所有函数共享这 nup 个上值
+#[allow(non_snake_case)]
+pub fn luaL_setfuncs(l: lua_State, regs: &[luaL_Reg], nup: isize) {
     for r in regs {
-        lua_pushcfunction(l.clone(), r.func);
-        lua_setglobal(l.clone(), r.name);
+        for _ in 0..nup {
+            lua_pushvalue(l.clone(), -nup);
+        }
+        lua_pushcclosure(l.clone(), r.func, nup);
+        lua_setfield(l.clone(), -(nup + 2), r.name);
+    }
+    for _ in 0..nup {
+        lua_pop(l.clone(), 1);
+    }
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_newlib(l: lua_State, regs: &[luaL_Reg]) {
+    lua_createtable(l.clone(), 0, regs.len() as isize);
+    luaL_setfuncs(l, regs, 0);
+}
+
+// 确保 t[fname] 是一个表并压栈，已经存在时返回 true
+#[allow(non_snake_case)]
+pub fn luaL_getsubtable(l: lua_State, idx: isize, fname: &str) -> bool {
+    if lua_getfield(l.clone(), idx, fname) == LUA_TTABLE {
+        return true;
+    }
+    lua_pop(l.clone(), 1);
+    let idx = lua_absindex(l.clone(), idx);
+    lua_newtable(l.clone());
+    lua_pushvalue(l.clone(), -1);
+    lua_setfield(l, idx, fname);
+    false
+}
+
+// 参考 luaL_requiref：模块还没有加载时调用 openf 打开它并记录到 _LOADED 中，
+// glb 为 true 时同时设置同名的全局变量，最后模块留在栈顶
+#[allow(non_snake_case)]
+pub fn luaL_requiref(l: lua_State, modname: &str, openf: fn(lua_State) -> isize, glb: bool) {
+    luaL_getsubtable(l.clone(), LUA_REGISTRYINDEX, "_LOADED");
+    lua_getfield(l.clone(), -1, modname);
+    if !lua_toboolean(l.clone(), -1) {
+        lua_pop(l.clone(), 1);
+        openf(l.clone());
+        lua_pushvalue(l.clone(), -1);
+        lua_setfield(l.clone(), -3, modname);
+    }
+    lua_remove(l.clone(), -2);
+    if glb {
+        lua_pushvalue(l.clone(), -1);
+        lua_setglobal(l, modname);
+    }
+}
+
+// 错误信息前面加上调用者的位置
+#[allow(non_snake_case)]
+pub fn luaL_error(l: lua_State, message: &str) -> ! {
+    luaL_where(l.clone(), 1);
+    let location = lua_tostring(l.clone(), -1);
+    lua_pop(l.clone(), 1);
+    lua_pushstring(l.clone(), &format!("{}{}", location, message));
+    lua_error(l)
+}
+
+// 压入 "chunkname:currentline: "，level 层不是 Lua 函数时压入空字符串
+#[allow(non_snake_case)]
+pub fn luaL_where(l: lua_State, level: isize) {
+    let location = l.borrow().location(level);
+    lua_pushstring(l, &location)
+}
+
+// 参考 pushfuncname：优先使用已加载模块中的名字，其次是从调用处推断出的名字
+fn func_description(l: lua_State, ar: &lua_Debug, level: isize) -> String {
+    if let Some(name) = l.borrow().global_func_name(level) {
+        format!("function '{}'", name)
+    } else if !ar.namewhat.is_empty() {
+        format!("{} '{}'", ar.namewhat, ar.name.as_deref().unwrap_or("?"))
+    } else if ar.what == "main" {
+        "main chunk".to_string()
+    } else if ar.what != "C" {
+        format!("function <{}:{}>", ar.short_src, ar.linedefined)
+    } else {
+        "?".to_string()
+    }
+}
+
+// 压入 l1 从 level 层开始的调用栈回溯，层数太多时只保留开头的 LEVELS1 层和最后的 LEVELS2 层
+#[allow(non_snake_case)]
+pub fn luaL_traceback(l: lua_State, l1: lua_State, msg: Option<&str>, level: isize) {
+    const LEVELS1: isize = 10;
+    const LEVELS2: isize = 11;
+    let mut ar = lua_Debug::default();
+    let mut last = 0;
+    while lua_getstack(l1.clone(), last + 1, &mut ar) {
+        last += 1;
+    }
+    let mut n1 = if last - level > LEVELS1 + LEVELS2 {
+        LEVELS1
+    } else {
+        -1
+    };
+    let mut traceback = match msg {
+        Some(msg) => format!("{}\n", msg),
+        None => String::new(),
+    };
+    traceback.push_str("stack traceback:");
+    let mut level = level;
+    while lua_getstack(l1.clone(), level, &mut ar) {
+        if n1 == 0 {
+            traceback.push_str("\n\t...");
+            level = last - LEVELS2 + 1;
+        } else {
+            lua_getinfo(l1.clone(), "Slnt", &mut ar);
+            traceback.push_str(&format!("\n\t{}:", ar.short_src));
+            if ar.currentline > 0 {
+                traceback.push_str(&format!("{}:", ar.currentline));
+            }
+            traceback.push_str(" in ");
+            traceback.push_str(&func_description(l1.clone(), &ar, level));
+            if ar.istailcall {
+                traceback.push_str("\n\t(...tail calls...)");
+            }
+            level += 1;
+        }
+        n1 -= 1;
+    }
+    lua_pushstring(l, &traceback)
+}
+
+// 参考 lauxlib.c 的 luaL_argerror：函数名优先从调用处的指令推断，
+// 推断不出来时在已加载的模块中查找
+#[allow(non_snake_case)]
+pub fn luaL_argerror(l: lua_State, arg: isize, extramsg: &str) -> ! {
+    if !lua_getstack(l.clone(), 0, &mut lua_Debug::default()) {
+        luaL_error(l, &format!("bad argument #{} ({})", arg, extramsg))
+    }
+    let mut arg = arg;
+    let name = l.borrow().func_name(0);
+    if let Some((name, "method")) = &name {
+        arg -= 1;
+        if arg == 0 {
+            luaL_error(l, &format!("calling '{}' on bad self ({})", name, extramsg))
+        }
+    }
+    let name = match name {
+        Some((name, _)) => name,
+        None => l
+            .borrow()
+            .global_func_name(0)
+            .unwrap_or_else(|| "?".to_string()),
+    };
+    luaL_error(
+        l,
+        &format!("bad argument #{} to '{}' ({})", arg, name, extramsg),
+    )
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_typeerror(l: lua_State, arg: isize, tname: &str) -> ! {
+    let typearg = if luaL_getmetafield(l.clone(), arg, "__name") == LUA_TSTRING {
+        lua_tostring(l.clone(), -1)
+    } else if lua_type(l.clone(), arg) == LUA_TLIGHTUSERDATA {
+        "light userdata".to_string()
+    } else {
+        luaL_typename(l.clone(), arg).to_string()
+    };
+    let msg = format!("{} expected, got {}", tname, typearg);
+    luaL_argerror(l, arg, &msg)
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_typename(l: lua_State, i: isize) -> &'static str {
+    lua_typename(l.clone(), lua_type(l, i))
+}
+
+// 元表中有 e 字段时把它压栈并返回它的类型，否则什么也不压，返回 LUA_TNIL
+#[allow(non_snake_case)]
+pub fn luaL_getmetafield(l: lua_State, obj: isize, e: &str) -> isize {
+    if !lua_getmetatable(l.clone(), obj) {
+        return LUA_TNIL;
+    }
+    lua_pushstring(l.clone(), e);
+    let tt = lua_rawget(l.clone(), -2);
+    if tt == LUA_TNIL {
+        lua_pop(l, 2);
+    } else {
+        lua_remove(l, -2);
+    }
+    tt
+}
+
+// 元表中有 event 字段时以 obj 为参数调用它，压入一个结果并返回 true
+#[allow(non_snake_case)]
+pub fn luaL_callmeta(l: lua_State, obj: isize, event: &str) -> bool {
+    let obj = lua_absindex(l.clone(), obj);
+    if luaL_getmetafield(l.clone(), obj, event) == LUA_TNIL {
+        return false;
+    }
+    lua_pushvalue(l.clone(), obj);
+    lua_call(l, 1, 1);
+    true
+}
+
+// 参考 luaL_fileresult：成功时压入 true，失败时压入 nil、错误信息和错误码
+#[allow(non_snake_case)]
+pub fn luaL_fileresult<T>(l: lua_State, res: std::io::Result<T>, fname: Option<&str>) -> usize {
+    match res {
+        Ok(_) => {
+            lua_pushboolean(l, true);
+            1
+        }
+        Err(e) => {
+            let msg = strerror(&e);
+            lua_pushnil(l.clone());
+            match fname {
+                Some(fname) => lua_pushstring(l.clone(), &format!("{}: {}", fname, msg)),
+                None => lua_pushstring(l.clone(), &msg),
+            }
+            lua_pushinteger(l, e.raw_os_error().unwrap_or(0) as isize);
+            3
+        }
+    }
+}
+
+// 参考 luaL_execresult：正常退出并且返回 0 时压入 true，否则压入 nil，
+// 然后是 "exit" 或 "signal" 以及退出码或信号
+#[allow(non_snake_case)]
+pub fn luaL_execresult(l: lua_State, status: std::process::ExitStatus) -> usize {
+    let (what, code) = match status.code() {
+        Some(code) => ("exit", code),
+        None => ("signal", exit_signal(&status)),
+    };
+    if what == "exit" && code == 0 {
+        lua_pushboolean(l.clone(), true);
+    } else {
+        lua_pushnil(l.clone());
+    }
+    lua_pushstring(l.clone(), what);
+    lua_pushinteger(l, code as isize);
+    3
+}
+
+#[cfg(unix)]
+fn exit_signal(status: &std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal().unwrap_or(0)
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: &std::process::ExitStatus) -> i32 {
+    0
+}
+
+// 去掉 io::Error 附加的 " (os error N)"，和 strerror 的结果一致
+pub(crate) fn strerror(e: &std::io::Error) -> String {
+    let msg = e.to_string();
+    match e.raw_os_error() {
+        Some(code) => msg
+            .trim_end_matches(&format!(" (os error {})", code))
+            .to_string(),
+        None => msg,
+    }
+}
+
+// 带元方法的取长度，结果必须是整数
+#[allow(non_snake_case)]
+pub fn luaL_len(l: lua_State, idx: isize) -> lua_Integer {
+    lua_len(l.clone(), idx);
+    let mut isnum = false;
+    let n = lua_tointegerx(l.clone(), -1, Some(&mut isnum));
+    if !isnum {
+        luaL_error(l, "object length is not an integer");
+    }
+    lua_pop(l, 1);
+    n
+}
+
+// 参考 luaL_tolstring：按 tostring 的规则把任意值转换为字符串，结果同时压栈
+#[allow(non_snake_case)]
+pub fn luaL_tolstring(l: lua_State, idx: isize) -> String {
+    if luaL_callmeta(l.clone(), idx, "__tostring") {
+        if !lua_isstring(l.clone(), -1) {
+            luaL_error(l, "'__tostring' must return a string");
+        }
+        return lua_tostring(l, -1);
+    }
+    let s = match lua_type(l.clone(), idx) {
+        LUA_TNUMBER | LUA_TSTRING => {
+            lua_pushvalue(l.clone(), idx);
+            return lua_tostring(l, -1);
+        }
+        LUA_TBOOLEAN => lua_toboolean(l.clone(), idx).to_string(),
+        LUA_TNIL => "nil".to_string(),
+        _ => {
+            let kind = if luaL_getmetafield(l.clone(), idx, "__name") == LUA_TSTRING {
+                let name = lua_tostring(l.clone(), -1);
+                lua_pop(l.clone(), 1);
+                name
+            } else {
+                luaL_typename(l.clone(), idx).to_string()
+            };
+            format!("{}: 0x{:08x}", kind, lua_topointer(l.clone(), idx))
+        }
+    };
+    lua_pushstring(l, &s);
+    s
+}
+
+fn tag_error(l: lua_State, arg: isize, tag: isize) -> ! {
+    let tname = lua_typename(l.clone(), tag);
+    luaL_typeerror(l, arg, tname)
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_argcheck(l: lua_State, cond: bool, arg: isize, extramsg: &str) {
+    if !cond {
+        luaL_argerror(l, arg, extramsg)
+    }
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_checktype(l: lua_State, arg: isize, t: isize) {
+    if lua_type(l.clone(), arg) != t {
+        tag_error(l, arg, t)
+    }
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_checkany(l: lua_State, arg: isize) {
+    if lua_type(l.clone(), arg) == LUA_TNONE {
+        luaL_argerror(l, arg, "value expected")
+    }
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_checkinteger(l: lua_State, arg: isize) -> lua_Integer {
+    let mut isnum = false;
+    let d = lua_tointegerx(l.clone(), arg, Some(&mut isnum));
+    if !isnum {
+        if lua_isnumber(l.clone(), arg) {
+            luaL_argerror(l, arg, "number has no integer representation")
+        }
+        tag_error(l, arg, LUA_TNUMBER)
+    }
+    d
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_checknumber(l: lua_State, arg: isize) -> lua_Number {
+    let mut isnum = false;
+    let d = lua_tonumberx(l.clone(), arg, Some(&mut isnum));
+    if !isnum {
+        tag_error(l, arg, LUA_TNUMBER)
+    }
+    d
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_checklstring(l: lua_State, arg: isize) -> String {
+    match lua_tolstring(l.clone(), arg) {
+        Some(s) => s,
+        None => tag_error(l, arg, LUA_TSTRING),
+    }
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_checkstring(l: lua_State, arg: isize) -> String {
+    luaL_checklstring(l, arg)
+}
+
+// 取得字符串的原始字节
+#[allow(non_snake_case)]
+pub fn luaL_checkbytes(l: lua_State, arg: isize) -> Vec<u8> {
+    match lua_tobytes(l.clone(), arg) {
+        Some(s) => s,
+        None => tag_error(l, arg, LUA_TSTRING),
+    }
+}
+
+// 参数为 none 或 nil 时使用默认值
+#[allow(non_snake_case)]
+pub fn luaL_optinteger(l: lua_State, arg: isize, def: lua_Integer) -> lua_Integer {
+    if lua_isnoneornil(l.clone(), arg) {
+        def
+    } else {
+        luaL_checkinteger(l, arg)
+    }
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_optnumber(l: lua_State, arg: isize, def: lua_Number) -> lua_Number {
+    if lua_isnoneornil(l.clone(), arg) {
+        def
+    } else {
+        luaL_checknumber(l, arg)
+    }
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_optlstring(l: lua_State, arg: isize, def: &str) -> String {
+    if lua_isnoneornil(l.clone(), arg) {
+        def.to_string()
+    } else {
+        luaL_checklstring(l, arg)
+    }
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_optstring(l: lua_State, arg: isize, def: &str) -> String {
+    luaL_optlstring(l, arg, def)
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_optbytes(l: lua_State, arg: isize, def: &[u8]) -> Vec<u8> {
+    if lua_isnoneornil(l.clone(), arg) {
+        def.to_vec()
+    } else {
+        luaL_checkbytes(l, arg)
+    }
+}
+
+// 返回参数在 lst 中的位置，def 是参数缺省时使用的选项
+#[allow(non_snake_case)]
+pub fn luaL_checkoption(l: lua_State, arg: isize, def: Option<&str>, lst: &[&str]) -> usize {
+    let name = match def {
+        Some(def) => luaL_optstring(l.clone(), arg, def),
+        None => luaL_checkstring(l.clone(), arg),
+    };
+    match lst.iter().position(|&o| o == name) {
+        Some(i) => i,
+        None => luaL_argerror(l, arg, &format!("invalid option '{}'", name)),
+    }
+}
+
+// 引用表中 0 号位置保存空闲链表的表头
+const FREELIST: isize = 0;
+
+// 参考 luaL_ref：弹出栈顶的值存入表 t，返回一个唯一的整数引用，
+// 释放的引用会通过空闲链表复用
+#[allow(non_snake_case)]
+pub fn luaL_ref(l: lua_State, t: isize) -> isize {
+    if lua_isnil(l.clone(), -1) {
+        lua_pop(l, 1);
+        return LUA_REFNIL;
+    }
+    let t = lua_absindex(l.clone(), t);
+    lua_rawgeti(l.clone(), t, FREELIST);
+    let r = lua_tointeger(l.clone(), -1) as isize;
+    lua_pop(l.clone(), 1);
+    let r = if r != 0 {
+        lua_rawgeti(l.clone(), t, r);
+        lua_rawseti(l.clone(), t, FREELIST);
+        r
+    } else {
+        lua_rawlen(l.clone(), t) as isize + 1
+    };
+    lua_rawseti(l, t, r);
+    r
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_unref(l: lua_State, t: isize, r: isize) {
+    if r >= 0 {
+        let t = lua_absindex(l.clone(), t);
+        lua_rawgeti(l.clone(), t, FREELIST);
+        lua_rawseti(l.clone(), t, r);
+        lua_pushinteger(l.clone(), r);
+        lua_rawseti(l, t, FREELIST);
+    }
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_newmetatable(l: lua_State, tname: &str) -> bool {
+    if luaL_getmetatable(l.clone(), tname) != LUA_TNIL {
+        return false;
+    }
+    lua_pop(l.clone(), 1);
+    l.borrow_mut().push(LuaValue::new_table(0, 2));
+    lua_pushstring(l.clone(), tname);
+    lua_setfield(l.clone(), -2, "__name");
+    lua_pushvalue(l.clone(), -1);
+    lua_setfield(l, LUA_REGISTRYINDEX, tname);
+    true
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_getmetatable(l: lua_State, tname: &str) -> isize {
+    lua_getfield(l, LUA_REGISTRYINDEX, tname)
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_setmetatable(l: lua_State, tname: &str) {
+    luaL_getmetatable(l.clone(), tname);
+    lua_setmetatable(l, -2);
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_testudata(l: lua_State, ud: isize, tname: &str) -> Option<Rc<RefCell<LuaUserData>>> {
+    let u = lua_touserdata(l.clone(), ud)?;
+    if !lua_getmetatable(l.clone(), ud) {
+        return None;
+    }
+    luaL_getmetatable(l.clone(), tname);
+    let same = l
+        .borrow()
+        .get(lua_absindex(l.clone(), -1))
+        .raw_equal(&l.borrow().get(lua_absindex(l.clone(), -2)));
+    lua_pop(l.clone(), 1);
+    lua_pop(l, 1);
+    if same {
+        Some(u)
+    } else {
+        None
+    }
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_checkudata(l: lua_State, ud: isize, tname: &str) -> Rc<RefCell<LuaUserData>> {
+    match luaL_testudata(l.clone(), ud, tname) {
+        Some(u) => u,
+        None => luaL_typeerror(l, ud, tname),
     }
 }
@@ -1,3 +1,9 @@
+pub const LUA_VERSION: &str = "Lua 5.3";
+
+// io 库中文件句柄的元表名称
+pub const LUA_FILEHANDLE: &str = "FILE*";
+
+pub const LUA_TNONE: isize = -1;
 pub const LUA_TNIL: isize = 0;
 pub const LUA_TBOOLEAN: isize = 1;
 pub const LUA_TLIGHTUSERDATA: isize = 2;
@@ -7,8 +13,70 @@ pub const LUA_TTABLE: isize = 5;
 pub const LUA_TFUNCTION: isize = 6;
 pub const LUA_TUSERDATA: isize = 7;
 pub const LUA_TTHREAD: isize = 8;
+pub const LUA_NUMTAGS: isize = 9;
+
+pub const LUA_OK: isize = 0;
+pub const LUA_YIELD: isize = 1;
+pub const LUA_ERRRUN: isize = 2;
+pub const LUA_ERRSYNTAX: isize = 3;
+pub const LUA_ERRMEM: isize = 4;
+pub const LUA_ERRGCMM: isize = 5;
+pub const LUA_ERRERR: isize = 6;
+pub const LUA_ERRFILE: isize = 7;
+
+pub const LUA_OPADD: isize = 0;
+pub const LUA_OPSUB: isize = 1;
+pub const LUA_OPMUL: isize = 2;
+pub const LUA_OPMOD: isize = 3;
+pub const LUA_OPPOW: isize = 4;
+pub const LUA_OPDIV: isize = 5;
+pub const LUA_OPIDIV: isize = 6;
+pub const LUA_OPBAND: isize = 7;
+pub const LUA_OPBOR: isize = 8;
+pub const LUA_OPBXOR: isize = 9;
+pub const LUA_OPSHL: isize = 10;
+pub const LUA_OPSHR: isize = 11;
+pub const LUA_OPUNM: isize = 12;
+pub const LUA_OPBNOT: isize = 13;
+
+pub const LUA_OPEQ: isize = 0;
+pub const LUA_OPLT: isize = 1;
+pub const LUA_OPLE: isize = 2;
+
+pub const LUA_MULTRET: isize = -1;
+
+// __index/__newindex 链的最大长度
+pub const MAXTAGLOOP: usize = 2000;
 
 pub const LUA_MINSTACK: usize = 20;
 pub const LUAI_MAXSTACK: usize = 1000000;
 pub const LUA_REGISTRYINDEX: isize = -(LUAI_MAXSTACK as isize) - 1000;
+pub const LUA_RIDX_MAINTHREAD: isize = 1;
 pub const LUA_RIDX_GLOBALS: isize = 2;
+pub const LUA_RIDX_LAST: isize = LUA_RIDX_GLOBALS;
+
+// luaL_ref 的特殊返回值
+pub const LUA_NOREF: isize = -2;
+pub const LUA_REFNIL: isize = -1;
+
+pub const LUA_GCSTOP: isize = 0;
+pub const LUA_GCRESTART: isize = 1;
+pub const LUA_GCCOLLECT: isize = 2;
+pub const LUA_GCCOUNT: isize = 3;
+pub const LUA_GCCOUNTB: isize = 4;
+pub const LUA_GCSTEP: isize = 5;
+pub const LUA_GCSETPAUSE: isize = 6;
+pub const LUA_GCSETSTEPMUL: isize = 7;
+pub const LUA_GCISRUNNING: isize = 9;
+
+// 调试钩子的事件和对应的掩码
+pub const LUA_HOOKCALL: isize = 0;
+pub const LUA_HOOKRET: isize = 1;
+pub const LUA_HOOKLINE: isize = 2;
+pub const LUA_HOOKCOUNT: isize = 3;
+pub const LUA_HOOKTAILCALL: isize = 4;
+
+pub const LUA_MASKCALL: isize = 1 << LUA_HOOKCALL;
+pub const LUA_MASKRET: isize = 1 << LUA_HOOKRET;
+pub const LUA_MASKLINE: isize = 1 << LUA_HOOKLINE;
+pub const LUA_MASKCOUNT: isize = 1 << LUA_HOOKCOUNT;
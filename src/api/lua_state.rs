@@ -1,11 +1,15 @@
-use crate::api::lua_CFunction;
+use super::{lua_Debug, lua_Hook};
 use crate::chunk::binary::Prototype;
-use crate::state::LuaValue;
+use crate::state::{LuaValue, NativeFunction};
+use std::any::Any;
 use std::cell::RefCell;
 use std::rc::Rc;
 
 #[allow(non_camel_case_types)]
 pub trait luaState {
+    // 取得具体的状态类型，高层接口用它从原生函数收到的 lua_State 恢复 Lua
+    fn as_any(&self) -> &dyn Any;
+
     fn abs_index(&self, idx: isize) -> isize;
     fn get_top(&self) -> isize;
 
@@ -13,19 +17,55 @@ pub trait luaState {
     fn push(&mut self, value: LuaValue);
     fn pop(&mut self, n: isize);
     fn pushvalue(&mut self, index: isize);
+    fn settop(&mut self, index: isize);
+    fn rotate(&mut self, index: isize, n: isize);
+    fn copy(&mut self, from: isize, to: isize);
+    fn check_stack(&mut self, n: isize) -> bool;
 
-    fn get_global(&mut self, name: &str);
-    fn raw_geti(&mut self, idx: isize, n: isize);
+    fn get_global(&mut self, name: &str) -> isize;
+    fn get_table(&mut self, index: isize) -> isize;
+    fn get_field(&mut self, index: isize, name: &str) -> isize;
+    fn get_i(&mut self, index: isize, n: isize) -> isize;
+    fn raw_get(&mut self, index: isize) -> isize;
+    fn raw_geti(&mut self, index: isize, n: isize) -> isize;
+    fn create_table(&mut self, narr: isize, nrec: isize);
 
     fn set_global(&mut self, value: &str);
+    fn set_table(&mut self, index: isize);
     fn set_field(&mut self, index: isize, name: &str);
+    fn set_i(&mut self, index: isize, n: isize);
+    fn raw_set(&mut self, index: isize);
+    fn raw_seti(&mut self, index: isize, n: isize);
+
+    fn push_native_closure(&mut self, func: NativeFunction, n: isize);
+
+    fn get_metatable(&mut self, index: isize) -> bool;
+    fn set_metatable(&mut self, index: isize);
+    fn get_uservalue(&mut self, index: isize) -> isize;
+    fn set_uservalue(&mut self, index: isize);
+    fn set_upvalue(&mut self, index: isize, n: isize) -> Option<String>;
 
-    fn push_native_function(&mut self, func: lua_CFunction);
+    fn gc(&mut self, what: isize, data: isize) -> isize;
+    fn close(&mut self);
 
     fn load(&mut self, proto: Prototype);
     fn call(&mut self, nargs: isize, nresults: isize);
+    fn pcall(&mut self, nargs: isize, nresults: isize, msgh: isize) -> isize;
 
     fn lua_type(&self, index: isize) -> isize;
+    fn arith(&mut self, op: isize);
+    fn compare(&mut self, index1: isize, index2: isize, op: isize) -> bool;
+    fn raw_equal(&self, index1: isize, index2: isize) -> bool;
+    fn concat(&mut self, n: isize);
+    fn len(&mut self, index: isize);
+    fn next(&mut self, index: isize) -> bool;
+
+    fn to_numberx(&self, index: isize) -> Option<f64>;
+    fn to_integerx(&self, index: isize) -> Option<i64>;
+    fn to_boolean(&self, index: isize) -> bool;
+    fn to_lstring(&mut self, index: isize) -> Option<Vec<u8>>;
+    fn raw_len(&self, index: isize) -> usize;
+    fn string_to_number(&mut self, s: &str) -> usize;
     fn is_number(&self, index: isize) -> bool;
     fn is_string(&self, index: isize) -> bool;
     fn is_cfunction(&self, index: isize) -> bool;
@@ -34,6 +74,21 @@ pub trait luaState {
     fn is_nil(&self, index: isize) -> bool;
     fn is_boolean(&self, index: isize) -> bool;
     fn is_function(&self, index: isize) -> bool;
+
+    fn get_stack(&self, level: isize, ar: &mut lua_Debug) -> bool;
+    fn get_info(&mut self, what: &str, ar: &mut lua_Debug) -> bool;
+    fn get_local(&mut self, ar: Option<&lua_Debug>, n: isize) -> Option<String>;
+    fn set_local(&mut self, ar: &lua_Debug, n: isize) -> Option<String>;
+    fn push_upvalue(&mut self, index: isize, n: isize) -> Option<String>;
+    fn upvalue_id(&self, index: isize, n: isize) -> Option<usize>;
+    fn upvalue_join(&mut self, index1: isize, n1: isize, index2: isize, n2: isize);
+    fn set_hook(&mut self, func: Option<lua_Hook>, mask: isize, count: isize);
+    fn get_hook(&self) -> Option<lua_Hook>;
+    fn get_hook_mask(&self) -> isize;
+    fn get_hook_count(&self) -> isize;
+    fn func_name(&self, level: isize) -> Option<(String, &'static str)>;
+    fn global_func_name(&self, level: isize) -> Option<String>;
+    fn location(&self, level: isize) -> String;
 }
 
 #[allow(non_camel_case_types)]
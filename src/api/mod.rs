@@ -7,20 +7,71 @@ pub use self::aux_lib::*;
 pub use self::constants::*;
 pub use self::lua_state::*;
 pub use self::std_libs::*;
-use crate::state::LuaState;
+pub use crate::state::LuaUserData;
 pub use crate::state::LuaValue;
+use crate::state::{CallInfo, LuaState};
+pub use crate::stdlib::{OsHost, SystemHost};
+use std::any::Any;
 use std::cell::RefCell;
 use std::rc::Rc;
 
 #[allow(non_camel_case_types)]
 pub type lua_CFunction = fn(lua_State) -> usize;
 
+#[allow(non_camel_case_types)]
+pub type lua_Number = f64;
+
+#[allow(non_camel_case_types)]
+pub type lua_Integer = i64;
+
+pub const fn lua_upvalueindex(i: isize) -> isize {
+    LUA_REGISTRYINDEX - i
+}
+
+// lua_error 以 panic 的方式展开调用栈，错误对象留在栈顶。
+// 因此不能用 panic = "abort" 编译，否则 pcall 无法捕获错误。
+// 默认的 panic 钩子会打印这些错误，可以调用 llua::install_panic_hook 忽略它们
+#[derive(Debug)]
+pub struct LuaError {
+    pub status: isize,
+    pub message: String,
+}
+
+// 调试接口使用的活动记录，lua_getstack 记住对应的调用，lua_getinfo 按选项填写其余字段
+#[allow(non_camel_case_types)]
+#[derive(Clone, Default)]
+pub struct lua_Debug {
+    pub event: isize,
+    pub name: Option<String>,
+    // "global"、"local"、"method"、"field"、"upvalue" 或者 ""
+    pub namewhat: String,
+    // "Lua"、"C" 或者 "main"
+    pub what: String,
+    pub source: String,
+    pub currentline: isize,
+    pub linedefined: isize,
+    pub lastlinedefined: isize,
+    pub nups: usize,
+    pub nparams: usize,
+    pub isvararg: bool,
+    pub istailcall: bool,
+    pub short_src: String,
+    pub(crate) i_ci: Option<Rc<RefCell<CallInfo>>>,
+}
+
+#[allow(non_camel_case_types)]
+pub type lua_Hook = fn(lua_State, &mut lua_Debug);
+
 // state manipulation
 
 pub fn create_state(l: LuaState) -> lua_State {
     Rc::new(RefCell::new(l))
 }
 
+pub fn lua_close(l: lua_State) {
+    l.borrow_mut().close()
+}
+
 // basic stack manipulation
 
 pub fn lua_absindex(l: lua_State, idx: isize) -> isize {
@@ -31,11 +82,44 @@ pub fn lua_gettop(l: lua_State) -> isize {
     l.borrow().get_top()
 }
 
+pub fn lua_settop(l: lua_State, idx: isize) {
+    l.borrow_mut().settop(idx)
+}
+
 pub fn lua_pushvalue(l: lua_State, idx: isize) {
     let index = lua_absindex(l.clone(), idx);
     l.borrow_mut().pushvalue(index)
 }
 
+pub fn lua_rotate(l: lua_State, idx: isize, n: isize) {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().rotate(index, n)
+}
+
+pub fn lua_copy(l: lua_State, fromidx: isize, toidx: isize) {
+    let from = lua_absindex(l.clone(), fromidx);
+    let to = lua_absindex(l.clone(), toidx);
+    l.borrow_mut().copy(from, to)
+}
+
+pub fn lua_checkstack(l: lua_State, n: isize) -> bool {
+    l.borrow_mut().check_stack(n)
+}
+
+pub fn lua_insert(l: lua_State, idx: isize) {
+    lua_rotate(l, idx, 1)
+}
+
+pub fn lua_remove(l: lua_State, idx: isize) {
+    lua_rotate(l.clone(), idx, -1);
+    lua_pop(l, 1)
+}
+
+pub fn lua_replace(l: lua_State, idx: isize) {
+    lua_copy(l.clone(), -1, idx);
+    lua_pop(l, 1)
+}
+
 // access functions (stack -> C)
 
 pub fn lua_isnil(l: lua_State, idx: isize) -> bool {
@@ -68,32 +152,145 @@ pub fn lua_isfunction(l: lua_State, idx: isize) -> bool {
     l.borrow().is_function(index)
 }
 
+pub fn lua_isuserdata(l: lua_State, idx: isize) -> bool {
+    let t = lua_type(l, idx);
+    t == LUA_TUSERDATA || t == LUA_TLIGHTUSERDATA
+}
+
+pub fn lua_islightuserdata(l: lua_State, idx: isize) -> bool {
+    lua_type(l, idx) == LUA_TLIGHTUSERDATA
+}
+
 pub fn lua_istable(l: lua_State, idx: isize) -> bool {
     let index = lua_absindex(l.clone(), idx);
     l.borrow().is_table(index)
 }
 
+pub fn lua_isnone(l: lua_State, idx: isize) -> bool {
+    lua_type(l, idx) == LUA_TNONE
+}
+
+pub fn lua_isnoneornil(l: lua_State, idx: isize) -> bool {
+    lua_type(l, idx) <= 0
+}
+
 pub fn lua_type(l: lua_State, idx: isize) -> isize {
     let index = lua_absindex(l.clone(), idx);
     l.borrow().lua_type(index)
 }
 
-pub fn lua_tointeger(l: lua_State, idx: isize) -> LuaValue {
+pub fn lua_typename(_l: lua_State, tp: isize) -> &'static str {
+    match tp {
+        LUA_TNIL => "nil",
+        LUA_TBOOLEAN => "boolean",
+        LUA_TLIGHTUSERDATA => "userdata",
+        LUA_TNUMBER => "number",
+        LUA_TSTRING => "string",
+        LUA_TTABLE => "table",
+        LUA_TFUNCTION => "function",
+        LUA_TUSERDATA => "userdata",
+        LUA_TTHREAD => "thread",
+        _ => "no value",
+    }
+}
+
+// isnum 对应 C API 中的 int *isnum 出参
+pub fn lua_tonumberx(l: lua_State, idx: isize, isnum: Option<&mut bool>) -> lua_Number {
+    let index = lua_absindex(l.clone(), idx);
+    let n = l.borrow().to_numberx(index);
+    if let Some(isnum) = isnum {
+        *isnum = n.is_some();
+    }
+    n.unwrap_or(0.0)
+}
+
+pub fn lua_tointegerx(l: lua_State, idx: isize, isnum: Option<&mut bool>) -> lua_Integer {
+    let index = lua_absindex(l.clone(), idx);
+    let n = l.borrow().to_integerx(index);
+    if let Some(isnum) = isnum {
+        *isnum = n.is_some();
+    }
+    n.unwrap_or(0)
+}
+
+pub fn lua_tonumber(l: lua_State, idx: isize) -> lua_Number {
+    lua_tonumberx(l, idx, None)
+}
+
+pub fn lua_tointeger(l: lua_State, idx: isize) -> lua_Integer {
+    lua_tointegerx(l, idx, None)
+}
+
+pub fn lua_toboolean(l: lua_State, idx: isize) -> bool {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow().to_boolean(index)
+}
+
+// 不能转换为字符串时返回 None（C API 中的 NULL），数字会被原地转换。
+// 字符串的内容不是合法的 UTF-8 时，非法的部分会被替换，需要原始内容时用 lua_tobytes
+pub fn lua_tolstring(l: lua_State, idx: isize) -> Option<String> {
+    lua_tobytes(l, idx).map(|s| match String::from_utf8(s) {
+        Ok(s) => s,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    })
+}
+
+pub fn lua_tobytes(l: lua_State, idx: isize) -> Option<Vec<u8>> {
     let index = lua_absindex(l.clone(), idx);
-    l.borrow().get(index)
+    l.borrow_mut().to_lstring(index)
 }
 
 pub fn lua_tostring(l: lua_State, idx: isize) -> String {
+    lua_tolstring(l, idx).unwrap_or_default()
+}
+
+pub fn lua_rawlen(l: lua_State, idx: isize) -> usize {
     let index = lua_absindex(l.clone(), idx);
-    if let LuaValue::String(s) = l.borrow().get(index) {
-        s
+    l.borrow().raw_len(index)
+}
+
+// 转换成功时把结果压栈并返回字符串长度加一，否则返回 0
+pub fn lua_stringtonumber(l: lua_State, s: &str) -> usize {
+    l.borrow_mut().string_to_number(s)
+}
+
+pub fn lua_touserdata(l: lua_State, idx: isize) -> Option<Rc<RefCell<LuaUserData>>> {
+    let index = lua_absindex(l.clone(), idx);
+    if let LuaValue::UserData(u) = l.borrow().get(index) {
+        Some(u)
     } else {
-        "".to_string()
+        None
+    }
+}
+
+pub fn lua_topointer(l: lua_State, idx: isize) -> usize {
+    let index = lua_absindex(l.clone(), idx);
+    let v = l.borrow().get(index);
+    match v {
+        LuaValue::LightUserData(p) => p,
+        _ => v.gc_id().unwrap_or(0),
     }
 }
 
 // Comparison and arithmetic functions
 
+// 弹出两个操作数（一元运算弹出一个），压入运算结果
+pub fn lua_arith(l: lua_State, op: isize) {
+    l.borrow_mut().arith(op)
+}
+
+pub fn lua_rawequal(l: lua_State, idx1: isize, idx2: isize) -> bool {
+    let index1 = lua_absindex(l.clone(), idx1);
+    let index2 = lua_absindex(l.clone(), idx2);
+    l.borrow().raw_equal(index1, index2)
+}
+
+pub fn lua_compare(l: lua_State, idx1: isize, idx2: isize, op: isize) -> bool {
+    let index1 = lua_absindex(l.clone(), idx1);
+    let index2 = lua_absindex(l.clone(), idx2);
+    l.borrow_mut().compare(index1, index2, op)
+}
+
 // push functions (C -> stack)
 
 pub fn lua_pushnil(l: lua_State) {
@@ -113,11 +310,44 @@ pub fn lua_pushinteger(l: lua_State, value: isize) {
 }
 
 pub fn lua_pushstring(l: lua_State, value: &str) {
-    l.borrow_mut().push(LuaValue::String(value.to_string()))
+    l.borrow_mut().push(LuaValue::String(value.into()))
+}
+
+// Lua 字符串是任意的字节序列
+pub fn lua_pushbytes(l: lua_State, value: &[u8]) {
+    l.borrow_mut().push(LuaValue::String(value.to_vec()))
+}
+
+pub fn lua_pushcclosure(l: lua_State, func: lua_CFunction, n: isize) {
+    l.borrow_mut().push_native_closure(Rc::new(func), n)
 }
 
 pub fn lua_pushcfunction(l: lua_State, func: lua_CFunction) {
-    l.borrow_mut().push_native_function(func)
+    lua_pushcclosure(l, func, 0)
+}
+
+// 捕获了 Rust 状态的闭包，n 个上值和 lua_pushcclosure 一样从栈顶弹出
+pub fn lua_pushrustclosure<F>(l: lua_State, func: F, n: isize)
+where
+    F: Fn(lua_State) -> usize + 'static,
+{
+    l.borrow_mut().push_native_closure(Rc::new(func), n)
+}
+
+pub fn lua_pushrustclosure_mut<F>(l: lua_State, func: F, n: isize)
+where
+    F: FnMut(lua_State) -> usize + 'static,
+{
+    let func = RefCell::new(func);
+    let wrapper = move |l: lua_State| match func.try_borrow_mut() {
+        Ok(mut f) => f(l),
+        Err(_) => luaL_error(l, "attempt to re-enter a running native function"),
+    };
+    l.borrow_mut().push_native_closure(Rc::new(wrapper), n)
+}
+
+pub fn lua_pushlightuserdata(l: lua_State, p: usize) {
+    l.borrow_mut().push(LuaValue::LightUserData(p))
 }
 
 pub fn lua_pushglobaltable(l: lua_State) {
@@ -125,17 +355,72 @@ pub fn lua_pushglobaltable(l: lua_State) {
 }
 
 pub fn lua_pop(l: lua_State, n: isize) {
-    l.borrow_mut().pop(n)
+    lua_settop(l, -n - 1)
 }
 
 // get functions (Lua -> stack)
 
-pub fn lua_getglobal(l: lua_State, name: &str) {
+pub fn lua_getglobal(l: lua_State, name: &str) -> isize {
     l.borrow_mut().get_global(name)
 }
 
-pub fn lua_rawgeti(l: lua_State, idx: isize, n: isize) {
-    l.borrow_mut().raw_geti(idx, n)
+pub fn lua_gettable(l: lua_State, idx: isize) -> isize {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().get_table(index)
+}
+
+pub fn lua_getfield(l: lua_State, idx: isize, name: &str) -> isize {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().get_field(index, name)
+}
+
+pub fn lua_geti(l: lua_State, idx: isize, n: isize) -> isize {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().get_i(index, n)
+}
+
+pub fn lua_rawget(l: lua_State, idx: isize) -> isize {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().raw_get(index)
+}
+
+pub fn lua_rawgeti(l: lua_State, idx: isize, n: isize) -> isize {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().raw_geti(index, n)
+}
+
+// 以轻量 userdata p 为键读取，不触发元方法
+pub fn lua_rawgetp(l: lua_State, idx: isize, p: usize) -> isize {
+    let index = lua_absindex(l.clone(), idx);
+    lua_pushlightuserdata(l.clone(), p);
+    lua_rawget(l, index)
+}
+
+pub fn lua_createtable(l: lua_State, narr: isize, nrec: isize) {
+    l.borrow_mut().create_table(narr, nrec)
+}
+
+pub fn lua_newtable(l: lua_State) {
+    lua_createtable(l, 0, 0)
+}
+
+pub fn lua_newuserdata(l: lua_State, data: Box<dyn Any>) -> Rc<RefCell<LuaUserData>> {
+    let ud = LuaValue::new_userdata(data);
+    l.borrow_mut().push(ud.clone());
+    match ud {
+        LuaValue::UserData(u) => u,
+        _ => unreachable!(),
+    }
+}
+
+pub fn lua_getmetatable(l: lua_State, idx: isize) -> bool {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().get_metatable(index)
+}
+
+pub fn lua_getuservalue(l: lua_State, idx: isize) -> isize {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().get_uservalue(index)
 }
 
 // set functions (stack -> Lua)
@@ -144,8 +429,14 @@ pub fn lua_setglobal(l: lua_State, value: &str) {
     l.borrow_mut().set_global(value)
 }
 
+pub fn lua_register(l: lua_State, name: &str, f: lua_CFunction) {
+    lua_pushcfunction(l.clone(), f);
+    lua_setglobal(l, name)
+}
+
 pub fn lua_settable(l: lua_State, idx: isize) {
-    unimplemented!();
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().set_table(index)
 }
 
 pub fn lua_setfield(l: lua_State, idx: isize, name: &str) {
@@ -153,14 +444,143 @@ pub fn lua_setfield(l: lua_State, idx: isize, name: &str) {
     l.borrow_mut().set_field(index, name)
 }
 
+pub fn lua_seti(l: lua_State, idx: isize, n: isize) {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().set_i(index, n)
+}
+
+pub fn lua_rawset(l: lua_State, idx: isize) {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().raw_set(index)
+}
+
+pub fn lua_rawseti(l: lua_State, idx: isize, n: isize) {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().raw_seti(index, n)
+}
+
+pub fn lua_rawsetp(l: lua_State, idx: isize, p: usize) {
+    let index = lua_absindex(l.clone(), idx);
+    lua_pushlightuserdata(l.clone(), p);
+    lua_insert(l.clone(), -2);
+    lua_rawset(l, index)
+}
+
+pub fn lua_setmetatable(l: lua_State, idx: isize) {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().set_metatable(index)
+}
+
+pub fn lua_setuservalue(l: lua_State, idx: isize) {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().set_uservalue(index)
+}
+
+// 弹出栈顶的值设置为闭包的第 n 个上值，返回上值的名字
+pub fn lua_setupvalue(l: lua_State, funcindex: isize, n: isize) -> Option<String> {
+    let index = lua_absindex(l.clone(), funcindex);
+    l.borrow_mut().set_upvalue(index, n)
+}
+
 // 'load' and 'call' functions (load and run Lua code)
 
 pub fn lua_call(l: lua_State, nargs: isize, nresults: isize) {
     l.borrow_mut().call(nargs, nresults)
 }
 
+// 保护模式下调用，出错时返回错误码并把错误对象留在栈顶；msgh 为 0 表示没有消息处理函数
+pub fn lua_pcall(l: lua_State, nargs: isize, nresults: isize, msgh: isize) -> isize {
+    let msgh = if msgh == 0 {
+        0
+    } else {
+        lua_absindex(l.clone(), msgh)
+    };
+    l.borrow_mut().pcall(nargs, nresults, msgh)
+}
+
 // coroutine functions
 
 // garbage-collection function and options
 
+pub fn lua_gc(l: lua_State, what: isize, data: isize) -> isize {
+    l.borrow_mut().gc(what, data)
+}
+
 // miscellaneous functions
+
+pub fn lua_concat(l: lua_State, n: isize) {
+    l.borrow_mut().concat(n)
+}
+
+pub fn lua_len(l: lua_State, idx: isize) {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().len(index)
+}
+
+// 弹出一个键，压入表中它的下一个键值对；遍历结束时什么也不压，返回 false
+pub fn lua_next(l: lua_State, idx: isize) -> bool {
+    let index = lua_absindex(l.clone(), idx);
+    l.borrow_mut().next(index)
+}
+
+pub fn lua_error(l: lua_State) -> ! {
+    let message = lua_tostring(l.clone(), -1);
+    std::panic::panic_any(LuaError {
+        status: LUA_ERRRUN,
+        message,
+    })
+}
+
+// debug API
+
+pub fn lua_getstack(l: lua_State, level: isize, ar: &mut lua_Debug) -> bool {
+    l.borrow().get_stack(level, ar)
+}
+
+// what 以 '>' 开头时查询栈顶弹出的函数；选项 'f' 压入函数本身，'L' 压入有效行号的表
+pub fn lua_getinfo(l: lua_State, what: &str, ar: &mut lua_Debug) -> bool {
+    l.borrow_mut().get_info(what, ar)
+}
+
+// ar 为 None 时只能查询栈顶的 Lua 函数的参数名，什么也不压栈
+pub fn lua_getlocal(l: lua_State, ar: Option<&lua_Debug>, n: isize) -> Option<String> {
+    l.borrow_mut().get_local(ar, n)
+}
+
+// 弹出栈顶的值赋给局部变量，没有这个局部变量时不弹出
+pub fn lua_setlocal(l: lua_State, ar: &lua_Debug, n: isize) -> Option<String> {
+    l.borrow_mut().set_local(ar, n)
+}
+
+// 把闭包的第 n 个上值压栈，返回上值的名字
+pub fn lua_getupvalue(l: lua_State, funcindex: isize, n: isize) -> Option<String> {
+    let index = lua_absindex(l.clone(), funcindex);
+    l.borrow_mut().push_upvalue(index, n)
+}
+
+pub fn lua_upvalueid(l: lua_State, funcindex: isize, n: isize) -> Option<usize> {
+    let index = lua_absindex(l.clone(), funcindex);
+    l.borrow().upvalue_id(index, n)
+}
+
+pub fn lua_upvaluejoin(l: lua_State, funcindex1: isize, n1: isize, funcindex2: isize, n2: isize) {
+    let f1 = lua_absindex(l.clone(), funcindex1);
+    let f2 = lua_absindex(l.clone(), funcindex2);
+    l.borrow_mut().upvalue_join(f1, n1, f2, n2)
+}
+
+pub fn lua_sethook(l: lua_State, func: Option<lua_Hook>, mask: isize, count: isize) {
+    l.borrow_mut().set_hook(func, mask, count)
+}
+
+pub fn lua_gethook(l: lua_State) -> Option<lua_Hook> {
+    l.borrow().get_hook()
+}
+
+pub fn lua_gethookmask(l: lua_State) -> isize {
+    l.borrow().get_hook_mask()
+}
+
+pub fn lua_gethookcount(l: lua_State) -> isize {
+    l.borrow().get_hook_count()
+}
@@ -2,9 +2,170 @@
 
 use super::lua_State;
 use crate::api::*;
-use crate::stdlib::basic_print;
+use crate::stdlib::*;
+use std::rc::Rc;
 
-const BASE_FUNCTION: &'static [luaL_Reg] = &[register_lib_function("print", basic_print)];
+const BASE_FUNCTION: &'static [luaL_Reg] = &[
+    register_lib_function("assert", basic_assert),
+    register_lib_function("collectgarbage", basic_collectgarbage),
+    register_lib_function("dofile", basic_dofile),
+    register_lib_function("error", basic_error),
+    register_lib_function("getmetatable", basic_getmetatable),
+    register_lib_function("ipairs", basic_ipairs),
+    register_lib_function("loadfile", basic_loadfile),
+    register_lib_function("load", basic_load),
+    register_lib_function("next", basic_next),
+    register_lib_function("pairs", basic_pairs),
+    register_lib_function("pcall", basic_pcall),
+    register_lib_function("print", basic_print),
+    register_lib_function("rawequal", basic_rawequal),
+    register_lib_function("rawlen", basic_rawlen),
+    register_lib_function("rawget", basic_rawget),
+    register_lib_function("rawset", basic_rawset),
+    register_lib_function("select", basic_select),
+    register_lib_function("setmetatable", basic_setmetatable),
+    register_lib_function("tonumber", basic_tonumber),
+    register_lib_function("tostring", basic_tostring),
+    register_lib_function("type", basic_type),
+    register_lib_function("xpcall", basic_xpcall),
+];
+
+const STRING_FUNCTION: &[luaL_Reg] = &[
+    register_lib_function("byte", str_byte),
+    register_lib_function("char", str_char),
+    register_lib_function("find", str_find),
+    register_lib_function("format", str_format),
+    register_lib_function("gmatch", str_gmatch),
+    register_lib_function("gsub", str_gsub),
+    register_lib_function("len", str_len),
+    register_lib_function("lower", str_lower),
+    register_lib_function("match", str_match),
+    register_lib_function("pack", str_pack),
+    register_lib_function("packsize", str_packsize),
+    register_lib_function("rep", str_rep),
+    register_lib_function("reverse", str_reverse),
+    register_lib_function("sub", str_sub),
+    register_lib_function("unpack", str_unpack),
+    register_lib_function("upper", str_upper),
+];
+
+const TABLE_FUNCTION: &[luaL_Reg] = &[
+    register_lib_function("concat", tab_concat),
+    register_lib_function("insert", tab_insert),
+    register_lib_function("move", tab_move),
+    register_lib_function("pack", tab_pack),
+    register_lib_function("remove", tab_remove),
+    register_lib_function("sort", tab_sort),
+    register_lib_function("unpack", tab_unpack),
+];
+
+const MATH_FUNCTION: &[luaL_Reg] = &[
+    register_lib_function("abs", math_abs),
+    register_lib_function("acos", math_acos),
+    register_lib_function("asin", math_asin),
+    register_lib_function("atan", math_atan),
+    register_lib_function("ceil", math_ceil),
+    register_lib_function("cos", math_cos),
+    register_lib_function("deg", math_deg),
+    register_lib_function("exp", math_exp),
+    register_lib_function("floor", math_floor),
+    register_lib_function("fmod", math_fmod),
+    register_lib_function("log", math_log),
+    register_lib_function("max", math_max),
+    register_lib_function("min", math_min),
+    register_lib_function("modf", math_modf),
+    register_lib_function("rad", math_rad),
+    register_lib_function("sin", math_sin),
+    register_lib_function("sqrt", math_sqrt),
+    register_lib_function("tan", math_tan),
+    register_lib_function("tointeger", math_tointeger),
+    register_lib_function("type", math_type),
+    register_lib_function("ult", math_ult),
+];
+
+const UTF8_FUNCTION: &[luaL_Reg] = &[
+    register_lib_function("char", utf8_char),
+    register_lib_function("codepoint", utf8_codepoint),
+    register_lib_function("codes", utf8_codes),
+    register_lib_function("len", utf8_len),
+    register_lib_function("offset", utf8_offset),
+];
+
+const OS_FUNCTION: &[luaL_Reg] = &[
+    register_lib_function("difftime", os_difftime),
+    register_lib_function("remove", os_remove),
+    register_lib_function("rename", os_rename),
+    register_lib_function("tmpname", os_tmpname),
+];
+
+const IO_FUNCTION: &[luaL_Reg] = &[
+    register_lib_function("close", io_close),
+    register_lib_function("flush", io_flush),
+    register_lib_function("input", io_input),
+    register_lib_function("lines", io_lines),
+    register_lib_function("open", io_open),
+    register_lib_function("output", io_output),
+    register_lib_function("popen", io_popen),
+    register_lib_function("read", io_read),
+    register_lib_function("tmpfile", io_tmpfile),
+    register_lib_function("type", io_type),
+    register_lib_function("write", io_write),
+];
+
+// 文件句柄的元表，它同时也是方法表
+const FILE_METHODS: &[luaL_Reg] = &[
+    register_lib_function("close", file_close),
+    register_lib_function("flush", file_flush),
+    register_lib_function("lines", file_lines),
+    register_lib_function("read", file_read),
+    register_lib_function("seek", file_seek),
+    register_lib_function("setvbuf", file_setvbuf),
+    register_lib_function("write", file_write),
+    register_lib_function("__close", file_gc),
+    register_lib_function("__gc", file_gc),
+    register_lib_function("__tostring", file_tostring),
+];
+
+const PACKAGE_FUNCTION: &[luaL_Reg] = &[
+    register_lib_function("loadlib", ll_loadlib),
+    register_lib_function("searchpath", ll_searchpath),
+];
+
+// 注册到全局表中，以 package 表作为上值
+const LL_FUNCTION: &[luaL_Reg] = &[register_lib_function("require", ll_require)];
+
+const DEBUG_FUNCTION: &[luaL_Reg] = &[
+    register_lib_function("gethook", db_gethook),
+    register_lib_function("getinfo", db_getinfo),
+    register_lib_function("getlocal", db_getlocal),
+    register_lib_function("getmetatable", db_getmetatable),
+    register_lib_function("getregistry", db_getregistry),
+    register_lib_function("getupvalue", db_getupvalue),
+    register_lib_function("getuservalue", db_getuservalue),
+    register_lib_function("sethook", db_sethook),
+    register_lib_function("setlocal", db_setlocal),
+    register_lib_function("setmetatable", db_setmetatable),
+    register_lib_function("setupvalue", db_setupvalue),
+    register_lib_function("setuservalue", db_setuservalue),
+    register_lib_function("traceback", db_traceback),
+    register_lib_function("upvalueid", db_upvalueid),
+    register_lib_function("upvaluejoin", db_upvaluejoin),
+];
+
+type OpenFunction = fn(lua_State) -> isize;
+
+// luaL_openlibs 打开的标准库，基础库注册为 _G
+const LOADED_LIBS: &[(&str, OpenFunction)] = &[
+    ("_G", luaopen_base),
+    ("package", luaopen_package),
+    ("string", luaopen_string),
+    ("table", luaopen_table),
+    ("io", luaopen_io),
+    ("os", luaopen_os),
+    ("math", luaopen_math),
+    ("utf8", luaopen_utf8),
+    ("debug", luaopen_debug),
+];
 
 const fn register_lib_function(name: &'static str, func: lua_CFunction) -> luaL_Reg {
     luaL_Reg { name, func }
@@ -12,8 +173,124 @@ const fn register_lib_function(name: &'static str, func: lua_CFunction) -> luaL_
 
 pub fn luaopen_base(l: lua_State) -> isize {
     lua_pushglobaltable(l.clone());
-    luaL_setfuncs(l.clone(), BASE_FUNCTION);
+    luaL_setfuncs(l.clone(), BASE_FUNCTION, 0);
+    lua_pushvalue(l.clone(), -1);
+    lua_setfield(l.clone(), -2, "_G");
+    lua_pushstring(l.clone(), LUA_VERSION);
+    lua_setfield(l, -2, "_VERSION");
+    1
+}
+
+// 所有字符串共享一个元表，它的 __index 是 string 表，所以可以用 s:rep(3) 的形式调用
+pub fn luaopen_string(l: lua_State) -> isize {
+    luaL_newlib(l.clone(), STRING_FUNCTION);
+    lua_createtable(l.clone(), 0, 1);
+    lua_pushstring(l.clone(), "");
+    lua_pushvalue(l.clone(), -2);
+    lua_setmetatable(l.clone(), -2);
+    lua_pop(l.clone(), 1);
+    lua_pushvalue(l.clone(), -2);
+    lua_setfield(l.clone(), -2, "__index");
+    lua_pop(l, 1);
+    1
+}
+
+pub fn luaopen_package(l: lua_State) -> isize {
+    luaL_newlib(l.clone(), PACKAGE_FUNCTION);
+    package_createsearchers(l.clone());
+    package_setfields(l.clone());
+    lua_pushglobaltable(l.clone());
+    lua_pushvalue(l.clone(), -2);
+    luaL_setfuncs(l.clone(), LL_FUNCTION, 1);
+    lua_pop(l, 1);
+    1
+}
+
+// 在 package.searchers 的末尾添加一个搜索器，例如从内存或者压缩包中提供模块。
+// 搜索器收到模块名，找到时返回加载函数和传给它的额外参数，否则返回说明原因的字符串
+#[allow(non_snake_case)]
+pub fn luaL_addsearcher<F>(l: lua_State, searcher: F)
+where
+    F: Fn(lua_State) -> usize + 'static,
+{
+    let top = lua_gettop(l.clone());
+    luaL_getsubtable(l.clone(), LUA_REGISTRYINDEX, "_LOADED");
+    if lua_getfield(l.clone(), -1, "package") == LUA_TTABLE
+        && lua_getfield(l.clone(), -1, "searchers") == LUA_TTABLE
+    {
+        let n = lua_rawlen(l.clone(), -1);
+        lua_pushrustclosure(l.clone(), searcher, 0);
+        lua_rawseti(l.clone(), -2, n as isize + 1);
+    }
+    lua_settop(l, top);
+}
+
+// 和 LUA_COMPAT_UNPACK 一样，把 table.unpack 也注册为全局的 unpack
+pub fn luaopen_table(l: lua_State) -> isize {
+    luaL_newlib(l.clone(), TABLE_FUNCTION);
+    lua_getfield(l.clone(), -1, "unpack");
+    lua_setglobal(l, "unpack");
+    1
+}
+
+pub fn luaopen_math(l: lua_State) -> isize {
+    luaL_newlib(l.clone(), MATH_FUNCTION);
+    lua_pushnumber(l.clone(), std::f64::consts::PI);
+    lua_setfield(l.clone(), -2, "pi");
+    lua_pushnumber(l.clone(), f64::INFINITY);
+    lua_setfield(l.clone(), -2, "huge");
+    lua_pushinteger(l.clone(), isize::MAX);
+    lua_setfield(l.clone(), -2, "maxinteger");
+    lua_pushinteger(l.clone(), isize::MIN);
+    lua_setfield(l.clone(), -2, "mininteger");
+    math_setrandfunc(l);
+    1
+}
+
+pub fn luaopen_utf8(l: lua_State) -> isize {
+    luaL_newlib(l.clone(), UTF8_FUNCTION);
+    lua_pushbytes(l.clone(), UTF8PATT);
+    lua_setfield(l, -2, "charpattern");
+    1
+}
+
+pub fn luaopen_debug(l: lua_State) -> isize {
+    luaL_newlib(l, DEBUG_FUNCTION);
+    1
+}
+
+pub fn luaopen_io(l: lua_State) -> isize {
+    luaL_newlib(l.clone(), IO_FUNCTION);
+    luaL_newmetatable(l.clone(), LUA_FILEHANDLE);
     lua_pushvalue(l.clone(), -1);
-    lua_setfield(l, -2, "_G");
-    0
+    lua_setfield(l.clone(), -2, "__index");
+    luaL_setfuncs(l.clone(), FILE_METHODS, 0);
+    lua_pop(l.clone(), 1);
+    io_createstdfiles(l);
+    1
+}
+
+// clock、date、exit、getenv 和 time 由 os_sethost 注册
+pub fn luaopen_os(l: lua_State) -> isize {
+    luaL_newlib(l.clone(), OS_FUNCTION);
+    os_sethost(l, Rc::new(SystemHost::new()));
+    1
+}
+
+// 替换已经打开的 os 库使用的宿主，例如在测试中固定时间或者拦截 os.exit
+#[allow(non_snake_case)]
+pub fn luaL_setoshost(l: lua_State, host: Rc<dyn OsHost>) {
+    luaL_getsubtable(l.clone(), LUA_REGISTRYINDEX, "_LOADED");
+    if lua_getfield(l.clone(), -1, "os") == LUA_TTABLE {
+        os_sethost(l.clone(), host);
+    }
+    lua_pop(l, 2);
+}
+
+#[allow(non_snake_case)]
+pub fn luaL_openlibs(l: lua_State) {
+    for (name, openf) in LOADED_LIBS {
+        luaL_requiref(l.clone(), name, *openf, true);
+        lua_pop(l.clone(), 1);
+    }
 }
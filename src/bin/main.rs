@@ -35,7 +35,7 @@ fn main() {
 
 fn lua_main(input: &str) {
     let l = luaL_newstate();
-    luaopen_base(l.clone());
+    luaL_openlibs(l.clone());
     luaL_loadfile(l.clone(), input);
     lua_call(l.clone(), 0, 0);
 }
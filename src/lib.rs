@@ -2,7 +2,10 @@
 #![feature(const_fn_fn_ptr_basics)]
 pub mod api;
 pub mod chunk;
+pub mod lua;
 pub mod state;
 #[macro_use]
 pub mod vm;
 pub(crate) mod stdlib;
+
+pub use state::install_panic_hook;
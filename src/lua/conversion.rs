@@ -0,0 +1,356 @@
+use crate::lua::reference::LuaRef;
+use crate::lua::{Error, Function, Lua, LuaString, Result, Table};
+use crate::state::LuaValue;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::Hash;
+use std::ops::{Deref, DerefMut};
+
+pub trait ToLua {
+    fn to_lua(self, lua: &Lua) -> Result<LuaValue>;
+}
+
+pub trait FromLua: Sized {
+    fn from_lua(value: LuaValue, lua: &Lua) -> Result<Self>;
+}
+
+// 多个值：函数的参数列表和返回值列表
+pub trait ToLuaMulti {
+    fn to_lua_multi(self, lua: &Lua) -> Result<Vec<LuaValue>>;
+}
+
+pub trait FromLuaMulti: Sized {
+    fn from_lua_multi(values: Vec<LuaValue>, lua: &Lua) -> Result<Self>;
+}
+
+impl ToLua for LuaValue {
+    fn to_lua(self, _: &Lua) -> Result<LuaValue> {
+        Ok(self)
+    }
+}
+
+impl FromLua for LuaValue {
+    fn from_lua(value: LuaValue, _: &Lua) -> Result<Self> {
+        Ok(value)
+    }
+}
+
+impl ToLua for bool {
+    fn to_lua(self, _: &Lua) -> Result<LuaValue> {
+        Ok(LuaValue::Boolean(self))
+    }
+}
+
+// 和 Lua 的条件判断一样，只有 nil 和 false 为假
+impl FromLua for bool {
+    fn from_lua(value: LuaValue, _: &Lua) -> Result<Self> {
+        Ok(value.to_boolean())
+    }
+}
+
+// 能放进 i64 的整数转换为 Lua 整数，否则转换为浮点数
+macro_rules! impl_integer {
+    ($($t:ty),+) => {
+        $(
+            impl ToLua for $t {
+                fn to_lua(self, _: &Lua) -> Result<LuaValue> {
+                    match i64::try_from(self) {
+                        Ok(i) => Ok(LuaValue::Integer(i)),
+                        Err(_) => Ok(LuaValue::Number(self as f64)),
+                    }
+                }
+            }
+
+            impl FromLua for $t {
+                fn from_lua(value: LuaValue, _: &Lua) -> Result<Self> {
+                    let i = match value.to_integer() {
+                        Some(i) => i,
+                        None => {
+                            return Err(Error::from_lua_conversion(
+                                value.type_name(),
+                                stringify!($t),
+                            ))
+                        }
+                    };
+                    <$t>::try_from(i).map_err(|_| Error::FromLuaConversionError {
+                        from: "number",
+                        to: stringify!($t),
+                        message: Some("out of range".to_string()),
+                    })
+                }
+            }
+        )+
+    };
+}
+
+impl_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl ToLua for f64 {
+    fn to_lua(self, _: &Lua) -> Result<LuaValue> {
+        Ok(LuaValue::Number(self))
+    }
+}
+
+impl FromLua for f64 {
+    fn from_lua(value: LuaValue, _: &Lua) -> Result<Self> {
+        match value.to_number() {
+            Some(n) => Ok(n),
+            None => Err(Error::from_lua_conversion(value.type_name(), "f64")),
+        }
+    }
+}
+
+impl ToLua for f32 {
+    fn to_lua(self, _: &Lua) -> Result<LuaValue> {
+        Ok(LuaValue::Number(self as f64))
+    }
+}
+
+impl FromLua for f32 {
+    fn from_lua(value: LuaValue, _: &Lua) -> Result<Self> {
+        match value.to_number() {
+            Some(n) => Ok(n as f32),
+            None => Err(Error::from_lua_conversion(value.type_name(), "f32")),
+        }
+    }
+}
+
+impl ToLua for String {
+    fn to_lua(self, _: &Lua) -> Result<LuaValue> {
+        Ok(LuaValue::String(self.into_bytes()))
+    }
+}
+
+impl ToLua for &str {
+    fn to_lua(self, _: &Lua) -> Result<LuaValue> {
+        Ok(LuaValue::String(self.into()))
+    }
+}
+
+// 数字会按 Lua 的规则转换为字符串，不是合法的 UTF-8 时转换失败
+impl FromLua for String {
+    fn from_lua(value: LuaValue, _: &Lua) -> Result<Self> {
+        match value.to_bytes().map(String::from_utf8) {
+            Some(Ok(s)) => Ok(s),
+            Some(Err(_)) => Err(Error::FromLuaConversionError {
+                from: "string",
+                to: "String",
+                message: Some("invalid utf-8 encoding".to_string()),
+            }),
+            None => Err(Error::from_lua_conversion(value.type_name(), "String")),
+        }
+    }
+}
+
+impl ToLua for LuaString {
+    fn to_lua(self, _: &Lua) -> Result<LuaValue> {
+        Ok(LuaValue::String(self.0))
+    }
+}
+
+impl FromLua for LuaString {
+    fn from_lua(value: LuaValue, _: &Lua) -> Result<Self> {
+        match value.to_bytes() {
+            Some(s) => Ok(LuaString(s)),
+            None => Err(Error::from_lua_conversion(value.type_name(), "string")),
+        }
+    }
+}
+
+impl ToLua for Table {
+    fn to_lua(self, _: &Lua) -> Result<LuaValue> {
+        Ok(self.0.value())
+    }
+}
+
+impl FromLua for Table {
+    fn from_lua(value: LuaValue, lua: &Lua) -> Result<Self> {
+        match value {
+            LuaValue::Table(_) => Ok(Table(LuaRef::new(lua, value))),
+            _ => Err(Error::from_lua_conversion(value.type_name(), "table")),
+        }
+    }
+}
+
+impl ToLua for Function {
+    fn to_lua(self, _: &Lua) -> Result<LuaValue> {
+        Ok(self.0.value())
+    }
+}
+
+impl FromLua for Function {
+    fn from_lua(value: LuaValue, lua: &Lua) -> Result<Self> {
+        match value {
+            LuaValue::Closure(_) => Ok(Function(LuaRef::new(lua, value))),
+            _ => Err(Error::from_lua_conversion(value.type_name(), "function")),
+        }
+    }
+}
+
+impl<T: ToLua> ToLua for Option<T> {
+    fn to_lua(self, lua: &Lua) -> Result<LuaValue> {
+        match self {
+            Some(v) => v.to_lua(lua),
+            None => Ok(LuaValue::Nil),
+        }
+    }
+}
+
+impl<T: FromLua> FromLua for Option<T> {
+    fn from_lua(value: LuaValue, lua: &Lua) -> Result<Self> {
+        match value {
+            LuaValue::Nil => Ok(None),
+            value => Ok(Some(T::from_lua(value, lua)?)),
+        }
+    }
+}
+
+// Vec 对应从 1 开始的序列
+impl<T: ToLua> ToLua for Vec<T> {
+    fn to_lua(self, lua: &Lua) -> Result<LuaValue> {
+        let table = LuaValue::new_table(self.len(), 0);
+        if let LuaValue::Table(t) = &table {
+            for (i, v) in self.into_iter().enumerate() {
+                let v = v.to_lua(lua)?;
+                t.borrow_mut().set(LuaValue::Integer(i as i64 + 1), v);
+            }
+        }
+        Ok(table)
+    }
+}
+
+impl<T: FromLua> FromLua for Vec<T> {
+    fn from_lua(value: LuaValue, lua: &Lua) -> Result<Self> {
+        match value {
+            LuaValue::Table(_) => Table::from_lua(value, lua)?.sequence_values().collect(),
+            _ => Err(Error::from_lua_conversion(value.type_name(), "Vec")),
+        }
+    }
+}
+
+impl<K: ToLua, V: ToLua> ToLua for HashMap<K, V> {
+    fn to_lua(self, lua: &Lua) -> Result<LuaValue> {
+        let table = LuaValue::new_table(0, self.len());
+        if let LuaValue::Table(t) = &table {
+            for (k, v) in self {
+                let k = k.to_lua(lua)?;
+                let v = v.to_lua(lua)?;
+                t.borrow_mut().set(k, v);
+            }
+        }
+        Ok(table)
+    }
+}
+
+impl<K: FromLua + Eq + Hash, V: FromLua> FromLua for HashMap<K, V> {
+    fn from_lua(value: LuaValue, lua: &Lua) -> Result<Self> {
+        match value {
+            LuaValue::Table(_) => Table::from_lua(value, lua)?.pairs().collect(),
+            _ => Err(Error::from_lua_conversion(value.type_name(), "HashMap")),
+        }
+    }
+}
+
+// 可变数量的值，放在参数或返回值元组的最后接收剩下的所有值
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Variadic<T>(Vec<T>);
+
+impl<T> Variadic<T> {
+    pub fn new() -> Variadic<T> {
+        Variadic(Vec::new())
+    }
+}
+
+impl<T> From<Vec<T>> for Variadic<T> {
+    fn from(v: Vec<T>) -> Variadic<T> {
+        Variadic(v)
+    }
+}
+
+impl<T> Deref for Variadic<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Variadic<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        &mut self.0
+    }
+}
+
+impl<T: ToLua> ToLuaMulti for Variadic<T> {
+    fn to_lua_multi(self, lua: &Lua) -> Result<Vec<LuaValue>> {
+        self.0.into_iter().map(|v| v.to_lua(lua)).collect()
+    }
+}
+
+impl<T: FromLua> FromLuaMulti for Variadic<T> {
+    fn from_lua_multi(values: Vec<LuaValue>, lua: &Lua) -> Result<Self> {
+        let values = values.into_iter().map(|v| T::from_lua(v, lua));
+        Ok(Variadic(values.collect::<Result<Vec<T>>>()?))
+    }
+}
+
+impl<T: ToLua> ToLuaMulti for T {
+    fn to_lua_multi(self, lua: &Lua) -> Result<Vec<LuaValue>> {
+        Ok(vec![self.to_lua(lua)?])
+    }
+}
+
+// 单个值取第一个返回值，没有返回值时当作 nil
+impl<T: FromLua> FromLuaMulti for T {
+    fn from_lua_multi(values: Vec<LuaValue>, lua: &Lua) -> Result<Self> {
+        let value = values.into_iter().next().unwrap_or(LuaValue::Nil);
+        T::from_lua(value, lua)
+    }
+}
+
+impl ToLuaMulti for () {
+    fn to_lua_multi(self, _: &Lua) -> Result<Vec<LuaValue>> {
+        Ok(Vec::new())
+    }
+}
+
+impl FromLuaMulti for () {
+    fn from_lua_multi(_: Vec<LuaValue>, _: &Lua) -> Result<Self> {
+        Ok(())
+    }
+}
+
+// 元组的各个元素依次对应各个值，多余的值丢弃，不够时补 nil；
+// 最后一个元素可以是 Variadic，接收剩下的所有值
+macro_rules! impl_tuple {
+    ($($name:ident),* ; $last:ident) => {
+        impl<$($name: ToLua,)* $last: ToLuaMulti> ToLuaMulti for ($($name,)* $last,) {
+            #[allow(non_snake_case)]
+            fn to_lua_multi(self, lua: &Lua) -> Result<Vec<LuaValue>> {
+                let ($($name,)* $last,) = self;
+                let mut values = vec![$($name.to_lua(lua)?),*];
+                values.extend($last.to_lua_multi(lua)?);
+                Ok(values)
+            }
+        }
+
+        impl<$($name: FromLua,)* $last: FromLuaMulti> FromLuaMulti for ($($name,)* $last,) {
+            #[allow(non_snake_case, unused_mut)]
+            fn from_lua_multi(values: Vec<LuaValue>, lua: &Lua) -> Result<Self> {
+                let mut values = values.into_iter();
+                $(let $name = $name::from_lua(values.next().unwrap_or(LuaValue::Nil), lua)?;)*
+                let $last = $last::from_lua_multi(values.collect(), lua)?;
+                Ok(($($name,)* $last,))
+            }
+        }
+    };
+}
+
+impl_tuple!(; A);
+impl_tuple!(A; B);
+impl_tuple!(A, B; C);
+impl_tuple!(A, B, C; D);
+impl_tuple!(A, B, C, D; E);
+impl_tuple!(A, B, C, D, E; F);
+impl_tuple!(A, B, C, D, E, F; G);
+impl_tuple!(A, B, C, D, E, F, G; H);
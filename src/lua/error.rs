@@ -0,0 +1,95 @@
+use crate::api::*;
+use crate::state::LuaValue;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    SyntaxError(String),
+    RuntimeError(String),
+    MemoryError(String),
+    FromLuaConversionError {
+        from: &'static str,
+        to: &'static str,
+        message: Option<String>,
+    },
+    ToLuaConversionError {
+        from: &'static str,
+        to: &'static str,
+        message: Option<String>,
+    },
+    UserDataTypeMismatch,
+    UserDataBorrowError,
+    UserDataBorrowMutError,
+    SerializeError(String),
+    DeserializeError(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    // 根据 lua_pcall 的错误码和栈顶的错误对象构造错误
+    pub(crate) fn from_status(status: isize, err: LuaValue) -> Error {
+        let message = match err.to_str() {
+            Some(s) => s,
+            None => format!("(error object is a {} value)", err.type_name()),
+        };
+        match status {
+            LUA_ERRSYNTAX => Error::SyntaxError(message),
+            LUA_ERRMEM => Error::MemoryError(message),
+            _ => Error::RuntimeError(message),
+        }
+    }
+
+    pub(crate) fn from_lua_conversion(from: &'static str, to: &'static str) -> Error {
+        Error::FromLuaConversionError {
+            from,
+            to,
+            message: None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::SyntaxError(msg) => write!(f, "syntax error: {}", msg),
+            Error::RuntimeError(msg) => write!(f, "runtime error: {}", msg),
+            Error::MemoryError(msg) => write!(f, "memory error: {}", msg),
+            Error::FromLuaConversionError { from, to, message } => {
+                write!(f, "error converting Lua {} to {}", from, to)?;
+                match message {
+                    Some(message) => write!(f, " ({})", message),
+                    None => Ok(()),
+                }
+            }
+            Error::ToLuaConversionError { from, to, message } => {
+                write!(f, "error converting {} to Lua {}", from, to)?;
+                match message {
+                    Some(message) => write!(f, " ({})", message),
+                    None => Ok(()),
+                }
+            }
+            Error::UserDataTypeMismatch => write!(f, "userdata is not expected type"),
+            Error::UserDataBorrowError => write!(f, "userdata already mutably borrowed"),
+            Error::UserDataBorrowMutError => write!(f, "userdata already borrowed"),
+            Error::SerializeError(msg) => write!(f, "serialize error: {}", msg),
+            Error::DeserializeError(msg) => write!(f, "deserialize error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(feature = "serde")]
+impl serde::ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::SerializeError(msg.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::DeserializeError(msg.to_string())
+    }
+}
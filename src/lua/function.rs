@@ -0,0 +1,20 @@
+use crate::lua::reference::LuaRef;
+use crate::lua::{FromLuaMulti, Result, ToLuaMulti};
+
+#[derive(Clone)]
+pub struct Function(pub(crate) LuaRef);
+
+impl Function {
+    // 参数和返回值都可以是多个，比如 func.call::<(i64, String), bool>((1, s))
+    pub fn call<A: ToLuaMulti, R: FromLuaMulti>(&self, args: A) -> Result<R> {
+        let lua = &self.0.lua;
+        let args = args.to_lua_multi(lua)?;
+        let nargs = args.len();
+        self.0.push();
+        for arg in args {
+            lua.push_value(arg);
+        }
+        let results = lua.call_protected(nargs)?;
+        R::from_lua_multi(results, lua)
+    }
+}
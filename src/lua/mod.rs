@@ -0,0 +1,23 @@
+// 建立在 api 之上的安全接口：值通过注册表引用持有，错误以 Result 返回
+
+mod conversion;
+mod error;
+mod function;
+mod reference;
+mod runtime;
+#[cfg(feature = "serde")]
+mod serde;
+mod string;
+mod table;
+mod userdata;
+
+pub use self::conversion::{FromLua, FromLuaMulti, ToLua, ToLuaMulti, Variadic};
+pub use self::error::{Error, Result};
+pub use self::function::Function;
+pub use self::runtime::{Chunk, Lua};
+#[cfg(feature = "serde")]
+pub use self::serde::{Deserializer, Serializer};
+pub use self::string::LuaString;
+pub use self::table::{Table, TablePairs, TableSequence};
+pub use self::userdata::{AnyUserData, UserData, UserDataFields, UserDataMethods};
+pub use crate::state::LuaValue;
@@ -0,0 +1,42 @@
+use crate::api::*;
+use crate::lua::Lua;
+use crate::state::LuaValue;
+
+// 用 luaL_ref 把值存在注册表中，Rust 持有引用期间值不会被回收
+pub(crate) struct LuaRef {
+    pub(crate) lua: Lua,
+    index: isize,
+}
+
+impl LuaRef {
+    pub(crate) fn new(lua: &Lua, value: LuaValue) -> LuaRef {
+        let l = lua.lua_state();
+        l.borrow_mut().push(value);
+        let index = luaL_ref(l, LUA_REGISTRYINDEX);
+        LuaRef {
+            lua: lua.clone(),
+            index,
+        }
+    }
+
+    pub(crate) fn push(&self) {
+        lua_rawgeti(self.lua.lua_state(), LUA_REGISTRYINDEX, self.index);
+    }
+
+    pub(crate) fn value(&self) -> LuaValue {
+        self.push();
+        self.lua.pop_value()
+    }
+}
+
+impl Clone for LuaRef {
+    fn clone(&self) -> LuaRef {
+        LuaRef::new(&self.lua, self.value())
+    }
+}
+
+impl Drop for LuaRef {
+    fn drop(&mut self) {
+        luaL_unref(self.lua.lua_state(), LUA_REGISTRYINDEX, self.index);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::api::*;
+use crate::lua::reference::LuaRef;
+use crate::lua::{Error, FromLuaMulti, Function, LuaString, Result, Table, ToLuaMulti};
+use crate::state::{LuaState, LuaValue};
+
+// LuaState 本身就是共享的句柄，每次调用 api 时包装出新的 lua_State，
+// 这样原生函数里再通过同一个 Lua 访问虚拟机也不会和外层的借用冲突
+#[derive(Clone)]
+pub struct Lua {
+    state: LuaState,
+}
+
+impl Lua {
+    pub fn new() -> Lua {
+        let lua = Lua {
+            state: LuaState::new(),
+        };
+        let l = lua.lua_state();
+        luaL_openlibs(l.clone());
+        lua_settop(l, 0);
+        lua
+    }
+
+    // 原生函数收到的 lua_State 和创建它的 Lua 共享同一个虚拟机
+    pub fn from_lua_state(l: &lua_State) -> Lua {
+        let state = match l.borrow().as_any().downcast_ref::<LuaState>() {
+            Some(state) => state.clone(),
+            None => unreachable!(),
+        };
+        Lua { state }
+    }
+
+    // 需要直接使用 api 时取得底层的 lua_State
+    pub fn lua_state(&self) -> lua_State {
+        create_state(self.state.clone())
+    }
+
+    // 把 Rust 闭包包装成 Lua 函数，参数和返回值通过 FromLuaMulti/ToLuaMulti 转换，
+    // 返回的 Err 作为 Lua 错误抛出。闭包不能捕获 Lua 本身，否则虚拟机和闭包互相引用，
+    // 需要时使用传入的 &Lua
+    pub fn create_function<A, R, F>(&self, func: F) -> Result<Function>
+    where
+        A: FromLuaMulti,
+        R: ToLuaMulti,
+        F: Fn(&Lua, A) -> Result<R> + 'static,
+    {
+        let l = self.lua_state();
+        lua_pushrustclosure(l, move |l| call_rust_function(l, &func), 0);
+        Ok(Function(LuaRef::new(self, self.pop_value())))
+    }
+
+    pub fn create_function_mut<A, R, F>(&self, mut func: F) -> Result<Function>
+    where
+        A: FromLuaMulti,
+        R: ToLuaMulti,
+        F: FnMut(&Lua, A) -> Result<R> + 'static,
+    {
+        let l = self.lua_state();
+        lua_pushrustclosure_mut(l, move |l| call_rust_function(l, &mut func), 0);
+        Ok(Function(LuaRef::new(self, self.pop_value())))
+    }
+
+    pub fn load<S: AsRef<[u8]> + ?Sized>(&self, chunk: &S) -> Chunk {
+        Chunk {
+            lua: self.clone(),
+            source: chunk.as_ref().to_vec(),
+            name: "chunk".to_string(),
+        }
+    }
+
+    pub fn globals(&self) -> Table {
+        let l = self.lua_state();
+        lua_pushglobaltable(l);
+        Table(LuaRef::new(self, self.pop_value()))
+    }
+
+    pub fn create_table(&self) -> Table {
+        Table(LuaRef::new(self, LuaValue::new_table(0, 0)))
+    }
+
+    pub fn create_string<S: AsRef<[u8]> + ?Sized>(&self, s: &S) -> LuaString {
+        LuaString(s.as_ref().to_vec())
+    }
+
+    pub(crate) fn push_value(&self, value: LuaValue) {
+        self.lua_state().borrow_mut().push(value)
+    }
+
+    pub(crate) fn pop_value(&self) -> LuaValue {
+        let l = self.lua_state();
+        let value = l.borrow().get(lua_absindex(l.clone(), -1));
+        lua_pop(l, 1);
+        value
+    }
+
+    // 在保护模式下调用栈上的函数，它的下面是 nargs 个参数，返回全部结果
+    pub(crate) fn call_protected(&self, nargs: usize) -> Result<Vec<LuaValue>> {
+        let l = self.lua_state();
+        let base = lua_gettop(l.clone()) - nargs as isize - 1;
+        let status = lua_pcall(l.clone(), nargs as isize, LUA_MULTRET, 0);
+        if status != LUA_OK {
+            let err = self.pop_value();
+            return Err(Error::from_status(status, err));
+        }
+        let mut results = Vec::new();
+        for i in base + 1..=lua_gettop(l.clone()) {
+            results.push(l.borrow().get(lua_absindex(l.clone(), i)));
+        }
+        lua_settop(l, base);
+        Ok(results)
+    }
+}
+
+fn call_rust_function<A, R, F>(l: lua_State, func: F) -> usize
+where
+    A: FromLuaMulti,
+    R: ToLuaMulti,
+    F: FnOnce(&Lua, A) -> Result<R>,
+{
+    let lua = Lua::from_lua_state(&l);
+    let mut args = Vec::new();
+    for i in 1..=lua_gettop(l.clone()) {
+        args.push(l.borrow().get(i));
+    }
+    let results = A::from_lua_multi(args, &lua)
+        .and_then(|args| func(&lua, args))
+        .and_then(|results| results.to_lua_multi(&lua));
+    match results {
+        Ok(results) => {
+            let n = results.len();
+            for v in results {
+                lua.push_value(v);
+            }
+            n
+        }
+        // 嵌套调用中的 Lua 错误原样抛出，不再加位置前缀
+        Err(Error::RuntimeError(msg)) => {
+            lua_pushstring(l.clone(), &msg);
+            lua_error(l)
+        }
+        Err(e) => luaL_error(l, &e.to_string()),
+    }
+}
+
+impl Default for Lua {
+    fn default() -> Lua {
+        Lua::new()
+    }
+}
+
+// lua.load 返回的待执行的代码块
+pub struct Chunk {
+    lua: Lua,
+    source: Vec<u8>,
+    name: String,
+}
+
+impl Chunk {
+    pub fn set_name(mut self, name: &str) -> Chunk {
+        self.name = name.to_string();
+        self
+    }
+
+    pub fn into_function(self) -> Result<Function> {
+        let l = self.lua.lua_state();
+        let status = luaL_loadbuffer(l, &self.source, &self.name);
+        let value = self.lua.pop_value();
+        if status != LUA_OK {
+            return Err(Error::from_status(status, value));
+        }
+        Ok(Function(LuaRef::new(&self.lua, value)))
+    }
+
+    pub fn exec(self) -> Result<()> {
+        self.call(())
+    }
+
+    pub fn eval<R: FromLuaMulti>(self) -> Result<R> {
+        self.call(())
+    }
+
+    pub fn call<A: ToLuaMulti, R: FromLuaMulti>(self, args: A) -> Result<R> {
+        self.into_function()?.call(args)
+    }
+}
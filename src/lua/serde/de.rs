@@ -0,0 +1,429 @@
+use crate::lua::{Error, Lua, Result};
+use crate::state::{LuaTable, LuaValue};
+use serde::de::value::StringDeserializer;
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+// 把 LuaValue 树反序列化为 Rust 类型。带数组元表的表或者键恰好是 1..n 的表
+// 作为序列，其他表作为映射；nil 和 null 哨兵都表示空值
+pub struct Deserializer {
+    value: LuaValue,
+    array_metatable: Rc<RefCell<LuaTable>>,
+    // 当前路径上正在反序列化的表，用来发现循环引用
+    visited: Rc<RefCell<HashSet<usize>>>,
+}
+
+impl Deserializer {
+    pub fn new(lua: &Lua, value: LuaValue) -> Deserializer {
+        Deserializer {
+            value,
+            array_metatable: lua.array_metatable_raw(),
+            visited: Rc::new(RefCell::new(HashSet::new())),
+        }
+    }
+
+    fn child(&self, value: LuaValue) -> Deserializer {
+        Deserializer {
+            value,
+            array_metatable: self.array_metatable.clone(),
+            visited: self.visited.clone(),
+        }
+    }
+
+    fn is_null(&self) -> bool {
+        matches!(self.value, LuaValue::Nil | LuaValue::LightUserData(0))
+    }
+
+    // 序列返回按顺序排列的值，否则返回 None
+    fn sequence(&self, t: &Rc<RefCell<LuaTable>>) -> Option<Vec<LuaValue>> {
+        let t = t.borrow();
+        let is_array = match &t.metatable {
+            Some(mt) => Rc::ptr_eq(mt, &self.array_metatable),
+            None => false,
+        };
+        let n = t.border();
+        if !is_array && (n == 0 || t.entries().len() != n) {
+            return None;
+        }
+        Some(
+            (1..=n)
+                .map(|i| t.get(LuaValue::Integer(i as i64)))
+                .collect(),
+        )
+    }
+
+    // 在 f 执行期间把表记录在当前路径上
+    fn enter<T, F>(&self, t: &Rc<RefCell<LuaTable>>, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        let id = Rc::as_ptr(t) as usize;
+        if !self.visited.borrow_mut().insert(id) {
+            return Err(Error::DeserializeError(
+                "recursive table detected".to_string(),
+            ));
+        }
+        let result = f();
+        self.visited.borrow_mut().remove(&id);
+        result
+    }
+
+    fn visit_table<'de, V: Visitor<'de>>(
+        self,
+        t: Rc<RefCell<LuaTable>>,
+        visitor: V,
+        string_keys: bool,
+    ) -> Result<V::Value> {
+        self.enter(&t, || match self.sequence(&t) {
+            Some(values) => visitor.visit_seq(SeqDeserializer {
+                de: &self,
+                values: values.into_iter(),
+            }),
+            None => {
+                let entries = t.borrow().entries();
+                if string_keys {
+                    check_string_keys(&entries)?;
+                }
+                visitor.visit_map(MapDeserializer {
+                    de: &self,
+                    entries: entries.into_iter(),
+                    value: None,
+                })
+            }
+        })
+    }
+
+    fn deserialize_integer<'de, V, T, F>(self, visitor: V, visit: F) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+        T: TryFrom<i64>,
+        F: FnOnce(V, T) -> Result<V::Value>,
+    {
+        let i = match self.value {
+            LuaValue::Integer(_) | LuaValue::Number(_) => self.value.to_integer(),
+            _ => None,
+        };
+        match i {
+            Some(i) => match T::try_from(i) {
+                Ok(v) => visit(visitor, v),
+                Err(_) => Err(Error::FromLuaConversionError {
+                    from: "integer",
+                    to: std::any::type_name::<T>(),
+                    message: Some(format!("{} is out of range", i)),
+                }),
+            },
+            None => de::Deserializer::deserialize_any(self, visitor),
+        }
+    }
+}
+
+// 反序列化为结构体或者自描述的值时，表的键必须都是字符串
+fn check_string_keys(entries: &[(LuaValue, LuaValue)]) -> Result<()> {
+    for (k, _) in entries {
+        if let LuaValue::String(_) = k {
+            continue;
+        }
+        return Err(Error::DeserializeError(format!(
+            "non-string key of type '{}' in table",
+            k.type_name()
+        )));
+    }
+    Ok(())
+}
+
+macro_rules! deserialize_integer {
+    ($($method:ident => $t:ty, $visit:ident;)+) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+                self.deserialize_integer(visitor, |v: V, i: $t| v.$visit(i))
+            }
+        )+
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value.clone() {
+            LuaValue::Nil | LuaValue::LightUserData(0) => visitor.visit_unit(),
+            LuaValue::Boolean(b) => visitor.visit_bool(b),
+            LuaValue::Integer(i) => visitor.visit_i64(i),
+            LuaValue::Number(n) => visitor.visit_f64(n),
+            // 不是合法 UTF-8 的字符串只能作为字节串
+            LuaValue::String(s) => match String::from_utf8(s) {
+                Ok(s) => visitor.visit_string(s),
+                Err(e) => visitor.visit_byte_buf(e.into_bytes()),
+            },
+            LuaValue::Table(t) => self.visit_table(t, visitor, true),
+            value => Err(Error::DeserializeError(format!(
+                "cannot deserialize a {} value",
+                value.type_name()
+            ))),
+        }
+    }
+
+    deserialize_integer! {
+        deserialize_i8 => i8, visit_i8;
+        deserialize_i16 => i16, visit_i16;
+        deserialize_i32 => i32, visit_i32;
+        deserialize_i64 => i64, visit_i64;
+        deserialize_u8 => u8, visit_u8;
+        deserialize_u16 => u16, visit_u16;
+        deserialize_u32 => u32, visit_u32;
+        deserialize_u64 => u64, visit_u64;
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.is_null() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.is_null() {
+            visitor.visit_unit()
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // 没有数组元表的空表也可以作为空序列
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match &self.value {
+            LuaValue::Table(t) if t.borrow().entries().is_empty() => {
+                visitor.visit_seq(SeqDeserializer {
+                    de: &self,
+                    values: Vec::new().into_iter(),
+                })
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            LuaValue::String(s) => visitor.visit_byte_buf(s),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    // 映射的键可以是任意类型，由目标类型决定能否接受
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value.clone() {
+            LuaValue::Table(t) => {
+                let entries = t.borrow().entries();
+                self.enter(&t, || {
+                    visitor.visit_map(MapDeserializer {
+                        de: &self,
+                        entries: entries.into_iter(),
+                        value: None,
+                    })
+                })
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.value.clone() {
+            LuaValue::Table(t) => {
+                let entries = t.borrow().entries();
+                check_string_keys(&entries)?;
+                self.enter(&t, || {
+                    visitor.visit_map(MapDeserializer {
+                        de: &self,
+                        entries: entries.into_iter(),
+                        value: None,
+                    })
+                })
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    // 单元变体是字符串，其他变体是只有一个键的表 { 变体名 = 值 }
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.value.clone() {
+            LuaValue::String(variant) => visitor.visit_enum(EnumDeserializer {
+                variant: String::from_utf8_lossy(&variant).into_owned(),
+                value: None,
+            }),
+            LuaValue::Table(t) => {
+                let mut entries = t.borrow().entries();
+                match (entries.pop(), entries.is_empty()) {
+                    (Some((LuaValue::String(variant), value)), true) => {
+                        visitor.visit_enum(EnumDeserializer {
+                            variant: String::from_utf8_lossy(&variant).into_owned(),
+                            value: Some(self.child(value)),
+                        })
+                    }
+                    _ => Err(Error::DeserializeError(
+                        "expected a table with a single string key for an enum".to_string(),
+                    )),
+                }
+            }
+            value => Err(Error::DeserializeError(format!(
+                "expected a string or table for an enum, got {}",
+                value.type_name()
+            ))),
+        }
+    }
+
+    // 忽略的值不会被访问，其中的函数或循环引用也不会报错
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool f32 f64 char str string tuple tuple_struct identifier
+    }
+}
+
+struct SeqDeserializer<'a> {
+    de: &'a Deserializer,
+    values: std::vec::IntoIter<LuaValue>,
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for SeqDeserializer<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        match self.values.next() {
+            Some(value) => seed.deserialize(self.de.child(value)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+struct MapDeserializer<'a> {
+    de: &'a Deserializer,
+    entries: std::vec::IntoIter<(LuaValue, LuaValue)>,
+    value: Option<LuaValue>,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for MapDeserializer<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(self.de.child(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(self.de.child(value)),
+            None => Err(Error::DeserializeError(
+                "next_value_seed called before next_key_seed".to_string(),
+            )),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    value: Option<Deserializer>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<(T::Value, Self::Variant)> {
+        let variant: StringDeserializer<Error> = self.variant.into_deserializer();
+        let variant = seed.deserialize(variant)?;
+        Ok((variant, VariantDeserializer { value: self.value }))
+    }
+}
+
+struct VariantDeserializer {
+    value: Option<Deserializer>,
+}
+
+impl VariantDeserializer {
+    fn value(self, expected: &str) -> Result<Deserializer> {
+        match self.value {
+            Some(value) => Ok(value),
+            None => Err(Error::DeserializeError(format!(
+                "expected {}, got a unit variant",
+                expected
+            ))),
+        }
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        match self.value {
+            Some(value) => de::Deserialize::deserialize(value),
+            None => Ok(()),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self.value("a newtype variant")?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(self.value("a tuple variant")?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        de::Deserializer::deserialize_struct(self.value("a struct variant")?, "", fields, visitor)
+    }
+}
@@ -0,0 +1,55 @@
+// serde 支持：实现了 Serialize 的类型转换为 Lua 值，Lua 值反序列化为 Rust 类型
+
+mod de;
+mod ser;
+
+pub use self::de::Deserializer;
+pub use self::ser::Serializer;
+
+use crate::api::*;
+use crate::lua::reference::LuaRef;
+use crate::lua::{Lua, Result, Table};
+use crate::state::{LuaTable, LuaValue};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// 数组元表在注册表中的键，只用到它的地址
+static ARRAY_METATABLE_KEY: u8 = 0;
+
+impl Lua {
+    // 表示 null 的哨兵值（值为 0 的 light userdata）。
+    // nil 放进表里会留下空洞，所以 None 和 () 都序列化为它
+    pub fn null(&self) -> LuaValue {
+        LuaValue::LightUserData(0)
+    }
+
+    // 带有这个元表的表总是被当作数组，空数组和空表因此可以区分
+    pub fn array_metatable(&self) -> Table {
+        let l = self.lua_state();
+        let key = &ARRAY_METATABLE_KEY as *const u8 as usize;
+        if lua_rawgetp(l.clone(), LUA_REGISTRYINDEX, key) != LUA_TTABLE {
+            lua_pop(l.clone(), 1);
+            lua_newtable(l.clone());
+            lua_pushvalue(l.clone(), -1);
+            lua_rawsetp(l, LUA_REGISTRYINDEX, key);
+        }
+        Table(LuaRef::new(self, self.pop_value()))
+    }
+
+    pub fn to_value<T: Serialize + ?Sized>(&self, t: &T) -> Result<LuaValue> {
+        t.serialize(Serializer::new(self))
+    }
+
+    pub fn from_value<T: DeserializeOwned>(&self, value: LuaValue) -> Result<T> {
+        T::deserialize(Deserializer::new(self, value))
+    }
+
+    fn array_metatable_raw(&self) -> Rc<RefCell<LuaTable>> {
+        match self.array_metatable().0.value() {
+            LuaValue::Table(t) => t,
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use crate::lua::{Error, Lua, Result};
+use crate::state::{LuaTable, LuaValue};
+use serde::ser::{self, Serialize};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+// 直接构造 LuaValue：结构体和映射转换为表，序列转换为带数组元表的表，
+// None 和 () 转换为 null 哨兵
+#[derive(Clone)]
+pub struct Serializer {
+    null: LuaValue,
+    array_metatable: Rc<RefCell<LuaTable>>,
+}
+
+impl Serializer {
+    pub fn new(lua: &Lua) -> Serializer {
+        Serializer {
+            null: lua.null(),
+            array_metatable: lua.array_metatable_raw(),
+        }
+    }
+
+    fn new_array(&self, len: usize) -> LuaValue {
+        let table = LuaValue::new_table(len, 0);
+        if let LuaValue::Table(t) = &table {
+            t.borrow_mut().metatable = Some(self.array_metatable.clone());
+        }
+        table
+    }
+}
+
+fn table_set(table: &LuaValue, key: LuaValue, value: LuaValue) -> Result<()> {
+    match key {
+        LuaValue::Nil => return Err(Error::SerializeError("table key is nil".to_string())),
+        LuaValue::Number(n) if n.is_nan() => {
+            return Err(Error::SerializeError("table key is NaN".to_string()))
+        }
+        _ => (),
+    }
+    if let LuaValue::Table(t) = table {
+        t.borrow_mut().set(key, value);
+    }
+    Ok(())
+}
+
+// 只有一个键的表 { 变体名 = 值 }
+fn variant_table(variant: &'static str, value: LuaValue) -> Result<LuaValue> {
+    let table = LuaValue::new_table(0, 1);
+    table_set(&table, LuaValue::String(variant.into()), value)?;
+    Ok(table)
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    type SerializeSeq = SerializeSeq;
+    type SerializeTuple = SerializeSeq;
+    type SerializeTupleStruct = SerializeSeq;
+    type SerializeTupleVariant = SerializeTupleVariant;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeStructVariant;
+
+    fn serialize_bool(self, v: bool) -> Result<LuaValue> {
+        Ok(LuaValue::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<LuaValue> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<LuaValue> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<LuaValue> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<LuaValue> {
+        Ok(LuaValue::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<LuaValue> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<LuaValue> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<LuaValue> {
+        self.serialize_i64(v as i64)
+    }
+
+    // 和 ToLua 不同，超出 i64 范围的整数不会悄悄变成浮点数
+    fn serialize_u64(self, v: u64) -> Result<LuaValue> {
+        match i64::try_from(v) {
+            Ok(i) => Ok(LuaValue::Integer(i)),
+            Err(_) => Err(Error::ToLuaConversionError {
+                from: "u64",
+                to: "integer",
+                message: Some(format!("{} is out of range", v)),
+            }),
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<LuaValue> {
+        Ok(LuaValue::Number(v as f64))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<LuaValue> {
+        Ok(LuaValue::Number(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<LuaValue> {
+        Ok(LuaValue::String(v.to_string().into_bytes()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<LuaValue> {
+        Ok(LuaValue::String(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<LuaValue> {
+        Ok(LuaValue::String(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<LuaValue> {
+        Ok(self.null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<LuaValue> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<LuaValue> {
+        Ok(self.null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<LuaValue> {
+        Ok(self.null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<LuaValue> {
+        Ok(LuaValue::String(variant.into()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<LuaValue> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<LuaValue> {
+        variant_table(variant, value.serialize(self)?)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeSeq> {
+        let table = self.new_array(len.unwrap_or(0));
+        Ok(SerializeSeq {
+            ser: self,
+            table,
+            index: 0,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeSeq> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeSeq> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeTupleVariant> {
+        Ok(SerializeTupleVariant {
+            variant,
+            seq: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeMap> {
+        Ok(SerializeMap {
+            ser: self,
+            table: LuaValue::new_table(0, len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeMap> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeStructVariant> {
+        Ok(SerializeStructVariant {
+            variant,
+            map: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+pub struct SerializeSeq {
+    ser: Serializer,
+    table: LuaValue,
+    index: i64,
+}
+
+impl ser::SerializeSeq for SerializeSeq {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let value = value.serialize(self.ser.clone())?;
+        self.index += 1;
+        table_set(&self.table, LuaValue::Integer(self.index), value)
+    }
+
+    fn end(self) -> Result<LuaValue> {
+        Ok(self.table)
+    }
+}
+
+impl ser::SerializeTuple for SerializeSeq {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<LuaValue> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeSeq {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<LuaValue> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub struct SerializeTupleVariant {
+    variant: &'static str,
+    seq: SerializeSeq,
+}
+
+impl ser::SerializeTupleVariant for SerializeTupleVariant {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        ser::SerializeSeq::serialize_element(&mut self.seq, value)
+    }
+
+    fn end(self) -> Result<LuaValue> {
+        variant_table(self.variant, self.seq.table)
+    }
+}
+
+pub struct SerializeMap {
+    ser: Serializer,
+    table: LuaValue,
+    key: Option<LuaValue>,
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.key = Some(key.serialize(self.ser.clone())?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = match self.key.take() {
+            Some(key) => key,
+            None => {
+                return Err(Error::SerializeError(
+                    "serialize_value called before serialize_key".to_string(),
+                ))
+            }
+        };
+        let value = value.serialize(self.ser.clone())?;
+        table_set(&self.table, key, value)
+    }
+
+    fn end(self) -> Result<LuaValue> {
+        Ok(self.table)
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        let value = value.serialize(self.ser.clone())?;
+        table_set(&self.table, LuaValue::String(key.into()), value)
+    }
+
+    fn end(self) -> Result<LuaValue> {
+        Ok(self.table)
+    }
+}
+
+pub struct SerializeStructVariant {
+    variant: &'static str,
+    map: SerializeMap,
+}
+
+impl ser::SerializeStructVariant for SerializeStructVariant {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        ser::SerializeStruct::serialize_field(&mut self.map, key, value)
+    }
+
+    fn end(self) -> Result<LuaValue> {
+        variant_table(self.variant, self.map.table)
+    }
+}
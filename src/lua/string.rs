@@ -0,0 +1,28 @@
+use crate::lua::{Error, Result};
+
+// 字符串是值类型，不受垃圾回收管理，直接持有内容
+#[derive(Clone, Debug, PartialEq)]
+pub struct LuaString(pub(crate) Vec<u8>);
+
+impl LuaString {
+    // Lua 字符串是字节序列，不是合法的 UTF-8 时返回错误
+    pub fn to_str(&self) -> Result<&str> {
+        std::str::from_utf8(&self.0).map_err(|_| Error::FromLuaConversionError {
+            from: "string",
+            to: "&str",
+            message: Some("invalid utf-8 encoding".to_string()),
+        })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
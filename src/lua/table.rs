@@ -0,0 +1,188 @@
+use crate::api::*;
+use crate::lua::reference::LuaRef;
+use crate::lua::{FromLua, Lua, Result, ToLua};
+use crate::state::LuaValue;
+use std::marker::PhantomData;
+
+// 会触发元方法的操作放在原生函数里通过 lua_pcall 调用，元方法出错时返回 Err
+fn protected_get(l: lua_State) -> usize {
+    lua_gettable(l, 1);
+    1
+}
+
+fn protected_set(l: lua_State) -> usize {
+    lua_settable(l, 1);
+    0
+}
+
+fn protected_raw_set(l: lua_State) -> usize {
+    lua_rawset(l, 1);
+    0
+}
+
+fn protected_len(l: lua_State) -> usize {
+    lua_len(l, 1);
+    1
+}
+
+#[derive(Clone)]
+pub struct Table(pub(crate) LuaRef);
+
+impl Table {
+    fn call_protected(&self, func: lua_CFunction, args: Vec<LuaValue>) -> Result<LuaValue> {
+        let lua = &self.0.lua;
+        lua_pushcfunction(lua.lua_state(), func);
+        self.0.push();
+        let nargs = args.len() + 1;
+        for arg in args {
+            lua.push_value(arg);
+        }
+        let mut results = lua.call_protected(nargs)?;
+        Ok(results.pop().unwrap_or(LuaValue::Nil))
+    }
+
+    pub fn get<K: ToLua, V: FromLua>(&self, key: K) -> Result<V> {
+        let lua = &self.0.lua;
+        let key = key.to_lua(lua)?;
+        let value = self.call_protected(protected_get, vec![key])?;
+        V::from_lua(value, lua)
+    }
+
+    pub fn set<K: ToLua, V: ToLua>(&self, key: K, value: V) -> Result<()> {
+        let lua = &self.0.lua;
+        let key = key.to_lua(lua)?;
+        let value = value.to_lua(lua)?;
+        self.call_protected(protected_set, vec![key, value])?;
+        Ok(())
+    }
+
+    pub fn raw_get<K: ToLua, V: FromLua>(&self, key: K) -> Result<V> {
+        let lua = &self.0.lua;
+        let key = key.to_lua(lua)?;
+        let value = match self.0.value() {
+            LuaValue::Table(t) => t.borrow().get(key),
+            _ => LuaValue::Nil,
+        };
+        V::from_lua(value, lua)
+    }
+
+    // 键为 nil 或 NaN 时返回错误
+    pub fn raw_set<K: ToLua, V: ToLua>(&self, key: K, value: V) -> Result<()> {
+        let lua = &self.0.lua;
+        let key = key.to_lua(lua)?;
+        let value = value.to_lua(lua)?;
+        self.call_protected(protected_raw_set, vec![key, value])?;
+        Ok(())
+    }
+
+    pub fn contains_key<K: ToLua>(&self, key: K) -> Result<bool> {
+        let value: LuaValue = self.get(key)?;
+        Ok(!value.is_nil())
+    }
+
+    // 和 # 运算符一样会调用 __len
+    pub fn len(&self) -> Result<i64> {
+        let lua = &self.0.lua;
+        let value = self.call_protected(protected_len, vec![])?;
+        i64::from_lua(value, lua)
+    }
+
+    pub fn raw_len(&self) -> usize {
+        let l = self.0.lua.lua_state();
+        self.0.push();
+        let n = lua_rawlen(l.clone(), -1);
+        lua_pop(l, 1);
+        n
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self.0.value() {
+            LuaValue::Table(t) => t.borrow().entries().is_empty(),
+            _ => true,
+        }
+    }
+
+    pub fn get_metatable(&self) -> Option<Table> {
+        let lua = &self.0.lua;
+        let l = lua.lua_state();
+        self.0.push();
+        let mt = if lua_getmetatable(l.clone(), -1) {
+            Some(Table(LuaRef::new(lua, lua.pop_value())))
+        } else {
+            None
+        };
+        lua_pop(l, 1);
+        mt
+    }
+
+    pub fn set_metatable(&self, metatable: Option<Table>) {
+        let lua = &self.0.lua;
+        let l = lua.lua_state();
+        self.0.push();
+        match metatable {
+            Some(mt) => mt.0.push(),
+            None => lua_pushnil(l.clone()),
+        }
+        lua_setmetatable(l.clone(), -2);
+        lua_pop(l, 1);
+    }
+
+    // 遍历表中所有的键值对，遍历的是调用时的快照，顺序不确定
+    pub fn pairs<K: FromLua, V: FromLua>(&self) -> TablePairs<K, V> {
+        let entries = match self.0.value() {
+            LuaValue::Table(t) => t.borrow().entries(),
+            _ => Vec::new(),
+        };
+        TablePairs {
+            lua: self.0.lua.clone(),
+            entries: entries.into_iter(),
+            _phantom: PhantomData,
+        }
+    }
+
+    // 按 1, 2, 3... 的顺序遍历，遇到第一个 nil 时停止
+    pub fn sequence_values<V: FromLua>(&self) -> TableSequence<V> {
+        TableSequence {
+            table: self.clone(),
+            index: 1,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+pub struct TablePairs<K, V> {
+    lua: Lua,
+    entries: std::vec::IntoIter<(LuaValue, LuaValue)>,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<K: FromLua, V: FromLua> Iterator for TablePairs<K, V> {
+    type Item = Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.entries.next()?;
+        let pair =
+            K::from_lua(key, &self.lua).and_then(|k| V::from_lua(value, &self.lua).map(|v| (k, v)));
+        Some(pair)
+    }
+}
+
+pub struct TableSequence<V> {
+    table: Table,
+    index: i64,
+    _phantom: PhantomData<V>,
+}
+
+impl<V: FromLua> Iterator for TableSequence<V> {
+    type Item = Result<V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let value = match self.table.raw_get::<_, LuaValue>(self.index) {
+            Ok(LuaValue::Nil) => return None,
+            Ok(value) => value,
+            Err(e) => return Some(Err(e)),
+        };
+        self.index += 1;
+        Some(V::from_lua(value, &self.table.0.lua))
+    }
+}
@@ -0,0 +1,383 @@
+use crate::api::*;
+use crate::lua::reference::LuaRef;
+use crate::lua::{Error, FromLua, FromLuaMulti, Lua, Result, Table, ToLua, ToLuaMulti, Variadic};
+use crate::state::LuaValue;
+use std::any::Any;
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// 通过 Lua 访问的 Rust 类型。同一类型的所有值共享一个元表，
+// 元表在第一次创建该类型的 userdata 时根据注册的方法和字段生成
+pub trait UserData: Sized + 'static {
+    fn add_fields(_fields: &mut UserDataFields<Self>) {}
+
+    fn add_methods(_methods: &mut UserDataMethods<Self>) {}
+}
+
+type Callback = Box<dyn Fn(&Lua, Vec<LuaValue>) -> Result<Vec<LuaValue>>>;
+
+// userdata 中保存的是 Rc<RefCell<T>>：取出 Rc 之后就不再占用 LuaUserData 本身的借用，
+// 方法执行期间再次调用 Lua 也不会冲突，对 T 的借用冲突则作为 Lua 错误报告
+fn userdata_cell(value: &LuaValue) -> Option<Rc<dyn Any>> {
+    match value {
+        LuaValue::UserData(u) => u.borrow().downcast_ref::<Rc<dyn Any>>().cloned(),
+        _ => None,
+    }
+}
+
+// 取出第一个参数作为 self
+fn take_self(args: &mut Vec<LuaValue>) -> Result<Rc<dyn Any>> {
+    let value = if args.is_empty() {
+        LuaValue::Nil
+    } else {
+        args.remove(0)
+    };
+    userdata_cell(&value).ok_or(Error::UserDataTypeMismatch)
+}
+
+fn borrow<T: 'static>(cell: &Rc<dyn Any>) -> Result<Ref<'_, T>> {
+    match cell.downcast_ref::<RefCell<T>>() {
+        Some(cell) => cell.try_borrow().map_err(|_| Error::UserDataBorrowError),
+        None => Err(Error::UserDataTypeMismatch),
+    }
+}
+
+fn borrow_mut<T: 'static>(cell: &Rc<dyn Any>) -> Result<RefMut<'_, T>> {
+    match cell.downcast_ref::<RefCell<T>>() {
+        Some(cell) => cell
+            .try_borrow_mut()
+            .map_err(|_| Error::UserDataBorrowMutError),
+        None => Err(Error::UserDataTypeMismatch),
+    }
+}
+
+fn function_callback<A, R, F>(function: F) -> Callback
+where
+    A: FromLuaMulti,
+    R: ToLuaMulti,
+    F: Fn(&Lua, A) -> Result<R> + 'static,
+{
+    Box::new(move |lua, args| function(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua))
+}
+
+fn method_callback<T, A, R, F>(method: F) -> Callback
+where
+    T: 'static,
+    A: FromLuaMulti,
+    R: ToLuaMulti,
+    F: Fn(&Lua, &T, A) -> Result<R> + 'static,
+{
+    Box::new(move |lua, mut args| {
+        let cell = take_self(&mut args)?;
+        let args = A::from_lua_multi(args, lua)?;
+        let data = borrow::<T>(&cell)?;
+        method(lua, &data, args)?.to_lua_multi(lua)
+    })
+}
+
+fn method_mut_callback<T, A, R, F>(method: F) -> Callback
+where
+    T: 'static,
+    A: FromLuaMulti,
+    R: ToLuaMulti,
+    F: Fn(&Lua, &mut T, A) -> Result<R> + 'static,
+{
+    Box::new(move |lua, mut args| {
+        let cell = take_self(&mut args)?;
+        let args = A::from_lua_multi(args, lua)?;
+        let mut data = borrow_mut::<T>(&cell)?;
+        method(lua, &mut data, args)?.to_lua_multi(lua)
+    })
+}
+
+pub struct UserDataMethods<T> {
+    methods: Vec<(String, Callback)>,
+    meta_methods: Vec<(String, Callback)>,
+    _phantom: std::marker::PhantomData<T>,
+}
+
+impl<T: UserData> UserDataMethods<T> {
+    // 通过 obj:name(...) 调用，self 以共享借用传入
+    pub fn add_method<A, R, F>(&mut self, name: &str, method: F)
+    where
+        A: FromLuaMulti,
+        R: ToLuaMulti,
+        F: Fn(&Lua, &T, A) -> Result<R> + 'static,
+    {
+        self.methods
+            .push((name.to_string(), method_callback(method)));
+    }
+
+    // self 以可变借用传入，同一个值上的方法重入时返回错误
+    pub fn add_method_mut<A, R, F>(&mut self, name: &str, method: F)
+    where
+        A: FromLuaMulti,
+        R: ToLuaMulti,
+        F: Fn(&Lua, &mut T, A) -> Result<R> + 'static,
+    {
+        self.methods
+            .push((name.to_string(), method_mut_callback(method)));
+    }
+
+    // 不接收 self 的函数，例如 obj.new(...)
+    pub fn add_function<A, R, F>(&mut self, name: &str, function: F)
+    where
+        A: FromLuaMulti,
+        R: ToLuaMulti,
+        F: Fn(&Lua, A) -> Result<R> + 'static,
+    {
+        self.methods
+            .push((name.to_string(), function_callback(function)));
+    }
+
+    // 元方法的名字和元表中的一样，例如 "__tostring"、"__eq"
+    pub fn add_meta_method<A, R, F>(&mut self, name: &str, method: F)
+    where
+        A: FromLuaMulti,
+        R: ToLuaMulti,
+        F: Fn(&Lua, &T, A) -> Result<R> + 'static,
+    {
+        self.meta_methods
+            .push((name.to_string(), method_callback(method)));
+    }
+
+    pub fn add_meta_method_mut<A, R, F>(&mut self, name: &str, method: F)
+    where
+        A: FromLuaMulti,
+        R: ToLuaMulti,
+        F: Fn(&Lua, &mut T, A) -> Result<R> + 'static,
+    {
+        self.meta_methods
+            .push((name.to_string(), method_mut_callback(method)));
+    }
+
+    // 二元运算的元方法中 userdata 可能是任意一个操作数，用这种形式自己处理参数
+    pub fn add_meta_function<A, R, F>(&mut self, name: &str, function: F)
+    where
+        A: FromLuaMulti,
+        R: ToLuaMulti,
+        F: Fn(&Lua, A) -> Result<R> + 'static,
+    {
+        self.meta_methods
+            .push((name.to_string(), function_callback(function)));
+    }
+}
+
+pub struct UserDataFields<T> {
+    getters: HashMap<String, Callback>,
+    setters: HashMap<String, Callback>,
+    _phantom: std::marker::PhantomData<T>,
+}
+
+impl<T: UserData> UserDataFields<T> {
+    // 通过 obj.name 读取
+    pub fn add_field_method_get<R, F>(&mut self, name: &str, getter: F)
+    where
+        R: ToLua,
+        F: Fn(&Lua, &T) -> Result<R> + 'static,
+    {
+        let getter = method_callback(move |lua, data, ()| getter(lua, data));
+        self.getters.insert(name.to_string(), getter);
+    }
+
+    // 通过 obj.name = value 写入
+    pub fn add_field_method_set<A, F>(&mut self, name: &str, setter: F)
+    where
+        A: FromLua,
+        F: Fn(&Lua, &mut T, A) -> Result<()> + 'static,
+    {
+        let setter = method_mut_callback(setter);
+        self.setters.insert(name.to_string(), setter);
+    }
+}
+
+// 生成 __index：依次查找字段、方法和用户注册的 __index
+fn index_callback(
+    getters: HashMap<String, Callback>,
+    methods: LuaValue,
+    index: Option<Callback>,
+) -> Callback {
+    Box::new(move |lua, args| {
+        let key = args.get(1).cloned().unwrap_or(LuaValue::Nil);
+        if let Some(name) = string_key(&key) {
+            if let Some(getter) = getters.get(name) {
+                return getter(lua, args);
+            }
+            if let LuaValue::Table(t) = &methods {
+                let method = t.borrow().get(key.clone());
+                if !method.is_nil() {
+                    return Ok(vec![method]);
+                }
+            }
+        }
+        match &index {
+            Some(index) => index(lua, args),
+            None => Ok(vec![LuaValue::Nil]),
+        }
+    })
+}
+
+fn string_key(key: &LuaValue) -> Option<&str> {
+    match key {
+        LuaValue::String(name) => std::str::from_utf8(name).ok(),
+        _ => None,
+    }
+}
+
+// 生成 __newindex：写入字段或者交给用户注册的 __newindex，否则报错
+fn newindex_callback(setters: HashMap<String, Callback>, newindex: Option<Callback>) -> Callback {
+    Box::new(move |lua, mut args| {
+        let key = args.get(1).cloned().unwrap_or(LuaValue::Nil);
+        if let Some(name) = string_key(&key) {
+            if let Some(setter) = setters.get(name) {
+                args.remove(1);
+                return setter(lua, args);
+            }
+        }
+        match &newindex {
+            Some(newindex) => newindex(lua, args),
+            None => Err(Error::RuntimeError(match key.to_str() {
+                Some(name) => format!("attempt to set unknown field '{}'", name),
+                None => format!("attempt to set a {} field", key.type_name()),
+            })),
+        }
+    })
+}
+
+impl Lua {
+    // 把 Rust 值放进 userdata，元表以类型名为键缓存在注册表中
+    pub fn create_userdata<T: UserData>(&self, data: T) -> Result<AnyUserData> {
+        let l = self.lua_state();
+        let cell: Rc<dyn Any> = Rc::new(RefCell::new(data));
+        lua_newuserdata(l.clone(), Box::new(cell));
+        if luaL_newmetatable(l.clone(), std::any::type_name::<T>()) {
+            let mt = self.pop_value();
+            self.init_metatable::<T>(&mt)?;
+            self.push_value(mt);
+        }
+        lua_setmetatable(l, -2);
+        AnyUserData::from_lua(self.pop_value(), self)
+    }
+
+    fn init_metatable<T: UserData>(&self, mt: &LuaValue) -> Result<()> {
+        let mut fields = UserDataFields {
+            getters: HashMap::new(),
+            setters: HashMap::new(),
+            _phantom: std::marker::PhantomData,
+        };
+        T::add_fields(&mut fields);
+        let mut methods = UserDataMethods {
+            methods: Vec::new(),
+            meta_methods: Vec::new(),
+            _phantom: std::marker::PhantomData,
+        };
+        T::add_methods(&mut methods);
+
+        let mut meta_methods: HashMap<String, Callback> =
+            methods.meta_methods.into_iter().collect();
+        let index = meta_methods.remove("__index");
+        let newindex = meta_methods.remove("__newindex");
+        for (name, callback) in meta_methods {
+            raw_set(mt, &name, self.create_callback(callback)?);
+        }
+
+        let has_methods = !methods.methods.is_empty();
+        let method_table = LuaValue::new_table(0, methods.methods.len());
+        for (name, callback) in methods.methods {
+            raw_set(&method_table, &name, self.create_callback(callback)?);
+        }
+        // 只有方法时 __index 直接指向方法表
+        if fields.getters.is_empty() && index.is_none() {
+            if has_methods {
+                raw_set(mt, "__index", method_table);
+            }
+        } else {
+            let index = index_callback(fields.getters, method_table, index);
+            raw_set(mt, "__index", self.create_callback(index)?);
+        }
+        if !fields.setters.is_empty() || newindex.is_some() {
+            let newindex = newindex_callback(fields.setters, newindex);
+            raw_set(mt, "__newindex", self.create_callback(newindex)?);
+        }
+        Ok(())
+    }
+
+    fn create_callback(&self, callback: Callback) -> Result<LuaValue> {
+        let function = self.create_function(move |lua, args: Variadic<LuaValue>| {
+            callback(lua, args.to_vec()).map(Variadic::from)
+        })?;
+        Ok(function.0.value())
+    }
+}
+
+fn raw_set(table: &LuaValue, key: &str, value: LuaValue) {
+    if let LuaValue::Table(t) = table {
+        t.borrow_mut().set(LuaValue::String(key.into()), value);
+    }
+}
+
+// 任意类型的 userdata，取出数据时检查类型
+#[derive(Clone)]
+pub struct AnyUserData(pub(crate) LuaRef, Option<Rc<dyn Any>>);
+
+impl AnyUserData {
+    pub fn is<T: UserData>(&self) -> bool {
+        match &self.1 {
+            Some(cell) => cell.is::<RefCell<T>>(),
+            None => false,
+        }
+    }
+
+    // 借用冲突时返回 UserDataBorrowError/UserDataBorrowMutError，不会 panic
+    pub fn borrow<T: UserData>(&self) -> Result<Ref<'_, T>> {
+        match &self.1 {
+            Some(cell) => borrow::<T>(cell),
+            None => Err(Error::UserDataTypeMismatch),
+        }
+    }
+
+    pub fn borrow_mut<T: UserData>(&self) -> Result<RefMut<'_, T>> {
+        match &self.1 {
+            Some(cell) => borrow_mut::<T>(cell),
+            None => Err(Error::UserDataTypeMismatch),
+        }
+    }
+
+    pub fn get_metatable(&self) -> Option<Table> {
+        let lua = &self.0.lua;
+        let l = lua.lua_state();
+        self.0.push();
+        let mt = if lua_getmetatable(l.clone(), -1) {
+            Some(Table(LuaRef::new(lua, lua.pop_value())))
+        } else {
+            None
+        };
+        lua_pop(l, 1);
+        mt
+    }
+}
+
+impl ToLua for AnyUserData {
+    fn to_lua(self, _: &Lua) -> Result<LuaValue> {
+        Ok(self.0.value())
+    }
+}
+
+impl FromLua for AnyUserData {
+    fn from_lua(value: LuaValue, lua: &Lua) -> Result<Self> {
+        match value {
+            LuaValue::UserData(_) => {
+                let cell = userdata_cell(&value);
+                Ok(AnyUserData(LuaRef::new(lua, value), cell))
+            }
+            _ => Err(Error::from_lua_conversion(value.type_name(), "userdata")),
+        }
+    }
+}
+
+impl<T: UserData> ToLua for T {
+    fn to_lua(self, lua: &Lua) -> Result<LuaValue> {
+        lua.create_userdata(self)?.to_lua(lua)
+    }
+}
@@ -0,0 +1,350 @@
+use crate::api::*;
+use crate::state::{float_to_integer, LuaState, LuaValue};
+
+// 算术、比较、连接和取长度运算，API 和虚拟机的指令共用这里的实现
+
+const EVENTS: [&str; 14] = [
+    "__add", "__sub", "__mul", "__mod", "__pow", "__div", "__idiv", "__band", "__bor", "__bxor",
+    "__shl", "__shr", "__unm", "__bnot",
+];
+
+fn is_bitwise(op: isize) -> bool {
+    (LUA_OPBAND..=LUA_OPSHR).contains(&op) || op == LUA_OPBNOT
+}
+
+// 参考 luaV_mod：结果的符号和除数相同
+fn int_mod(a: i64, b: i64) -> i64 {
+    let m = a.wrapping_rem(b);
+    if m != 0 && (m ^ b) < 0 {
+        m + b
+    } else {
+        m
+    }
+}
+
+// 参考 luaV_div：向负无穷取整
+fn int_div(a: i64, b: i64) -> i64 {
+    let q = a.wrapping_div(b);
+    if a.wrapping_rem(b) != 0 && (a ^ b) < 0 {
+        q - 1
+    } else {
+        q
+    }
+}
+
+fn float_mod(a: f64, b: f64) -> f64 {
+    let m = a % b;
+    if m * b < 0.0 {
+        m + b
+    } else {
+        m
+    }
+}
+
+fn shift_left(x: i64, y: i64) -> i64 {
+    if y <= -64 || y >= 64 {
+        0
+    } else if y >= 0 {
+        ((x as u64) << y) as i64
+    } else {
+        ((x as u64) >> -y) as i64
+    }
+}
+
+fn int_arith(op: isize, a: i64, b: i64) -> i64 {
+    match op {
+        LUA_OPADD => a.wrapping_add(b),
+        LUA_OPSUB => a.wrapping_sub(b),
+        LUA_OPMUL => a.wrapping_mul(b),
+        LUA_OPMOD => int_mod(a, b),
+        LUA_OPIDIV => int_div(a, b),
+        LUA_OPBAND => a & b,
+        LUA_OPBOR => a | b,
+        LUA_OPBXOR => a ^ b,
+        LUA_OPSHL => shift_left(a, b),
+        LUA_OPSHR => shift_left(a, b.wrapping_neg()),
+        LUA_OPUNM => a.wrapping_neg(),
+        LUA_OPBNOT => !a,
+        _ => unreachable!(),
+    }
+}
+
+fn float_arith(op: isize, a: f64, b: f64) -> f64 {
+    match op {
+        LUA_OPADD => a + b,
+        LUA_OPSUB => a - b,
+        LUA_OPMUL => a * b,
+        LUA_OPMOD => float_mod(a, b),
+        LUA_OPPOW => a.powf(b),
+        LUA_OPDIV => a / b,
+        LUA_OPIDIV => (a / b).floor(),
+        LUA_OPUNM => -a,
+        _ => unreachable!(),
+    }
+}
+
+fn is_number(v: &LuaValue) -> bool {
+    matches!(v, LuaValue::Integer(_) | LuaValue::Number(_))
+}
+
+fn is_string_like(v: &LuaValue) -> bool {
+    matches!(
+        v,
+        LuaValue::String(_) | LuaValue::Integer(_) | LuaValue::Number(_)
+    )
+}
+
+// 参考 l_intfitsf：绝对值不超过 2^53 的整数可以精确地转换为浮点数
+fn int_fits_float(i: i64) -> bool {
+    (-(1i64 << 53)..=(1i64 << 53)).contains(&i)
+}
+
+// 参考 LTintfloat：i < f 等价于 i < ceil(f)
+fn lt_int_float(i: i64, f: f64) -> bool {
+    if int_fits_float(i) {
+        return (i as f64) < f;
+    }
+    match float_to_integer(f.ceil()) {
+        Some(fi) => i < fi,
+        None => f > 0.0,
+    }
+}
+
+// 参考 LEintfloat：i <= f 等价于 i <= floor(f)
+fn le_int_float(i: i64, f: f64) -> bool {
+    if int_fits_float(i) {
+        return (i as f64) <= f;
+    }
+    match float_to_integer(f.floor()) {
+        Some(fi) => i <= fi,
+        None => f > 0.0,
+    }
+}
+
+// 参考 LTnum 和 LEnum：整数和浮点数比较时不能简单地把整数转换为浮点数
+fn lt_num(a: &LuaValue, b: &LuaValue) -> bool {
+    match (a, b) {
+        (LuaValue::Integer(x), LuaValue::Integer(y)) => x < y,
+        (LuaValue::Integer(i), LuaValue::Number(f)) => lt_int_float(*i, *f),
+        (LuaValue::Number(f), LuaValue::Integer(i)) => !f.is_nan() && !le_int_float(*i, *f),
+        (LuaValue::Number(x), LuaValue::Number(y)) => x < y,
+        _ => unreachable!(),
+    }
+}
+
+fn le_num(a: &LuaValue, b: &LuaValue) -> bool {
+    match (a, b) {
+        (LuaValue::Integer(x), LuaValue::Integer(y)) => x <= y,
+        (LuaValue::Integer(i), LuaValue::Number(f)) => le_int_float(*i, *f),
+        (LuaValue::Number(f), LuaValue::Integer(i)) => !f.is_nan() && !lt_int_float(*i, *f),
+        (LuaValue::Number(x), LuaValue::Number(y)) => x <= y,
+        _ => unreachable!(),
+    }
+}
+
+impl LuaState {
+    // 参考 luaO_arith：不需要元方法时返回 None
+    fn raw_arith(&mut self, op: isize, a: &LuaValue, b: &LuaValue) -> Option<LuaValue> {
+        if is_bitwise(op) {
+            let (x, y) = (a.to_integer()?, b.to_integer()?);
+            return Some(LuaValue::Integer(int_arith(op, x, y)));
+        }
+        match (op, a, b) {
+            (LUA_OPPOW, _, _) | (LUA_OPDIV, _, _) => (),
+            (_, LuaValue::Integer(x), LuaValue::Integer(y)) => {
+                if *y == 0 && (op == LUA_OPMOD || op == LUA_OPIDIV) {
+                    let name = if op == LUA_OPMOD { "n%0" } else { "n//0" };
+                    self.runtime_error(format!("attempt to perform '{}'", name))
+                }
+                return Some(LuaValue::Integer(int_arith(op, *x, *y)));
+            }
+            _ => (),
+        }
+        let (x, y) = (a.to_number()?, b.to_number()?);
+        Some(LuaValue::Number(float_arith(op, x, y)))
+    }
+
+    // 参考 luaT_trybinTM：先找第一个操作数的元方法，再找第二个
+    pub fn arith_op(&mut self, op: isize, a: LuaValue, b: LuaValue) -> LuaValue {
+        if let Some(v) = self.raw_arith(op, &a, &b) {
+            return v;
+        }
+        let event = EVENTS[op as usize];
+        let tm = match self.metafield(&a, event) {
+            LuaValue::Nil => self.metafield(&b, event),
+            tm => tm,
+        };
+        if !tm.is_nil() {
+            return self.call_metamethod(tm, a, b, None);
+        }
+        if is_bitwise(op) {
+            if is_number(&a) && is_number(&b) {
+                self.runtime_error("number has no integer representation".to_string())
+            }
+            let bad = if is_number(&a) { &b } else { &a };
+            self.runtime_error(format!(
+                "attempt to perform bitwise operation on a {} value",
+                bad.type_name()
+            ))
+        }
+        let bad = if a.to_number().is_some() { &b } else { &a };
+        self.runtime_error(format!(
+            "attempt to perform arithmetic on a {} value",
+            bad.type_name()
+        ))
+    }
+
+    fn compare_tm(&mut self, event: &str, a: &LuaValue, b: &LuaValue) -> Option<bool> {
+        let tm = match self.metafield(a, event) {
+            LuaValue::Nil => self.metafield(b, event),
+            tm => tm,
+        };
+        if tm.is_nil() {
+            return None;
+        }
+        Some(
+            self.call_metamethod(tm, a.clone(), b.clone(), None)
+                .to_boolean(),
+        )
+    }
+
+    fn compare_error(&mut self, a: &LuaValue, b: &LuaValue) -> ! {
+        let (t1, t2) = (a.type_name(), b.type_name());
+        if t1 == t2 {
+            self.runtime_error(format!("attempt to compare two {} values", t1))
+        } else {
+            self.runtime_error(format!("attempt to compare {} with {}", t1, t2))
+        }
+    }
+
+    // 参考 luaV_equalobj：只有表和 userdata 才会调用 __eq
+    pub fn values_equal(&mut self, a: &LuaValue, b: &LuaValue) -> bool {
+        if a.raw_equal(b) {
+            return true;
+        }
+        match (a, b) {
+            (LuaValue::Table(_), LuaValue::Table(_))
+            | (LuaValue::UserData(_), LuaValue::UserData(_)) => {
+                self.compare_tm("__eq", a, b).unwrap_or(false)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn less_than(&mut self, a: &LuaValue, b: &LuaValue) -> bool {
+        match (a, b) {
+            (LuaValue::String(x), LuaValue::String(y)) => x < y,
+            _ if is_number(a) && is_number(b) => lt_num(a, b),
+            _ => match self.compare_tm("__lt", a, b) {
+                Some(r) => r,
+                None => self.compare_error(a, b),
+            },
+        }
+    }
+
+    // 没有 __le 时用 not (b < a) 代替
+    pub fn less_equal(&mut self, a: &LuaValue, b: &LuaValue) -> bool {
+        match (a, b) {
+            (LuaValue::String(x), LuaValue::String(y)) => x <= y,
+            _ if is_number(a) && is_number(b) => le_num(a, b),
+            _ => match self.compare_tm("__le", a, b) {
+                Some(r) => r,
+                None => match self.compare_tm("__lt", b, a) {
+                    Some(r) => !r,
+                    None => self.compare_error(a, b),
+                },
+            },
+        }
+    }
+
+    pub fn concat_value(&mut self, a: LuaValue, b: LuaValue) -> LuaValue {
+        if is_string_like(&a) && is_string_like(&b) {
+            let mut s = a.to_bytes().unwrap();
+            s.extend(b.to_bytes().unwrap());
+            return LuaValue::String(s);
+        }
+        let tm = match self.metafield(&a, "__concat") {
+            LuaValue::Nil => self.metafield(&b, "__concat"),
+            tm => tm,
+        };
+        if !tm.is_nil() {
+            return self.call_metamethod(tm, a, b, None);
+        }
+        let bad = if is_string_like(&a) { &b } else { &a };
+        self.runtime_error(format!(
+            "attempt to concatenate a {} value",
+            bad.type_name()
+        ))
+    }
+
+    // 参考 luaV_concat：从右往左两两连接
+    pub fn concat_values(&mut self, values: Vec<LuaValue>) -> LuaValue {
+        let mut values = values;
+        let mut result = match values.pop() {
+            Some(v) => v,
+            None => return LuaValue::String(Vec::new()),
+        };
+        while let Some(v) = values.pop() {
+            result = self.concat_value(v, result);
+        }
+        result
+    }
+
+    // 参考 luaV_objlen
+    pub fn len_value(&mut self, v: LuaValue) -> LuaValue {
+        if let LuaValue::String(s) = &v {
+            return LuaValue::Integer(s.len() as i64);
+        }
+        let tm = self.metafield(&v, "__len");
+        if !tm.is_nil() {
+            return self.call_metamethod(tm, v.clone(), v, None);
+        }
+        match &v {
+            LuaValue::Table(t) => LuaValue::Integer(t.borrow().border() as i64),
+            _ => self.runtime_error(format!(
+                "attempt to get length of a {} value",
+                v.type_name()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn int_arith_test() {
+        assert_eq!(int_mod(-5, 3), 1);
+        assert_eq!(int_mod(5, -3), -1);
+        assert_eq!(int_div(-7, 2), -4);
+        assert_eq!(int_div(i64::MIN, -1), i64::MIN);
+        assert_eq!(shift_left(1, 63), i64::MIN);
+        assert_eq!(shift_left(-1, -60), 15);
+        assert_eq!(shift_left(1, 64), 0);
+        assert_eq!(float_mod(-5.5, 2.0), 0.5);
+    }
+
+    #[test]
+    fn mixed_compare_test() {
+        let max = LuaValue::Integer(i64::MAX);
+        let min = LuaValue::Integer(i64::MIN);
+        let two63 = LuaValue::Number(9223372036854775808.0);
+        let big = LuaValue::Integer((1 << 53) + 1);
+        let big_f = LuaValue::Number((1i64 << 53) as f64);
+        let nan = LuaValue::Number(f64::NAN);
+        // 2^63 - 1 转换为浮点数后等于 2^63，但是整数仍然小于它
+        assert!(lt_num(&max, &two63));
+        assert!(!le_num(&two63, &max));
+        assert!(le_num(&LuaValue::Number(-9223372036854775808.0), &min));
+        assert!(!lt_num(&LuaValue::Number(-9223372036854775808.0), &min));
+        assert!(lt_num(&big_f, &big));
+        assert!(!le_num(&big, &big_f));
+        assert!(lt_num(&big, &LuaValue::Number(f64::INFINITY)));
+        assert!(lt_num(&LuaValue::Number(f64::NEG_INFINITY), &min));
+        assert!(!lt_num(&big, &nan) && !le_num(&big, &nan));
+        assert!(!lt_num(&nan, &big) && !le_num(&nan, &big));
+        assert!(lt_num(&LuaValue::Integer(1), &LuaValue::Number(1.5)));
+        assert!(le_num(&LuaValue::Number(1.0), &LuaValue::Integer(1)));
+    }
+}
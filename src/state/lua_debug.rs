@@ -0,0 +1,484 @@
+use crate::api::*;
+use crate::chunk::binary::{ConstantValue, Prototype};
+use crate::state::{CallInfo, LuaClosure, LuaState, LuaValue};
+use crate::vm::opcodes::*;
+use crate::vm::Instruction;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// 参考 ldebug.c：根据调用处的字节码推断被调用函数的名字
+
+fn constant_name(p: &Prototype, index: isize) -> Option<String> {
+    // RK 操作数大于 0xFF 时表示常量
+    if index <= 0xFF {
+        return None;
+    }
+    match &p.constants.get((index - 0xFF - 1) as usize)?.const_value {
+        ConstantValue::ShortStr(s) => Some(s.value.clone()),
+        _ => None,
+    }
+}
+
+fn upvalue_name(p: &Prototype, index: isize) -> String {
+    match p.upvalue_names.get(index as usize) {
+        Some(name) => name.value.clone(),
+        None => "?".to_string(),
+    }
+}
+
+// 参考 luaF_getlocalname：pc 处第 n 个活跃的局部变量
+fn local_name(p: &Prototype, reg: isize, pc: usize) -> Option<String> {
+    let mut n = reg + 1;
+    for var in &p.loc_vars {
+        if var.start_pc as usize > pc {
+            break;
+        }
+        if pc < var.end_pc as usize {
+            n -= 1;
+            if n == 0 {
+                return Some(var.var_name.value.clone());
+            }
+        }
+    }
+    None
+}
+
+// 参考 findsetreg：最后一条修改了寄存器 reg 的指令
+fn find_set_reg(p: &Prototype, lastpc: usize, reg: isize) -> Option<usize> {
+    let mut setreg = None;
+    for pc in 0..lastpc {
+        let i = p.code[pc];
+        let (a, b, _) = i.abc();
+        let changed = match i.opcode() {
+            OP_LOADNIL => a <= reg && reg <= a + b,
+            OP_TFORCALL => reg >= a + 2,
+            OP_CALL | OP_TAILCALL => reg >= a,
+            OP_SETTABUP | OP_SETUPVAL | OP_SETTABLE | OP_JMP | OP_EQ | OP_LT | OP_LE | OP_TEST
+            | OP_RETURN | OP_SETLIST | OP_EXTRAARG => false,
+            _ => a == reg,
+        };
+        if changed {
+            setreg = Some(pc);
+        }
+    }
+    setreg
+}
+
+// 参考 getobjname：返回名字和名字的种类（global、local、method、field 等）
+fn object_name(p: &Prototype, lastpc: usize, reg: isize) -> Option<(String, &'static str)> {
+    if let Some(name) = local_name(p, reg, lastpc) {
+        return Some((name, "local"));
+    }
+    let pc = find_set_reg(p, lastpc, reg)?;
+    let i = p.code[pc];
+    let (a, b, c) = i.abc();
+    match i.opcode() {
+        OP_MOVE if b < a => object_name(p, pc, b),
+        OP_GETTABUP => {
+            let name = constant_name(p, c)?;
+            let what = if upvalue_name(p, b) == "_ENV" {
+                "global"
+            } else {
+                "field"
+            };
+            Some((name, what))
+        }
+        OP_GETTABLE => {
+            let name = constant_name(p, c)?;
+            let what = if local_name(p, b, pc).as_deref() == Some("_ENV") {
+                "global"
+            } else {
+                "field"
+            };
+            Some((name, what))
+        }
+        OP_GETUPVAL => Some((upvalue_name(p, b), "upvalue")),
+        OP_LOADK => {
+            let (_, bx) = i.a_bx();
+            constant_name(p, bx + 0xFF + 1).map(|name| (name, "constant"))
+        }
+        OP_SELF => constant_name(p, c).map(|name| (name, "method")),
+        _ => None,
+    }
+}
+
+// 对应 lua_State 中和钩子有关的字段
+#[derive(Default)]
+pub(crate) struct HookState {
+    pub(crate) func: Option<lua_Hook>,
+    pub(crate) mask: isize,
+    pub(crate) base_count: isize,
+    count: isize,
+    // 钩子函数执行期间不再触发钩子
+    pub(crate) running: bool,
+    // 上一条执行过的指令，用来判断是否进入了新的一行
+    pub(crate) oldpc: usize,
+}
+
+impl HookState {
+    // 参考 lua_sethook：没有钩子函数或者掩码为 0 时关闭钩子
+    pub(crate) fn set(&mut self, func: Option<lua_Hook>, mask: isize, count: isize, oldpc: usize) {
+        let (func, mask) = match func {
+            Some(f) if mask != 0 => (Some(f), mask),
+            _ => (None, 0),
+        };
+        self.func = func;
+        self.mask = mask;
+        self.base_count = count;
+        self.count = count;
+        self.oldpc = oldpc;
+    }
+}
+
+// pc 指向下一条指令，正在执行的是它前面的一条
+fn current_line(p: &Prototype, pc: usize) -> isize {
+    match p.line_info.get(pc.saturating_sub(1)) {
+        Some(line) => *line as isize,
+        None => -1,
+    }
+}
+
+impl LuaState {
+    // 对应 getfuncname：只有被 Lua 函数调用时才能从调用指令推断名字
+    pub(crate) fn func_name_at(&self, level: isize) -> Option<(String, &'static str)> {
+        self.ci_at(level)?;
+        let caller = self.ci_at(level + 1)?;
+        let caller = caller.borrow();
+        let func = caller.get_func();
+        let func = func.borrow();
+        if func.function.is_some() || caller.get_pc() == 0 {
+            return None;
+        }
+        let p = &func.proto;
+        let pc = caller.get_pc() - 1;
+        let i = p.code[pc];
+        match i.opcode() {
+            OP_CALL | OP_TAILCALL => object_name(p, pc, i.abc().0),
+            OP_TFORCALL => Some(("for iterator".to_string(), "for iterator")),
+            _ => None,
+        }
+    }
+
+    fn function_at(&self, level: isize) -> Option<LuaValue> {
+        let ci = self.ci_at(level)?;
+        let func = ci.borrow().get_func();
+        Some(LuaValue::Closure(func))
+    }
+
+    // 参考 pushglobalfuncname：在 _LOADED 的各个模块中查找函数，名字形如 "mod.field"，
+    // 没有 _LOADED 时直接查找全局变量表
+    pub(crate) fn global_func_name_at(&self, level: isize) -> Option<String> {
+        let func = self.function_at(level)?;
+        let registry = self.get(LUA_REGISTRYINDEX);
+        let registry = match &registry {
+            LuaValue::Table(t) => t.borrow(),
+            _ => return None,
+        };
+        let loaded = match registry.get(LuaValue::String("_LOADED".into())) {
+            LuaValue::Table(t) => t.borrow().entries(),
+            _ => vec![(LuaValue::Nil, registry.get_array(LUA_RIDX_GLOBALS))],
+        };
+        for (module, t) in loaded {
+            let fields = match t {
+                LuaValue::Table(t) => t.borrow().entries(),
+                _ => continue,
+            };
+            for (key, value) in fields {
+                if let (LuaValue::String(key), true) = (&key, value.raw_equal(&func)) {
+                    let key = String::from_utf8_lossy(key);
+                    return Some(match &module {
+                        LuaValue::String(m) if m != b"_G" => {
+                            format!("{}.{}", String::from_utf8_lossy(m), key)
+                        }
+                        _ => key.into_owned(),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn ci_index(&self, ci: &Rc<RefCell<CallInfo>>) -> Option<usize> {
+        self.base_ci.borrow().iter().position(|c| Rc::ptr_eq(c, ci))
+    }
+
+    // 参考 lua_getinfo 和 auxgetinfo
+    pub(crate) fn info_of(&mut self, what: &str, ar: &mut lua_Debug) -> bool {
+        let (what, func, ci) = match what.strip_prefix('>') {
+            Some(what) => (what, self.stack.borrow_mut().pop(), None),
+            None => match &ar.i_ci {
+                Some(ci) => {
+                    let func = LuaValue::Closure(ci.borrow().get_func());
+                    (what, func, Some(ci.clone()))
+                }
+                None => return false,
+            },
+        };
+        let cl = match &func {
+            LuaValue::Closure(c) => Some(c.clone()),
+            _ => None,
+        };
+        // 只有 Lua 函数才有原型上的调试信息
+        let proto = cl
+            .as_ref()
+            .filter(|c| c.borrow().function.is_none())
+            .map(|c| c.borrow().proto.clone());
+        let mut status = true;
+        for option in what.chars() {
+            match option {
+                'S' => match &proto {
+                    Some(p) => {
+                        ar.source = p.source.clone().unwrap_or_else(|| "=?".to_string());
+                        ar.linedefined = p.line_defined as isize;
+                        ar.lastlinedefined = p.last_line_defined as isize;
+                        ar.what = if p.line_defined == 0 { "main" } else { "Lua" }.to_string();
+                        ar.short_src = chunk_id(&ar.source);
+                    }
+                    None => {
+                        ar.source = "=[C]".to_string();
+                        ar.linedefined = -1;
+                        ar.lastlinedefined = -1;
+                        ar.what = "C".to_string();
+                        ar.short_src = chunk_id(&ar.source);
+                    }
+                },
+                'l' => {
+                    ar.currentline = match (&ci, &proto) {
+                        (Some(ci), Some(p)) => current_line(p, ci.borrow().get_pc()),
+                        _ => -1,
+                    }
+                }
+                'u' => {
+                    ar.nups = cl.as_ref().map_or(0, |c| c.borrow().upvalues.len());
+                    match &proto {
+                        Some(p) => {
+                            ar.isvararg = p.is_vararg != 0;
+                            ar.nparams = p.num_params as usize;
+                        }
+                        None => {
+                            ar.isvararg = true;
+                            ar.nparams = 0;
+                        }
+                    }
+                }
+                // 还没有实现尾调用，每次调用都有自己的 CallInfo
+                't' => ar.istailcall = false,
+                'n' => {
+                    let depth = self.ci_depth();
+                    let name = ci
+                        .as_ref()
+                        .and_then(|ci| self.ci_index(ci))
+                        .and_then(|i| self.func_name_at((depth - 1 - i) as isize));
+                    match name {
+                        Some((name, namewhat)) => {
+                            ar.name = Some(name);
+                            ar.namewhat = namewhat.to_string();
+                        }
+                        None => {
+                            ar.name = None;
+                            ar.namewhat = String::new();
+                        }
+                    }
+                }
+                'L' | 'f' => (),
+                _ => status = false,
+            }
+        }
+        if what.contains('f') {
+            self.push(func);
+        }
+        if what.contains('L') {
+            // 参考 collectvalidlines：有效行号作为键，值都是 true
+            let lines = match &proto {
+                Some(p) => {
+                    let t = LuaValue::new_table(0, p.line_info.len());
+                    if let LuaValue::Table(lines) = &t {
+                        for line in &p.line_info {
+                            let key = LuaValue::Integer(*line as i64);
+                            lines.borrow_mut().set(key, LuaValue::Boolean(true));
+                        }
+                    }
+                    t
+                }
+                None => LuaValue::Nil,
+            };
+            self.push(lines);
+        }
+        status
+    }
+
+    // 栈顶是 Lua 函数时返回它第 n 个参数的名字
+    pub(crate) fn param_name(&self, n: isize) -> Option<String> {
+        let top = self.stack.borrow().get_top();
+        match self.stack.borrow().get(top - 1) {
+            LuaValue::Closure(c) if c.borrow().function.is_none() => {
+                local_name(&c.borrow().proto, n - 1, 0)
+            }
+            _ => None,
+        }
+    }
+
+    // 参考 findlocal：返回第 n 个局部变量在栈上的位置和名字，没有名字但在函数的栈帧中的
+    // 位置叫做 "(*temporary)"。还不支持可变参数，n 为负数时总是返回 None
+    pub(crate) fn find_local(
+        &self,
+        ci: &Rc<RefCell<CallInfo>>,
+        n: isize,
+    ) -> Option<(isize, String)> {
+        let (base, name) = {
+            let ci = ci.borrow();
+            let func = ci.get_func();
+            let func = func.borrow();
+            let name = if func.function.is_none() {
+                local_name(&func.proto, n - 1, ci.get_pc().saturating_sub(1))
+            } else {
+                None
+            };
+            (ci.get_base(), name)
+        };
+        let name = match name {
+            Some(name) => name,
+            None => {
+                // 栈帧的上限是下一层调用的函数所在的位置，最内层的是栈顶
+                let index = self.ci_index(ci)?;
+                let limit = match self.base_ci.borrow().get(index + 1) {
+                    Some(next) => next.borrow().get_base(),
+                    None => self.stack.borrow().get_top(),
+                };
+                if n > 0 && limit - (base + 1) >= n {
+                    "(*temporary)".to_string()
+                } else {
+                    return None;
+                }
+            }
+        };
+        Some((base + n, name))
+    }
+
+    // index 处的闭包有第 n 个上值时返回闭包和上值的名字，原生函数的上值没有名字，用空字符串表示
+    pub(crate) fn aux_upvalue(
+        &self,
+        index: isize,
+        n: isize,
+    ) -> Option<(Rc<RefCell<LuaClosure>>, String)> {
+        let c = match self.get(index) {
+            LuaValue::Closure(c) => c,
+            _ => return None,
+        };
+        if n < 1 || n as usize > c.borrow().upvalues.len() {
+            return None;
+        }
+        let name = {
+            let c = c.borrow();
+            if c.function.is_some() {
+                String::new()
+            } else {
+                upvalue_name(&c.proto, n - 1)
+            }
+        };
+        Some((c, name))
+    }
+
+    // 参考 luaD_hook：钩子看到的 level 0 就是触发事件的函数，钩子执行前后栈顶不变
+    pub(crate) fn call_hook(&mut self, event: isize, line: isize) {
+        let func = {
+            let hook = self.hook.borrow();
+            match hook.func {
+                Some(f) if !hook.running && hook.mask & (1 << event) != 0 => f,
+                _ => return,
+            }
+        };
+        let mut ar = lua_Debug {
+            event,
+            currentline: line,
+            i_ci: Some(self.current_ci()),
+            ..Default::default()
+        };
+        let top = self.stack.borrow().get_top();
+        self.hook.borrow_mut().running = true;
+        func(Rc::new(RefCell::new(self.clone())), &mut ar);
+        self.hook.borrow_mut().running = false;
+        self.set_top(&top);
+    }
+
+    // 参考 luaG_traceexec：在执行每条指令之前调用，这时 pc 已经指向下一条指令。
+    // 进入函数、向回跳转或者进入新的一行时触发行钩子
+    pub(crate) fn trace_exec(&mut self) {
+        let (mask, counthook) = {
+            let mut hook = self.hook.borrow_mut();
+            if hook.mask & (LUA_MASKLINE | LUA_MASKCOUNT) == 0 {
+                return;
+            }
+            hook.count -= 1;
+            let counthook = hook.count == 0 && hook.mask & LUA_MASKCOUNT != 0;
+            if counthook {
+                hook.count = hook.base_count;
+            }
+            (hook.mask, counthook)
+        };
+        if counthook {
+            self.call_hook(LUA_HOOKCOUNT, -1);
+        }
+        let ci = self.current_ci();
+        let pc = ci.borrow().get_pc();
+        if mask & LUA_MASKLINE != 0 {
+            let func = ci.borrow().get_func();
+            let p = func.borrow().proto.clone();
+            let oldpc = self.hook.borrow().oldpc;
+            let newline = current_line(&p, pc);
+            if pc == 1 || pc <= oldpc || newline != current_line(&p, oldpc) {
+                self.call_hook(LUA_HOOKLINE, newline);
+            }
+        }
+        self.hook.borrow_mut().oldpc = pc;
+    }
+
+    // 参考 luaL_where：level 层的函数是 Lua 函数并且有行号信息时返回 "chunkname:currentline: "
+    pub(crate) fn location_at(&self, level: isize) -> String {
+        let ci = match self.ci_at(level) {
+            Some(ci) => ci,
+            None => return String::new(),
+        };
+        let ci = ci.borrow();
+        let func = ci.get_func();
+        let func = func.borrow();
+        if func.function.is_some() {
+            return String::new();
+        }
+        let p = &func.proto;
+        match p.line_info.get(ci.get_pc().saturating_sub(1)) {
+            Some(line) => {
+                let source = p.source.as_deref().unwrap_or("=?");
+                format!("{}:{}: ", chunk_id(source), line)
+            }
+            None => String::new(),
+        }
+    }
+}
+
+// 参考 luaO_chunkid：'=' 开头的原样使用，'@' 开头的是文件名，过长时保留结尾，
+// 其他的是代码本身，只取第一行
+pub(crate) fn chunk_id(source: &str) -> String {
+    const LUA_IDSIZE: usize = 60;
+    if let Some(s) = source.strip_prefix('=') {
+        s.chars().take(LUA_IDSIZE - 1).collect()
+    } else if let Some(s) = source.strip_prefix('@') {
+        let n = s.chars().count();
+        if n < LUA_IDSIZE {
+            s.to_string()
+        } else {
+            let tail: String = s.chars().skip(n - (LUA_IDSIZE - 4)).collect();
+            format!("...{}", tail)
+        }
+    } else {
+        // 留出 [string "..."] 的位置
+        let max = LUA_IDSIZE - 15;
+        let first = source.lines().next().unwrap_or("");
+        if first.len() == source.len() && first.chars().count() <= max {
+            format!("[string \"{}\"]", first)
+        } else {
+            let first: String = first.chars().take(max).collect();
+            format!("[string \"{}...\"]", first)
+        }
+    }
+}
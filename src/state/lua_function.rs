@@ -1,34 +1,43 @@
-use crate::api::lua_CFunction;
+use crate::api::lua_State;
 use crate::chunk::binary::Prototype;
 use crate::state::LuaValue;
 use nom::lib::std::fmt::{Debug, Formatter};
 use nom::lib::std::hash::Hash;
+use std::cell::RefCell;
 use std::fmt;
 use std::hash::Hasher;
 use std::rc::Rc;
 
+// 原生函数：普通的 lua_CFunction 和捕获了 Rust 状态的闭包统一用它表示
+pub type NativeFunction = Rc<dyn Fn(lua_State) -> usize>;
+
 #[derive(Clone)]
 pub struct LuaClosure {
     pub proto: Rc<Prototype>,
-    pub function: Option<lua_CFunction>,
-    pub upvalues: Vec<LuaValue>,
+    pub function: Option<NativeFunction>,
+    // 每个上值是一个共享的单元，debug.upvaluejoin 之后两个闭包引用同一个单元
+    pub upvalues: Vec<Rc<RefCell<LuaValue>>>,
 }
 
 impl PartialEq for LuaClosure {
-    fn eq(&self, _other: &Self) -> bool {
-        unimplemented!()
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
     }
 }
 
 impl Hash for LuaClosure {
-    fn hash<H: Hasher>(&self, _state: &mut H) {
-        unimplemented!()
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self as *const LuaClosure).hash(state)
     }
 }
 
 impl Debug for LuaClosure {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        unimplemented!()
+        if self.function.is_some() {
+            write!(f, "function: builtin: {:p}", self)
+        } else {
+            write!(f, "function: {:p}", self)
+        }
     }
 }
 
@@ -41,11 +50,14 @@ impl LuaClosure {
         }
     }
 
-    pub fn new_native(func: lua_CFunction) -> LuaClosure {
+    pub fn new_native(func: NativeFunction, upvalues: Vec<LuaValue>) -> LuaClosure {
         LuaClosure {
             proto: Rc::new(Prototype::new()),
             function: Some(func),
-            upvalues: Vec::new(),
+            upvalues: upvalues
+                .into_iter()
+                .map(|v| Rc::new(RefCell::new(v)))
+                .collect(),
         }
     }
 
@@ -0,0 +1,241 @@
+use crate::state::{LuaTable, LuaValue};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+// 对象的回收由 Rc 引用计数完成，这里的回收器只负责 Lua 语义层面的工作：
+// 从根集合出发标记可达对象，清理弱表中不可达的项，挑出需要调用 __gc 的对象
+
+pub struct GcState {
+    pub running: bool,
+    // 设置元表时带有 __gc 字段的对象，按标记顺序排列
+    pub finobj: Vec<LuaValue>,
+    // 已经不可达、等待调用 __gc 的对象
+    pub tobefnz: Vec<LuaValue>,
+}
+
+impl GcState {
+    pub fn new() -> GcState {
+        GcState {
+            running: true,
+            finobj: Vec::new(),
+            tobefnz: Vec::new(),
+        }
+    }
+
+    // 对应 luaC_checkfinalizer：元表带有 __gc 时把对象登记为需要终结
+    pub fn check_finalizer(&mut self, obj: &LuaValue, mt: &Rc<RefCell<LuaTable>>) {
+        let id = match obj.gc_id() {
+            Some(id) => id,
+            None => return,
+        };
+        if mt
+            .borrow()
+            .get_hash(LuaValue::String("__gc".into()))
+            .is_nil()
+        {
+            return;
+        }
+        if self.finobj.iter().any(|o| o.gc_id() == Some(id)) {
+            return;
+        }
+        self.finobj.push(obj.clone());
+    }
+
+    // lua_close 时所有登记过的对象都要终结，后标记的先终结
+    pub fn separate_all(&mut self) {
+        while let Some(obj) = self.finobj.pop() {
+            self.tobefnz.push(obj);
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum WeakMode {
+    Strong,
+    Values,
+    Keys,
+    All,
+}
+
+pub struct Collector {
+    marked: HashSet<usize>,
+    gray: Vec<LuaValue>,
+    weak: Vec<Rc<RefCell<LuaTable>>>,
+    ephemeron: Vec<Rc<RefCell<LuaTable>>>,
+    allweak: Vec<Rc<RefCell<LuaTable>>>,
+}
+
+impl Collector {
+    pub fn new() -> Collector {
+        Collector {
+            marked: HashSet::new(),
+            gray: Vec::new(),
+            weak: Vec::new(),
+            ephemeron: Vec::new(),
+            allweak: Vec::new(),
+        }
+    }
+
+    pub fn is_marked(&self, value: &LuaValue) -> bool {
+        match value.gc_id() {
+            Some(id) => self.marked.contains(&id),
+            // 字符串、数字等不是可回收对象，永远不会从弱表中移除
+            None => true,
+        }
+    }
+
+    pub fn mark(&mut self, value: &LuaValue) {
+        if let Some(id) = value.gc_id() {
+            if self.marked.insert(id) {
+                self.gray.push(value.clone());
+            }
+        }
+    }
+
+    fn mark_table(&mut self, table: &Rc<RefCell<LuaTable>>) {
+        self.mark(&LuaValue::Table(table.clone()))
+    }
+
+    pub fn propagate(&mut self) {
+        while let Some(value) = self.gray.pop() {
+            match value {
+                LuaValue::Table(t) => self.traverse_table(&t),
+                LuaValue::Closure(c) => {
+                    let upvalues: Vec<LuaValue> = c
+                        .borrow()
+                        .upvalues
+                        .iter()
+                        .map(|cell| cell.borrow().clone())
+                        .collect();
+                    for v in &upvalues {
+                        self.mark(v);
+                    }
+                }
+                LuaValue::UserData(u) => {
+                    let (mt, user_value) = {
+                        let u = u.borrow();
+                        (u.metatable.clone(), u.user_value.clone())
+                    };
+                    if let Some(mt) = &mt {
+                        self.mark_table(mt);
+                    }
+                    self.mark(&user_value);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn traverse_table(&mut self, table: &Rc<RefCell<LuaTable>>) {
+        let (mt, entries) = {
+            let t = table.borrow();
+            (t.metatable.clone(), t.entries())
+        };
+        if let Some(mt) = &mt {
+            self.mark_table(mt);
+        }
+        match weak_mode(&mt) {
+            WeakMode::Strong => {
+                for (k, v) in &entries {
+                    self.mark(k);
+                    self.mark(v);
+                }
+            }
+            WeakMode::Values => {
+                for (k, _) in &entries {
+                    self.mark(k);
+                }
+                self.weak.push(table.clone());
+            }
+            WeakMode::Keys => {
+                // ephemeron：只有键可达时值才可达
+                for (k, v) in &entries {
+                    if self.is_marked(k) {
+                        self.mark(v);
+                    }
+                }
+                self.ephemeron.push(table.clone());
+            }
+            WeakMode::All => self.allweak.push(table.clone()),
+        }
+    }
+
+    // 反复遍历 ephemeron 表，直到没有新的值被标记
+    pub fn converge_ephemerons(&mut self) {
+        loop {
+            self.propagate();
+            let mut changed = false;
+            for table in self.ephemeron.clone() {
+                for (k, v) in table.borrow().entries() {
+                    if self.is_marked(&k) && !self.is_marked(&v) {
+                        self.mark(&v);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    fn clear_values(&self, tables: &[Rc<RefCell<LuaTable>>]) {
+        for table in tables {
+            table.borrow_mut().retain(|_, v| self.is_marked(v));
+        }
+    }
+
+    fn clear_keys(&self, tables: &[Rc<RefCell<LuaTable>>]) {
+        for table in tables {
+            table.borrow_mut().retain(|k, _| self.is_marked(k));
+        }
+    }
+
+    // 对应 lgc.c 中的 atomic：调用前根集合必须已经标记完成
+    pub fn atomic(&mut self, gc: &mut GcState) {
+        self.converge_ephemerons();
+        // 被终结的对象在调用 __gc 之前就从弱值表中移除
+        self.clear_values(&self.weak);
+        self.clear_values(&self.allweak);
+        let orig_weak = self.weak.len();
+        let orig_all = self.allweak.len();
+
+        // 不可达的待终结对象按标记的逆序排队，并复活它们引用到的所有对象
+        let mut i = gc.finobj.len();
+        while i > 0 {
+            i -= 1;
+            if !self.is_marked(&gc.finobj[i]) {
+                let obj = gc.finobj.remove(i);
+                gc.tobefnz.push(obj);
+            }
+        }
+        for obj in gc.tobefnz.clone() {
+            self.mark(&obj);
+        }
+        self.converge_ephemerons();
+
+        // 复活的对象要等到下一次回收才从弱键表中移除
+        self.clear_keys(&self.ephemeron);
+        self.clear_keys(&self.allweak);
+        self.clear_values(&self.weak[orig_weak..]);
+        self.clear_values(&self.allweak[orig_all..]);
+    }
+}
+
+fn weak_mode(mt: &Option<Rc<RefCell<LuaTable>>>) -> WeakMode {
+    let mode = match mt {
+        Some(mt) => mt.borrow().get_hash(LuaValue::String("__mode".into())),
+        None => return WeakMode::Strong,
+    };
+    if let LuaValue::String(mode) = mode {
+        match (mode.contains(&b'k'), mode.contains(&b'v')) {
+            (true, true) => WeakMode::All,
+            (true, false) => WeakMode::Keys,
+            (false, true) => WeakMode::Values,
+            (false, false) => WeakMode::Strong,
+        }
+    } else {
+        WeakMode::Strong
+    }
+}
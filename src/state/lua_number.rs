@@ -0,0 +1,273 @@
+use crate::state::LuaValue;
+
+// 字符串和数字之间的转换规则，参考 lobject.c 中的 luaO_str2num 和 tostringbuff
+
+// 参考 l_str2int：十进制溢出时返回 None 交给浮点数处理，十六进制按补码回绕
+fn str_to_int(s: &str) -> Option<i64> {
+    let s = s.trim_matches(is_space);
+    let (neg, digits) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let mut a: i64 = 0;
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        if hex.is_empty() {
+            return None;
+        }
+        for c in hex.chars() {
+            let d = c.to_digit(16)? as i64;
+            a = a.wrapping_mul(16).wrapping_add(d);
+        }
+    } else {
+        if digits.is_empty() {
+            return None;
+        }
+        for c in digits.chars() {
+            let d = c.to_digit(10)? as i64;
+            a = a.checked_mul(10)?.checked_add(d)?;
+        }
+    }
+    Some(if neg { a.wrapping_neg() } else { a })
+}
+
+// 参考 l_str2d：不接受 inf 和 nan
+fn str_to_float(s: &str) -> Option<f64> {
+    if s.contains(&['n', 'N'][..]) {
+        return None;
+    }
+    let s = s.trim_matches(is_space);
+    if s.contains(&['x', 'X'][..]) {
+        return hex_to_float(s);
+    }
+    if !is_decimal(s) {
+        return None;
+    }
+    s.parse::<f64>().ok()
+}
+
+fn is_decimal(s: &str) -> bool {
+    let b = s.as_bytes();
+    let mut i = 0;
+    if i < b.len() && (b[i] == b'+' || b[i] == b'-') {
+        i += 1;
+    }
+    let start = i;
+    while i < b.len() && b[i].is_ascii_digit() {
+        i += 1;
+    }
+    let mut ndigits = i - start;
+    if i < b.len() && b[i] == b'.' {
+        i += 1;
+        let frac = i;
+        while i < b.len() && b[i].is_ascii_digit() {
+            i += 1;
+        }
+        ndigits += i - frac;
+    }
+    if ndigits == 0 {
+        return false;
+    }
+    if i < b.len() && (b[i] == b'e' || b[i] == b'E') {
+        i += 1;
+        if i < b.len() && (b[i] == b'+' || b[i] == b'-') {
+            i += 1;
+        }
+        let exp = i;
+        while i < b.len() && b[i].is_ascii_digit() {
+            i += 1;
+        }
+        if i == exp {
+            return false;
+        }
+    }
+    i == b.len()
+}
+
+// 参考 lua_strx2number：0x 开头，可选的小数部分和以 2 为底的 p 指数
+fn hex_to_float(s: &str) -> Option<f64> {
+    let (neg, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let s = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"))?;
+    let mut chars = s.chars().peekable();
+    let mut r = 0.0f64;
+    let mut e: i64 = 0;
+    let mut any = false;
+    let mut dot = false;
+    while let Some(&c) = chars.peek() {
+        if c == '.' && !dot {
+            dot = true;
+        } else if let Some(d) = c.to_digit(16) {
+            r = r * 16.0 + d as f64;
+            if dot {
+                e -= 4;
+            }
+            any = true;
+        } else {
+            break;
+        }
+        chars.next();
+    }
+    if !any {
+        return None;
+    }
+    if let Some(&c) = chars.peek() {
+        if c == 'p' || c == 'P' {
+            chars.next();
+            let rest: String = chars.collect();
+            let (eneg, digits) = match rest.as_bytes().first() {
+                Some(b'-') => (true, &rest[1..]),
+                Some(b'+') => (false, &rest[1..]),
+                _ => (false, &rest[..]),
+            };
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            // 指数很大时饱和，结果自然溢出为无穷大或者下溢为 0
+            let exp = digits.bytes().fold(0i64, |a, b| {
+                a.saturating_mul(10).saturating_add((b - b'0') as i64)
+            });
+            e = e.saturating_add(if eneg { -exp } else { exp });
+        } else {
+            return None;
+        }
+    }
+    let r = ldexp(r, e);
+    Some(if neg { -r } else { r })
+}
+
+// r * 2^e，分段相乘，避免中间结果提前溢出或下溢
+fn ldexp(mut r: f64, mut e: i64) -> f64 {
+    while e > 1000 && r.is_finite() && r != 0.0 {
+        r *= 2f64.powi(1000);
+        e -= 1000;
+    }
+    while e < -1000 && r.is_finite() && r != 0.0 {
+        r *= 2f64.powi(-1000);
+        e += 1000;
+    }
+    if e.abs() > 1000 {
+        return r;
+    }
+    r * 2f64.powi(e as i32)
+}
+
+fn is_space(c: char) -> bool {
+    c == ' ' || ('\t'..='\r').contains(&c)
+}
+
+// 字符串转换为整数或浮点数，格式不合法时返回 None
+pub fn str_to_number(s: &str) -> Option<LuaValue> {
+    match str_to_int(s) {
+        Some(i) => Some(LuaValue::Integer(i)),
+        None => str_to_float(s).map(LuaValue::Number),
+    }
+}
+
+// 浮点数只有数学值恰好是整数时才能转换为整数
+pub fn float_to_integer(n: f64) -> Option<i64> {
+    if n.fract() == 0.0 && (-9223372036854775808.0..9223372036854775808.0).contains(&n) {
+        Some(n as i64)
+    } else {
+        None
+    }
+}
+
+// 等价于 LUAI_NUMFFORMAT "%.14g"，看起来像整数的结果补上 ".0"
+pub fn float_to_string(n: f64) -> String {
+    let s = fmt_g(n, 14);
+    if s.bytes().all(|b| b == b'-' || b.is_ascii_digit()) {
+        s + ".0"
+    } else {
+        s
+    }
+}
+
+// C 语言 printf 的 %.{precision}g
+pub fn fmt_g(n: f64, precision: usize) -> String {
+    if n.is_nan() {
+        return if n.is_sign_negative() { "-nan" } else { "nan" }.to_string();
+    }
+    if n.is_infinite() {
+        return if n < 0.0 { "-inf" } else { "inf" }.to_string();
+    }
+    let p = if precision == 0 { 1 } else { precision };
+    let e = format!("{:.*e}", p - 1, n);
+    let (mantissa, exp) = e.split_at(e.find('e').unwrap());
+    let x: i32 = exp[1..].parse().unwrap();
+    if x < -4 || x >= p as i32 {
+        let mantissa = strip_zeros(mantissa);
+        let sign = if x < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", mantissa, sign, x.abs())
+    } else {
+        strip_zeros(&format!("{:.*}", (p as i32 - 1 - x) as usize, n)).to_string()
+    }
+}
+
+fn strip_zeros(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn str_to_number_test() {
+        assert_eq!(str_to_number(" 10 "), Some(LuaValue::Integer(10)));
+        assert_eq!(str_to_number("-0x10"), Some(LuaValue::Integer(-16)));
+        assert_eq!(
+            str_to_number("0xffffffffffffffff"),
+            Some(LuaValue::Integer(-1))
+        );
+        assert_eq!(str_to_number("1e2"), Some(LuaValue::Number(100.0)));
+        assert_eq!(str_to_number(".5"), Some(LuaValue::Number(0.5)));
+        assert_eq!(str_to_number("0x1p4"), Some(LuaValue::Number(16.0)));
+        assert_eq!(str_to_number("0x.8"), Some(LuaValue::Number(0.5)));
+        assert_eq!(
+            str_to_number("9223372036854775808"),
+            Some(LuaValue::Number(9223372036854775808.0))
+        );
+        assert_eq!(
+            str_to_number("0x1p99999999999"),
+            Some(LuaValue::Number(f64::INFINITY))
+        );
+        assert_eq!(
+            str_to_number("0x1p-99999999999"),
+            Some(LuaValue::Number(0.0))
+        );
+        assert_eq!(str_to_number("0x1p-1074"), Some(LuaValue::Number(5e-324)));
+        assert_eq!(
+            str_to_number("0x1000p-1080"),
+            Some(LuaValue::Number(64.0 * 5e-324))
+        );
+        assert_eq!(str_to_number("inf"), None);
+        assert_eq!(str_to_number("1e"), None);
+        assert_eq!(str_to_number("0x"), None);
+        assert_eq!(str_to_number(""), None);
+        assert_eq!(str_to_number("1 2"), None);
+    }
+
+    #[test]
+    fn float_to_string_test() {
+        assert_eq!(float_to_string(1.0), "1.0");
+        assert_eq!(float_to_string(-0.5), "-0.5");
+        assert_eq!(float_to_string(1e15), "1e+15");
+        assert_eq!(float_to_string(1e100), "1e+100");
+        assert_eq!(float_to_string(0.1), "0.1");
+        assert_eq!(float_to_string(1.0 / 3.0), "0.33333333333333");
+        assert_eq!(float_to_string(1e-5), "1e-05");
+        assert_eq!(float_to_string(123456.789), "123456.789");
+        assert_eq!(float_to_string(f64::INFINITY), "inf");
+    }
+}
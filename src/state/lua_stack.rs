@@ -1,3 +1,4 @@
+use crate::api::LUAI_MAXSTACK;
 use crate::state::lua_value::LuaValue;
 
 #[derive(Clone)]
@@ -17,22 +18,15 @@ impl LuaStack {
         self.top
     }
 
+    // 对象靠 Rc 释放，栈顶以上的槽位要清空，否则会一直持有已经出栈的值
     pub fn set_top(&mut self, index: &isize) {
         let idx = *index;
-        if idx >= self.top {
-            let size = idx - self.top;
-            for _ in 0..size {
-                self.stack.push(LuaValue::Nil);
-                self.top += 1;
-            }
-        } else {
-            let mut i = self.top;
-            while i > idx {
-                self.stack.pop();
-                i -= 1;
-                self.top -= 1;
-            }
-        };
+        while self.top < idx {
+            self.push(LuaValue::Nil);
+        }
+        while self.top > idx {
+            self.pop();
+        }
     }
 
     pub fn set_size(&mut self, index: isize) {
@@ -46,17 +40,41 @@ impl LuaStack {
     }
 
     pub fn push(&mut self, value: LuaValue) {
-        self.stack.push(value);
+        if (self.top as usize) < self.stack.len() {
+            self.stack[self.top as usize] = value;
+        } else {
+            self.stack.push(value);
+        }
         self.top += 1;
     }
 
     pub fn pop(&mut self) -> LuaValue {
         self.top -= 1;
-        self.stack.pop().unwrap()
+        std::mem::replace(&mut self.stack[self.top as usize], LuaValue::Nil)
+    }
+
+    // 保证还能再放 n 个值，超过 LUAI_MAXSTACK 时返回 false
+    pub fn check(&mut self, n: usize) -> bool {
+        let top = self.top as usize;
+        if top + n > LUAI_MAXSTACK {
+            return false;
+        }
+        if top + n > self.stack.len() {
+            self.stack.reserve(top + n - self.stack.len());
+        }
+        true
+    }
+
+    pub fn reverse(&mut self, from: isize, to: isize) {
+        self.stack[from as usize..=to as usize].reverse();
     }
 
+    // 超出已分配空间的位置视为 nil
     pub fn get(&self, index: isize) -> LuaValue {
-        self.stack[index as usize].clone()
+        match self.stack.get(index as usize) {
+            Some(v) => v.clone(),
+            None => LuaValue::Nil,
+        }
     }
 
     pub fn set(&mut self, index: isize, value: LuaValue) {
@@ -1,9 +1,13 @@
 use crate::api::*;
 use crate::chunk::binary::{Constant, ConstantValue, Prototype};
-use crate::state::{LuaClosure, LuaStack, LuaTable, LuaValue};
+use crate::state::lua_debug::HookState;
+use crate::state::lua_gc::{Collector, GcState};
+use crate::state::{str_to_number, LuaClosure, LuaStack, LuaTable, LuaValue, NativeFunction};
+use crate::vm::opcodes::OP_RETURN;
 use crate::vm::Instruction;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::Once;
 
 pub struct CallInfo {
     func: Rc<RefCell<LuaClosure>>,
@@ -35,6 +39,10 @@ impl CallInfo {
         }
     }
 
+    pub fn add_pc(&mut self, n: isize) {
+        self.pc = (self.pc as isize + n) as usize;
+    }
+
     pub fn get_const(&self, index: isize) -> Constant {
         self.func.borrow().proto.constants[index as usize].clone()
     }
@@ -50,21 +58,53 @@ impl CallInfo {
     pub fn get_top(&self) -> isize {
         self.top.clone()
     }
+
+    pub fn get_pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn get_func(&self) -> Rc<RefCell<LuaClosure>> {
+        self.func.clone()
+    }
 }
 
+// LuaState 的各部分都是共享的，clone 得到的是同一个虚机的另一个句柄，
+// 原生函数通过它直接操作真实的栈和调用链
 #[derive(Clone)]
 pub struct LuaState {
     registry: LuaValue,
-    pub stack: LuaStack,
-    base_ci: Vec<Rc<RefCell<CallInfo>>>,
-    ci: isize,
+    pub stack: Rc<RefCell<LuaStack>>,
+    pub(crate) base_ci: Rc<RefCell<Vec<Rc<RefCell<CallInfo>>>>>,
+    gc: Rc<RefCell<GcState>>,
+    // 表和 userdata 之外的值按类型共享元表
+    type_metatables: Rc<RefCell<Vec<LuaValue>>>,
+    pub(crate) hook: Rc<RefCell<HookState>>,
+}
+
+static PANIC_HOOK: Once = Once::new();
+
+// LuaError 是正常的错误展开，不需要默认钩子打印的 panic 信息；其他 panic 交给原来的钩子。
+// 这会替换整个进程的 panic 钩子，所以不会自动安装，由嵌入的程序决定是否调用
+pub fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let prev = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if info.payload().downcast_ref::<LuaError>().is_none() {
+                prev(info);
+            }
+        }));
+    });
 }
 
 impl LuaState {
     pub fn new() -> LuaState {
         // 全局变量表
-        let registry = LuaValue::new_table(3, 0);
+        let registry = LuaValue::new_table(LUA_RIDX_LAST as usize, 0);
+        let stack = Rc::new(RefCell::new(LuaStack::new(30)));
         if let LuaValue::Table(t) = &registry {
+            // 还没有协程类型，主线程用指向它的栈的轻量 userdata 表示
+            let main_thread = LuaValue::LightUserData(Rc::as_ptr(&stack) as usize);
+            t.borrow_mut().set_array(LUA_RIDX_MAINTHREAD, main_thread);
             let global = LuaValue::new_table(0, 0);
             t.borrow_mut().set_array(LUA_RIDX_GLOBALS, global);
         }
@@ -74,33 +114,79 @@ impl LuaState {
         ci.top = 0;
         LuaState {
             registry,
-            stack: LuaStack::new(30),
-            // base: 0,
-            base_ci: vec![Rc::new(RefCell::new(ci))],
-            ci: 0,
+            stack,
+            base_ci: Rc::new(RefCell::new(vec![Rc::new(RefCell::new(ci))])),
+            gc: Rc::new(RefCell::new(GcState::new())),
+            type_metatables: Rc::new(RefCell::new(vec![LuaValue::Nil; LUA_NUMTAGS as usize])),
+            hook: Rc::new(RefCell::new(HookState::default())),
+        }
+    }
+
+    // 注册表中的全局变量表
+    fn globals(&self) -> LuaValue {
+        match &self.registry {
+            LuaValue::Table(reg) => reg.borrow().get_array(LUA_RIDX_GLOBALS),
+            _ => LuaValue::Nil,
+        }
+    }
+
+    // 参考 luaT_gettmbyobj
+    pub fn metatable_of(&self, v: &LuaValue) -> Option<Rc<RefCell<LuaTable>>> {
+        match v {
+            LuaValue::Table(_) | LuaValue::UserData(_) => v.metatable(),
+            _ => match &self.type_metatables.borrow()[v.lua_type() as usize] {
+                LuaValue::Table(mt) => Some(mt.clone()),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn metafield(&self, v: &LuaValue, name: &str) -> LuaValue {
+        match self.metatable_of(v) {
+            Some(mt) => mt.borrow().get_hash(LuaValue::String(name.into())),
+            None => LuaValue::Nil,
         }
     }
 
     pub fn set_top(&mut self, index: &isize) {
-        self.stack.set_top(index)
+        self.stack.borrow_mut().set_top(index)
+    }
+
+    // 当前正在执行的函数的 CallInfo
+    pub fn current_ci(&self) -> Rc<RefCell<CallInfo>> {
+        self.base_ci.borrow().last().unwrap().clone()
+    }
+
+    pub fn ci_depth(&self) -> usize {
+        self.base_ci.borrow().len()
+    }
+
+    // level 0 是当前函数，1 是调用它的函数，依此类推；最底层的 ci 不对应任何函数
+    pub(crate) fn ci_at(&self, level: isize) -> Option<Rc<RefCell<CallInfo>>> {
+        let depth = self.ci_depth() as isize;
+        if level < 0 || level >= depth - 1 {
+            return None;
+        }
+        Some(self.base_ci.borrow()[(depth - 1 - level) as usize].clone())
     }
 
     pub fn fetch(&mut self) -> Option<u32> {
-        if self.base_ci.len() == 0 {
+        if self.ci_depth() == 0 {
             return None;
         }
-        self.base_ci[self.ci as usize].borrow_mut().fetch()
+        self.current_ci().borrow_mut().fetch()
+    }
+
+    pub fn add_pc(&mut self, n: isize) {
+        self.current_ci().borrow_mut().add_pc(n)
     }
 
     pub fn get_const(&mut self, index: isize) -> LuaValue {
-        let c = self.base_ci[self.ci as usize]
-            .borrow()
-            .get_const(index)
-            .clone();
+        let c = self.current_ci().borrow().get_const(index).clone();
         let v = match &c.const_value {
             ConstantValue::Nil => LuaValue::Nil,
             ConstantValue::Integer(v) => LuaValue::Integer(*v),
-            ConstantValue::ShortStr(v) => LuaValue::String(v.clone().value),
+            ConstantValue::ShortStr(v) => LuaValue::String(v.value.clone().into_bytes()),
             _ => {
                 dbg!(&c.const_value);
                 unimplemented!()
@@ -113,17 +199,16 @@ impl LuaState {
         if index > 0xFF {
             self.get_const(index - 0xFF - 1)
         } else {
-            let v = self.stack.get(self.get_base() + index + 1).clone();
-            v
+            self.get_register(index)
         }
     }
 
     pub fn get_register(&self, index: isize) -> LuaValue {
-        self.stack.get(self.get_base() + index + 1)
+        self.stack.borrow().get(self.get_base() + index + 1)
     }
 
     pub fn get_value(&self, index: isize) -> LuaValue {
-        self.stack.get(self.get_base() + index)
+        self.stack.borrow().get(self.get_base() + index)
     }
 
     pub fn load_proto(&mut self, proto: Rc<Prototype>) -> LuaValue {
@@ -144,7 +229,7 @@ impl LuaState {
                     } else {
                         self.get_rk(proto.upvalues[i].idx.clone() as isize)
                     };
-                    closure.upvalues.push(v);
+                    closure.upvalues.push(Rc::new(RefCell::new(v)));
                 }
             }
         }
@@ -153,97 +238,269 @@ impl LuaState {
     }
 
     pub fn get_subproto(&self, index: isize) -> Rc<Prototype> {
-        self.base_ci[self.ci as usize]
-            .borrow()
-            .load_proto(index)
-            .clone()
-    }
-
-    pub fn create_table(&mut self, array_size: isize, hash_size: isize) -> LuaValue {
-        LuaValue::Table(Rc::new(RefCell::new(LuaTable::new(
-            array_size as usize,
-            hash_size as usize,
-        ))))
+        self.current_ci().borrow().load_proto(index).clone()
     }
 
     pub fn set_register(&mut self, index: isize, value: LuaValue) {
-        self.stack.set(self.get_base() + index + 1, value);
+        let base = self.get_base();
+        self.stack.borrow_mut().set(base + index + 1, value);
     }
 
     pub fn set_value(&mut self, index: isize, value: LuaValue) {
-        self.stack.set(self.get_base() + index, value);
+        let base = self.get_base();
+        self.stack.borrow_mut().set(base + index, value);
     }
 
     pub fn get_upvalue(&self, index: isize) -> LuaValue {
-        let ci = self.base_ci[self.ci as usize].clone();
-        let x = ci.borrow().func.borrow().upvalues[index as usize].clone();
+        let ci = self.current_ci();
+        let x = ci.borrow().func.borrow().upvalues[index as usize]
+            .borrow()
+            .clone();
         x
     }
 
-    pub fn precall(&mut self, a: isize, b: isize, c: isize) {
-        if let LuaValue::Closure(func) = self.get_value(a) {
-            if func.borrow().function.is_some() {
-                let mut ci = CallInfo::new(func.clone(), self.get_base() + a + 1);
-                ci.nresults = c - 1;
-                self.base_ci.push(Rc::new(RefCell::new(ci)));
-                self.ci += 1;
-                func.borrow().function.unwrap()(Rc::new(RefCell::new(self.clone())));
-                let argc = b - 1;
-                for _ in 0..argc {
-                    self.stack.pop();
-                }
-                self.base_ci.pop();
-                self.ci -= 1;
-                return;
+    // OP_CALL：R(A) 是被调用的函数，B-1 个参数（B 为 0 时参数一直到栈顶），
+    // 需要 C-1 个返回值（C 为 0 时即 LUA_MULTRET）
+    pub fn call_register(&mut self, a: isize, b: isize, c: isize) {
+        let func_idx = self.get_base() + a + 1;
+        if b != 0 {
+            self.set_top(&(func_idx + b));
+        }
+        self.precall(func_idx, c - 1);
+    }
+
+    // OP_RETURN：返回 R(A) 开始的 B-1 个值（B 为 0 时一直到栈顶）
+    pub fn return_register(&mut self, a: isize, b: isize) {
+        let first = self.get_base() + a + 1;
+        let n = if b != 0 {
+            b - 1
+        } else {
+            self.stack.borrow().get_top() - first
+        };
+        self.postcall(first, n);
+    }
+
+    // 参考 luaD_precall：func_idx 是函数在栈上的绝对位置，参数紧随其后直到栈顶。
+    // 原生函数直接执行完毕并返回 true；Lua 函数只压入新的 CallInfo，返回 false，
+    // 由执行循环继续取指执行
+    pub fn precall(&mut self, func_idx: isize, nresults: isize) -> bool {
+        let value = self.stack.borrow().get(func_idx);
+        let func = match value {
+            LuaValue::Closure(func) => func,
+            v => self.runtime_error(format!("attempt to call a {} value", v.type_name())),
+        };
+        let native = func.borrow().function.clone();
+        match native {
+            Some(f) => {
+                let mut ci = CallInfo::new(func.clone(), func_idx);
+                ci.top = self.stack.borrow().get_top() + LUA_MINSTACK as isize;
+                ci.nresults = nresults;
+                self.base_ci.borrow_mut().push(Rc::new(RefCell::new(ci)));
+                self.call_hook(LUA_HOOKCALL, -1);
+                let n = f(Rc::new(RefCell::new(self.clone()))) as isize;
+                let first = self.stack.borrow().get_top() - n;
+                self.postcall(first, n);
+                true
+            }
+            None => {
+                let mut ci = CallInfo::new(func.clone(), func_idx);
+                ci.nresults = nresults;
+                let top = ci.get_top();
+                self.set_top(&top);
+                self.base_ci.borrow_mut().push(Rc::new(RefCell::new(ci)));
+                self.call_hook(LUA_HOOKCALL, -1);
+                false
             }
-            let mut ci = CallInfo::new(func.clone(), self.get_base() + a + 1);
-            ci.nresults = c - 1;
-            // self.base = ci.get_base();
-            let top = ci.get_top();
-            self.set_top(&top);
-            self.base_ci.push(Rc::new(RefCell::new(ci)));
-            self.ci += 1;
         }
     }
 
-    pub fn postcall(&mut self, a: isize, b: isize, _c: isize) {
-        if b == 1 {
+    // 参考 luaD_poscall：把 first 开始的 n 个返回值移动到函数原来所在的位置，
+    // 按调用者需要的个数补 nil 或截断
+    pub fn postcall(&mut self, first: isize, n: isize) {
+        // 返回钩子看到的 level 0 还是正在返回的函数
+        self.call_hook(LUA_HOOKRET, -1);
+        let ci = self.base_ci.borrow_mut().pop().unwrap();
+        if self.is_lua_frame() {
+            self.hook.borrow_mut().oldpc = self.current_ci().borrow().get_pc();
+        }
+        // base 是闭包在栈的位置索引，也是第一个返回值的目标位置
+        let res = ci.borrow().get_base();
+        let wanted = ci.borrow().nresults;
+        let count = if wanted == LUA_MULTRET || n < wanted {
+            n
+        } else {
+            wanted
+        };
+        {
+            let mut stack = self.stack.borrow_mut();
+            for i in 0..count {
+                let v = stack.get(first + i);
+                stack.set(res + i, v);
+            }
+            stack.set_top(&(res + count));
+        }
+        if wanted == LUA_MULTRET {
             return;
         }
+        self.set_top(&(res + wanted));
+        // 回到 Lua 函数时恢复它的栈帧大小
+        if self.is_lua_frame() {
+            let top = self.current_ci().borrow().get_top();
+            if top > res + wanted {
+                self.set_top(&top);
+            }
+        }
+    }
 
-        // base 是闭包在栈的位置索引，返回值第一个值的位置
-        let mut new_top = self.get_base();
+    pub(crate) fn is_lua_frame(&self) -> bool {
+        self.ci_depth() > 1 && self.current_ci().borrow().func.borrow().function.is_none()
+    }
 
-        if b > 1 {
-            // 返回值个数
-            let count = b - 1;
-            // 返回值起始位置
-            let start = a;
-            // 返回值目标位置
-            let index = -1;
-            for i in 0..count {
-                self.set_register(index + i, self.get_register(start + i))
+    // 写入栈上的位置或当前原生函数的上值
+    fn replace(&mut self, index: isize, value: LuaValue) {
+        if index < LUA_REGISTRYINDEX {
+            let n = (LUA_REGISTRYINDEX - index - 1) as usize;
+            let ci = self.current_ci();
+            let func = ci.borrow().func.clone();
+            let func = func.borrow();
+            if let Some(cell) = func.upvalues.get(n) {
+                *cell.borrow_mut() = value;
             }
-            new_top += count;
+        } else if index != LUA_REGISTRYINDEX {
+            self.set_value(index, value);
         }
+    }
 
-        let _ci = self.base_ci.pop().unwrap();
-        self.ci -= 1;
+    // 参考 luaV_finishget：表中没有的键沿着 __index 链查找
+    pub fn index_value(&mut self, t: LuaValue, key: LuaValue) -> LuaValue {
+        let mut t = t;
+        for _ in 0..MAXTAGLOOP {
+            let tm = match &t {
+                LuaValue::Table(table) => {
+                    let v = table.borrow().get(key.clone());
+                    if !v.is_nil() {
+                        return v;
+                    }
+                    match self.metafield(&t, "__index") {
+                        LuaValue::Nil => return LuaValue::Nil,
+                        tm => tm,
+                    }
+                }
+                _ => match self.metafield(&t, "__index") {
+                    LuaValue::Nil => {
+                        self.runtime_error(format!("attempt to index a {} value", t.type_name()))
+                    }
+                    tm => tm,
+                },
+            };
+            if let LuaValue::Closure(_) = tm {
+                return self.call_metamethod(tm, t, key, None);
+            }
+            t = tm;
+        }
+        self.runtime_error("'__index' chain too long; possible loop".to_string())
+    }
 
-        let wanted = self.base_ci[self.ci as usize].borrow().top;
-        if new_top < wanted {
-            new_top = wanted
+    // 参考 luaV_finishset：表中不存在的键交给 __newindex 处理
+    pub fn new_index(&mut self, t: LuaValue, key: LuaValue, value: LuaValue) {
+        let mut t = t;
+        for _ in 0..MAXTAGLOOP {
+            let tm = match &t {
+                LuaValue::Table(table) => {
+                    let exists = !table.borrow().get(key.clone()).is_nil();
+                    let tm = self.metafield(&t, "__newindex");
+                    if exists || tm.is_nil() {
+                        self.raw_set_value(table, key, value);
+                        return;
+                    }
+                    tm
+                }
+                _ => match self.metafield(&t, "__newindex") {
+                    LuaValue::Nil => {
+                        self.runtime_error(format!("attempt to index a {} value", t.type_name()))
+                    }
+                    tm => tm,
+                },
+            };
+            if let LuaValue::Closure(_) = tm {
+                self.call_metamethod(tm, t, key, Some(value));
+                return;
+            }
+            t = tm;
         }
-        self.set_top(&new_top);
+        self.runtime_error("'__newindex' chain too long; possible loop".to_string())
+    }
+
+    fn raw_set_value(&mut self, t: &Rc<RefCell<LuaTable>>, key: LuaValue, value: LuaValue) {
+        match &key {
+            LuaValue::Nil => self.runtime_error("index is nil".to_string()),
+            LuaValue::Number(n) if n.is_nan() => self.runtime_error("index is NaN".to_string()),
+            _ => t.borrow_mut().set(key, value),
+        }
+    }
+
+    // 调用 __index/__newindex 形式的元方法，有第三个参数时不需要返回值
+    pub(crate) fn call_metamethod(
+        &mut self,
+        tm: LuaValue,
+        t: LuaValue,
+        key: LuaValue,
+        value: Option<LuaValue>,
+    ) -> LuaValue {
+        self.push(tm);
+        self.push(t);
+        self.push(key);
+        match value {
+            Some(v) => {
+                self.push(v);
+                self.call_function(3, 0, &mut None);
+                LuaValue::Nil
+            }
+            None => {
+                self.call_function(2, 1, &mut None);
+                self.stack.borrow_mut().pop()
+            }
+        }
+    }
+
+    fn check_table(&mut self, index: isize) -> Rc<RefCell<LuaTable>> {
+        match self.get(index) {
+            LuaValue::Table(t) => t,
+            v => self.runtime_error(format!("table expected, got {}", v.type_name())),
+        }
+    }
+
+    // 执行 f，把其中抛出的 LuaError 转换为错误码，其他 panic 继续向上传播
+    fn protect<F: FnOnce(&mut LuaState)>(&mut self, f: F) -> Result<(), isize> {
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(self))) {
+            Ok(()) => Ok(()),
+            Err(e) => match e.downcast_ref::<LuaError>() {
+                Some(err) => Err(err.status),
+                None => std::panic::resume_unwind(e),
+            },
+        }
+    }
+
+    // 把错误信息留在栈顶并抛出 LuaError
+    pub fn runtime_error(&mut self, message: String) -> ! {
+        self.push(LuaValue::String(message.clone().into_bytes()));
+        std::panic::panic_any(LuaError {
+            status: LUA_ERRRUN,
+            message,
+        })
     }
 }
 
 impl luaState for LuaState {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
     fn abs_index(&self, index: isize) -> isize {
-        if index >= 0 {
+        if index >= 0 || index <= LUA_REGISTRYINDEX {
             index
         } else {
-            let top = self.stack.get_top();
+            let top = self.stack.borrow().get_top();
             let base = self.get_base();
             top - base + index
         }
@@ -252,19 +509,76 @@ impl luaState for LuaState {
     // 参考 Lua 官方实现：L->top - (L->ci->func + 1)
     // Lua 使用的是指针运算，这里采用的是基于数组索引的运算
     fn get_top(&self) -> isize {
-        self.stack.get_top() - (self.get_base() + 1)
+        self.stack.borrow().get_top() - (self.get_base() + 1)
     }
 
     fn get(&self, index: isize) -> LuaValue {
+        if index == LUA_REGISTRYINDEX {
+            return self.registry.clone();
+        }
+        if index < LUA_REGISTRYINDEX {
+            // 当前运行的原生函数的上值
+            let n = (LUA_REGISTRYINDEX - index - 1) as usize;
+            let ci = self.current_ci();
+            let v = ci
+                .borrow()
+                .func
+                .borrow()
+                .upvalues
+                .get(n)
+                .map(|cell| cell.borrow().clone());
+            return v.unwrap_or(LuaValue::Nil);
+        }
         self.get_value(index)
     }
 
     fn push(&mut self, value: LuaValue) {
-        self.stack.push(value)
+        self.stack.borrow_mut().push(value)
     }
 
     fn pop(&mut self, n: isize) {
-        self.stack.pop();
+        self.settop(-n - 1)
+    }
+
+    fn settop(&mut self, index: isize) {
+        let top = if index >= 0 {
+            self.get_base() + 1 + index
+        } else {
+            self.stack.borrow().get_top() + index + 1
+        };
+        self.set_top(&top)
+    }
+
+    // 参考 lua_rotate：通过三次翻转把 [idx, top) 区间向栈顶方向旋转 n 个位置
+    fn rotate(&mut self, index: isize, n: isize) {
+        let mut stack = self.stack.borrow_mut();
+        let t = stack.get_top() - 1;
+        let p = self.get_base() + index;
+        let m = if n >= 0 { t - n } else { p - n - 1 };
+        stack.reverse(p, m);
+        stack.reverse(m + 1, t);
+        stack.reverse(p, t);
+    }
+
+    fn copy(&mut self, from: isize, to: isize) {
+        let v = self.get(from);
+        self.replace(to, v);
+    }
+
+    fn check_stack(&mut self, n: isize) -> bool {
+        if n < 0 {
+            return false;
+        }
+        let ok = self.stack.borrow_mut().check(n as usize);
+        if ok {
+            // 原生函数的栈帧随之扩大
+            let top = self.stack.borrow().get_top() + n;
+            let ci = self.current_ci();
+            if ci.borrow().top < top {
+                ci.borrow_mut().top = top;
+            }
+        }
+        ok
     }
 
     fn pushvalue(&mut self, index: isize) {
@@ -272,80 +586,379 @@ impl luaState for LuaState {
         self.push(v)
     }
 
-    fn get_global(&mut self, key: &str) {
-        if let LuaValue::Table(reg) = &self.registry {
-            if let LuaValue::Table(g) = reg.borrow_mut().get_array(LUA_RIDX_GLOBALS) {
-                let k = LuaValue::String(key.to_string());
-                let value = g.borrow_mut().get(k);
-                self.stack.push(value);
-            };
+    // 参考 lua_getglobal：和 lua_getfield 一样会调用全局表的 __index
+    fn get_global(&mut self, key: &str) -> isize {
+        let g = self.globals();
+        let v = self.index_value(g, LuaValue::String(key.into()));
+        let tp = v.lua_type();
+        self.push(v);
+        tp
+    }
+
+    fn get_table(&mut self, index: isize) -> isize {
+        let t = self.get(index);
+        let key = self.stack.borrow_mut().pop();
+        let v = self.index_value(t, key);
+        let tp = v.lua_type();
+        self.push(v);
+        tp
+    }
+
+    fn get_i(&mut self, index: isize, n: isize) -> isize {
+        let t = self.get(index);
+        let v = self.index_value(t, LuaValue::Integer(n as i64));
+        let tp = v.lua_type();
+        self.push(v);
+        tp
+    }
+
+    fn raw_get(&mut self, index: isize) -> isize {
+        let t = self.check_table(index);
+        let key = self.stack.borrow_mut().pop();
+        let v = t.borrow().get(key);
+        let tp = v.lua_type();
+        self.push(v);
+        tp
+    }
+
+    fn raw_geti(&mut self, index: isize, n: isize) -> isize {
+        let t = self.check_table(index);
+        let v = t.borrow().get(LuaValue::Integer(n as i64));
+        let tp = v.lua_type();
+        self.push(v);
+        tp
+    }
+
+    fn create_table(&mut self, narr: isize, nrec: isize) {
+        self.push(LuaValue::new_table(narr as usize, nrec as usize))
+    }
+
+    fn set_global(&mut self, key: &str) {
+        let g = self.globals();
+        let v = self.stack.borrow_mut().pop();
+        self.new_index(g, LuaValue::String(key.into()), v);
+    }
+
+    fn set_table(&mut self, index: isize) {
+        let t = self.get(index);
+        let v = self.stack.borrow_mut().pop();
+        let key = self.stack.borrow_mut().pop();
+        self.new_index(t, key, v);
+    }
+
+    fn set_field(&mut self, index: isize, name: &str) {
+        let t = self.get(index);
+        let v = self.stack.borrow_mut().pop();
+        self.new_index(t, LuaValue::String(name.into()), v);
+    }
+
+    fn set_i(&mut self, index: isize, n: isize) {
+        let t = self.get(index);
+        let v = self.stack.borrow_mut().pop();
+        self.new_index(t, LuaValue::Integer(n as i64), v);
+    }
+
+    fn raw_set(&mut self, index: isize) {
+        let t = self.check_table(index);
+        let v = self.stack.borrow_mut().pop();
+        let key = self.stack.borrow_mut().pop();
+        self.raw_set_value(&t, key, v);
+    }
+
+    fn raw_seti(&mut self, index: isize, n: isize) {
+        let t = self.check_table(index);
+        let v = self.stack.borrow_mut().pop();
+        t.borrow_mut().set(LuaValue::Integer(n as i64), v);
+    }
+
+    fn push_native_closure(&mut self, func: NativeFunction, n: isize) {
+        let mut upvalues = Vec::with_capacity(n as usize);
+        for _ in 0..n {
+            upvalues.push(self.stack.borrow_mut().pop());
         }
+        upvalues.reverse();
+        let closure = LuaValue::new_native_closure(func, upvalues);
+        self.push(closure);
     }
 
-    fn raw_geti(&mut self, idx: isize, n: isize) {
-        if idx == LUA_REGISTRYINDEX {
-            if let LuaValue::Table(reg) = &self.registry {
-                if let v = reg.borrow_mut().get_array(n) {
-                    self.stack.push(v);
-                }
+    fn get_metatable(&mut self, index: isize) -> bool {
+        match self.metatable_of(&self.get(index)) {
+            Some(mt) => {
+                self.push(LuaValue::Table(mt));
+                true
             }
+            None => false,
         }
     }
 
-    fn set_global(&mut self, key: &str) {
-        if let LuaValue::Table(reg) = &self.registry {
-            if let LuaValue::Table(g) = reg.borrow_mut().get_array(LUA_RIDX_GLOBALS) {
-                let value = self.stack.pop();
-                let k = LuaValue::String(key.to_string());
-                g.borrow_mut().set_hash(k, value);
+    fn set_metatable(&mut self, index: isize) {
+        let mt = match self.stack.borrow_mut().pop() {
+            LuaValue::Table(mt) => Some(mt),
+            _ => None,
+        };
+        let obj = self.get(index);
+        match &obj {
+            LuaValue::Table(t) => t.borrow_mut().metatable = mt.clone(),
+            LuaValue::UserData(u) => u.borrow_mut().metatable = mt.clone(),
+            _ => {
+                let mt = mt.clone().map_or(LuaValue::Nil, LuaValue::Table);
+                self.type_metatables.borrow_mut()[obj.lua_type() as usize] = mt;
             }
         }
+        if let Some(mt) = &mt {
+            self.gc.borrow_mut().check_finalizer(&obj, mt);
+        }
     }
 
-    fn set_field(&mut self, index: isize, name: &str) {
-        let v = self.stack.pop();
-        if let LuaValue::Table(t) = self.get(index) {
-            t.borrow_mut()
-                .set_hash(LuaValue::String(name.to_string()), v)
+    fn get_uservalue(&mut self, index: isize) -> isize {
+        let v = match self.get(index) {
+            LuaValue::UserData(u) => u.borrow().user_value.clone(),
+            _ => LuaValue::Nil,
+        };
+        let t = v.lua_type();
+        self.push(v);
+        t
+    }
+
+    fn set_uservalue(&mut self, index: isize) {
+        let v = self.stack.borrow_mut().pop();
+        if let LuaValue::UserData(u) = self.get(index) {
+            u.borrow_mut().user_value = v;
         }
     }
 
-    fn push_native_function(&mut self, func: fn(lua_State) -> usize) {
-        let closure = LuaValue::new_native_closure(func);
-        self.push(closure);
+    fn set_upvalue(&mut self, index: isize, n: isize) -> Option<String> {
+        let (c, name) = self.aux_upvalue(index, n)?;
+        let v = self.stack.borrow_mut().pop();
+        *c.borrow().upvalues[n as usize - 1].borrow_mut() = v;
+        Some(name)
+    }
+
+    fn push_upvalue(&mut self, index: isize, n: isize) -> Option<String> {
+        let (c, name) = self.aux_upvalue(index, n)?;
+        let v = c.borrow().upvalues[n as usize - 1].borrow().clone();
+        self.push(v);
+        Some(name)
+    }
+
+    // 用上值单元的地址作为标识，引用同一个单元的上值标识相同。
+    // 和 5.3 不同，虚拟机创建闭包时按值捕获局部变量，
+    // 两个闭包捕获同一个局部变量得到的是不同的单元
+    fn upvalue_id(&self, index: isize, n: isize) -> Option<usize> {
+        let (c, _) = self.aux_upvalue(index, n)?;
+        let c = c.borrow();
+        Some(Rc::as_ptr(&c.upvalues[n as usize - 1]) as usize)
+    }
+
+    // 让 index1 处闭包的第 n1 个上值引用 index2 处闭包的第 n2 个上值的单元
+    fn upvalue_join(&mut self, index1: isize, n1: isize, index2: isize, n2: isize) {
+        let (c1, c2) = match (self.get(index1), self.get(index2)) {
+            (LuaValue::Closure(c1), LuaValue::Closure(c2)) => (c1, c2),
+            _ => return,
+        };
+        let cell = c2.borrow().upvalues[n2 as usize - 1].clone();
+        c1.borrow_mut().upvalues[n1 as usize - 1] = cell;
+    }
+
+    fn get_field(&mut self, index: isize, name: &str) -> isize {
+        let t = self.get(index);
+        let v = self.index_value(t, LuaValue::String(name.into()));
+        let tp = v.lua_type();
+        self.push(v);
+        tp
+    }
+
+    fn gc(&mut self, what: isize, _data: isize) -> isize {
+        match what {
+            LUA_GCSTOP => {
+                self.gc.borrow_mut().running = false;
+                0
+            }
+            LUA_GCRESTART => {
+                self.gc.borrow_mut().running = true;
+                0
+            }
+            LUA_GCCOLLECT | LUA_GCSTEP => {
+                self.full_gc();
+                0
+            }
+            LUA_GCISRUNNING => self.gc.borrow().running as isize,
+            _ => 0,
+        }
+    }
+
+    fn close(&mut self) {
+        self.gc.borrow_mut().separate_all();
+        self.call_all_pending_finalizers();
     }
 
     fn load(&mut self, proto: Prototype) {
         let closure = self.load_proto(Rc::new(proto));
-        self.stack.push(closure);
+        self.stack.borrow_mut().push(closure);
     }
 
     fn call(&mut self, nargs: isize, nresults: isize) {
-        self.internal_call(nargs, &mut Option::None)
+        self.call_function(nargs, nresults, &mut Option::None)
+    }
+
+    // 参考 luaD_pcall：出错时恢复调用链和栈顶，错误对象放在原来函数的位置。
+    // 消息处理函数在调用链展开之前调用，这样它还能看到出错时的调用栈；
+    // 钩子函数中的错误也会被捕获，这时要恢复钩子的状态
+    fn pcall(&mut self, nargs: isize, nresults: isize, msgh: isize) -> isize {
+        let func_idx = self.stack.borrow().get_top() - nargs - 1;
+        let depth = self.ci_depth();
+        let in_hook = self.hook.borrow().running;
+        let handler = if msgh == 0 {
+            LuaValue::Nil
+        } else {
+            self.get(msgh)
+        };
+        let mut status = match self.protect(|l| l.call(nargs, nresults)) {
+            Ok(()) => return LUA_OK,
+            Err(status) => status,
+        };
+        let mut err = self.stack.borrow_mut().pop();
+        if !handler.is_nil() {
+            self.push(handler);
+            self.push(err);
+            err = match self.protect(|l| l.call(1, 1)) {
+                Ok(()) => self.stack.borrow_mut().pop(),
+                Err(_) => {
+                    status = LUA_ERRERR;
+                    LuaValue::String("error in error handling".into())
+                }
+            };
+        }
+        self.base_ci.borrow_mut().truncate(depth);
+        self.hook.borrow_mut().running = in_hook;
+        self.set_top(&func_idx);
+        self.push(err);
+        status
     }
 
     fn lua_type(&self, index: isize) -> isize {
-        match &self.get_value(index) {
-            LuaValue::Nil => LUA_TNIL,
-            LuaValue::Boolean(_) => LUA_TBOOLEAN,
-            LuaValue::Integer(_) => LUA_TNUMBER,
-            LuaValue::Number(_) => LUA_TNUMBER,
-            LuaValue::String(_) => LUA_TSTRING,
-            LuaValue::Table(_) => LUA_TTABLE,
-            LuaValue::Closure(_) => LUA_TFUNCTION,
+        // 可接受但超出栈顶的索引
+        if index > 0 && index > self.get_top() {
+            return LUA_TNONE;
         }
+        self.get(index).lua_type()
     }
 
     fn is_number(&self, index: isize) -> bool {
-        self.lua_type(index) == LUA_TNUMBER
+        self.get(index).to_number().is_some()
     }
 
     fn is_string(&self, index: isize) -> bool {
-        self.lua_type(index) == LUA_TSTRING
+        let t = self.lua_type(index);
+        t == LUA_TSTRING || t == LUA_TNUMBER
+    }
+
+    fn arith(&mut self, op: isize) {
+        let b = self.stack.borrow_mut().pop();
+        // 一元运算只有一个操作数，第二个操作数用它自己的拷贝
+        let a = if op == LUA_OPUNM || op == LUA_OPBNOT {
+            b.clone()
+        } else {
+            self.stack.borrow_mut().pop()
+        };
+        let v = self.arith_op(op, a, b);
+        self.push(v);
+    }
+
+    fn compare(&mut self, index1: isize, index2: isize, op: isize) -> bool {
+        if self.lua_type(index1) == LUA_TNONE || self.lua_type(index2) == LUA_TNONE {
+            return false;
+        }
+        let (a, b) = (self.get(index1), self.get(index2));
+        match op {
+            LUA_OPEQ => self.values_equal(&a, &b),
+            LUA_OPLT => self.less_than(&a, &b),
+            LUA_OPLE => self.less_equal(&a, &b),
+            _ => self.runtime_error(format!("invalid option {}", op)),
+        }
+    }
+
+    fn raw_equal(&self, index1: isize, index2: isize) -> bool {
+        if self.lua_type(index1) == LUA_TNONE || self.lua_type(index2) == LUA_TNONE {
+            return false;
+        }
+        self.get(index1).raw_equal(&self.get(index2))
+    }
+
+    fn concat(&mut self, n: isize) {
+        let mut values = Vec::with_capacity(n as usize);
+        for _ in 0..n {
+            values.push(self.stack.borrow_mut().pop());
+        }
+        values.reverse();
+        let v = self.concat_values(values);
+        self.push(v);
+    }
+
+    fn len(&mut self, index: isize) {
+        let v = self.get(index);
+        let len = self.len_value(v);
+        self.push(len);
+    }
+
+    fn next(&mut self, index: isize) -> bool {
+        let t = self.check_table(index);
+        let key = self.stack.borrow_mut().pop();
+        let entry = t.borrow().next(key);
+        match entry {
+            Ok(Some((k, v))) => {
+                self.push(k);
+                self.push(v);
+                true
+            }
+            Ok(None) => false,
+            Err(msg) => self.runtime_error(msg.to_string()),
+        }
+    }
+
+    fn to_numberx(&self, index: isize) -> Option<f64> {
+        self.get(index).to_number()
+    }
+
+    fn to_integerx(&self, index: isize) -> Option<i64> {
+        self.get(index).to_integer()
+    }
+
+    fn to_boolean(&self, index: isize) -> bool {
+        self.get(index).to_boolean()
+    }
+
+    // 和官方实现一样，数字会被原地转换为字符串
+    fn to_lstring(&mut self, index: isize) -> Option<Vec<u8>> {
+        let v = self.get(index);
+        let s = v.to_bytes()?;
+        if let LuaValue::Integer(_) | LuaValue::Number(_) = v {
+            self.replace(index, LuaValue::String(s.clone()));
+        }
+        Some(s)
+    }
+
+    fn raw_len(&self, index: isize) -> usize {
+        match self.get(index) {
+            LuaValue::String(s) => s.len(),
+            LuaValue::Table(t) => t.borrow().border(),
+            LuaValue::UserData(u) => std::mem::size_of_val(&*u.borrow().data),
+            _ => 0,
+        }
+    }
+
+    fn string_to_number(&mut self, s: &str) -> usize {
+        match str_to_number(s) {
+            Some(v) => {
+                self.push(v);
+                s.len() + 1
+            }
+            None => 0,
+        }
     }
 
     fn is_cfunction(&self, index: isize) -> bool {
-        let v = &self.stack.get(index);
+        let v = &self.get(index);
         if let LuaValue::Closure(f) = (*v).clone() {
             if f.borrow().function.is_some() {
                 true
@@ -358,7 +971,7 @@ impl luaState for LuaState {
     }
 
     fn is_integer(&self, index: isize) -> bool {
-        let v = &self.stack.get(index);
+        let v = &self.get(index);
         if let LuaValue::Integer(_) = *v {
             true
         } else {
@@ -381,39 +994,160 @@ impl luaState for LuaState {
     fn is_function(&self, index: isize) -> bool {
         self.lua_type(index) == LUA_TFUNCTION
     }
+
+    fn get_stack(&self, level: isize, ar: &mut lua_Debug) -> bool {
+        ar.i_ci = self.ci_at(level);
+        ar.i_ci.is_some()
+    }
+
+    fn get_info(&mut self, what: &str, ar: &mut lua_Debug) -> bool {
+        self.info_of(what, ar)
+    }
+
+    fn get_local(&mut self, ar: Option<&lua_Debug>, n: isize) -> Option<String> {
+        let ci = match ar {
+            Some(ar) => ar.i_ci.clone()?,
+            None => return self.param_name(n),
+        };
+        let (pos, name) = self.find_local(&ci, n)?;
+        let v = self.stack.borrow().get(pos);
+        self.push(v);
+        Some(name)
+    }
+
+    fn set_local(&mut self, ar: &lua_Debug, n: isize) -> Option<String> {
+        let (pos, name) = self.find_local(ar.i_ci.as_ref()?, n)?;
+        let v = self.stack.borrow_mut().pop();
+        self.stack.borrow_mut().set(pos, v);
+        Some(name)
+    }
+
+    fn set_hook(&mut self, func: Option<lua_Hook>, mask: isize, count: isize) {
+        let oldpc = if self.is_lua_frame() {
+            self.current_ci().borrow().get_pc()
+        } else {
+            0
+        };
+        self.hook.borrow_mut().set(func, mask, count, oldpc)
+    }
+
+    fn get_hook(&self) -> Option<lua_Hook> {
+        self.hook.borrow().func
+    }
+
+    fn get_hook_mask(&self) -> isize {
+        self.hook.borrow().mask
+    }
+
+    fn get_hook_count(&self) -> isize {
+        self.hook.borrow().base_count
+    }
+
+    fn func_name(&self, level: isize) -> Option<(String, &'static str)> {
+        self.func_name_at(level)
+    }
+
+    fn global_func_name(&self, level: isize) -> Option<String> {
+        self.global_func_name_at(level)
+    }
+
+    fn location(&self, level: isize) -> String {
+        self.location_at(level)
+    }
 }
 
 impl LuaState {
+    #[cfg(test)]
     pub(crate) fn internal_call(
         &mut self,
         nargs: isize,
         hook: &mut Option<&mut dyn FnMut(&LuaState)>,
     ) {
-        let index = self.get_top() - nargs;
-        if let LuaValue::Closure(func) = self.get_value(index) {
-            let ci = CallInfo::new(func.clone(), index);
-            self.stack.set_top(&ci.get_top());
-            self.base_ci.push(Rc::new(RefCell::new(ci)));
-            self.ci += 1;
-
-            loop {
-                match self.fetch() {
-                    Some(inst) => {
-                        inst.execute(self);
-                        match hook {
-                            Some(f) => f(self),
-                            None => (),
+        self.call_function(nargs, LUA_MULTRET, hook)
+    }
+
+    // 调用栈顶下方 nargs+1 处的函数，Lua 函数一直执行到它返回为止
+    pub(crate) fn call_function(
+        &mut self,
+        nargs: isize,
+        nresults: isize,
+        hook: &mut Option<&mut dyn FnMut(&LuaState)>,
+    ) {
+        let func_idx = self.stack.borrow().get_top() - nargs - 1;
+        let depth = self.ci_depth();
+        if self.precall(func_idx, nresults) {
+            return;
+        }
+        while self.ci_depth() > depth {
+            match self.fetch() {
+                Some(inst) => {
+                    self.trace_exec();
+                    // 最外层的 RETURN 执行之后栈帧已经释放，回调要在它之前调用
+                    if inst.opcode() == OP_RETURN && self.ci_depth() == depth + 1 {
+                        if let Some(f) = hook {
+                            f(self)
                         }
+                        inst.execute(self);
+                        continue;
                     }
-                    None => {
-                        break;
+                    inst.execute(self);
+                    if let Some(f) = hook {
+                        f(self)
                     }
                 }
+                None => {
+                    break;
+                }
             }
         }
     }
 
     fn get_base(&self) -> isize {
-        self.base_ci[self.ci as usize].borrow().get_base()
+        self.current_ci().borrow().get_base()
+    }
+
+    // 完整的一次回收：标记根集合，清理弱表，然后调用不可达对象的 __gc
+    pub fn full_gc(&mut self) {
+        let mut collector = Collector::new();
+        collector.mark(&self.registry);
+        for mt in self.type_metatables.borrow().iter() {
+            collector.mark(mt);
+        }
+        {
+            let stack = self.stack.borrow();
+            for v in &stack.stack[..stack.get_top() as usize] {
+                collector.mark(v);
+            }
+        }
+        for ci in self.base_ci.borrow().iter() {
+            collector.mark(&LuaValue::Closure(ci.borrow().func.clone()));
+        }
+        for obj in &self.gc.borrow().tobefnz {
+            collector.mark(obj);
+        }
+        collector.atomic(&mut self.gc.borrow_mut());
+        self.call_all_pending_finalizers();
+    }
+
+    fn call_all_pending_finalizers(&mut self) {
+        loop {
+            let obj = {
+                let mut gc = self.gc.borrow_mut();
+                if gc.tobefnz.is_empty() {
+                    break;
+                }
+                gc.tobefnz.remove(0)
+            };
+            let tm = self.metafield(&obj, "__gc");
+            if let LuaValue::Closure(_) = tm {
+                // 参考 GCTM：__gc 中的错误（LUA_ERRGCMM）不向外传播，其余的终结器照常调用
+                let top = self.stack.borrow().get_top();
+                self.push(tm);
+                self.push(obj);
+                if self.pcall(1, 0, 0) != LUA_OK {
+                    self.set_top(&top);
+                }
+            }
+        }
     }
 }
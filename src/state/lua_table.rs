@@ -1,11 +1,40 @@
 use crate::state::LuaValue;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Debug, Formatter};
 use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
-#[derive(Clone, Debug)]
+// 哈希部分的键：表、函数等按引用比较，和 Lua 的原始相等语义一致
+#[derive(Clone)]
+struct TableKey(LuaValue);
+
+impl PartialEq for TableKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.raw_equal(&other.0)
+    }
+}
+
+impl Eq for TableKey {}
+
+impl Hash for TableKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.0.gc_id() {
+            Some(id) => id.hash(state),
+            None => self.0.hash(state),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct LuaTable {
     array: Vec<LuaValue>,
-    map: HashMap<LuaValue, LuaValue>,
+    // 哈希部分按插入顺序保存在 node 中，slots 记录每个键在 node 中的下标，
+    // 这样 next 可以直接从上一个键的位置继续
+    node: Vec<(TableKey, LuaValue)>,
+    slots: HashMap<TableKey, usize>,
+    pub metatable: Option<Rc<RefCell<LuaTable>>>,
 }
 
 impl PartialEq for LuaTable {
@@ -20,11 +49,23 @@ impl Hash for LuaTable {
     }
 }
 
+impl Debug for LuaTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // 不输出元表，避免 mt.__index = mt 这类自引用导致无限递归
+        f.debug_struct("LuaTable")
+            .field("array", &self.array)
+            .field("hash_size", &self.slots.len())
+            .finish()
+    }
+}
+
 impl LuaTable {
     pub fn new(array_size: usize, hash_size: usize) -> LuaTable {
         LuaTable {
             array: vec![LuaValue::Nil; array_size + 1],
-            map: HashMap::with_capacity(hash_size),
+            node: Vec::with_capacity(hash_size),
+            slots: HashMap::with_capacity(hash_size),
+            metatable: None,
         }
     }
 
@@ -32,6 +73,28 @@ impl LuaTable {
         self.array.len() - 1
     }
 
+    // 参考 luaH_getn：返回一个边界 n，满足 t[n] 不为 nil 而 t[n+1] 为 nil
+    pub fn border(&self) -> usize {
+        let n = self.len();
+        if n > 0 && self.array[n].is_nil() {
+            let (mut i, mut j) = (0, n);
+            while j - i > 1 {
+                let m = (i + j) / 2;
+                if self.array[m].is_nil() {
+                    j = m;
+                } else {
+                    i = m;
+                }
+            }
+            return i;
+        }
+        let mut j = n + 1;
+        while !self.get_hash(LuaValue::Integer(j as i64)).is_nil() {
+            j += 1;
+        }
+        j - 1
+    }
+
     pub fn get_array(&self, index: isize) -> LuaValue {
         self.array[index as usize].clone()
     }
@@ -41,21 +104,139 @@ impl LuaTable {
     }
 
     pub fn get_hash(&self, key: LuaValue) -> LuaValue {
-        if let Some(value) = self.map.get(&key) {
-            return value.clone();
+        match self.slots.get(&TableKey(key)) {
+            Some(&i) => self.node[i].1.clone(),
+            None => LuaValue::Nil,
         }
-        LuaValue::Nil
     }
 
     pub fn get(&self, key: LuaValue) -> LuaValue {
-        match key {
+        match key.normalize_key() {
             LuaValue::Nil => LuaValue::Nil,
-            LuaValue::Integer(i) => self.get_array(i as isize),
-            _ => self.get_hash(key),
+            LuaValue::Integer(i) if i > 0 && (i as usize) < self.array.len() => {
+                self.get_array(i as isize)
+            }
+            key => self.get_hash(key),
         }
     }
 
+    // 赋值为 nil 时保留槽位，遍历过程中清除已有的键不会打乱 next 的顺序；
+    // 只有插入新键、node 需要扩容时才真正删除这些槽位
     pub fn set_hash(&mut self, key: LuaValue, value: LuaValue) {
-        self.map.insert(key, value);
+        let key = TableKey(key);
+        if let Some(&i) = self.slots.get(&key) {
+            self.node[i].1 = value;
+            return;
+        }
+        if value.is_nil() {
+            return;
+        }
+        if self.node.len() == self.node.capacity() {
+            self.node.retain(|(_, v)| !v.is_nil());
+            self.rebuild_slots();
+        }
+        self.slots.insert(key.clone(), self.node.len());
+        self.node.push((key, value));
+    }
+
+    fn rebuild_slots(&mut self) {
+        self.slots.clear();
+        for (i, (k, _)) in self.node.iter().enumerate() {
+            self.slots.insert(k.clone(), i);
+        }
+    }
+
+    pub fn set(&mut self, key: LuaValue, value: LuaValue) {
+        match key.normalize_key() {
+            LuaValue::Nil => (),
+            LuaValue::Integer(i) if i > 0 && (i as usize) < self.array.len() => {
+                self.set_array(i as isize, value)
+            }
+            LuaValue::Integer(i) if i as usize == self.array.len() && !value.is_nil() => {
+                self.append(value)
+            }
+            key => self.set_hash(key, value),
+        }
+    }
+
+    // 相当于 rehash 把连续的整数键放进数组部分：数组部分紧接着的键赋值时扩大数组，
+    // 并把哈希部分中随后的整数键也移过来，这样边界总在数组部分，#t 不用逐个探测哈希部分
+    fn append(&mut self, value: LuaValue) {
+        self.array.push(value);
+        loop {
+            let key = TableKey(LuaValue::Integer(self.array.len() as i64));
+            match self.slots.get(&key) {
+                Some(&i) if !self.node[i].1.is_nil() => {
+                    // 槽位留在 node 中，下次压缩时再删除
+                    let v = std::mem::replace(&mut self.node[i].1, LuaValue::Nil);
+                    self.slots.remove(&key);
+                    self.array.push(v);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    // 遍历数组部分和哈希部分的所有非 nil 项
+    pub fn entries(&self) -> Vec<(LuaValue, LuaValue)> {
+        let mut entries = Vec::new();
+        for i in 1..self.array.len() {
+            if !self.array[i].is_nil() {
+                entries.push((LuaValue::Integer(i as i64), self.array[i].clone()));
+            }
+        }
+        for (k, v) in &self.node {
+            if !v.is_nil() {
+                entries.push((k.0.clone(), v.clone()));
+            }
+        }
+        entries
+    }
+
+    // 参考 luaH_next：先遍历数组部分，再按插入顺序遍历哈希部分，
+    // 遍历结束时返回 None，键不在表中时返回错误
+    pub fn next(&self, key: LuaValue) -> Result<Option<(LuaValue, LuaValue)>, &'static str> {
+        let start = match key.normalize_key() {
+            LuaValue::Nil => 1,
+            LuaValue::Integer(i) if i > 0 && (i as usize) < self.array.len() => i as usize + 1,
+            key => return self.next_hash(Some(TableKey(key))),
+        };
+        for i in start..self.array.len() {
+            if !self.array[i].is_nil() {
+                return Ok(Some((LuaValue::Integer(i as i64), self.array[i].clone())));
+            }
+        }
+        self.next_hash(None)
+    }
+
+    fn next_hash(
+        &self,
+        key: Option<TableKey>,
+    ) -> Result<Option<(LuaValue, LuaValue)>, &'static str> {
+        let start = match key {
+            Some(key) => match self.slots.get(&key) {
+                Some(&i) => i + 1,
+                None => return Err("invalid key to 'next'"),
+            },
+            None => 0,
+        };
+        Ok(self.node[start..]
+            .iter()
+            .find(|(_, v)| !v.is_nil())
+            .map(|(k, v)| (k.0.clone(), v.clone())))
+    }
+
+    // 删除满足条件的项，供垃圾回收清理弱表
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&LuaValue, &LuaValue) -> bool,
+    {
+        for i in 1..self.array.len() {
+            if !self.array[i].is_nil() && !f(&LuaValue::Integer(i as i64), &self.array[i]) {
+                self.array[i] = LuaValue::Nil;
+            }
+        }
+        self.node.retain(|(k, v)| !v.is_nil() && f(&k.0, v));
+        self.rebuild_slots();
     }
 }
@@ -0,0 +1,42 @@
+use crate::state::{LuaTable, LuaValue};
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+pub struct LuaUserData {
+    pub data: Box<dyn Any>,
+    pub metatable: Option<Rc<RefCell<LuaTable>>>,
+    pub user_value: LuaValue,
+}
+
+impl PartialEq for LuaUserData {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Debug for LuaUserData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "userdata: {:p}", self)
+    }
+}
+
+impl LuaUserData {
+    pub fn new(data: Box<dyn Any>) -> LuaUserData {
+        LuaUserData {
+            data,
+            metatable: None,
+            user_value: LuaValue::Nil,
+        }
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.data.downcast_ref::<T>()
+    }
+
+    pub fn downcast_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.data.downcast_mut::<T>()
+    }
+}
@@ -1,6 +1,10 @@
-use crate::api::lua_CFunction;
+use crate::api::*;
 use crate::chunk::binary::Prototype;
-use crate::state::{LuaClosure, LuaTable};
+use crate::state::{
+    float_to_integer, float_to_string, str_to_number, LuaClosure, LuaTable, LuaUserData,
+    NativeFunction,
+};
+use std::any::Any;
 use std::cell::RefCell;
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
@@ -11,9 +15,11 @@ pub enum LuaValue {
     Boolean(bool),
     Integer(i64),
     Number(f64),
-    String(String),
+    String(Vec<u8>),
     Table(Rc<RefCell<LuaTable>>),
     Closure(Rc<RefCell<LuaClosure>>),
+    UserData(Rc<RefCell<LuaUserData>>),
+    LightUserData(usize),
 }
 
 impl Eq for LuaValue {}
@@ -26,8 +32,10 @@ impl Hash for LuaValue {
             LuaValue::Integer(v) => v.hash(state),
             LuaValue::Number(v) => v.to_be_bytes().hash(state),
             LuaValue::String(v) => v.hash(state),
-            LuaValue::Table(v) => v.borrow_mut().hash(state),
-            LuaValue::Closure(v) => v.borrow_mut().hash(state),
+            LuaValue::Table(v) => Rc::as_ptr(v).hash(state),
+            LuaValue::Closure(v) => Rc::as_ptr(v).hash(state),
+            LuaValue::UserData(v) => Rc::as_ptr(v).hash(state),
+            LuaValue::LightUserData(v) => v.hash(state),
         }
     }
 }
@@ -37,8 +45,14 @@ impl LuaValue {
         LuaValue::Closure(Rc::new(RefCell::new(LuaClosure::new(Rc::new(proto)))))
     }
 
-    pub fn new_native_closure(func: lua_CFunction) -> LuaValue {
-        LuaValue::Closure(Rc::new(RefCell::new(LuaClosure::new_native(func))))
+    pub fn new_native_closure(func: NativeFunction, upvalues: Vec<LuaValue>) -> LuaValue {
+        LuaValue::Closure(Rc::new(RefCell::new(LuaClosure::new_native(
+            func, upvalues,
+        ))))
+    }
+
+    pub fn new_userdata(data: Box<dyn Any>) -> LuaValue {
+        LuaValue::UserData(Rc::new(RefCell::new(LuaUserData::new(data))))
     }
 
     pub fn new_table(array_size: usize, hash_size: usize) -> LuaValue {
@@ -51,4 +65,141 @@ impl LuaValue {
             _ => false,
         }
     }
+
+    pub fn lua_type(&self) -> isize {
+        match self {
+            LuaValue::Nil => LUA_TNIL,
+            LuaValue::Boolean(_) => LUA_TBOOLEAN,
+            LuaValue::Integer(_) => LUA_TNUMBER,
+            LuaValue::Number(_) => LUA_TNUMBER,
+            LuaValue::String(_) => LUA_TSTRING,
+            LuaValue::Table(_) => LUA_TTABLE,
+            LuaValue::Closure(_) => LUA_TFUNCTION,
+            LuaValue::UserData(_) => LUA_TUSERDATA,
+            LuaValue::LightUserData(_) => LUA_TLIGHTUSERDATA,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            LuaValue::Nil => "nil",
+            LuaValue::Boolean(_) => "boolean",
+            LuaValue::Integer(_) | LuaValue::Number(_) => "number",
+            LuaValue::String(_) => "string",
+            LuaValue::Table(_) => "table",
+            LuaValue::Closure(_) => "function",
+            LuaValue::UserData(_) | LuaValue::LightUserData(_) => "userdata",
+        }
+    }
+
+    // 只有表和完整 userdata 拥有独立的元表
+    pub fn metatable(&self) -> Option<Rc<RefCell<LuaTable>>> {
+        match self {
+            LuaValue::Table(t) => t.borrow().metatable.clone(),
+            LuaValue::UserData(u) => u.borrow().metatable.clone(),
+            _ => None,
+        }
+    }
+
+    pub fn get_metafield(&self, name: &str) -> LuaValue {
+        match self.metatable() {
+            Some(mt) => mt.borrow().get_hash(LuaValue::String(name.into())),
+            None => LuaValue::Nil,
+        }
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self, LuaValue::Nil)
+    }
+
+    // 可回收对象的身份标识（Rc 指针地址），非引用类型返回 None
+    pub fn gc_id(&self) -> Option<usize> {
+        match self {
+            LuaValue::Table(t) => Some(Rc::as_ptr(t) as *const u8 as usize),
+            LuaValue::Closure(c) => Some(Rc::as_ptr(c) as *const u8 as usize),
+            LuaValue::UserData(u) => Some(Rc::as_ptr(u) as *const u8 as usize),
+            _ => None,
+        }
+    }
+
+    // 原始相等（不触发元方法）：引用类型比较身份，整数和浮点数按数学值比较
+    pub fn raw_equal(&self, other: &LuaValue) -> bool {
+        match (self, other) {
+            (LuaValue::Nil, LuaValue::Nil) => true,
+            (LuaValue::Boolean(a), LuaValue::Boolean(b)) => a == b,
+            (LuaValue::Integer(a), LuaValue::Integer(b)) => a == b,
+            (LuaValue::Number(a), LuaValue::Number(b)) => a == b,
+            (LuaValue::Integer(i), LuaValue::Number(n))
+            | (LuaValue::Number(n), LuaValue::Integer(i)) => {
+                LuaValue::Number(*n).normalize_key() == LuaValue::Integer(*i)
+            }
+            (LuaValue::String(a), LuaValue::String(b)) => a == b,
+            (LuaValue::LightUserData(a), LuaValue::LightUserData(b)) => a == b,
+            _ => match (self.gc_id(), other.gc_id()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+
+    // nil 和 false 为假，其余都为真
+    pub fn to_boolean(&self) -> bool {
+        match self {
+            LuaValue::Nil => false,
+            LuaValue::Boolean(b) => *b,
+            _ => true,
+        }
+    }
+
+    // 数字和可以转换为数字的字符串
+    pub fn to_number(&self) -> Option<f64> {
+        match self {
+            LuaValue::Integer(i) => Some(*i as f64),
+            LuaValue::Number(n) => Some(*n),
+            LuaValue::String(s) => match str_to_number(std::str::from_utf8(s).ok()?)? {
+                LuaValue::Integer(i) => Some(i as f64),
+                LuaValue::Number(n) => Some(n),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // 浮点数必须恰好是整数值才能转换
+    pub fn to_integer(&self) -> Option<i64> {
+        match self {
+            LuaValue::Integer(i) => Some(*i),
+            LuaValue::Number(n) => float_to_integer(*n),
+            LuaValue::String(s) => str_to_number(std::str::from_utf8(s).ok()?)?.to_integer(),
+            _ => None,
+        }
+    }
+
+    // 字符串和数字可以转换为字符串
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            LuaValue::String(s) => Some(s.clone()),
+            LuaValue::Integer(i) => Some(i.to_string().into_bytes()),
+            LuaValue::Number(n) => Some(float_to_string(*n).into_bytes()),
+            _ => None,
+        }
+    }
+
+    // 不是合法 UTF-8 的部分会被替换
+    pub fn to_str(&self) -> Option<String> {
+        match self {
+            LuaValue::String(s) => Some(String::from_utf8_lossy(s).into_owned()),
+            _ => self.to_bytes().map(|b| String::from_utf8(b).unwrap()),
+        }
+    }
+
+    // 作为表的键时，值为整数的浮点数统一转换成整数
+    pub fn normalize_key(self) -> LuaValue {
+        if let LuaValue::Number(n) = self {
+            if let Some(i) = float_to_integer(n) {
+                return LuaValue::Integer(i);
+            }
+        }
+        self
+    }
 }
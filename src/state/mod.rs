@@ -1,11 +1,19 @@
+mod lua_arith;
+mod lua_debug;
 mod lua_function;
+mod lua_gc;
+mod lua_number;
 mod lua_stack;
 mod lua_state;
 mod lua_table;
+mod lua_userdata;
 mod lua_value;
 
-pub use lua_function::LuaClosure;
+pub use lua_function::{LuaClosure, NativeFunction};
+pub use lua_number::{float_to_integer, float_to_string, fmt_g, str_to_number};
 pub use lua_stack::LuaStack;
-pub use lua_state::LuaState;
+pub(crate) use lua_state::CallInfo;
+pub use lua_state::{install_panic_hook, LuaState};
 pub use lua_table::LuaTable;
+pub use lua_userdata::LuaUserData;
 pub use lua_value::LuaValue;
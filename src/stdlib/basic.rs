@@ -1,12 +1,396 @@
 use crate::api::*;
+use std::io::Write;
 
+// 参考 lbaselib.c
+
+// 每个参数通过全局的 tostring 转换，以制表符分隔
 pub fn basic_print(l: lua_State) -> usize {
-    let argc = l.borrow().get_top();
-    for i in 1..=argc {
-        if lua_isstring(l.clone(), i) {
-            print!("{}", lua_tostring(l.clone(), i));
+    let n = lua_gettop(l.clone());
+    lua_getglobal(l.clone(), "tostring");
+    let mut line = Vec::new();
+    for i in 1..=n {
+        lua_pushvalue(l.clone(), -1);
+        lua_pushvalue(l.clone(), i);
+        lua_call(l.clone(), 1, 1);
+        if !lua_isstring(l.clone(), -1) {
+            luaL_error(l, "'tostring' must return a string to 'print'");
+        }
+        if i > 1 {
+            line.push(b'\t');
         }
+        line.extend(lua_tobytes(l.clone(), -1).unwrap_or_default());
+        lua_pop(l.clone(), 1);
     }
-    println!();
+    line.push(b'\n');
+    let mut out = std::io::stdout();
+    let _ = out.write_all(&line).and_then(|_| out.flush());
     0
 }
+
+pub fn basic_type(l: lua_State) -> usize {
+    let t = lua_type(l.clone(), 1);
+    luaL_argcheck(l.clone(), t != LUA_TNONE, 1, "value expected");
+    let name = lua_typename(l.clone(), t);
+    lua_pushstring(l, name);
+    1
+}
+
+pub fn basic_tostring(l: lua_State) -> usize {
+    luaL_checkany(l.clone(), 1);
+    luaL_tolstring(l, 1);
+    1
+}
+
+fn is_space(c: char) -> bool {
+    c == ' ' || ('\t'..='\r').contains(&c)
+}
+
+// 参考 l_str2int：按指定进制转换整数，允许前后的空白和正负号，溢出时回绕
+fn str_to_int(s: &str, base: u32) -> Option<i64> {
+    let s = s.trim_matches(is_space);
+    let (neg, digits) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    let mut n: i64 = 0;
+    for c in digits.chars() {
+        let d = c.to_digit(36).filter(|d| *d < base)?;
+        n = n.wrapping_mul(base as i64).wrapping_add(d as i64);
+    }
+    Some(if neg { n.wrapping_neg() } else { n })
+}
+
+pub fn basic_tonumber(l: lua_State) -> usize {
+    if lua_isnoneornil(l.clone(), 2) {
+        match lua_type(l.clone(), 1) {
+            LUA_TNUMBER => {
+                lua_settop(l, 1);
+                return 1;
+            }
+            LUA_TSTRING => {
+                let s = lua_tostring(l.clone(), 1);
+                if lua_stringtonumber(l.clone(), &s) != 0 {
+                    return 1;
+                }
+            }
+            _ => luaL_checkany(l.clone(), 1),
+        }
+    } else {
+        let base = luaL_checkinteger(l.clone(), 2);
+        luaL_checktype(l.clone(), 1, LUA_TSTRING);
+        let s = lua_tostring(l.clone(), 1);
+        luaL_argcheck(l.clone(), (2..=36).contains(&base), 2, "base out of range");
+        if let Some(n) = str_to_int(&s, base as u32) {
+            lua_pushinteger(l, n as isize);
+            return 1;
+        }
+    }
+    lua_pushnil(l);
+    1
+}
+
+// select('#', ...) 返回参数个数，select(n, ...) 返回第 n 个及之后的参数，负数从末尾数起
+pub fn basic_select(l: lua_State) -> usize {
+    let n = lua_gettop(l.clone()) as i64;
+    if lua_type(l.clone(), 1) == LUA_TSTRING && lua_tostring(l.clone(), 1).starts_with('#') {
+        lua_pushinteger(l, (n - 1) as isize);
+        return 1;
+    }
+    let mut i = luaL_checkinteger(l.clone(), 1);
+    if i < 0 {
+        i += n;
+    } else if i > n {
+        i = n;
+    }
+    luaL_argcheck(l, 1 <= i, 1, "index out of range");
+    (n - i) as usize
+}
+
+pub fn basic_assert(l: lua_State) -> usize {
+    if lua_toboolean(l.clone(), 1) {
+        return lua_gettop(l) as usize;
+    }
+    luaL_checkany(l.clone(), 1);
+    lua_remove(l.clone(), 1);
+    lua_pushstring(l.clone(), "assertion failed!");
+    // 有错误信息时保留错误信息，否则使用默认信息
+    lua_settop(l.clone(), 1);
+    lua_error(l)
+}
+
+// level 大于 0 时在字符串错误信息前面加上出错的位置
+pub fn basic_error(l: lua_State) -> usize {
+    let level = luaL_optinteger(l.clone(), 2, 1);
+    lua_settop(l.clone(), 1);
+    if lua_type(l.clone(), 1) == LUA_TSTRING && level > 0 {
+        luaL_where(l.clone(), level as isize);
+        lua_pushvalue(l.clone(), 1);
+        lua_concat(l.clone(), 2);
+    }
+    lua_error(l)
+}
+
+fn finish_pcall(l: lua_State, ok: bool, extra: isize) -> usize {
+    if !ok {
+        lua_pushboolean(l.clone(), false);
+        lua_pushvalue(l, -2);
+        return 2;
+    }
+    (lua_gettop(l) - extra) as usize
+}
+
+pub fn basic_pcall(l: lua_State) -> usize {
+    luaL_checkany(l.clone(), 1);
+    lua_pushboolean(l.clone(), true);
+    lua_insert(l.clone(), 1);
+    let nargs = lua_gettop(l.clone()) - 2;
+    let status = lua_pcall(l.clone(), nargs, LUA_MULTRET, 0);
+    finish_pcall(l, status == LUA_OK, 0)
+}
+
+// 栈上是 f, msgh, args...，调用前整理成 f, msgh, true, f, args...
+pub fn basic_xpcall(l: lua_State) -> usize {
+    let n = lua_gettop(l.clone());
+    luaL_checktype(l.clone(), 2, LUA_TFUNCTION);
+    lua_pushboolean(l.clone(), true);
+    lua_pushvalue(l.clone(), 1);
+    lua_rotate(l.clone(), 3, 2);
+    let status = lua_pcall(l.clone(), n - 2, LUA_MULTRET, 2);
+    finish_pcall(l, status == LUA_OK, 2)
+}
+
+pub fn basic_rawequal(l: lua_State) -> usize {
+    luaL_checkany(l.clone(), 1);
+    luaL_checkany(l.clone(), 2);
+    let eq = lua_rawequal(l.clone(), 1, 2);
+    lua_pushboolean(l, eq);
+    1
+}
+
+pub fn basic_rawlen(l: lua_State) -> usize {
+    let t = lua_type(l.clone(), 1);
+    luaL_argcheck(
+        l.clone(),
+        t == LUA_TTABLE || t == LUA_TSTRING,
+        1,
+        "table or string expected",
+    );
+    let n = lua_rawlen(l.clone(), 1);
+    lua_pushinteger(l, n as isize);
+    1
+}
+
+pub fn basic_rawget(l: lua_State) -> usize {
+    luaL_checktype(l.clone(), 1, LUA_TTABLE);
+    luaL_checkany(l.clone(), 2);
+    lua_settop(l.clone(), 2);
+    lua_rawget(l, 1);
+    1
+}
+
+pub fn basic_rawset(l: lua_State) -> usize {
+    luaL_checktype(l.clone(), 1, LUA_TTABLE);
+    luaL_checkany(l.clone(), 2);
+    luaL_checkany(l.clone(), 3);
+    lua_settop(l.clone(), 3);
+    lua_rawset(l, 1);
+    1
+}
+
+// 元表中有 __metatable 字段时返回它而不是元表本身
+pub fn basic_getmetatable(l: lua_State) -> usize {
+    luaL_checkany(l.clone(), 1);
+    if !lua_getmetatable(l.clone(), 1) {
+        lua_pushnil(l);
+        return 1;
+    }
+    luaL_getmetafield(l, 1, "__metatable");
+    1
+}
+
+pub fn basic_setmetatable(l: lua_State) -> usize {
+    let t = lua_type(l.clone(), 2);
+    luaL_checktype(l.clone(), 1, LUA_TTABLE);
+    luaL_argcheck(
+        l.clone(),
+        t == LUA_TNIL || t == LUA_TTABLE,
+        2,
+        "nil or table expected",
+    );
+    if luaL_getmetafield(l.clone(), 1, "__metatable") != LUA_TNIL {
+        luaL_error(l, "cannot change a protected metatable");
+    }
+    lua_settop(l.clone(), 2);
+    lua_setmetatable(l, 1);
+    1
+}
+
+pub fn basic_next(l: lua_State) -> usize {
+    luaL_checktype(l.clone(), 1, LUA_TTABLE);
+    lua_settop(l.clone(), 2);
+    if lua_next(l.clone(), 1) {
+        2
+    } else {
+        lua_pushnil(l);
+        1
+    }
+}
+
+pub fn basic_pairs(l: lua_State) -> usize {
+    luaL_checkany(l.clone(), 1);
+    if luaL_getmetafield(l.clone(), 1, "__pairs") == LUA_TNIL {
+        lua_pushcfunction(l.clone(), basic_next);
+        lua_pushvalue(l.clone(), 1);
+        lua_pushnil(l);
+    } else {
+        lua_pushvalue(l.clone(), 1);
+        lua_call(l, 1, 3);
+    }
+    3
+}
+
+// ipairs 的迭代函数，和 5.3 一样通过 lua_geti 取值，会触发 __index
+fn ipairs_aux(l: lua_State) -> usize {
+    let i = lua_tointeger(l.clone(), 2).wrapping_add(1);
+    lua_pushinteger(l.clone(), i as isize);
+    if lua_geti(l, 1, i as isize) == LUA_TNIL {
+        1
+    } else {
+        2
+    }
+}
+
+pub fn basic_ipairs(l: lua_State) -> usize {
+    luaL_checkany(l.clone(), 1);
+    lua_pushcfunction(l.clone(), ipairs_aux);
+    lua_pushvalue(l.clone(), 1);
+    lua_pushinteger(l, 0);
+    3
+}
+
+pub fn basic_collectgarbage(l: lua_State) -> usize {
+    const OPTS: &[&str] = &[
+        "stop",
+        "restart",
+        "collect",
+        "count",
+        "step",
+        "setpause",
+        "setstepmul",
+        "isrunning",
+    ];
+    const OPTSNUM: &[isize] = &[
+        LUA_GCSTOP,
+        LUA_GCRESTART,
+        LUA_GCCOLLECT,
+        LUA_GCCOUNT,
+        LUA_GCSTEP,
+        LUA_GCSETPAUSE,
+        LUA_GCSETSTEPMUL,
+        LUA_GCISRUNNING,
+    ];
+    let o = OPTSNUM[luaL_checkoption(l.clone(), 1, Some("collect"), OPTS)];
+    let ex = luaL_optinteger(l.clone(), 2, 0);
+    let res = lua_gc(l.clone(), o, ex as isize);
+    match o {
+        LUA_GCCOUNT => {
+            let b = lua_gc(l.clone(), LUA_GCCOUNTB, 0);
+            lua_pushnumber(l, res as f64 + b as f64 / 1024.0);
+        }
+        LUA_GCSTEP | LUA_GCISRUNNING => lua_pushboolean(l, res != 0),
+        _ => lua_pushinteger(l, res),
+    }
+    1
+}
+
+// 加载成功时设置 env 作为第一个上值（_ENV），失败时返回 nil 和错误信息
+fn load_aux(l: lua_State, status: isize, envidx: isize) -> usize {
+    if status != LUA_OK {
+        lua_pushnil(l.clone());
+        lua_insert(l, -2);
+        return 2;
+    }
+    if envidx != 0 {
+        lua_pushvalue(l.clone(), envidx);
+        lua_setupvalue(l, -2, 1);
+    }
+    1
+}
+
+// chunk 可以是字符串，也可以是依次返回各个片段的函数
+pub fn basic_load(l: lua_State) -> usize {
+    let envidx = if lua_isnone(l.clone(), 4) { 0 } else { 4 };
+    let mode = luaL_optstring(l.clone(), 3, "bt");
+    let (chunk, chunkname) = if lua_type(l.clone(), 1) == LUA_TSTRING {
+        let s = lua_tobytes(l.clone(), 1).unwrap_or_default();
+        let name = luaL_optstring(l.clone(), 2, &String::from_utf8_lossy(&s));
+        (s, name)
+    } else {
+        let name = luaL_optstring(l.clone(), 2, "=(load)");
+        luaL_checktype(l.clone(), 1, LUA_TFUNCTION);
+        let mut chunk = Vec::new();
+        loop {
+            lua_pushvalue(l.clone(), 1);
+            lua_call(l.clone(), 0, 1);
+            if lua_isnil(l.clone(), -1) {
+                lua_pop(l.clone(), 1);
+                break;
+            }
+            if !lua_isstring(l.clone(), -1) {
+                luaL_error(l, "reader function must return a string");
+            }
+            let piece = lua_tobytes(l.clone(), -1).unwrap_or_default();
+            lua_pop(l.clone(), 1);
+            if piece.is_empty() {
+                break;
+            }
+            chunk.extend(piece);
+        }
+        (chunk, name)
+    };
+    let status = load_chunk(l.clone(), &chunk, &chunkname, &mode);
+    load_aux(l, status, envidx)
+}
+
+fn load_chunk(l: lua_State, chunk: &[u8], chunkname: &str, mode: &str) -> isize {
+    if chunk.first() == Some(&0x1b) && !mode.contains('b') {
+        lua_pushstring(
+            l,
+            &format!("attempt to load a binary chunk (mode is '{}')", mode),
+        );
+        return LUA_ERRSYNTAX;
+    }
+    luaL_loadbuffer(l, chunk, chunkname)
+}
+
+pub fn basic_loadfile(l: lua_State) -> usize {
+    let filename = luaL_checkstring(l.clone(), 1);
+    let mode = luaL_optstring(l.clone(), 2, "bt");
+    let envidx = if lua_isnone(l.clone(), 3) { 0 } else { 3 };
+    let status = match std::fs::read(&filename) {
+        Ok(chunk) => load_chunk(l.clone(), &chunk, &format!("@{}", filename), &mode),
+        Err(e) => {
+            lua_pushstring(l.clone(), &format!("cannot open {}: {}", filename, e));
+            LUA_ERRFILE
+        }
+    };
+    load_aux(l, status, envidx)
+}
+
+pub fn basic_dofile(l: lua_State) -> usize {
+    let filename = luaL_checkstring(l.clone(), 1);
+    lua_settop(l.clone(), 1);
+    let chunk = match std::fs::read(&filename) {
+        Ok(chunk) => chunk,
+        Err(e) => luaL_error(l, &format!("cannot open {}: {}", filename, e)),
+    };
+    if luaL_loadbuffer(l.clone(), &chunk, &format!("@{}", filename)) != LUA_OK {
+        lua_error(l);
+    }
+    lua_call(l.clone(), 0, LUA_MULTRET);
+    (lua_gettop(l) - 1) as usize
+}
@@ -0,0 +1,340 @@
+use crate::api::*;
+
+// 还没有协程，各个函数都不支持可选的 thread 参数。和 ldblib.c 相比还有这些限制：
+// 尾调用不做标记，getinfo 返回的 istailcall 总是 false；
+// getlocal 和 setlocal 不支持负数的 n，取不到可变参数；
+// 创建闭包时按值捕获局部变量，捕获同一个局部变量的两个闭包不共享上值，只有 upvaluejoin 之后才共享。
+// debug.sethook 设置的 Lua 钩子函数保存在注册表的这个字段中
+const HOOKKEY: &str = "_HKEY";
+
+const HOOKNAMES: [&str; 5] = ["call", "return", "line", "count", "tail call"];
+
+fn push_name(l: lua_State, name: Option<&str>) {
+    match name {
+        Some(name) => lua_pushstring(l, name),
+        None => lua_pushnil(l),
+    }
+}
+
+pub fn db_getregistry(l: lua_State) -> usize {
+    lua_pushvalue(l, LUA_REGISTRYINDEX);
+    1
+}
+
+pub fn db_getmetatable(l: lua_State) -> usize {
+    luaL_checkany(l.clone(), 1);
+    if !lua_getmetatable(l.clone(), 1) {
+        lua_pushnil(l);
+    }
+    1
+}
+
+pub fn db_setmetatable(l: lua_State) -> usize {
+    let t = lua_type(l.clone(), 2);
+    luaL_argcheck(
+        l.clone(),
+        t == LUA_TNIL || t == LUA_TTABLE,
+        2,
+        "nil or table expected",
+    );
+    lua_settop(l.clone(), 2);
+    lua_setmetatable(l, 1);
+    1
+}
+
+pub fn db_getuservalue(l: lua_State) -> usize {
+    if lua_type(l.clone(), 1) != LUA_TUSERDATA {
+        lua_pushnil(l);
+    } else {
+        lua_getuservalue(l, 1);
+    }
+    1
+}
+
+pub fn db_setuservalue(l: lua_State) -> usize {
+    luaL_checktype(l.clone(), 1, LUA_TUSERDATA);
+    luaL_checkany(l.clone(), 2);
+    lua_settop(l.clone(), 2);
+    lua_setuservalue(l, 1);
+    1
+}
+
+fn settabss(l: lua_State, k: &str, v: Option<&str>) {
+    push_name(l.clone(), v);
+    lua_setfield(l, -2, k);
+}
+
+fn settabsi(l: lua_State, k: &str, v: isize) {
+    lua_pushinteger(l.clone(), v);
+    lua_setfield(l, -2, k);
+}
+
+fn settabsb(l: lua_State, k: &str, v: bool) {
+    lua_pushboolean(l.clone(), v);
+    lua_setfield(l, -2, k);
+}
+
+// lua_getinfo 压入的值在结果表下面，把它移到结果表中
+fn treatstackoption(l: lua_State, fname: &str) {
+    lua_rotate(l.clone(), -2, 1);
+    lua_setfield(l, -2, fname);
+}
+
+// debug.getinfo(f [, what])，f 是函数或者调用栈的层数
+pub fn db_getinfo(l: lua_State) -> usize {
+    let mut ar = lua_Debug::default();
+    let mut options = luaL_optstring(l.clone(), 2, "flnStu");
+    luaL_argcheck(
+        l.clone(),
+        !options.starts_with('>'),
+        2,
+        "invalid option '>'",
+    );
+    if lua_isfunction(l.clone(), 1) {
+        options = format!(">{}", options);
+        lua_pushvalue(l.clone(), 1);
+    } else {
+        let level = luaL_checkinteger(l.clone(), 1) as isize;
+        if !lua_getstack(l.clone(), level, &mut ar) {
+            lua_pushnil(l);
+            return 1;
+        }
+    }
+    if !lua_getinfo(l.clone(), &options, &mut ar) {
+        luaL_argerror(l, 2, "invalid option");
+    }
+    lua_newtable(l.clone());
+    if options.contains('S') {
+        settabss(l.clone(), "source", Some(&ar.source));
+        settabss(l.clone(), "short_src", Some(&ar.short_src));
+        settabsi(l.clone(), "linedefined", ar.linedefined);
+        settabsi(l.clone(), "lastlinedefined", ar.lastlinedefined);
+        settabss(l.clone(), "what", Some(&ar.what));
+    }
+    if options.contains('l') {
+        settabsi(l.clone(), "currentline", ar.currentline);
+    }
+    if options.contains('u') {
+        settabsi(l.clone(), "nups", ar.nups as isize);
+        settabsi(l.clone(), "nparams", ar.nparams as isize);
+        settabsb(l.clone(), "isvararg", ar.isvararg);
+    }
+    if options.contains('n') {
+        settabss(l.clone(), "name", ar.name.as_deref());
+        settabss(l.clone(), "namewhat", Some(&ar.namewhat));
+    }
+    if options.contains('t') {
+        settabsb(l.clone(), "istailcall", ar.istailcall);
+    }
+    if options.contains('L') {
+        treatstackoption(l.clone(), "activelines");
+    }
+    if options.contains('f') {
+        treatstackoption(l, "func");
+    }
+    1
+}
+
+// debug.getlocal(f, n)：f 是函数时只返回第 n 个参数的名字
+pub fn db_getlocal(l: lua_State) -> usize {
+    let nvar = luaL_checkinteger(l.clone(), 2) as isize;
+    if lua_isfunction(l.clone(), 1) {
+        lua_pushvalue(l.clone(), 1);
+        let name = lua_getlocal(l.clone(), None, nvar);
+        push_name(l, name.as_deref());
+        return 1;
+    }
+    let level = luaL_checkinteger(l.clone(), 1) as isize;
+    let mut ar = lua_Debug::default();
+    if !lua_getstack(l.clone(), level, &mut ar) {
+        luaL_argerror(l, 1, "level out of range");
+    }
+    match lua_getlocal(l.clone(), Some(&ar), nvar) {
+        Some(name) => {
+            lua_pushstring(l.clone(), &name);
+            lua_rotate(l, -2, 1);
+            2
+        }
+        None => {
+            lua_pushnil(l);
+            1
+        }
+    }
+}
+
+pub fn db_setlocal(l: lua_State) -> usize {
+    let level = luaL_checkinteger(l.clone(), 1) as isize;
+    let nvar = luaL_checkinteger(l.clone(), 2) as isize;
+    let mut ar = lua_Debug::default();
+    if !lua_getstack(l.clone(), level, &mut ar) {
+        luaL_argerror(l, 1, "level out of range");
+    }
+    luaL_checkany(l.clone(), 3);
+    lua_settop(l.clone(), 3);
+    let name = lua_setlocal(l.clone(), &ar, nvar);
+    if name.is_none() {
+        lua_pop(l.clone(), 1);
+    }
+    push_name(l, name.as_deref());
+    1
+}
+
+// get 为 true 时返回上值的名字和值，否则设置上值并返回名字
+fn auxupvalue(l: lua_State, get: bool) -> usize {
+    let n = luaL_checkinteger(l.clone(), 2) as isize;
+    luaL_checktype(l.clone(), 1, LUA_TFUNCTION);
+    let name = if get {
+        lua_getupvalue(l.clone(), 1, n)
+    } else {
+        lua_setupvalue(l.clone(), 1, n)
+    };
+    match name {
+        Some(name) => {
+            lua_pushstring(l.clone(), &name);
+            if get {
+                lua_insert(l, -2);
+                2
+            } else {
+                1
+            }
+        }
+        None => 0,
+    }
+}
+
+pub fn db_getupvalue(l: lua_State) -> usize {
+    auxupvalue(l, true)
+}
+
+pub fn db_setupvalue(l: lua_State) -> usize {
+    luaL_checkany(l.clone(), 3);
+    auxupvalue(l, false)
+}
+
+// argf 处的函数必须有第 argnup 个参数指定的上值
+fn checkupval(l: lua_State, argf: isize, argnup: isize) -> isize {
+    let nup = luaL_checkinteger(l.clone(), argnup) as isize;
+    luaL_checktype(l.clone(), argf, LUA_TFUNCTION);
+    let valid = lua_upvalueid(l.clone(), argf, nup).is_some();
+    luaL_argcheck(l, valid, argnup, "invalid upvalue index");
+    nup
+}
+
+pub fn db_upvalueid(l: lua_State) -> usize {
+    let n = checkupval(l.clone(), 1, 2);
+    let id = lua_upvalueid(l.clone(), 1, n).unwrap_or_default();
+    lua_pushlightuserdata(l, id);
+    1
+}
+
+pub fn db_upvaluejoin(l: lua_State) -> usize {
+    let n1 = checkupval(l.clone(), 1, 2);
+    let n2 = checkupval(l.clone(), 3, 4);
+    luaL_argcheck(
+        l.clone(),
+        !lua_iscfunction(l.clone(), 1),
+        1,
+        "Lua function expected",
+    );
+    luaL_argcheck(
+        l.clone(),
+        !lua_iscfunction(l.clone(), 3),
+        3,
+        "Lua function expected",
+    );
+    lua_upvaluejoin(l, 1, n1, 3, n2);
+    0
+}
+
+// 调用 debug.sethook 设置的钩子函数，参数是事件的名字和当前行号
+fn hookf(l: lua_State, ar: &mut lua_Debug) {
+    if lua_getfield(l.clone(), LUA_REGISTRYINDEX, HOOKKEY) == LUA_TFUNCTION {
+        lua_pushstring(l.clone(), HOOKNAMES[ar.event as usize]);
+        if ar.currentline >= 0 {
+            lua_pushinteger(l.clone(), ar.currentline);
+        } else {
+            lua_pushnil(l.clone());
+        }
+        lua_call(l, 2, 0);
+    }
+}
+
+fn makemask(smask: &str, count: isize) -> isize {
+    let mut mask = 0;
+    if smask.contains('c') {
+        mask |= LUA_MASKCALL;
+    }
+    if smask.contains('r') {
+        mask |= LUA_MASKRET;
+    }
+    if smask.contains('l') {
+        mask |= LUA_MASKLINE;
+    }
+    if count > 0 {
+        mask |= LUA_MASKCOUNT;
+    }
+    mask
+}
+
+fn unmakemask(mask: isize) -> String {
+    let mut smask = String::new();
+    if mask & LUA_MASKCALL != 0 {
+        smask.push('c');
+    }
+    if mask & LUA_MASKRET != 0 {
+        smask.push('r');
+    }
+    if mask & LUA_MASKLINE != 0 {
+        smask.push('l');
+    }
+    smask
+}
+
+// debug.sethook([hook, mask [, count]])，没有参数时关闭钩子
+pub fn db_sethook(l: lua_State) -> usize {
+    let (func, mask, count) = if lua_isnoneornil(l.clone(), 1) {
+        lua_settop(l.clone(), 1);
+        (None, 0, 0)
+    } else {
+        let smask = luaL_checkstring(l.clone(), 2);
+        luaL_checktype(l.clone(), 1, LUA_TFUNCTION);
+        let count = luaL_optinteger(l.clone(), 3, 0) as isize;
+        (Some(hookf as lua_Hook), makemask(&smask, count), count)
+    };
+    lua_pushvalue(l.clone(), 1);
+    lua_setfield(l.clone(), LUA_REGISTRYINDEX, HOOKKEY);
+    lua_sethook(l, func, mask, count);
+    0
+}
+
+pub fn db_gethook(l: lua_State) -> usize {
+    let mask = lua_gethookmask(l.clone());
+    match lua_gethook(l.clone()) {
+        None => lua_pushnil(l.clone()),
+        Some(hook) if hook as usize != hookf as lua_Hook as usize => {
+            lua_pushstring(l.clone(), "external hook")
+        }
+        Some(_) => {
+            lua_getfield(l.clone(), LUA_REGISTRYINDEX, HOOKKEY);
+        }
+    }
+    lua_pushstring(l.clone(), &unmakemask(mask));
+    lua_pushinteger(l.clone(), lua_gethookcount(l));
+    3
+}
+
+// debug.traceback([message [, level]])，message 不是字符串时原样返回
+pub fn db_traceback(l: lua_State) -> usize {
+    let msg = if lua_isstring(l.clone(), 1) {
+        Some(lua_tostring(l.clone(), 1))
+    } else {
+        None
+    };
+    if msg.is_none() && !lua_isnoneornil(l.clone(), 1) {
+        lua_pushvalue(l, 1);
+    } else {
+        let level = luaL_optinteger(l.clone(), 2, 1) as isize;
+        luaL_traceback(l.clone(), l, msg.as_deref(), level);
+    }
+    1
+}
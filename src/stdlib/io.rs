@@ -0,0 +1,797 @@
+use super::os::create_tmpfile;
+use crate::api::*;
+use crate::state::fmt_g;
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::rc::Rc;
+
+// 默认输入输出文件在注册表中的键
+const IO_PREFIX: &str = "_IO_";
+const IO_INPUT: &str = "_IO_input";
+const IO_OUTPUT: &str = "_IO_output";
+
+const LUAL_BUFFERSIZE: usize = 8192;
+// io.read("n") 最多读取的字符数
+const L_MAXLENNUM: usize = 200;
+// io.lines 最多接受的格式数量
+const MAXARGLINE: isize = 250;
+
+const EBADF: i32 = 9;
+const EINVAL: i32 = 22;
+const ESPIPE: i32 = 29;
+
+#[cfg(windows)]
+const SHELL: (&str, &str) = ("cmd", "/C");
+#[cfg(not(windows))]
+const SHELL: (&str, &str) = ("sh", "-c");
+
+enum Handle {
+    File(File),
+    Stdin,
+    Stdout,
+    Stderr,
+    Pipe(Child),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BufMode {
+    No,
+    Full,
+    Line,
+}
+
+const BUF_MODES: [BufMode; 3] = [BufMode::No, BufMode::Full, BufMode::Line];
+
+// 文件句柄 userdata 中保存的数据，handle 为 None 表示已经关闭。
+// 读写各有自己的缓冲，切换读写方向之前先清空另一边
+struct LStream {
+    handle: Option<Handle>,
+    rbuf: Vec<u8>,
+    rpos: usize,
+    wbuf: Vec<u8>,
+    mode: BufMode,
+    bufsize: usize,
+}
+
+type Stream = Rc<RefCell<LuaUserData>>;
+
+impl LStream {
+    fn new(handle: Handle) -> LStream {
+        let mode = match handle {
+            Handle::Stdout => BufMode::Line,
+            Handle::Stderr => BufMode::No,
+            _ => BufMode::Full,
+        };
+        LStream {
+            handle: Some(handle),
+            rbuf: Vec::new(),
+            rpos: 0,
+            wbuf: Vec::new(),
+            mode,
+            bufsize: LUAL_BUFFERSIZE,
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.handle.is_none()
+    }
+
+    fn is_std(&self) -> bool {
+        matches!(
+            self.handle,
+            Some(Handle::Stdin) | Some(Handle::Stdout) | Some(Handle::Stderr)
+        )
+    }
+
+    fn raw_read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.handle {
+            Some(Handle::File(f)) => f.read(buf),
+            Some(Handle::Stdin) => io::stdin().read(buf),
+            Some(Handle::Pipe(child)) => match &mut child.stdout {
+                Some(out) => out.read(buf),
+                None => Err(io::Error::from_raw_os_error(EBADF)),
+            },
+            _ => Err(io::Error::from_raw_os_error(EBADF)),
+        }
+    }
+
+    fn raw_write(&mut self, buf: &[u8]) -> io::Result<()> {
+        match &mut self.handle {
+            Some(Handle::File(f)) => f.write_all(buf),
+            Some(Handle::Stdout) => io::stdout().write_all(buf),
+            Some(Handle::Stderr) => io::stderr().write_all(buf),
+            Some(Handle::Pipe(child)) => match &mut child.stdin {
+                Some(input) => input.write_all(buf),
+                None => Err(io::Error::from_raw_os_error(EBADF)),
+            },
+            _ => Err(io::Error::from_raw_os_error(EBADF)),
+        }
+    }
+
+    // 读缓冲为空时从文件读入一块，返回 false 表示到达文件末尾
+    fn fill(&mut self) -> io::Result<bool> {
+        self.flush_write()?;
+        let mut buf = vec![0; LUAL_BUFFERSIZE];
+        let n = loop {
+            match self.raw_read(&mut buf) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                res => break res?,
+            }
+        };
+        buf.truncate(n);
+        self.rbuf = buf;
+        self.rpos = 0;
+        Ok(n > 0)
+    }
+
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        if self.rpos >= self.rbuf.len() && !self.fill()? {
+            return Ok(None);
+        }
+        Ok(Some(self.rbuf[self.rpos]))
+    }
+
+    // 读取一行，chop 为 true 时不保留换行符；文件末尾并且什么也没读到时返回 None
+    fn read_line(&mut self, chop: bool) -> io::Result<Option<Vec<u8>>> {
+        let mut line = Vec::new();
+        while self.peek()?.is_some() {
+            let avail = &self.rbuf[self.rpos..];
+            match avail.iter().position(|&c| c == b'\n') {
+                Some(i) => {
+                    line.extend_from_slice(&avail[..i]);
+                    if !chop {
+                        line.push(b'\n');
+                    }
+                    self.rpos += i + 1;
+                    return Ok(Some(line));
+                }
+                None => {
+                    line.extend_from_slice(avail);
+                    self.rpos = self.rbuf.len();
+                }
+            }
+        }
+        Ok(if line.is_empty() { None } else { Some(line) })
+    }
+
+    // 最多读取 n 个字节
+    fn read_chars(&mut self, n: usize) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        while out.len() < n && self.peek()?.is_some() {
+            let k = (n - out.len()).min(self.rbuf.len() - self.rpos);
+            out.extend_from_slice(&self.rbuf[self.rpos..self.rpos + k]);
+            self.rpos += k;
+        }
+        Ok(out)
+    }
+
+    fn read_all(&mut self) -> io::Result<Vec<u8>> {
+        self.read_chars(usize::MAX)
+    }
+
+    // 参考 liolib.c 的 read_number：读入最长的可能是数字的前缀，由调用者转换。
+    // 超过 L_MAXLENNUM 时返回空，表示格式错误
+    fn read_number(&mut self) -> io::Result<Vec<u8>> {
+        let mut rn = ReadNumber {
+            stream: self,
+            buff: Vec::new(),
+            invalid: false,
+        };
+        while matches!(rn.stream.peek()?, Some(c) if c.is_ascii_whitespace() || c == 0x0b) {
+            rn.stream.rpos += 1;
+        }
+        let mut count = 0;
+        let mut hex = false;
+        rn.test2(b"-+")?;
+        if rn.test2(b"00")? {
+            if rn.test2(b"xX")? {
+                hex = true;
+            } else {
+                count = 1;
+            }
+        }
+        count += rn.readdigits(hex)?;
+        if rn.test2(b"..")? {
+            count += rn.readdigits(hex)?;
+        }
+        if count > 0 && rn.test2(if hex { b"pP" } else { b"eE" })? {
+            rn.test2(b"-+")?;
+            rn.readdigits(false)?;
+        }
+        Ok(if rn.invalid { Vec::new() } else { rn.buff })
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.discard_read()?;
+        // 标准输出和 print 共用 Rust 的 stdout 缓冲，保证输出的顺序
+        if self.is_std() {
+            self.raw_write(data)?;
+            return if self.mode == BufMode::No {
+                self.flush()
+            } else {
+                Ok(())
+            };
+        }
+        self.wbuf.extend_from_slice(data);
+        let full = match self.mode {
+            BufMode::No => true,
+            BufMode::Line => data.contains(&b'\n'),
+            BufMode::Full => self.wbuf.len() >= self.bufsize,
+        };
+        if full {
+            self.flush_write()
+        } else {
+            Ok(())
+        }
+    }
+
+    // 写之前丢弃读缓冲，文件位置退回到实际读到的地方
+    fn discard_read(&mut self) -> io::Result<()> {
+        let unread = (self.rbuf.len() - self.rpos) as i64;
+        self.rbuf.clear();
+        self.rpos = 0;
+        match &mut self.handle {
+            Some(Handle::File(f)) if unread > 0 => f.seek(SeekFrom::Current(-unread)).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    fn flush_write(&mut self) -> io::Result<()> {
+        if self.wbuf.is_empty() {
+            return Ok(());
+        }
+        let buf = std::mem::take(&mut self.wbuf);
+        self.raw_write(&buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_write()?;
+        match &mut self.handle {
+            Some(Handle::File(f)) => f.flush(),
+            Some(Handle::Stdout) => io::stdout().flush(),
+            Some(Handle::Stderr) => io::stderr().flush(),
+            Some(Handle::Pipe(child)) => child.stdin.as_mut().map_or(Ok(()), |w| w.flush()),
+            _ => Ok(()),
+        }
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.flush_write()?;
+        let unread = (self.rbuf.len() - self.rpos) as i64;
+        self.rbuf.clear();
+        self.rpos = 0;
+        match &mut self.handle {
+            Some(Handle::File(f)) => match pos {
+                SeekFrom::Current(offset) => f.seek(SeekFrom::Current(offset - unread)),
+                _ => f.seek(pos),
+            },
+            _ => Err(io::Error::from_raw_os_error(ESPIPE)),
+        }
+    }
+
+    fn setvbuf(&mut self, mode: BufMode, size: usize) -> io::Result<()> {
+        self.flush_write()?;
+        self.mode = mode;
+        self.bufsize = size.max(1);
+        Ok(())
+    }
+
+    // 关闭管道时等待子进程结束并返回它的退出状态
+    fn close(&mut self) -> io::Result<Option<ExitStatus>> {
+        let res = self.flush_write();
+        self.rbuf.clear();
+        self.rpos = 0;
+        match self.handle.take() {
+            Some(Handle::Pipe(mut child)) => {
+                drop(child.stdin.take());
+                let status = child.wait();
+                res?;
+                status.map(Some)
+            }
+            _ => res.map(|_| None),
+        }
+    }
+}
+
+impl Drop for LStream {
+    fn drop(&mut self) {
+        let _ = self.flush_write();
+    }
+}
+
+struct ReadNumber<'a> {
+    stream: &'a mut LStream,
+    buff: Vec<u8>,
+    invalid: bool,
+}
+
+impl ReadNumber<'_> {
+    fn nextc(&mut self) -> io::Result<bool> {
+        if self.buff.len() >= L_MAXLENNUM {
+            self.invalid = true;
+            return Ok(false);
+        }
+        if let Some(c) = self.stream.peek()? {
+            self.buff.push(c);
+            self.stream.rpos += 1;
+        }
+        Ok(true)
+    }
+
+    // 下一个字符是 set 中的两个字符之一时读入它
+    fn test2(&mut self, set: &[u8; 2]) -> io::Result<bool> {
+        match self.stream.peek()? {
+            Some(c) if c == set[0] || c == set[1] => self.nextc(),
+            _ => Ok(false),
+        }
+    }
+
+    fn readdigits(&mut self, hex: bool) -> io::Result<usize> {
+        let mut count = 0;
+        while let Some(c) = self.stream.peek()? {
+            let digit = if hex {
+                c.is_ascii_hexdigit()
+            } else {
+                c.is_ascii_digit()
+            };
+            if !digit || !self.nextc()? {
+                break;
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+fn with_stream<R>(p: &Stream, f: impl FnOnce(&mut LStream) -> R) -> R {
+    match p.borrow_mut().downcast_mut::<LStream>() {
+        Some(s) => f(s),
+        None => unreachable!(),
+    }
+}
+
+fn isclosed(p: &Stream) -> bool {
+    with_stream(p, |s| s.is_closed())
+}
+
+fn newfile(l: lua_State, handle: Handle) {
+    lua_newuserdata(l.clone(), Box::new(LStream::new(handle)));
+    luaL_setmetatable(l, LUA_FILEHANDLE);
+}
+
+fn tolstream(l: lua_State) -> Stream {
+    luaL_checkudata(l, 1, LUA_FILEHANDLE)
+}
+
+fn tofile(l: lua_State) -> Stream {
+    let p = tolstream(l.clone());
+    if isclosed(&p) {
+        luaL_error(l, "attempt to use a closed file");
+    }
+    p
+}
+
+// 和 fopen 的 mode 一样：r、w 或 a，可以跟一个 +，最后是任意个 b
+fn check_mode(mode: &str) -> bool {
+    let m = match mode.as_bytes().split_first() {
+        Some((b'r', m)) | Some((b'w', m)) | Some((b'a', m)) => m,
+        _ => return false,
+    };
+    let m = m.strip_prefix(b"+").unwrap_or(m);
+    m.iter().all(|&c| c == b'b')
+}
+
+fn open_file(filename: &str, mode: &str) -> io::Result<File> {
+    let plus = mode.contains('+');
+    let mut options = OpenOptions::new();
+    match mode.as_bytes()[0] {
+        b'r' => options.read(true).write(plus),
+        b'w' => options.write(true).read(plus).create(true).truncate(true),
+        _ => options.append(true).read(plus).create(true),
+    };
+    options.open(filename)
+}
+
+fn opencheckfile(l: lua_State, filename: &str, mode: &str) {
+    match open_file(filename, mode) {
+        Ok(f) => newfile(l, Handle::File(f)),
+        Err(e) => luaL_error(
+            l,
+            &format!("cannot open file '{}' ({})", filename, strerror(&e)),
+        ),
+    }
+}
+
+fn getiofile(l: lua_State, findex: &str) -> Stream {
+    lua_getfield(l.clone(), LUA_REGISTRYINDEX, findex);
+    let p = match lua_touserdata(l.clone(), -1) {
+        Some(p) => p,
+        None => unreachable!(),
+    };
+    if isclosed(&p) {
+        luaL_error(
+            l,
+            &format!("standard {} file is closed", &findex[IO_PREFIX.len()..]),
+        );
+    }
+    p
+}
+
+// 栈上索引 1 处的文件，标准文件和 C 的 io_noclose 一样保持打开
+fn aux_close(l: lua_State) -> usize {
+    let p = tolstream(l.clone());
+    if with_stream(&p, |s| s.is_std()) {
+        lua_pushnil(l.clone());
+        lua_pushstring(l, "cannot close standard file");
+        return 2;
+    }
+    match with_stream(&p, |s| s.close()) {
+        Ok(Some(status)) => luaL_execresult(l, status),
+        res => luaL_fileresult(l, res, None),
+    }
+}
+
+fn g_iofile(l: lua_State, f: &str, mode: &str) -> usize {
+    if !lua_isnoneornil(l.clone(), 1) {
+        if lua_isstring(l.clone(), 1) {
+            let filename = lua_tostring(l.clone(), 1);
+            opencheckfile(l.clone(), &filename, mode);
+        } else {
+            tofile(l.clone());
+            lua_pushvalue(l.clone(), 1);
+        }
+        lua_setfield(l.clone(), LUA_REGISTRYINDEX, f);
+    }
+    lua_getfield(l, LUA_REGISTRYINDEX, f);
+    1
+}
+
+// 文件在索引 1 处，要读取的格式在它后面。返回的迭代器的上值依次是
+// 文件、格式数量、迭代结束时是否关闭文件以及各个格式
+fn aux_lines(l: lua_State, toclose: bool) {
+    let n = lua_gettop(l.clone()) - 1;
+    luaL_argcheck(
+        l.clone(),
+        n <= MAXARGLINE,
+        MAXARGLINE + 2,
+        "too many arguments",
+    );
+    lua_pushinteger(l.clone(), n);
+    lua_pushboolean(l.clone(), toclose);
+    lua_rotate(l.clone(), 2, 2);
+    lua_pushcclosure(l, io_readline, 3 + n);
+}
+
+fn io_readline(l: lua_State) -> usize {
+    let p = match lua_touserdata(l.clone(), lua_upvalueindex(1)) {
+        Some(p) => p,
+        None => unreachable!(),
+    };
+    let n = lua_tointeger(l.clone(), lua_upvalueindex(2)) as isize;
+    if isclosed(&p) {
+        luaL_error(l, "file is already closed");
+    }
+    lua_settop(l.clone(), 1);
+    for i in 1..=n {
+        lua_pushvalue(l.clone(), lua_upvalueindex(3 + i));
+    }
+    let n = g_read(l.clone(), &p, 2) as isize;
+    if lua_toboolean(l.clone(), -n) {
+        return n as usize;
+    }
+    if n > 1 {
+        // 第二个结果是错误信息
+        let msg = lua_tostring(l.clone(), -n + 1);
+        luaL_error(l, &msg);
+    }
+    if lua_toboolean(l.clone(), lua_upvalueindex(3)) {
+        lua_settop(l.clone(), 0);
+        lua_pushvalue(l.clone(), lua_upvalueindex(1));
+        aux_close(l);
+    }
+    0
+}
+
+fn read_line(l: lua_State, p: &Stream, chop: bool) -> io::Result<bool> {
+    let line = with_stream(p, |s| s.read_line(chop))?;
+    let success = line.is_some();
+    lua_pushbytes(l, &line.unwrap_or_default());
+    Ok(success)
+}
+
+fn read_chars(l: lua_State, p: &Stream, n: usize) -> io::Result<bool> {
+    let s = with_stream(p, |s| s.read_chars(n))?;
+    lua_pushbytes(l, &s);
+    Ok(!s.is_empty())
+}
+
+fn test_eof(l: lua_State, p: &Stream) -> io::Result<bool> {
+    let c = with_stream(p, |s| s.peek())?;
+    lua_pushstring(l, "");
+    Ok(c.is_some())
+}
+
+fn read_number(l: lua_State, p: &Stream) -> io::Result<bool> {
+    let buff = with_stream(p, |s| s.read_number())?;
+    match std::str::from_utf8(&buff) {
+        Ok(s) if lua_stringtonumber(l.clone(), s) != 0 => Ok(true),
+        _ => {
+            lua_pushnil(l);
+            Ok(false)
+        }
+    }
+}
+
+// 要读取的格式从 first 开始一直到栈顶，每个格式压入一个结果，
+// 失败的格式压入 nil 并且不再继续
+fn g_read(l: lua_State, p: &Stream, first: isize) -> usize {
+    let nargs = lua_gettop(l.clone()) - 1;
+    let mut n = first;
+    let mut res = Ok(true);
+    if nargs == 0 {
+        res = read_line(l.clone(), p, true);
+        n += 1;
+    } else {
+        while n < first + nargs && matches!(res, Ok(true)) {
+            res = if lua_type(l.clone(), n) == LUA_TNUMBER {
+                let size = luaL_checkinteger(l.clone(), n) as usize;
+                if size == 0 {
+                    test_eof(l.clone(), p)
+                } else {
+                    read_chars(l.clone(), p, size)
+                }
+            } else {
+                let format = luaL_checkstring(l.clone(), n);
+                // 兼容 5.2 的 "*l" 写法
+                let format = format.strip_prefix('*').unwrap_or(&format);
+                match format.bytes().next() {
+                    Some(b'n') => read_number(l.clone(), p),
+                    Some(b'l') => read_line(l.clone(), p, true),
+                    Some(b'L') => read_line(l.clone(), p, false),
+                    Some(b'a') => {
+                        let s = with_stream(p, |s| s.read_all());
+                        s.map(|s| {
+                            lua_pushbytes(l.clone(), &s);
+                            true
+                        })
+                    }
+                    _ => luaL_argerror(l, n, "invalid format"),
+                }
+            };
+            n += 1;
+        }
+    }
+    match res {
+        Err(e) => luaL_fileresult(l, Err::<(), _>(e), None),
+        Ok(success) => {
+            if !success {
+                lua_pop(l.clone(), 1);
+                lua_pushnil(l);
+            }
+            (n - first) as usize
+        }
+    }
+}
+
+// 要写入的值从 arg 开始一直到栈顶之前，文件本身在栈顶，成功时返回它
+fn g_write(l: lua_State, p: &Stream, arg: isize) -> usize {
+    let nargs = lua_gettop(l.clone()) - arg;
+    for arg in arg..arg + nargs {
+        let data = if lua_type(l.clone(), arg) == LUA_TNUMBER {
+            if lua_isinteger(l.clone(), arg) {
+                lua_tointeger(l.clone(), arg).to_string().into_bytes()
+            } else {
+                fmt_g(lua_tonumber(l.clone(), arg), 14).into_bytes()
+            }
+        } else {
+            luaL_checkbytes(l.clone(), arg)
+        };
+        if let Err(e) = with_stream(p, |s| s.write(&data)) {
+            return luaL_fileresult(l, Err::<(), _>(e), None);
+        }
+    }
+    1
+}
+
+pub fn io_close(l: lua_State) -> usize {
+    if lua_isnone(l.clone(), 1) {
+        lua_getfield(l.clone(), LUA_REGISTRYINDEX, IO_OUTPUT);
+    }
+    file_close(l)
+}
+
+pub fn io_flush(l: lua_State) -> usize {
+    let p = getiofile(l.clone(), IO_OUTPUT);
+    luaL_fileresult(l, with_stream(&p, |s| s.flush()), None)
+}
+
+pub fn io_input(l: lua_State) -> usize {
+    g_iofile(l, IO_INPUT, "r")
+}
+
+pub fn io_output(l: lua_State) -> usize {
+    g_iofile(l, IO_OUTPUT, "w")
+}
+
+// 没有文件名时逐行读取默认输入文件，否则打开文件并在读完时关闭它
+pub fn io_lines(l: lua_State) -> usize {
+    if lua_isnone(l.clone(), 1) {
+        lua_pushnil(l.clone());
+    }
+    if lua_isnil(l.clone(), 1) {
+        lua_getfield(l.clone(), LUA_REGISTRYINDEX, IO_INPUT);
+        lua_replace(l.clone(), 1);
+        tofile(l.clone());
+        aux_lines(l, false);
+    } else {
+        let filename = luaL_checkstring(l.clone(), 1);
+        opencheckfile(l.clone(), &filename, "r");
+        lua_replace(l.clone(), 1);
+        aux_lines(l, true);
+    }
+    1
+}
+
+pub fn io_open(l: lua_State) -> usize {
+    let filename = luaL_checkstring(l.clone(), 1);
+    let mode = luaL_optstring(l.clone(), 2, "r");
+    luaL_argcheck(l.clone(), check_mode(&mode), 2, "invalid mode");
+    match open_file(&filename, &mode) {
+        Ok(f) => {
+            newfile(l, Handle::File(f));
+            1
+        }
+        Err(e) => luaL_fileresult(l, Err::<(), _>(e), Some(&filename)),
+    }
+}
+
+// 通过 shell 执行命令，"r" 读取它的标准输出，"w" 写入它的标准输入
+pub fn io_popen(l: lua_State) -> usize {
+    let prog = luaL_checkstring(l.clone(), 1);
+    let mode = luaL_optstring(l.clone(), 2, "r");
+    luaL_argcheck(l.clone(), mode == "r" || mode == "w", 2, "invalid mode");
+    let mut command = Command::new(SHELL.0);
+    command.arg(SHELL.1).arg(&prog);
+    if mode == "r" {
+        command.stdout(Stdio::piped());
+    } else {
+        command.stdin(Stdio::piped());
+    }
+    match command.spawn() {
+        Ok(child) => {
+            newfile(l, Handle::Pipe(child));
+            1
+        }
+        Err(e) => luaL_fileresult(l, Err::<(), _>(e), Some(&prog)),
+    }
+}
+
+pub fn io_read(l: lua_State) -> usize {
+    let p = getiofile(l.clone(), IO_INPUT);
+    g_read(l, &p, 1)
+}
+
+// 临时文件创建后立即删除，关闭文件后不会留下任何东西
+pub fn io_tmpfile(l: lua_State) -> usize {
+    match create_tmpfile() {
+        Some((path, f)) => {
+            let _ = std::fs::remove_file(path);
+            newfile(l, Handle::File(f));
+            1
+        }
+        None => {
+            // io::Error::other 要到 Rust 1.74 才有，仓库用的 nightly 比它早
+            #[allow(unknown_lints, clippy::io_other_error)]
+            let e = io::Error::new(io::ErrorKind::Other, "unable to create a temporary file");
+            luaL_fileresult(l, Err::<(), _>(e), None)
+        }
+    }
+}
+
+pub fn io_type(l: lua_State) -> usize {
+    luaL_checkany(l.clone(), 1);
+    match luaL_testudata(l.clone(), 1, LUA_FILEHANDLE) {
+        Some(p) if isclosed(&p) => lua_pushstring(l, "closed file"),
+        Some(_) => lua_pushstring(l, "file"),
+        None => lua_pushnil(l),
+    }
+    1
+}
+
+pub fn io_write(l: lua_State) -> usize {
+    let p = getiofile(l.clone(), IO_OUTPUT);
+    g_write(l, &p, 1)
+}
+
+pub fn file_close(l: lua_State) -> usize {
+    tofile(l.clone());
+    aux_close(l)
+}
+
+pub fn file_flush(l: lua_State) -> usize {
+    let p = tofile(l.clone());
+    luaL_fileresult(l, with_stream(&p, |s| s.flush()), None)
+}
+
+pub fn file_lines(l: lua_State) -> usize {
+    tofile(l.clone());
+    aux_lines(l, false);
+    1
+}
+
+pub fn file_read(l: lua_State) -> usize {
+    let p = tofile(l.clone());
+    g_read(l, &p, 2)
+}
+
+pub fn file_seek(l: lua_State) -> usize {
+    let p = tofile(l.clone());
+    let op = luaL_checkoption(l.clone(), 2, Some("cur"), &["set", "cur", "end"]);
+    let offset = luaL_optinteger(l.clone(), 3, 0);
+    let res = match op {
+        0 if offset < 0 => Err(io::Error::from_raw_os_error(EINVAL)),
+        0 => with_stream(&p, |s| s.seek(SeekFrom::Start(offset as u64))),
+        1 => with_stream(&p, |s| s.seek(SeekFrom::Current(offset))),
+        _ => with_stream(&p, |s| s.seek(SeekFrom::End(offset))),
+    };
+    match res {
+        Ok(pos) => {
+            lua_pushinteger(l, pos as isize);
+            1
+        }
+        Err(e) => luaL_fileresult(l, Err::<(), _>(e), None),
+    }
+}
+
+pub fn file_setvbuf(l: lua_State) -> usize {
+    let p = tofile(l.clone());
+    let op = luaL_checkoption(l.clone(), 2, None, &["no", "full", "line"]);
+    let size = luaL_optinteger(l.clone(), 3, LUAL_BUFFERSIZE as lua_Integer);
+    let res = with_stream(&p, |s| s.setvbuf(BUF_MODES[op], size as usize));
+    luaL_fileresult(l, res, None)
+}
+
+pub fn file_write(l: lua_State) -> usize {
+    let p = tofile(l.clone());
+    lua_pushvalue(l.clone(), 1);
+    g_write(l, &p, 2)
+}
+
+// __gc 和 __close：关闭还没有关闭的文件
+pub fn file_gc(l: lua_State) -> usize {
+    let p = tolstream(l.clone());
+    if !isclosed(&p) {
+        aux_close(l);
+    }
+    0
+}
+
+pub fn file_tostring(l: lua_State) -> usize {
+    let p = tolstream(l.clone());
+    if isclosed(&p) {
+        lua_pushstring(l, "file (closed)");
+    } else {
+        let s = format!("file ({:#x})", lua_topointer(l.clone(), 1));
+        lua_pushstring(l, &s);
+    }
+    1
+}
+
+// 创建 io.stdin、io.stdout 和 io.stderr，前两个同时作为默认的输入输出文件
+pub fn io_createstdfiles(l: lua_State) {
+    let files = [
+        (Handle::Stdin, Some(IO_INPUT), "stdin"),
+        (Handle::Stdout, Some(IO_OUTPUT), "stdout"),
+        (Handle::Stderr, None, "stderr"),
+    ];
+    for (handle, key, name) in files {
+        newfile(l.clone(), handle);
+        if let Some(key) = key {
+            lua_pushvalue(l.clone(), -1);
+            lua_setfield(l.clone(), LUA_REGISTRYINDEX, key);
+        }
+        lua_setfield(l.clone(), -2, name);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::api::*;
+use std::cell::Cell;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+// 参考 lmathlib.c
+
+// 浮点数可以精确表示为整数时压入整数，否则压入浮点数
+fn pushnumint(l: lua_State, d: f64) {
+    if d >= -(2f64.powi(63)) && d < 2f64.powi(63) {
+        lua_pushinteger(l, d as isize);
+    } else {
+        lua_pushnumber(l, d);
+    }
+}
+
+pub fn math_abs(l: lua_State) -> usize {
+    if lua_isinteger(l.clone(), 1) {
+        // 最小的整数取反后还是它自己
+        let n = lua_tointeger(l.clone(), 1);
+        lua_pushinteger(l, n.wrapping_abs() as isize);
+    } else {
+        let n = luaL_checknumber(l.clone(), 1);
+        lua_pushnumber(l, n.abs());
+    }
+    1
+}
+
+pub fn math_floor(l: lua_State) -> usize {
+    if lua_isinteger(l.clone(), 1) {
+        lua_settop(l, 1);
+    } else {
+        let d = luaL_checknumber(l.clone(), 1).floor();
+        pushnumint(l, d);
+    }
+    1
+}
+
+pub fn math_ceil(l: lua_State) -> usize {
+    if lua_isinteger(l.clone(), 1) {
+        lua_settop(l, 1);
+    } else {
+        let d = luaL_checknumber(l.clone(), 1).ceil();
+        pushnumint(l, d);
+    }
+    1
+}
+
+pub fn math_fmod(l: lua_State) -> usize {
+    if lua_isinteger(l.clone(), 1) && lua_isinteger(l.clone(), 2) {
+        let m = lua_tointeger(l.clone(), 1);
+        let d = lua_tointeger(l.clone(), 2);
+        match d {
+            0 => luaL_argerror(l, 2, "zero"),
+            // 避免 mininteger % -1 溢出
+            -1 => lua_pushinteger(l, 0),
+            d => lua_pushinteger(l, (m % d) as isize),
+        }
+    } else {
+        let a = luaL_checknumber(l.clone(), 1);
+        let b = luaL_checknumber(l.clone(), 2);
+        lua_pushnumber(l, a % b);
+    }
+    1
+}
+
+// 整数部分向零取整，两个结果都是浮点数
+pub fn math_modf(l: lua_State) -> usize {
+    if lua_isinteger(l.clone(), 1) {
+        lua_settop(l.clone(), 1);
+        lua_pushnumber(l, 0.0);
+    } else {
+        let n = luaL_checknumber(l.clone(), 1);
+        let ip = n.trunc();
+        lua_pushnumber(l.clone(), ip);
+        // inf 和 -inf 的小数部分是 0
+        lua_pushnumber(l, if n == ip { 0.0 } else { n - ip });
+    }
+    2
+}
+
+pub fn math_sqrt(l: lua_State) -> usize {
+    let n = luaL_checknumber(l.clone(), 1);
+    lua_pushnumber(l, n.sqrt());
+    1
+}
+
+pub fn math_exp(l: lua_State) -> usize {
+    let n = luaL_checknumber(l.clone(), 1);
+    lua_pushnumber(l, n.exp());
+    1
+}
+
+pub fn math_log(l: lua_State) -> usize {
+    let x = luaL_checknumber(l.clone(), 1);
+    let res = if lua_isnoneornil(l.clone(), 2) {
+        x.ln()
+    } else {
+        let base = luaL_checknumber(l.clone(), 2);
+        if base == 2.0 {
+            x.log2()
+        } else if base == 10.0 {
+            x.log10()
+        } else {
+            x.ln() / base.ln()
+        }
+    };
+    lua_pushnumber(l, res);
+    1
+}
+
+pub fn math_sin(l: lua_State) -> usize {
+    let n = luaL_checknumber(l.clone(), 1);
+    lua_pushnumber(l, n.sin());
+    1
+}
+
+pub fn math_cos(l: lua_State) -> usize {
+    let n = luaL_checknumber(l.clone(), 1);
+    lua_pushnumber(l, n.cos());
+    1
+}
+
+pub fn math_tan(l: lua_State) -> usize {
+    let n = luaL_checknumber(l.clone(), 1);
+    lua_pushnumber(l, n.tan());
+    1
+}
+
+pub fn math_asin(l: lua_State) -> usize {
+    let n = luaL_checknumber(l.clone(), 1);
+    lua_pushnumber(l, n.asin());
+    1
+}
+
+pub fn math_acos(l: lua_State) -> usize {
+    let n = luaL_checknumber(l.clone(), 1);
+    lua_pushnumber(l, n.acos());
+    1
+}
+
+pub fn math_atan(l: lua_State) -> usize {
+    let y = luaL_checknumber(l.clone(), 1);
+    let x = luaL_optnumber(l.clone(), 2, 1.0);
+    lua_pushnumber(l, y.atan2(x));
+    1
+}
+
+pub fn math_deg(l: lua_State) -> usize {
+    let n = luaL_checknumber(l.clone(), 1);
+    lua_pushnumber(l, n * (180.0 / PI));
+    1
+}
+
+pub fn math_rad(l: lua_State) -> usize {
+    let n = luaL_checknumber(l.clone(), 1);
+    lua_pushnumber(l, n * (PI / 180.0));
+    1
+}
+
+pub fn math_tointeger(l: lua_State) -> usize {
+    let mut isnum = false;
+    let n = lua_tointegerx(l.clone(), 1, Some(&mut isnum));
+    if isnum {
+        lua_pushinteger(l, n as isize);
+    } else {
+        luaL_checkany(l.clone(), 1);
+        lua_pushnil(l);
+    }
+    1
+}
+
+pub fn math_type(l: lua_State) -> usize {
+    if lua_type(l.clone(), 1) == LUA_TNUMBER {
+        if lua_isinteger(l.clone(), 1) {
+            lua_pushstring(l, "integer");
+        } else {
+            lua_pushstring(l, "float");
+        }
+    } else {
+        luaL_checkany(l.clone(), 1);
+        lua_pushnil(l);
+    }
+    1
+}
+
+pub fn math_ult(l: lua_State) -> usize {
+    let a = luaL_checkinteger(l.clone(), 1);
+    let b = luaL_checkinteger(l.clone(), 2);
+    lua_pushboolean(l, (a as u64) < (b as u64));
+    1
+}
+
+// 返回参数中最小（max 为 true 时最大）的一个，保持它原来的类型
+fn min_max(l: lua_State, max: bool) -> usize {
+    let n = lua_gettop(l.clone());
+    luaL_argcheck(l.clone(), n >= 1, 1, "value expected");
+    let mut imin = 1;
+    for i in 1..=n {
+        luaL_checknumber(l.clone(), i);
+        let (a, b) = if max { (imin, i) } else { (i, imin) };
+        if lua_compare(l.clone(), a, b, LUA_OPLT) {
+            imin = i;
+        }
+    }
+    lua_pushvalue(l, imin);
+    1
+}
+
+pub fn math_min(l: lua_State) -> usize {
+    min_max(l, false)
+}
+
+pub fn math_max(l: lua_State) -> usize {
+    min_max(l, true)
+}
+
+// 伪随机数使用 xoshiro256**，同样的种子在任何平台上都产生同样的序列。
+// 默认的种子是固定的，需要不同的序列时调用 math.randomseed
+struct RanState {
+    s: Cell<[u64; 4]>,
+}
+
+impl RanState {
+    fn new() -> RanState {
+        let state = RanState {
+            s: Cell::new([0; 4]),
+        };
+        state.setseed(0, 0);
+        state
+    }
+
+    fn nextrand(&self) -> u64 {
+        let mut s = self.s.get();
+        let res = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        self.s.set(s);
+        res
+    }
+
+    fn setseed(&self, n1: u64, n2: u64) {
+        self.s.set([n1, 0xff, n2, 0]);
+        // 丢掉开始的几个值，让种子充分扩散
+        for _ in 0..16 {
+            self.nextrand();
+        }
+    }
+
+    // 把随机数均匀地映射到 [0, n]
+    fn project(&self, ran: u64, n: u64) -> u64 {
+        if n & n.wrapping_add(1) == 0 {
+            return ran & n;
+        }
+        // 不小于 n 的最小的 2^b - 1
+        let lim = u64::MAX >> n.leading_zeros();
+        let mut ran = ran & lim;
+        while ran > n {
+            ran = self.nextrand() & lim;
+        }
+        ran
+    }
+}
+
+fn math_random(l: lua_State, state: &RanState) -> usize {
+    let rv = state.nextrand();
+    let (low, up) = match lua_gettop(l.clone()) {
+        0 => {
+            // 取高 53 位得到 [0, 1) 之间的浮点数
+            lua_pushnumber(l, (rv >> 11) as f64 * 0.5f64.powi(53));
+            return 1;
+        }
+        1 => (1, luaL_checkinteger(l.clone(), 1)),
+        2 => (
+            luaL_checkinteger(l.clone(), 1),
+            luaL_checkinteger(l.clone(), 2),
+        ),
+        _ => luaL_error(l, "wrong number of arguments"),
+    };
+    luaL_argcheck(l.clone(), low <= up, 1, "interval is empty");
+    let n = state.project(rv, (up as u64).wrapping_sub(low as u64));
+    lua_pushinteger(l, n.wrapping_add(low as u64) as isize);
+    1
+}
+
+fn math_randomseed(l: lua_State, state: &RanState) -> usize {
+    let n1 = if lua_isinteger(l.clone(), 1) {
+        lua_tointeger(l.clone(), 1)
+    } else {
+        luaL_checknumber(l.clone(), 1) as i64
+    };
+    let n2 = luaL_optinteger(l, 2, 0);
+    state.setseed(n1 as u64, n2 as u64);
+    0
+}
+
+// random 和 randomseed 共享同一个生成器状态，注册到栈顶的表中
+pub fn math_setrandfunc(l: lua_State) {
+    let state = Rc::new(RanState::new());
+    let s = state.clone();
+    lua_pushrustclosure(l.clone(), move |l| math_random(l, &s), 0);
+    lua_setfield(l.clone(), -2, "random");
+    lua_pushrustclosure(l.clone(), move |l| math_randomseed(l, &state), 0);
+    lua_setfield(l, -2, "randomseed");
+}
@@ -1,3 +1,19 @@
 mod basic;
+mod debug;
+mod io;
+mod math;
+mod os;
+mod package;
+mod string;
+mod table;
+mod utf8;
 
 pub use basic::*;
+pub use debug::*;
+pub use io::*;
+pub use math::*;
+pub use os::*;
+pub use package::*;
+pub use string::*;
+pub use table::*;
+pub use utf8::*;
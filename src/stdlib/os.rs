@@ -0,0 +1,523 @@
+use crate::api::*;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 参考 loslib.c。时间、环境变量和退出都通过 OsHost 交给宿主处理，
+// 测试时可以换成固定的时间，或者拦截 os.exit 而不是结束进程
+pub trait OsHost {
+    // 从 1970-01-01 00:00:00 UTC 开始的秒数
+    fn time(&self) -> i64;
+    // 程序使用的处理器时间，单位是秒
+    fn clock(&self) -> f64;
+    fn getenv(&self, name: &str) -> Option<String>;
+    // 本地时间比 UTC 快的秒数，os.date 和 os.time 用它换算本地时间
+    fn utc_offset(&self, _time: i64) -> i64 {
+        0
+    }
+    // 本地时间在 time 时刻是否处于夏令时
+    fn is_dst(&self, _time: i64) -> bool {
+        false
+    }
+    // 宿主没有结束进程时 os.exit 正常返回
+    fn exit(&self, code: i32, close: bool);
+}
+
+// 默认的宿主，处理器时间和本地时区都来自 C 运行库
+pub struct SystemHost;
+
+impl SystemHost {
+    pub fn new() -> SystemHost {
+        SystemHost
+    }
+}
+
+impl Default for SystemHost {
+    fn default() -> SystemHost {
+        SystemHost::new()
+    }
+}
+
+impl OsHost for SystemHost {
+    fn time(&self) -> i64 {
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        }
+    }
+
+    fn clock(&self) -> f64 {
+        sys::clock()
+    }
+
+    fn getenv(&self, name: &str) -> Option<String> {
+        std::env::var_os(name).map(|v| v.to_string_lossy().into_owned())
+    }
+
+    fn utc_offset(&self, time: i64) -> i64 {
+        sys::utc_offset(time)
+    }
+
+    fn is_dst(&self, time: i64) -> bool {
+        sys::is_dst(time)
+    }
+
+    fn exit(&self, code: i32, _close: bool) {
+        std::process::exit(code)
+    }
+}
+
+// C 运行库中的处理器时间、localtime 和 gmtime
+mod sys {
+    #[cfg(unix)]
+    pub fn clock() -> f64 {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut ts) };
+        ts.tv_sec as f64 + ts.tv_nsec as f64 / 1e9
+    }
+
+    // MSVC 的 CLOCKS_PER_SEC 是 1000
+    #[cfg(windows)]
+    pub fn clock() -> f64 {
+        unsafe { libc::clock() as f64 / 1000.0 }
+    }
+
+    fn broken_down(t: libc::time_t, local: bool) -> Option<libc::tm> {
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        #[cfg(unix)]
+        let ok = unsafe {
+            if local {
+                !libc::localtime_r(&t, &mut tm).is_null()
+            } else {
+                !libc::gmtime_r(&t, &mut tm).is_null()
+            }
+        };
+        #[cfg(windows)]
+        let ok = unsafe {
+            if local {
+                libc::localtime_s(&mut tm, &t) == 0
+            } else {
+                libc::gmtime_s(&mut tm, &t) == 0
+            }
+        };
+        if ok {
+            Some(tm)
+        } else {
+            None
+        }
+    }
+
+    // 把分解后的时间当作 UTC 换算成秒数
+    fn seconds(tm: &libc::tm) -> i64 {
+        let days = super::days_from_civil(
+            tm.tm_year as i64 + 1900,
+            tm.tm_mon as i64 + 1,
+            tm.tm_mday as i64,
+        );
+        days * 86400 + tm.tm_hour as i64 * 3600 + tm.tm_min as i64 * 60 + tm.tm_sec as i64
+    }
+
+    // 同一时刻的本地时间和 UTC 时间之差，无法换算时按 UTC 处理
+    pub fn utc_offset(time: i64) -> i64 {
+        let t = time as libc::time_t;
+        match (broken_down(t, true), broken_down(t, false)) {
+            (Some(local), Some(utc)) => seconds(&local) - seconds(&utc),
+            _ => 0,
+        }
+    }
+
+    pub fn is_dst(time: i64) -> bool {
+        match broken_down(time as libc::time_t, true) {
+            Some(tm) => tm.tm_isdst > 0,
+            None => false,
+        }
+    }
+}
+
+// 日期和天数的换算，参考 http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400;
+    (if m <= 2 { y + 1 } else { y }, m, d)
+}
+
+// ISO 8601 中一年有几周
+fn iso_weeks(y: i64) -> i64 {
+    let p = |y: i64| (y + y.div_euclid(4) - y.div_euclid(100) + y.div_euclid(400)).rem_euclid(7);
+    if p(y) == 4 || p(y - 1) == 3 {
+        53
+    } else {
+        52
+    }
+}
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+// 分解后的时间，相当于 struct tm，但年份和月份不做偏移
+struct DateTime {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    min: i64,
+    sec: i64,
+    // 星期天是 0
+    wday: i64,
+    // 1 月 1 日是 0
+    yday: i64,
+}
+
+impl DateTime {
+    fn from_time(t: i64) -> DateTime {
+        let days = t.div_euclid(86400);
+        let secs = t.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year,
+            month,
+            day,
+            hour: secs / 3600,
+            min: secs / 60 % 60,
+            sec: secs % 60,
+            wday: (days + 4).rem_euclid(7),
+            yday: days - days_from_civil(year, 1, 1),
+        }
+    }
+
+    // ISO 8601 的年份和周数，每周从星期一开始
+    fn iso_week(&self) -> (i64, i64) {
+        let wday = (self.wday + 6) % 7 + 1;
+        let week = (self.yday + 1 - wday + 10) / 7;
+        if week < 1 {
+            (self.year - 1, iso_weeks(self.year - 1))
+        } else if week > iso_weeks(self.year) {
+            (self.year + 1, 1)
+        } else {
+            (self.year, week)
+        }
+    }
+}
+
+// 把 date 中的字段存到栈顶的表中
+fn setallfields(l: lua_State, date: &DateTime, isdst: bool) {
+    let fields = [
+        ("year", date.year),
+        ("month", date.month),
+        ("day", date.day),
+        ("hour", date.hour),
+        ("min", date.min),
+        ("sec", date.sec),
+        ("yday", date.yday + 1),
+        ("wday", date.wday + 1),
+    ];
+    for (k, v) in fields.iter() {
+        lua_pushinteger(l.clone(), *v as isize);
+        lua_setfield(l.clone(), -2, k);
+    }
+    lua_pushboolean(l.clone(), isdst);
+    lua_setfield(l, -2, "isdst");
+}
+
+// 字段的取值范围，避免计算时溢出
+const MAXDATEFIELD: i64 = i32::MAX as i64 / 2;
+
+fn getfield(l: lua_State, key: &str, d: Option<i64>) -> i64 {
+    let t = lua_getfield(l.clone(), -1, key);
+    let mut isnum = false;
+    let res = lua_tointegerx(l.clone(), -1, Some(&mut isnum));
+    let res = if !isnum {
+        match d {
+            _ if t != LUA_TNIL => luaL_error(l, &format!("field '{}' is not an integer", key)),
+            None => luaL_error(l, &format!("field '{}' missing in date table", key)),
+            Some(d) => d,
+        }
+    } else if !(-MAXDATEFIELD..=MAXDATEFIELD).contains(&res) {
+        luaL_error(l, &format!("field '{}' is out-of-bound", key))
+    } else {
+        res
+    };
+    lua_pop(l, 1);
+    res
+}
+
+// 合法的转换说明符，E 和 O 是只能跟在后面几个字符前的修饰符
+const STRFTIME_OPTIONS: &[u8] = b"aAbBcCdDeFgGhHIjmMnprRStTuUVwWxXyYzZ%";
+const STRFTIME_E: &[u8] = b"cCxXyY";
+const STRFTIME_O: &[u8] = b"deHImMSuUVwWy";
+
+// 按照 C locale 的 strftime 格式化一个转换说明符
+fn strftime(b: &mut Vec<u8>, conv: u8, date: &DateTime, offset: i64, utc: bool) {
+    let s = match conv {
+        b'a' => WEEKDAYS[date.wday as usize][..3].to_string(),
+        b'A' => WEEKDAYS[date.wday as usize].to_string(),
+        b'b' | b'h' => MONTHS[date.month as usize - 1][..3].to_string(),
+        b'B' => MONTHS[date.month as usize - 1].to_string(),
+        b'c' => return strftime_str(b, b"%a %b %e %H:%M:%S %Y", date, offset, utc),
+        b'C' => format!("{:02}", date.year.div_euclid(100)),
+        b'd' => format!("{:02}", date.day),
+        b'D' | b'x' => return strftime_str(b, b"%m/%d/%y", date, offset, utc),
+        b'e' => format!("{:2}", date.day),
+        b'F' => return strftime_str(b, b"%Y-%m-%d", date, offset, utc),
+        b'g' => format!("{:02}", date.iso_week().0.rem_euclid(100)),
+        b'G' => date.iso_week().0.to_string(),
+        b'H' => format!("{:02}", date.hour),
+        b'I' => format!("{:02}", (date.hour + 11) % 12 + 1),
+        b'j' => format!("{:03}", date.yday + 1),
+        b'm' => format!("{:02}", date.month),
+        b'M' => format!("{:02}", date.min),
+        b'n' => "\n".to_string(),
+        b'p' => if date.hour < 12 { "AM" } else { "PM" }.to_string(),
+        b'r' => return strftime_str(b, b"%I:%M:%S %p", date, offset, utc),
+        b'R' => return strftime_str(b, b"%H:%M", date, offset, utc),
+        b'S' => format!("{:02}", date.sec),
+        b't' => "\t".to_string(),
+        b'T' | b'X' => return strftime_str(b, b"%H:%M:%S", date, offset, utc),
+        b'u' => ((date.wday + 6) % 7 + 1).to_string(),
+        b'U' => format!("{:02}", (date.yday + 7 - date.wday) / 7),
+        b'V' => format!("{:02}", date.iso_week().1),
+        b'w' => date.wday.to_string(),
+        b'W' => format!("{:02}", (date.yday + 7 - (date.wday + 6) % 7) / 7),
+        b'y' => format!("{:02}", date.year.rem_euclid(100)),
+        b'Y' => date.year.to_string(),
+        b'z' => {
+            let sign = if offset < 0 { '-' } else { '+' };
+            let m = offset.abs() / 60;
+            format!("{}{:02}{:02}", sign, m / 60, m % 60)
+        }
+        // 没有时区名称，UTC 以外的时区用偏移表示
+        b'Z' if utc || offset == 0 => "UTC".to_string(),
+        b'Z' => return strftime(b, b'z', date, offset, utc),
+        _ => "%".to_string(),
+    };
+    b.extend_from_slice(s.as_bytes());
+}
+
+fn strftime_str(b: &mut Vec<u8>, fmt: &[u8], date: &DateTime, offset: i64, utc: bool) {
+    let mut i = 0;
+    while i < fmt.len() {
+        if fmt[i] == b'%' {
+            strftime(b, fmt[i + 1], date, offset, utc);
+            i += 2;
+        } else {
+            b.push(fmt[i]);
+            i += 1;
+        }
+    }
+}
+
+// os.date([format [, time]])：format 以 '!' 开头时使用 UTC，"*t" 返回表
+fn os_date(l: lua_State, host: &dyn OsHost) -> usize {
+    let s = luaL_optbytes(l.clone(), 1, b"%c");
+    let t = if lua_isnoneornil(l.clone(), 2) {
+        host.time()
+    } else {
+        luaL_checkinteger(l.clone(), 2)
+    };
+    let (utc, fmt) = match s.split_first() {
+        Some((b'!', fmt)) => (true, fmt),
+        _ => (false, &s[..]),
+    };
+    let offset = if utc { 0 } else { host.utc_offset(t) };
+    let date = DateTime::from_time(t + offset);
+    if fmt.starts_with(b"*t") {
+        lua_createtable(l.clone(), 0, 9);
+        setallfields(l, &date, !utc && host.is_dst(t));
+        return 1;
+    }
+    let mut b = Vec::new();
+    let mut i = 0;
+    while i < fmt.len() {
+        if fmt[i] != b'%' {
+            b.push(fmt[i]);
+            i += 1;
+            continue;
+        }
+        // 修饰符 E 和 O 对 C locale 没有影响
+        let conv = match (fmt.get(i + 1), fmt.get(i + 2)) {
+            (Some(b'E'), Some(&c)) if STRFTIME_E.contains(&c) => Some((c, 3)),
+            (Some(b'O'), Some(&c)) if STRFTIME_O.contains(&c) => Some((c, 3)),
+            (Some(&c), _) if STRFTIME_OPTIONS.contains(&c) => Some((c, 2)),
+            _ => None,
+        };
+        match conv {
+            Some((c, n)) => {
+                strftime(&mut b, c, &date, offset, utc);
+                i += n;
+            }
+            None => {
+                let conv = String::from_utf8_lossy(&fmt[i + 1..]);
+                luaL_argerror(l, 1, &format!("invalid conversion specifier '%{}'", conv));
+            }
+        }
+    }
+    lua_pushbytes(l, &b);
+    1
+}
+
+// os.time([table])：没有参数时返回当前时间，否则把表中的本地时间转换成时间戳，
+// 并把表中的字段规范化
+fn os_time(l: lua_State, host: &dyn OsHost) -> usize {
+    if lua_isnoneornil(l.clone(), 1) {
+        lua_pushinteger(l, host.time() as isize);
+        return 1;
+    }
+    luaL_checktype(l.clone(), 1, LUA_TTABLE);
+    lua_settop(l.clone(), 1);
+    let year = getfield(l.clone(), "year", None);
+    let month = getfield(l.clone(), "month", None);
+    let day = getfield(l.clone(), "day", None);
+    let hour = getfield(l.clone(), "hour", Some(12));
+    let min = getfield(l.clone(), "min", Some(0));
+    let sec = getfield(l.clone(), "sec", Some(0));
+    let days = days_from_civil(
+        year + (month - 1).div_euclid(12),
+        (month - 1).rem_euclid(12) + 1,
+        1,
+    ) + day
+        - 1;
+    let local = days * 86400 + hour * 3600 + min * 60 + sec;
+    let t = local - host.utc_offset(local);
+    setallfields(l.clone(), &DateTime::from_time(local), host.is_dst(t));
+    lua_pushinteger(l, t as isize);
+    1
+}
+
+fn os_clock(l: lua_State, host: &dyn OsHost) -> usize {
+    lua_pushnumber(l, host.clock());
+    1
+}
+
+fn os_getenv(l: lua_State, host: &dyn OsHost) -> usize {
+    let name = luaL_checkstring(l.clone(), 1);
+    match host.getenv(&name) {
+        Some(value) => lua_pushstring(l, &value),
+        None => lua_pushnil(l),
+    }
+    1
+}
+
+// os.exit([code [, close]])：code 可以是布尔值，true 表示成功
+fn os_exit(l: lua_State, host: &dyn OsHost) -> usize {
+    let code = if lua_isboolean(l.clone(), 1) {
+        if lua_toboolean(l.clone(), 1) {
+            0
+        } else {
+            1
+        }
+    } else {
+        luaL_optinteger(l.clone(), 1, 0) as i32
+    };
+    let close = lua_toboolean(l, 2);
+    host.exit(code, close);
+    0
+}
+
+pub fn os_difftime(l: lua_State) -> usize {
+    let t1 = luaL_checkinteger(l.clone(), 1);
+    let t2 = luaL_checkinteger(l.clone(), 2);
+    lua_pushnumber(l, (t1 - t2) as f64);
+    1
+}
+
+// 和 C 的 remove 一样也可以删除空目录
+pub fn os_remove(l: lua_State) -> usize {
+    let filename = luaL_checkstring(l.clone(), 1);
+    let res = match std::fs::metadata(&filename) {
+        Ok(m) if m.is_dir() => std::fs::remove_dir(&filename),
+        _ => std::fs::remove_file(&filename),
+    };
+    luaL_fileresult(l, res, Some(&filename))
+}
+
+pub fn os_rename(l: lua_State) -> usize {
+    let fromname = luaL_checkstring(l.clone(), 1);
+    let toname = luaL_checkstring(l.clone(), 2);
+    luaL_fileresult(l, std::fs::rename(&fromname, &toname), None)
+}
+
+// 在临时目录中创建一个新的文件，io.tmpfile 也使用它
+pub(crate) fn create_tmpfile() -> Option<(PathBuf, File)> {
+    let dir = std::env::temp_dir();
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    (0..100).find_map(|n| {
+        let suffix = (seed ^ std::process::id()).wrapping_add(n) % 0x100_0000;
+        let path = dir.join(format!("lua_{:06x}", suffix));
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .ok()
+            .map(|file| (path, file))
+    })
+}
+
+pub fn os_tmpname(l: lua_State) -> usize {
+    match create_tmpfile() {
+        Some((path, _)) => lua_pushstring(l, &path.to_string_lossy()),
+        None => luaL_error(l, "unable to generate a unique filename"),
+    }
+    1
+}
+
+type HostFunction = fn(lua_State, &dyn OsHost) -> usize;
+
+// 依赖宿主的函数捕获同一个 host，注册到栈顶的表中
+pub fn os_sethost(l: lua_State, host: Rc<dyn OsHost>) {
+    let funcs: [(&str, HostFunction); 5] = [
+        ("clock", os_clock),
+        ("date", os_date),
+        ("exit", os_exit),
+        ("getenv", os_getenv),
+        ("time", os_time),
+    ];
+    for (name, func) in funcs.iter() {
+        let (host, func) = (host.clone(), *func);
+        lua_pushrustclosure(l.clone(), move |l| func(l, host.as_ref()), 0);
+        lua_setfield(l.clone(), -2, name);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::api::*;
+
+const LUA_LOADED_TABLE: &str = "_LOADED";
+const LUA_PRELOAD_TABLE: &str = "_PRELOAD";
+
+#[cfg(windows)]
+const LUA_DIRSEP: &str = "\\";
+#[cfg(not(windows))]
+const LUA_DIRSEP: &str = "/";
+
+const LUA_PATH_SEP: &str = ";";
+const LUA_PATH_MARK: &str = "?";
+const LUA_EXEC_DIR: &str = "!";
+const LUA_IGMARK: &str = "-";
+// 环境变量中 ";;" 的位置替换成默认路径
+const AUXMARK: &str = "\u{1}";
+
+const LUA_PATH_VAR: &str = "LUA_PATH";
+const LUA_CPATH_VAR: &str = "LUA_CPATH";
+const LUA_VERSUFFIX: &str = "_5_3";
+
+const LUA_ROOT: &str = "/usr/local/";
+const LUA_LDIR: &str = "/usr/local/share/lua/5.3/";
+const LUA_CDIR: &str = "/usr/local/lib/lua/5.3/";
+
+// 不支持加载动态库，package.loadlib 和 C 一样返回这个错误
+const DLMSG: &str = "dynamic libraries not enabled; check your Lua installation";
+
+fn lua_path_default() -> String {
+    format!(
+        "{ldir}?.lua;{ldir}?/init.lua;{cdir}?.lua;{cdir}?/init.lua;./?.lua;./?/init.lua",
+        ldir = LUA_LDIR,
+        cdir = LUA_CDIR
+    )
+}
+
+fn lua_cpath_default() -> String {
+    format!(
+        "{cdir}?.so;{root}lib/lua/5.3/loadall.so;./?.so",
+        cdir = LUA_CDIR,
+        root = LUA_ROOT
+    )
+}
+
+// 依次把 path 中每个模板的 ? 替换成 name，返回第一个可以读取的文件，
+// 否则返回列出所有尝试过的文件的错误信息
+fn searchpath(name: &str, path: &str, sep: &str, dirsep: &str) -> Result<String, String> {
+    let name = if sep.is_empty() {
+        name.to_string()
+    } else {
+        name.replace(sep, dirsep)
+    };
+    let mut msg = String::new();
+    for template in path.split(LUA_PATH_SEP).filter(|t| !t.is_empty()) {
+        let filename = template.replace(LUA_PATH_MARK, &name);
+        if std::fs::File::open(&filename).is_ok() {
+            return Ok(filename);
+        }
+        msg.push_str(&format!("\n\tno file '{}'", filename));
+    }
+    Err(msg)
+}
+
+// package.searchpath(name, path [, sep [, rep]])
+pub fn ll_searchpath(l: lua_State) -> usize {
+    let name = luaL_checkstring(l.clone(), 1);
+    let path = luaL_checkstring(l.clone(), 2);
+    let sep = luaL_optstring(l.clone(), 3, ".");
+    let dirsep = luaL_optstring(l.clone(), 4, LUA_DIRSEP);
+    match searchpath(&name, &path, &sep, &dirsep) {
+        Ok(filename) => {
+            lua_pushstring(l, &filename);
+            1
+        }
+        Err(msg) => {
+            lua_pushnil(l.clone());
+            lua_pushstring(l, &msg);
+            2
+        }
+    }
+}
+
+pub fn ll_loadlib(l: lua_State) -> usize {
+    luaL_checkstring(l.clone(), 1);
+    luaL_checkstring(l.clone(), 2);
+    lua_pushnil(l.clone());
+    lua_pushstring(l.clone(), DLMSG);
+    lua_pushstring(l, "absent");
+    3
+}
+
+// 在上值 package 表的 pname 字段给出的路径中查找模块，找不到时把错误信息压栈
+fn findfile(l: lua_State, name: &str, pname: &str, dirsep: &str) -> Option<String> {
+    lua_getfield(l.clone(), lua_upvalueindex(1), pname);
+    let path = match lua_tolstring(l.clone(), -1) {
+        Some(path) => path,
+        None => luaL_error(l, &format!("'package.{}' must be a string", pname)),
+    };
+    lua_pop(l.clone(), 1);
+    match searchpath(name, &path, ".", dirsep) {
+        Ok(filename) => Some(filename),
+        Err(msg) => {
+            lua_pushstring(l, &msg);
+            None
+        }
+    }
+}
+
+// 加载成功时返回加载函数和文件名，文件名作为加载函数的第二个参数
+fn checkload(l: lua_State, stat: bool, name: &str, filename: &str) -> usize {
+    if stat {
+        lua_pushstring(l, filename);
+        return 2;
+    }
+    let err = lua_tostring(l.clone(), -1);
+    luaL_error(
+        l,
+        &format!(
+            "error loading module '{}' from file '{}':\n\t{}",
+            name, filename, err
+        ),
+    )
+}
+
+pub fn searcher_preload(l: lua_State) -> usize {
+    let name = luaL_checkstring(l.clone(), 1);
+    lua_getfield(l.clone(), LUA_REGISTRYINDEX, LUA_PRELOAD_TABLE);
+    if lua_getfield(l.clone(), -1, &name) == LUA_TNIL {
+        lua_pushstring(l, &format!("\n\tno field package.preload['{}']", name));
+    }
+    1
+}
+
+pub fn searcher_lua(l: lua_State) -> usize {
+    let name = luaL_checkstring(l.clone(), 1);
+    let filename = match findfile(l.clone(), &name, "path", LUA_DIRSEP) {
+        Some(filename) => filename,
+        None => return 1,
+    };
+    let status = match std::fs::read(&filename) {
+        Ok(chunk) => luaL_loadbuffer(l.clone(), &chunk, &format!("@{}", filename)),
+        Err(e) => {
+            let msg = format!("cannot open {}: {}", filename, strerror(&e));
+            lua_pushstring(l.clone(), &msg);
+            LUA_ERRFILE
+        }
+    };
+    checkload(l, status == LUA_OK, &name, &filename)
+}
+
+// 依次调用 package.searchers 中的搜索器，找到时把加载函数和额外的参数留在栈顶
+fn findloader(l: lua_State, name: &str) {
+    if lua_getfield(l.clone(), lua_upvalueindex(1), "searchers") != LUA_TTABLE {
+        luaL_error(l, "'package.searchers' must be a table");
+    }
+    let searchers = lua_gettop(l.clone());
+    let mut msg = String::new();
+    for i in 1.. {
+        if lua_rawgeti(l.clone(), searchers, i) == LUA_TNIL {
+            luaL_error(l, &format!("module '{}' not found:{}", name, msg));
+        }
+        lua_pushstring(l.clone(), name);
+        lua_call(l.clone(), 1, 2);
+        if lua_isfunction(l.clone(), -2) {
+            return;
+        }
+        if lua_isstring(l.clone(), -2) {
+            msg.push_str(&lua_tostring(l.clone(), -2));
+        }
+        lua_pop(l.clone(), 2);
+    }
+}
+
+pub fn ll_require(l: lua_State) -> usize {
+    let name = luaL_checkstring(l.clone(), 1);
+    lua_settop(l.clone(), 1);
+    lua_getfield(l.clone(), LUA_REGISTRYINDEX, LUA_LOADED_TABLE);
+    lua_getfield(l.clone(), 2, &name);
+    if lua_toboolean(l.clone(), -1) {
+        return 1;
+    }
+    lua_pop(l.clone(), 1);
+    findloader(l.clone(), &name);
+    // 模块名是加载函数的第一个参数，搜索器返回的额外值是第二个
+    lua_pushstring(l.clone(), &name);
+    lua_insert(l.clone(), -2);
+    lua_call(l.clone(), 2, 1);
+    if !lua_isnil(l.clone(), -1) {
+        lua_setfield(l.clone(), 2, &name);
+    }
+    if lua_getfield(l.clone(), 2, &name) == LUA_TNIL {
+        lua_pushboolean(l.clone(), true);
+        lua_pushvalue(l.clone(), -1);
+        lua_setfield(l, 2, &name);
+    }
+    1
+}
+
+// package.searchers 中的搜索器都以 package 表作为上值，package 表在栈顶
+pub fn package_createsearchers(l: lua_State) {
+    let searchers: [lua_CFunction; 2] = [searcher_preload, searcher_lua];
+    lua_createtable(l.clone(), searchers.len() as isize, 0);
+    for (i, searcher) in searchers.iter().enumerate() {
+        lua_pushvalue(l.clone(), -2);
+        lua_pushcclosure(l.clone(), *searcher, 1);
+        lua_rawseti(l.clone(), -2, i as isize + 1);
+    }
+    lua_setfield(l, -2, "searchers");
+}
+
+// 优先使用 LUA_PATH_5_3，其次是 LUA_PATH，其中的 ";;" 替换成默认路径
+fn package_setpath(l: lua_State, fieldname: &str, envname: &str, def: &str) {
+    let path = std::env::var(format!("{}{}", envname, LUA_VERSUFFIX))
+        .or_else(|_| std::env::var(envname))
+        .ok();
+    lua_getfield(l.clone(), LUA_REGISTRYINDEX, "LUA_NOENV");
+    let noenv = lua_toboolean(l.clone(), -1);
+    lua_pop(l.clone(), 1);
+    let path = match path {
+        Some(path) if !noenv => {
+            let sep2 = format!("{}{}", LUA_PATH_SEP, LUA_PATH_SEP);
+            let aux = format!("{}{}{}", LUA_PATH_SEP, AUXMARK, LUA_PATH_SEP);
+            path.replace(&sep2, &aux).replace(AUXMARK, def)
+        }
+        _ => def.to_string(),
+    };
+    lua_pushstring(l.clone(), &path);
+    lua_setfield(l, -2, fieldname);
+}
+
+pub fn package_setfields(l: lua_State) {
+    package_setpath(l.clone(), "path", LUA_PATH_VAR, &lua_path_default());
+    package_setpath(l.clone(), "cpath", LUA_CPATH_VAR, &lua_cpath_default());
+    let config = [
+        LUA_DIRSEP,
+        LUA_PATH_SEP,
+        LUA_PATH_MARK,
+        LUA_EXEC_DIR,
+        LUA_IGMARK,
+    ];
+    lua_pushstring(l.clone(), &(config.join("\n") + "\n"));
+    lua_setfield(l.clone(), -2, "config");
+    luaL_getsubtable(l.clone(), LUA_REGISTRYINDEX, LUA_LOADED_TABLE);
+    lua_setfield(l.clone(), -2, "loaded");
+    luaL_getsubtable(l.clone(), LUA_REGISTRYINDEX, LUA_PRELOAD_TABLE);
+    lua_setfield(l, -2, "preload");
+}
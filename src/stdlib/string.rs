@@ -0,0 +1,1429 @@
+use crate::api::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+// 参考 lstrlib.c。字符串按字节处理，位置都是从 1 开始的字节下标
+
+const L_ESC: u8 = b'%';
+const SPECIALS: &[u8] = b"^$*+?.([%-";
+const LUA_MAXCAPTURES: usize = 32;
+// 递归深度的上限
+const MAXCCALLS: usize = 200;
+// 一次匹配尝试中 do_match 被调用次数的上限，避免病态的模式长时间回溯。
+// 线性扫描每个字节大约调用一次，所以上限还要加上源字符串长度的若干倍
+const MAXSTEPS: usize = 1 << 22;
+
+const CAP_UNFINISHED: isize = -1;
+const CAP_POSITION: isize = -2;
+
+// 负数位置从末尾数起
+fn posrelat(pos: i64, len: usize) -> i64 {
+    if pos >= 0 {
+        pos
+    } else if pos.unsigned_abs() > len as u64 {
+        0
+    } else {
+        len as i64 + pos + 1
+    }
+}
+
+fn is_space(c: u8) -> bool {
+    c == b' ' || (b'\t'..=b'\r').contains(&c)
+}
+
+fn match_class(c: u8, cl: u8) -> bool {
+    let res = match cl.to_ascii_lowercase() {
+        b'a' => c.is_ascii_alphabetic(),
+        b'c' => c.is_ascii_control(),
+        b'd' => c.is_ascii_digit(),
+        b'g' => c.is_ascii_graphic(),
+        b'l' => c.is_ascii_lowercase(),
+        b'p' => c.is_ascii_punctuation(),
+        b's' => is_space(c),
+        b'u' => c.is_ascii_uppercase(),
+        b'w' => c.is_ascii_alphanumeric(),
+        b'x' => c.is_ascii_hexdigit(),
+        _ => return cl == c,
+    };
+    if cl.is_ascii_uppercase() {
+        !res
+    } else {
+        res
+    }
+}
+
+struct MatchState<'a> {
+    l: lua_State,
+    src: &'a [u8],
+    pat: &'a [u8],
+    matchdepth: usize,
+    steps: usize,
+    level: usize,
+    // 每个捕获的起始位置和长度，长度也可能是 CAP_UNFINISHED 或 CAP_POSITION
+    capture: [(usize, isize); LUA_MAXCAPTURES],
+}
+
+impl<'a> MatchState<'a> {
+    fn new(l: lua_State, src: &'a [u8], pat: &'a [u8]) -> MatchState<'a> {
+        MatchState {
+            l,
+            src,
+            pat,
+            matchdepth: MAXCCALLS,
+            steps: 0,
+            level: 0,
+            capture: [(0, 0); LUA_MAXCAPTURES],
+        }
+    }
+
+    fn error(&self, message: &str) -> ! {
+        luaL_error(self.l.clone(), message)
+    }
+
+    fn reprepstate(&mut self) {
+        self.level = 0;
+        self.steps = 0;
+        self.matchdepth = MAXCCALLS;
+    }
+
+    fn check_capture(&self, l: u8) -> usize {
+        let l = l as isize - b'1' as isize;
+        if l < 0 || l as usize >= self.level || self.capture[l as usize].1 == CAP_UNFINISHED {
+            self.error(&format!("invalid capture index %{}", l + 1));
+        }
+        l as usize
+    }
+
+    fn capture_to_close(&self) -> usize {
+        match (0..self.level)
+            .rev()
+            .find(|i| self.capture[*i].1 == CAP_UNFINISHED)
+        {
+            Some(level) => level,
+            None => self.error("invalid pattern capture"),
+        }
+    }
+
+    // 返回 p 处的单个字符类之后的位置
+    fn class_end(&self, p: usize) -> usize {
+        let pat = self.pat;
+        let mut p = p;
+        let c = pat[p];
+        p += 1;
+        if c == L_ESC {
+            if p >= pat.len() {
+                self.error("malformed pattern (ends with '%')");
+            }
+            return p + 1;
+        }
+        if c == b'[' {
+            if pat.get(p) == Some(&b'^') {
+                p += 1;
+            }
+            // 第一个 ']' 也属于字符集
+            loop {
+                if p >= pat.len() {
+                    self.error("malformed pattern (missing ']')");
+                }
+                let c = pat[p];
+                p += 1;
+                if c == L_ESC && p < pat.len() {
+                    p += 1;
+                }
+                if pat.get(p) == Some(&b']') {
+                    break;
+                }
+            }
+            return p + 1;
+        }
+        p
+    }
+
+    // p 指向 '['，ec 指向结尾的 ']'
+    fn match_bracket_class(&self, c: u8, p: usize, ec: usize) -> bool {
+        let pat = self.pat;
+        let mut sig = true;
+        let mut p = p + 1;
+        if pat[p] == b'^' {
+            sig = false;
+            p += 1;
+        }
+        while p < ec {
+            if pat[p] == L_ESC {
+                p += 1;
+                if match_class(c, pat[p]) {
+                    return sig;
+                }
+            } else if pat[p + 1] == b'-' && p + 2 < ec {
+                if pat[p] <= c && c <= pat[p + 2] {
+                    return sig;
+                }
+                p += 2;
+            } else if pat[p] == c {
+                return sig;
+            }
+            p += 1;
+        }
+        !sig
+    }
+
+    fn single_match(&self, s: usize, p: usize, ep: usize) -> bool {
+        if s >= self.src.len() {
+            return false;
+        }
+        let c = self.src[s];
+        match self.pat[p] {
+            b'.' => true,
+            L_ESC => match_class(c, self.pat[p + 1]),
+            b'[' => self.match_bracket_class(c, p, ep - 1),
+            pc => pc == c,
+        }
+    }
+
+    fn match_balance(&self, s: usize, p: usize) -> Option<usize> {
+        if p + 1 >= self.pat.len() {
+            self.error("malformed pattern (missing arguments to '%b')");
+        }
+        if self.src.get(s) != Some(&self.pat[p]) {
+            return None;
+        }
+        let (b, e) = (self.pat[p], self.pat[p + 1]);
+        let mut cont = 1;
+        for i in s + 1..self.src.len() {
+            let c = self.src[i];
+            if c == e {
+                cont -= 1;
+                if cont == 0 {
+                    return Some(i + 1);
+                }
+            } else if c == b {
+                cont += 1;
+            }
+        }
+        None
+    }
+
+    fn max_expand(&mut self, s: usize, p: usize, ep: usize) -> Option<usize> {
+        let mut i = 0;
+        while self.single_match(s + i, p, ep) {
+            i += 1;
+        }
+        // 尽可能多地重复，失败时逐个回退
+        loop {
+            if let Some(e) = self.do_match(s + i, ep + 1) {
+                return Some(e);
+            }
+            if i == 0 {
+                return None;
+            }
+            i -= 1;
+        }
+    }
+
+    fn min_expand(&mut self, s: usize, p: usize, ep: usize) -> Option<usize> {
+        let mut s = s;
+        loop {
+            if let Some(e) = self.do_match(s, ep + 1) {
+                return Some(e);
+            }
+            if self.single_match(s, p, ep) {
+                s += 1;
+            } else {
+                return None;
+            }
+        }
+    }
+
+    fn start_capture(&mut self, s: usize, p: usize, what: isize) -> Option<usize> {
+        if self.level >= LUA_MAXCAPTURES {
+            self.error("too many captures");
+        }
+        self.capture[self.level] = (s, what);
+        self.level += 1;
+        let res = self.do_match(s, p);
+        if res.is_none() {
+            self.level -= 1;
+        }
+        res
+    }
+
+    fn end_capture(&mut self, s: usize, p: usize) -> Option<usize> {
+        let l = self.capture_to_close();
+        self.capture[l].1 = (s - self.capture[l].0) as isize;
+        let res = self.do_match(s, p);
+        if res.is_none() {
+            self.capture[l].1 = CAP_UNFINISHED;
+        }
+        res
+    }
+
+    fn match_capture(&self, s: usize, l: u8) -> Option<usize> {
+        let l = self.check_capture(l);
+        let (init, len) = self.capture[l];
+        let len = len as usize;
+        if self.src.len() - s >= len && self.src[init..init + len] == self.src[s..s + len] {
+            Some(s + len)
+        } else {
+            None
+        }
+    }
+
+    // 参考 lstrlib.c 中的 match：从 src[s] 和 pat[p] 开始匹配，成功时返回匹配结束的位置
+    fn do_match(&mut self, s: usize, p: usize) -> Option<usize> {
+        self.matchdepth -= 1;
+        if self.matchdepth == 0 {
+            self.error("pattern too complex");
+        }
+        self.steps += 1;
+        if self.steps > MAXSTEPS + 4 * self.src.len() {
+            self.error("pattern too complex");
+        }
+        let pat = self.pat;
+        let (mut s, mut p) = (s, p);
+        let res = loop {
+            if p == pat.len() {
+                break Some(s);
+            }
+            match (pat[p], pat.get(p + 1).copied()) {
+                (b'(', Some(b')')) => break self.start_capture(s, p + 2, CAP_POSITION),
+                (b'(', _) => break self.start_capture(s, p + 1, CAP_UNFINISHED),
+                (b')', _) => break self.end_capture(s, p + 1),
+                (b'$', None) => break if s == self.src.len() { Some(s) } else { None },
+                (L_ESC, Some(b'b')) => match self.match_balance(s, p + 2) {
+                    Some(e) => {
+                        s = e;
+                        p += 4;
+                    }
+                    None => break None,
+                },
+                (L_ESC, Some(b'f')) => {
+                    p += 2;
+                    if pat.get(p) != Some(&b'[') {
+                        self.error("missing '[' after '%f' in pattern");
+                    }
+                    let ep = self.class_end(p);
+                    // 字符串的开头和结尾都当作 '\0'
+                    let prev = if s == 0 { 0 } else { self.src[s - 1] };
+                    let cur = self.src.get(s).copied().unwrap_or(0);
+                    if self.match_bracket_class(prev, p, ep - 1)
+                        || !self.match_bracket_class(cur, p, ep - 1)
+                    {
+                        break None;
+                    }
+                    p = ep;
+                }
+                (L_ESC, Some(c)) if c.is_ascii_digit() => match self.match_capture(s, c) {
+                    Some(e) => {
+                        s = e;
+                        p += 2;
+                    }
+                    None => break None,
+                },
+                _ => {
+                    let ep = self.class_end(p);
+                    let m = self.single_match(s, p, ep);
+                    match pat.get(ep) {
+                        Some(b'?') => {
+                            if m {
+                                if let Some(e) = self.do_match(s + 1, ep + 1) {
+                                    break Some(e);
+                                }
+                            }
+                            p = ep + 1;
+                        }
+                        Some(b'+') => {
+                            break if m {
+                                self.max_expand(s + 1, p, ep)
+                            } else {
+                                None
+                            }
+                        }
+                        Some(b'*') => break self.max_expand(s, p, ep),
+                        Some(b'-') => break self.min_expand(s, p, ep),
+                        _ => {
+                            if !m {
+                                break None;
+                            }
+                            s += 1;
+                            p = ep;
+                        }
+                    }
+                }
+            }
+        };
+        self.matchdepth += 1;
+        res
+    }
+
+    // 没有捕获时第 0 个捕获是整个匹配
+    fn push_onecapture(&self, i: usize, s: usize, e: usize) {
+        if i >= self.level {
+            if i != 0 {
+                self.error(&format!("invalid capture index %{}", i + 1));
+            }
+            lua_pushbytes(self.l.clone(), &self.src[s..e]);
+            return;
+        }
+        let (init, len) = self.capture[i];
+        match len {
+            CAP_UNFINISHED => self.error("unfinished capture"),
+            CAP_POSITION => lua_pushinteger(self.l.clone(), init as isize + 1),
+            len => lua_pushbytes(self.l.clone(), &self.src[init..init + len as usize]),
+        }
+    }
+
+    // s 为 None 时（string.find）没有捕获就不压入任何值
+    fn push_captures(&self, s: Option<usize>, e: usize) -> usize {
+        let nlevels = if self.level == 0 && s.is_some() {
+            1
+        } else {
+            self.level
+        };
+        if !lua_checkstack(self.l.clone(), nlevels as isize) {
+            self.error("too many captures");
+        }
+        for i in 0..nlevels {
+            self.push_onecapture(i, s.unwrap_or(0), e);
+        }
+        nlevels
+    }
+
+    fn add_s(&self, b: &mut Vec<u8>, s: usize, e: usize) {
+        let l = self.l.clone();
+        let news = lua_tobytes(l.clone(), 3).unwrap_or_default();
+        let mut i = 0;
+        while i < news.len() {
+            if news[i] != L_ESC {
+                b.push(news[i]);
+                i += 1;
+                continue;
+            }
+            i += 1;
+            match news.get(i) {
+                Some(&L_ESC) => b.push(L_ESC),
+                Some(b'0') => b.extend_from_slice(&self.src[s..e]),
+                Some(c) if c.is_ascii_digit() => {
+                    self.push_onecapture((c - b'1') as usize, s, e);
+                    luaL_tolstring(l.clone(), -1);
+                    let value = lua_tobytes(l.clone(), -1).unwrap_or_default();
+                    lua_pop(l.clone(), 2);
+                    b.extend_from_slice(&value);
+                }
+                _ => self.error("invalid use of '%' in replacement string"),
+            }
+            i += 1;
+        }
+    }
+
+    fn add_value(&self, b: &mut Vec<u8>, s: usize, e: usize, tr: isize) {
+        let l = self.l.clone();
+        match tr {
+            LUA_TFUNCTION => {
+                lua_pushvalue(l.clone(), 3);
+                let n = self.push_captures(Some(s), e);
+                lua_call(l.clone(), n as isize, 1);
+            }
+            LUA_TTABLE => {
+                self.push_onecapture(0, s, e);
+                lua_gettable(l.clone(), 3);
+            }
+            _ => return self.add_s(b, s, e),
+        }
+        // 结果是 nil 或 false 时保留原来的子串
+        if !lua_toboolean(l.clone(), -1) {
+            b.extend_from_slice(&self.src[s..e]);
+        } else if !lua_isstring(l.clone(), -1) {
+            let tname = luaL_typename(l.clone(), -1);
+            self.error(&format!("invalid replacement value (a {})", tname));
+        } else {
+            b.extend(lua_tobytes(l.clone(), -1).unwrap_or_default());
+        }
+        lua_pop(l, 1);
+    }
+}
+
+fn find_plain(s: &[u8], p: &[u8]) -> Option<usize> {
+    if p.is_empty() {
+        return Some(0);
+    }
+    s.windows(p.len()).position(|w| w == p)
+}
+
+fn str_find_aux(l: lua_State, find: bool) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    let p = luaL_checkbytes(l.clone(), 2);
+    let (src, pat) = (&s[..], &p[..]);
+    let init = posrelat(luaL_optinteger(l.clone(), 3, 1), src.len()).max(1) as usize;
+    if init > src.len() + 1 {
+        lua_pushnil(l);
+        return 1;
+    }
+    // 显式要求或者模式中没有特殊字符时做普通的子串查找
+    if find && (lua_toboolean(l.clone(), 4) || !pat.iter().any(|c| SPECIALS.contains(c))) {
+        if let Some(i) = find_plain(&src[init - 1..], pat) {
+            lua_pushinteger(l.clone(), (init + i) as isize);
+            lua_pushinteger(l, (init + i + pat.len() - 1) as isize);
+            return 2;
+        }
+    } else {
+        let mut ms = MatchState::new(l.clone(), src, pat);
+        let anchor = pat.first() == Some(&b'^');
+        let p = anchor as usize;
+        let mut s1 = init - 1;
+        loop {
+            ms.reprepstate();
+            if let Some(e) = ms.do_match(s1, p) {
+                if find {
+                    lua_pushinteger(l.clone(), s1 as isize + 1);
+                    lua_pushinteger(l, e as isize);
+                    return ms.push_captures(None, 0) + 2;
+                }
+                return ms.push_captures(Some(s1), e);
+            }
+            if anchor || s1 >= src.len() {
+                break;
+            }
+            s1 += 1;
+        }
+    }
+    lua_pushnil(l);
+    1
+}
+
+pub fn str_find(l: lua_State) -> usize {
+    str_find_aux(l, true)
+}
+
+pub fn str_match(l: lua_State) -> usize {
+    str_find_aux(l, false)
+}
+
+// 迭代函数保存字符串、模式、当前位置和上一次匹配的结束位置，空匹配不会在同一位置出现两次
+pub fn str_gmatch(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    let p = luaL_checkbytes(l.clone(), 2);
+    let pos = Rc::new(Cell::new(0));
+    let lastmatch = Rc::new(Cell::new(None));
+    lua_pushrustclosure(
+        l,
+        move |l: lua_State| {
+            let mut ms = MatchState::new(l, &s, &p);
+            for src in pos.get()..=s.len() {
+                ms.reprepstate();
+                match ms.do_match(src, 0) {
+                    Some(e) if Some(e) != lastmatch.get() => {
+                        pos.set(e);
+                        lastmatch.set(Some(e));
+                        return ms.push_captures(Some(src), e);
+                    }
+                    _ => (),
+                }
+            }
+            0
+        },
+        0,
+    );
+    1
+}
+
+pub fn str_gsub(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    let p = luaL_checkbytes(l.clone(), 2);
+    let (src, pat) = (&s[..], &p[..]);
+    let tr = lua_type(l.clone(), 3);
+    let max_s = luaL_optinteger(l.clone(), 4, src.len() as i64 + 1);
+    luaL_argcheck(
+        l.clone(),
+        tr == LUA_TNUMBER || tr == LUA_TSTRING || tr == LUA_TFUNCTION || tr == LUA_TTABLE,
+        3,
+        "string/function/table expected",
+    );
+    let anchor = pat.first() == Some(&b'^');
+    let p = anchor as usize;
+    let mut ms = MatchState::new(l.clone(), src, pat);
+    let mut b = Vec::with_capacity(src.len());
+    let (mut s, mut n, mut lastmatch) = (0, 0, None);
+    while n < max_s {
+        ms.reprepstate();
+        match ms.do_match(s, p) {
+            Some(e) if Some(e) != lastmatch => {
+                n += 1;
+                ms.add_value(&mut b, s, e, tr);
+                s = e;
+                lastmatch = Some(e);
+            }
+            _ if s < src.len() => {
+                b.push(src[s]);
+                s += 1;
+            }
+            _ => break,
+        }
+        if anchor {
+            break;
+        }
+    }
+    b.extend_from_slice(&src[s..]);
+    lua_pushbytes(l.clone(), &b);
+    lua_pushinteger(l, n as isize);
+    2
+}
+
+// 字符串长度的上限
+const MAXSIZE: usize = i32::MAX as usize;
+
+pub fn str_len(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    lua_pushinteger(l, s.len() as isize);
+    1
+}
+
+pub fn str_sub(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    let len = s.len();
+    let start = posrelat(luaL_checkinteger(l.clone(), 2), len).max(1) as usize;
+    let end = posrelat(luaL_optinteger(l.clone(), 3, -1), len).min(len as i64);
+    if end >= start as i64 {
+        lua_pushbytes(l, &s[start - 1..end as usize]);
+    } else {
+        lua_pushstring(l, "");
+    }
+    1
+}
+
+pub fn str_reverse(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    let b: Vec<u8> = s.into_iter().rev().collect();
+    lua_pushbytes(l, &b);
+    1
+}
+
+pub fn str_lower(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    lua_pushbytes(l, &s.to_ascii_lowercase());
+    1
+}
+
+pub fn str_upper(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    lua_pushbytes(l, &s.to_ascii_uppercase());
+    1
+}
+
+pub fn str_rep(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    let n = luaL_checkinteger(l.clone(), 2);
+    let sep = luaL_optbytes(l.clone(), 3, b"");
+    if n <= 0 || s.len() + sep.len() == 0 {
+        lua_pushstring(l, "");
+        return 1;
+    }
+    let n = n as usize;
+    if (s.len() + sep.len()).saturating_mul(n) > MAXSIZE {
+        luaL_error(l, "resulting string too large");
+    }
+    let mut b = Vec::with_capacity((s.len() + sep.len()) * n);
+    for i in 0..n {
+        if i > 0 {
+            b.extend_from_slice(&sep);
+        }
+        b.extend_from_slice(&s);
+    }
+    lua_pushbytes(l, &b);
+    1
+}
+
+pub fn str_byte(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    let len = s.len();
+    let posi = posrelat(luaL_optinteger(l.clone(), 2, 1), len);
+    let pose = posrelat(luaL_optinteger(l.clone(), 3, posi), len).min(len as i64);
+    let posi = posi.max(1);
+    if posi > pose {
+        return 0;
+    }
+    let n = (pose - posi + 1) as usize;
+    if n >= i32::MAX as usize || !lua_checkstack(l.clone(), n as isize) {
+        luaL_error(l, "string slice too long");
+    }
+    for c in &s[posi as usize - 1..pose as usize] {
+        lua_pushinteger(l.clone(), *c as isize);
+    }
+    n
+}
+
+pub fn str_char(l: lua_State) -> usize {
+    let n = lua_gettop(l.clone());
+    let mut b = Vec::with_capacity(n as usize);
+    for i in 1..=n {
+        let c = luaL_checkinteger(l.clone(), i);
+        luaL_argcheck(l.clone(), (0..=255).contains(&c), i, "value out of range");
+        b.push(c as u8);
+    }
+    lua_pushbytes(l, &b);
+    1
+}
+
+const L_FMTFLAGS: &[u8] = b"-+ #0";
+
+// printf 转换说明中的标志、宽度和精度
+#[derive(Default)]
+struct FormatSpec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alt: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl FormatSpec {
+    // 参考 scanformat：标志最多 5 个，宽度和精度最多两位数字，返回转换字符的位置
+    fn scan(l: lua_State, fmt: &[u8], start: usize) -> (FormatSpec, usize) {
+        let mut spec = FormatSpec::default();
+        let mut p = start;
+        while let Some(c) = fmt.get(p).filter(|c| L_FMTFLAGS.contains(c)) {
+            match c {
+                b'-' => spec.left = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'#' => spec.alt = true,
+                _ => spec.zero = true,
+            }
+            p += 1;
+        }
+        if p - start > L_FMTFLAGS.len() {
+            luaL_error(l, "invalid format (repeated flags)");
+        }
+        let digits = |p: &mut usize| {
+            let mut n = 0;
+            for _ in 0..2 {
+                match fmt.get(*p).filter(|c| c.is_ascii_digit()) {
+                    Some(c) => n = n * 10 + (c - b'0') as usize,
+                    None => break,
+                }
+                *p += 1;
+            }
+            n
+        };
+        spec.width = digits(&mut p);
+        if fmt.get(p) == Some(&b'.') {
+            p += 1;
+            spec.precision = Some(digits(&mut p));
+        }
+        if matches!(fmt.get(p), Some(c) if c.is_ascii_digit()) {
+            luaL_error(l, "invalid format (width or precision too long)");
+        }
+        (spec, p)
+    }
+
+    fn is_plain(&self) -> bool {
+        !(self.left || self.plus || self.space || self.alt || self.zero)
+            && self.width == 0
+            && self.precision.is_none()
+    }
+
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    // 补齐宽度，数字补零时零放在符号和 0x 之类的前缀之后
+    fn pad(&self, prefix: &str, body: &[u8], zero: bool) -> Vec<u8> {
+        let fill = self.width.saturating_sub(prefix.len() + body.len());
+        let mut b = Vec::with_capacity(fill + prefix.len() + body.len());
+        if !self.left && !zero {
+            b.resize(fill, b' ');
+        }
+        b.extend_from_slice(prefix.as_bytes());
+        if !self.left && zero {
+            b.resize(b.len() + fill, b'0');
+        }
+        b.extend_from_slice(body);
+        if self.left {
+            b.resize(b.len() + fill, b' ');
+        }
+        b
+    }
+
+    // 整数转换指定了精度时忽略 '0' 标志
+    fn integer_digits(&self, digits: String, is_zero: bool) -> String {
+        match self.precision {
+            Some(0) if is_zero => String::new(),
+            Some(p) if digits.len() < p => "0".repeat(p - digits.len()) + &digits,
+            _ => digits,
+        }
+    }
+
+    fn format_integer(&self, n: i64) -> Vec<u8> {
+        let digits = self.integer_digits(n.unsigned_abs().to_string(), n == 0);
+        let zero = self.zero && self.precision.is_none();
+        self.pad(self.sign(n < 0), digits.as_bytes(), zero)
+    }
+
+    fn format_unsigned(&self, n: u64, conv: u8) -> Vec<u8> {
+        let digits = match conv {
+            b'o' => format!("{:o}", n),
+            b'x' => format!("{:x}", n),
+            b'X' => format!("{:X}", n),
+            _ => n.to_string(),
+        };
+        let mut digits = self.integer_digits(digits, n == 0);
+        let mut prefix = "";
+        if self.alt {
+            match conv {
+                b'o' if !digits.starts_with('0') => digits.insert(0, '0'),
+                b'x' if n != 0 => prefix = "0x",
+                b'X' if n != 0 => prefix = "0X",
+                _ => (),
+            }
+        }
+        let zero = self.zero && self.precision.is_none();
+        self.pad(prefix, digits.as_bytes(), zero)
+    }
+
+    fn format_float(&self, n: f64, conv: u8) -> Vec<u8> {
+        let upper = conv.is_ascii_uppercase();
+        let mut prefix = self.sign(n.is_sign_negative()).to_string();
+        let body = if n.is_nan() {
+            "nan".to_string()
+        } else if n.is_infinite() {
+            "inf".to_string()
+        } else {
+            let (n, precision) = (n.abs(), self.precision);
+            match conv.to_ascii_lowercase() {
+                b'f' => fmt_fixed(n, precision.unwrap_or(6), self.alt),
+                b'e' => fmt_exp(n, precision.unwrap_or(6), self.alt),
+                b'g' => fmt_general(n, precision.unwrap_or(6), self.alt),
+                _ => {
+                    prefix.push_str("0x");
+                    fmt_hex(n, precision, self.alt)
+                }
+            }
+        };
+        let zero = self.zero && n.is_finite();
+        if upper {
+            let prefix = prefix.to_ascii_uppercase();
+            self.pad(&prefix, body.to_ascii_uppercase().as_bytes(), zero)
+        } else {
+            self.pad(&prefix, body.as_bytes(), zero)
+        }
+    }
+}
+
+// 以下几个函数格式化非负的有限浮点数，对应 %f、%e、%g 和 %a
+fn fmt_fixed(n: f64, precision: usize, alt: bool) -> String {
+    let mut s = format!("{:.*}", precision, n);
+    if alt && precision == 0 {
+        s.push('.');
+    }
+    s
+}
+
+fn fmt_exp(n: f64, precision: usize, alt: bool) -> String {
+    let s = format!("{:.*e}", precision, n);
+    let (mantissa, exp) = s.split_at(s.find('e').unwrap());
+    let exp: i32 = exp[1..].parse().unwrap();
+    let point = if alt && precision == 0 { "." } else { "" };
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{}{}e{}{:02}", mantissa, point, sign, exp.abs())
+}
+
+// 指数 x 满足 -4 <= x < p 时用 %f 的形式，否则用 %e 的形式；没有 '#' 标志时去掉小数末尾的 0
+fn fmt_general(n: f64, precision: usize, alt: bool) -> String {
+    let p = precision.max(1);
+    let s = format!("{:.*e}", p - 1, n);
+    let x: i64 = s[s.find('e').unwrap() + 1..].parse().unwrap();
+    let (mut mantissa, exp) = if x >= -4 && x < p as i64 {
+        (
+            fmt_fixed(n, (p as i64 - 1 - x) as usize, false),
+            String::new(),
+        )
+    } else {
+        let s = fmt_exp(n, p - 1, false);
+        let e = s.find('e').unwrap();
+        (s[..e].to_string(), s[e..].to_string())
+    };
+    if alt {
+        if !mantissa.contains('.') {
+            mantissa.push('.');
+        }
+    } else if mantissa.contains('.') {
+        mantissa = mantissa
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string();
+    }
+    mantissa + &exp
+}
+
+// 十六进制浮点数，不含 "0x" 前缀。没有精度时输出去掉末尾 0 的全部 13 位小数，
+// 有精度时按就近舍入（相等时取偶数）截断
+fn fmt_hex(n: f64, precision: Option<usize>, alt: bool) -> String {
+    const FRAC_DIGITS: usize = 13;
+    let bits = n.to_bits();
+    let biased = (bits >> 52) & 0x7ff;
+    let frac = bits & ((1 << 52) - 1);
+    let (lead, exp) = match (biased, frac) {
+        (0, 0) => (0, 0),
+        (0, _) => (0, -1022),
+        _ => (1, biased as i64 - 1023),
+    };
+    let (lead, digits) = match precision {
+        Some(p) if p < FRAC_DIGITS => {
+            let shift = (FRAC_DIGITS - p) * 4;
+            let m = (lead << 52) | frac;
+            let (rem, half) = (m & ((1 << shift) - 1), 1 << (shift - 1));
+            let mut m = m >> shift;
+            if rem > half || (rem == half && m & 1 == 1) {
+                m += 1;
+            }
+            let digits = if p > 0 {
+                format!("{:0width$x}", m & ((1 << (p * 4)) - 1), width = p)
+            } else {
+                String::new()
+            };
+            (m >> (p * 4), digits)
+        }
+        Some(p) => (
+            lead,
+            format!("{:013x}", frac) + &"0".repeat(p - FRAC_DIGITS),
+        ),
+        None => (
+            lead,
+            format!("{:013x}", frac).trim_end_matches('0').to_string(),
+        ),
+    };
+    let point = if digits.is_empty() && !alt { "" } else { "." };
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{}{}{}p{}{}", lead, point, digits, sign, exp.abs())
+}
+
+// 参考 addquoted：结果可以被 Lua 读回得到同样的字符串
+fn add_quoted(b: &mut Vec<u8>, s: &[u8]) {
+    b.push(b'"');
+    for (i, c) in s.iter().enumerate() {
+        match c {
+            b'"' | b'\\' | b'\n' => {
+                b.push(b'\\');
+                b.push(*c);
+            }
+            b'\r' => b.extend_from_slice(b"\\r"),
+            0 => b.extend_from_slice(b"\\0"),
+            c if c.is_ascii_control() => {
+                // 后面紧跟数字时必须写满三位
+                let next_is_digit = matches!(s.get(i + 1), Some(c) if c.is_ascii_digit());
+                if next_is_digit {
+                    b.extend_from_slice(format!("\\{:03}", c).as_bytes());
+                } else {
+                    b.extend_from_slice(format!("\\{}", c).as_bytes());
+                }
+            }
+            c => b.push(*c),
+        }
+    }
+    b.push(b'"');
+}
+
+fn add_literal(l: lua_State, b: &mut Vec<u8>, arg: isize) {
+    match lua_type(l.clone(), arg) {
+        LUA_TSTRING => add_quoted(b, &lua_tobytes(l, arg).unwrap_or_default()),
+        LUA_TNUMBER if lua_isinteger(l.clone(), arg) => {
+            // 最小的整数写成十进制时会被读成浮点数
+            let n = lua_tointeger(l, arg);
+            let s = if n == i64::MIN {
+                format!("0x{:x}", n)
+            } else {
+                n.to_string()
+            };
+            b.extend_from_slice(s.as_bytes());
+        }
+        LUA_TNUMBER => {
+            let n = lua_tonumber(l, arg);
+            let s = if n.is_nan() {
+                "(0/0)".to_string()
+            } else if n.is_infinite() {
+                let sign = if n < 0.0 { "-" } else { "" };
+                format!("{}1e9999", sign)
+            } else {
+                let sign = if n.is_sign_negative() { "-" } else { "" };
+                format!("{}0x{}", sign, fmt_hex(n.abs(), None, false))
+            };
+            b.extend_from_slice(s.as_bytes());
+        }
+        LUA_TNIL | LUA_TBOOLEAN => {
+            let s = luaL_tolstring(l.clone(), arg);
+            lua_pop(l, 1);
+            b.extend_from_slice(s.as_bytes());
+        }
+        _ => luaL_argerror(l, arg, "value has no literal form"),
+    }
+}
+
+pub fn str_format(l: lua_State) -> usize {
+    let top = lua_gettop(l.clone());
+    let fmt = luaL_checkbytes(l.clone(), 1);
+    let fmt = &fmt[..];
+    let mut b = Vec::with_capacity(fmt.len());
+    let mut arg = 1;
+    let mut i = 0;
+    while i < fmt.len() {
+        let c = fmt[i];
+        i += 1;
+        if c != L_ESC {
+            b.push(c);
+            continue;
+        }
+        if fmt.get(i) == Some(&L_ESC) {
+            b.push(L_ESC);
+            i += 1;
+            continue;
+        }
+        arg += 1;
+        if arg > top {
+            luaL_argerror(l, arg, "no value");
+        }
+        let (spec, p) = FormatSpec::scan(l.clone(), fmt, i);
+        i = p + 1;
+        let conv = fmt.get(p).copied().unwrap_or(0);
+        match conv {
+            b'c' => {
+                let c = luaL_checkinteger(l.clone(), arg) as u8;
+                b.extend(spec.pad("", &[c], false));
+            }
+            b'd' | b'i' => {
+                let n = luaL_checkinteger(l.clone(), arg);
+                b.extend(spec.format_integer(n));
+            }
+            b'o' | b'u' | b'x' | b'X' => {
+                let n = luaL_checkinteger(l.clone(), arg);
+                b.extend(spec.format_unsigned(n as u64, conv));
+            }
+            b'a' | b'A' | b'e' | b'E' | b'f' | b'F' | b'g' | b'G' => {
+                let n = luaL_checknumber(l.clone(), arg);
+                b.extend(spec.format_float(n, conv));
+            }
+            b'q' => add_literal(l.clone(), &mut b, arg),
+            b's' => {
+                luaL_tolstring(l.clone(), arg);
+                let s = lua_tobytes(l.clone(), -1).unwrap_or_default();
+                lua_pop(l.clone(), 1);
+                let s = &s[..];
+                if spec.is_plain() {
+                    b.extend_from_slice(s);
+                } else {
+                    luaL_argcheck(l.clone(), !s.contains(&0), arg, "string contains zeros");
+                    match spec.precision {
+                        // 没有精度的长字符串原样保留，不做填充
+                        None if s.len() >= 100 => b.extend_from_slice(s),
+                        precision => {
+                            let n = precision.map_or(s.len(), |p| p.min(s.len()));
+                            b.extend(spec.pad("", &s[..n], false));
+                        }
+                    }
+                }
+            }
+            _ => {
+                let option = String::from_utf8_lossy(&fmt[p.min(fmt.len())..i.min(fmt.len())]);
+                luaL_error(l, &format!("invalid option '%{}' to 'format'", option));
+            }
+        }
+    }
+    lua_pushbytes(l, &b);
+    1
+}
+
+// string.pack/unpack/packsize，参考 lstrlib.c 中 PACK/UNPACK 部分
+
+// 整数最多可以占用的字节数
+const MAXINTSIZE: usize = 16;
+// lua_Integer 的字节数
+const SZINT: usize = 8;
+// '!' 默认的最大对齐
+const MAXALIGN: usize = 8;
+const PACKPADBYTE: u8 = 0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum KOption {
+    Int,
+    Uint,
+    Float,
+    Double,
+    Char,
+    String,
+    Zstr,
+    Padding,
+    PaddAlign,
+    Nop,
+}
+
+struct Header {
+    l: lua_State,
+    islittle: bool,
+    maxalign: usize,
+}
+
+impl Header {
+    fn new(l: lua_State) -> Header {
+        Header {
+            l,
+            islittle: cfg!(target_endian = "little"),
+            maxalign: 1,
+        }
+    }
+
+    fn getnum(fmt: &[u8], i: &mut usize, df: usize) -> usize {
+        if !matches!(fmt.get(*i), Some(c) if c.is_ascii_digit()) {
+            return df;
+        }
+        let mut a = 0;
+        while let Some(c) = fmt.get(*i).filter(|c| c.is_ascii_digit()) {
+            if a > (MAXSIZE - 9) / 10 {
+                break;
+            }
+            a = a * 10 + (c - b'0') as usize;
+            *i += 1;
+        }
+        a
+    }
+
+    fn getnumlimit(&self, fmt: &[u8], i: &mut usize, df: usize) -> usize {
+        let sz = Header::getnum(fmt, i, df);
+        if sz > MAXINTSIZE || sz == 0 {
+            luaL_error(
+                self.l.clone(),
+                &format!("integral size ({}) out of limits [1,{}]", sz, MAXINTSIZE),
+            );
+        }
+        sz
+    }
+
+    // 读出一个选项和它占用的字节数
+    fn getoption(&mut self, fmt: &[u8], i: &mut usize) -> (KOption, usize) {
+        let opt = fmt[*i];
+        *i += 1;
+        match opt {
+            b'b' => (KOption::Int, 1),
+            b'B' => (KOption::Uint, 1),
+            b'h' => (KOption::Int, 2),
+            b'H' => (KOption::Uint, 2),
+            b'l' | b'j' => (KOption::Int, 8),
+            b'L' | b'J' | b'T' => (KOption::Uint, 8),
+            b'f' => (KOption::Float, 4),
+            b'd' | b'n' => (KOption::Double, 8),
+            b'i' => (KOption::Int, self.getnumlimit(fmt, i, 4)),
+            b'I' => (KOption::Uint, self.getnumlimit(fmt, i, 4)),
+            b's' => (KOption::String, self.getnumlimit(fmt, i, 8)),
+            b'c' => match Header::getnum(fmt, i, usize::MAX) {
+                usize::MAX => luaL_error(self.l.clone(), "missing size for format option 'c'"),
+                size => (KOption::Char, size),
+            },
+            b'z' => (KOption::Zstr, 0),
+            b'x' => (KOption::Padding, 1),
+            b'X' => (KOption::PaddAlign, 0),
+            b' ' => (KOption::Nop, 0),
+            b'<' => {
+                self.islittle = true;
+                (KOption::Nop, 0)
+            }
+            b'>' => {
+                self.islittle = false;
+                (KOption::Nop, 0)
+            }
+            b'=' => {
+                self.islittle = cfg!(target_endian = "little");
+                (KOption::Nop, 0)
+            }
+            b'!' => {
+                self.maxalign = self.getnumlimit(fmt, i, MAXALIGN);
+                (KOption::Nop, 0)
+            }
+            c => luaL_error(
+                self.l.clone(),
+                &format!("invalid format option '{}'", c as char),
+            ),
+        }
+    }
+
+    // 返回选项、大小以及在 totalsize 处需要补齐的字节数。'X' 按照下一个选项的大小对齐，
+    // 并且会消耗掉这个选项
+    fn getdetails(
+        &mut self,
+        totalsize: usize,
+        fmt: &[u8],
+        i: &mut usize,
+    ) -> (KOption, usize, usize) {
+        let (opt, size) = self.getoption(fmt, i);
+        let mut align = size;
+        if opt == KOption::PaddAlign {
+            if *i >= fmt.len() {
+                luaL_argerror(self.l.clone(), 1, "invalid next option for option 'X'");
+            }
+            let (next, next_size) = self.getoption(fmt, i);
+            align = next_size;
+            if next == KOption::Char || align == 0 {
+                luaL_argerror(self.l.clone(), 1, "invalid next option for option 'X'");
+            }
+        }
+        if align <= 1 || opt == KOption::Char {
+            return (opt, size, 0);
+        }
+        if align > self.maxalign {
+            align = self.maxalign;
+        }
+        if !align.is_power_of_two() {
+            luaL_argerror(
+                self.l.clone(),
+                1,
+                "format asks for alignment not power of 2",
+            );
+        }
+        (opt, size, (align - (totalsize & (align - 1))) & (align - 1))
+    }
+}
+
+// 超过 lua_Integer 的高位字节按符号扩展
+fn packint(b: &mut Vec<u8>, n: u64, islittle: bool, size: usize, neg: bool) {
+    let mut buff: Vec<u8> = (0..size)
+        .map(|i| match i {
+            i if i < SZINT => (n >> (i * 8)) as u8,
+            _ if neg => 0xff,
+            _ => 0,
+        })
+        .collect();
+    if !islittle {
+        buff.reverse();
+    }
+    b.extend(buff);
+}
+
+fn unpackint(l: lua_State, s: &[u8], islittle: bool, size: usize, issigned: bool) -> i64 {
+    let byte = |i: usize| if islittle { s[i] } else { s[size - 1 - i] };
+    let limit = size.min(SZINT);
+    let mut res: u64 = 0;
+    for i in (0..limit).rev() {
+        res = (res << 8) | byte(i) as u64;
+    }
+    if size < SZINT {
+        if issigned {
+            let mask = 1u64 << (size * 8 - 1);
+            res = (res ^ mask).wrapping_sub(mask);
+        }
+    } else if size > SZINT {
+        // 多出来的字节只能是符号扩展
+        let mask = if !issigned || (res as i64) >= 0 {
+            0
+        } else {
+            0xff
+        };
+        if (limit..size).any(|i| byte(i) != mask) {
+            luaL_error(
+                l,
+                &format!("{}-byte integer does not fit into Lua Integer", size),
+            );
+        }
+    }
+    res as i64
+}
+
+pub fn str_pack(l: lua_State) -> usize {
+    let fmt = luaL_checkbytes(l.clone(), 1);
+    let mut h = Header::new(l.clone());
+    let mut b = Vec::new();
+    let mut arg = 1;
+    let mut i = 0;
+    while i < fmt.len() {
+        let (opt, size, ntoalign) = h.getdetails(b.len(), &fmt, &mut i);
+        b.resize(b.len() + ntoalign, PACKPADBYTE);
+        match opt {
+            KOption::Int => {
+                arg += 1;
+                let n = luaL_checkinteger(l.clone(), arg);
+                if size < SZINT {
+                    let lim = 1i64 << (size * 8 - 1);
+                    luaL_argcheck(l.clone(), -lim <= n && n < lim, arg, "integer overflow");
+                }
+                packint(&mut b, n as u64, h.islittle, size, n < 0);
+            }
+            KOption::Uint => {
+                arg += 1;
+                let n = luaL_checkinteger(l.clone(), arg);
+                if size < SZINT {
+                    luaL_argcheck(
+                        l.clone(),
+                        (n as u64) < 1u64 << (size * 8),
+                        arg,
+                        "unsigned overflow",
+                    );
+                }
+                packint(&mut b, n as u64, h.islittle, size, false);
+            }
+            KOption::Float => {
+                arg += 1;
+                let n = luaL_checknumber(l.clone(), arg) as f32;
+                if h.islittle {
+                    b.extend_from_slice(&n.to_le_bytes());
+                } else {
+                    b.extend_from_slice(&n.to_be_bytes());
+                }
+            }
+            KOption::Double => {
+                arg += 1;
+                let n = luaL_checknumber(l.clone(), arg);
+                if h.islittle {
+                    b.extend_from_slice(&n.to_le_bytes());
+                } else {
+                    b.extend_from_slice(&n.to_be_bytes());
+                }
+            }
+            KOption::Char => {
+                arg += 1;
+                let s = luaL_checkbytes(l.clone(), arg);
+                luaL_argcheck(
+                    l.clone(),
+                    s.len() <= size,
+                    arg,
+                    "string longer than given size",
+                );
+                b.extend_from_slice(&s);
+                b.resize(b.len() + size - s.len(), PACKPADBYTE);
+            }
+            KOption::String => {
+                arg += 1;
+                let s = luaL_checkbytes(l.clone(), arg);
+                luaL_argcheck(
+                    l.clone(),
+                    size >= SZINT || (s.len() as u64) < 1u64 << (size * 8),
+                    arg,
+                    "string length does not fit in given size",
+                );
+                packint(&mut b, s.len() as u64, h.islittle, size, false);
+                b.extend_from_slice(&s);
+            }
+            KOption::Zstr => {
+                arg += 1;
+                let s = luaL_checkbytes(l.clone(), arg);
+                luaL_argcheck(l.clone(), !s.contains(&0), arg, "string contains zeros");
+                b.extend_from_slice(&s);
+                b.push(0);
+            }
+            KOption::Padding => b.push(PACKPADBYTE),
+            KOption::PaddAlign | KOption::Nop => {}
+        }
+    }
+    lua_pushbytes(l, &b);
+    1
+}
+
+pub fn str_packsize(l: lua_State) -> usize {
+    let fmt = luaL_checkbytes(l.clone(), 1);
+    let mut h = Header::new(l.clone());
+    let mut totalsize = 0;
+    let mut i = 0;
+    while i < fmt.len() {
+        let (opt, size, ntoalign) = h.getdetails(totalsize, &fmt, &mut i);
+        luaL_argcheck(
+            l.clone(),
+            opt != KOption::String && opt != KOption::Zstr,
+            1,
+            "variable-length format",
+        );
+        let size = size + ntoalign;
+        luaL_argcheck(
+            l.clone(),
+            totalsize <= MAXSIZE - size,
+            1,
+            "format result too large",
+        );
+        totalsize += size;
+    }
+    lua_pushinteger(l, totalsize as isize);
+    1
+}
+
+pub fn str_unpack(l: lua_State) -> usize {
+    let fmt = luaL_checkbytes(l.clone(), 1);
+    let data = luaL_checkbytes(l.clone(), 2);
+    let ld = data.len();
+    let pos = posrelat(luaL_optinteger(l.clone(), 3, 1), ld) - 1;
+    luaL_argcheck(
+        l.clone(),
+        pos >= 0 && pos as usize <= ld,
+        3,
+        "initial position out of string",
+    );
+    let mut pos = pos as usize;
+    let mut h = Header::new(l.clone());
+    let mut n = 0;
+    let mut i = 0;
+    while i < fmt.len() {
+        let (opt, size, ntoalign) = h.getdetails(pos, &fmt, &mut i);
+        if ntoalign + size > ld - pos {
+            luaL_argerror(l.clone(), 2, "data string too short");
+        }
+        pos += ntoalign;
+        if !lua_checkstack(l.clone(), 2) {
+            luaL_error(l.clone(), "too many results");
+        }
+        n += 1;
+        let s = &data[pos..];
+        match opt {
+            KOption::Int | KOption::Uint => {
+                let res = unpackint(l.clone(), s, h.islittle, size, opt == KOption::Int);
+                lua_pushinteger(l.clone(), res as isize);
+            }
+            KOption::Float => {
+                let mut buff = [0; 4];
+                buff.copy_from_slice(&s[..4]);
+                let f = if h.islittle {
+                    f32::from_le_bytes(buff)
+                } else {
+                    f32::from_be_bytes(buff)
+                };
+                lua_pushnumber(l.clone(), f as f64);
+            }
+            KOption::Double => {
+                let mut buff = [0; 8];
+                buff.copy_from_slice(&s[..8]);
+                let f = if h.islittle {
+                    f64::from_le_bytes(buff)
+                } else {
+                    f64::from_be_bytes(buff)
+                };
+                lua_pushnumber(l.clone(), f);
+            }
+            KOption::Char => lua_pushbytes(l.clone(), &s[..size]),
+            KOption::String => {
+                let len = unpackint(l.clone(), s, h.islittle, size, false) as u64;
+                luaL_argcheck(
+                    l.clone(),
+                    len <= (ld - pos - size) as u64,
+                    2,
+                    "data string too short",
+                );
+                let len = len as usize;
+                lua_pushbytes(l.clone(), &s[size..size + len]);
+                pos += len;
+            }
+            KOption::Zstr => {
+                let len = match s.iter().position(|&c| c == 0) {
+                    Some(len) => len,
+                    None => luaL_argerror(l.clone(), 2, "unfinished string for format 'z'"),
+                };
+                lua_pushbytes(l.clone(), &s[..len]);
+                pos += len + 1;
+            }
+            KOption::Padding | KOption::PaddAlign | KOption::Nop => n -= 1,
+        }
+        pos += size;
+    }
+    lua_pushinteger(l, pos as isize + 1);
+    n + 1
+}
@@ -0,0 +1,352 @@
+use crate::api::*;
+
+// 参考 ltablib.c。元素都通过 lua_geti/lua_seti 读写，所以会触发 __index/__newindex
+
+// checktab 需要的操作
+const TAB_R: u8 = 1;
+const TAB_W: u8 = 2;
+const TAB_L: u8 = 4;
+const TAB_RW: u8 = TAB_R | TAB_W;
+
+// 不是表的参数也可以，只要它的元表提供了需要的元方法
+fn checktab(l: lua_State, arg: isize, what: u8) {
+    if lua_type(l.clone(), arg) == LUA_TTABLE {
+        return;
+    }
+    if lua_getmetatable(l.clone(), arg) {
+        let fields = [(TAB_R, "__index"), (TAB_W, "__newindex"), (TAB_L, "__len")];
+        let mut n = 1;
+        let mut ok = true;
+        for (flag, key) in fields.iter() {
+            if ok && what & flag != 0 {
+                lua_pushstring(l.clone(), key);
+                n += 1;
+                ok = lua_rawget(l.clone(), -n) != LUA_TNIL;
+            }
+        }
+        lua_pop(l.clone(), n);
+        if ok {
+            return;
+        }
+    }
+    luaL_checktype(l, arg, LUA_TTABLE);
+}
+
+fn aux_getn(l: lua_State, arg: isize, what: u8) -> i64 {
+    checktab(l.clone(), arg, what | TAB_L);
+    luaL_len(l, arg)
+}
+
+pub fn tab_insert(l: lua_State) -> usize {
+    // 插入后的第一个空位
+    let e = aux_getn(l.clone(), 1, TAB_RW).wrapping_add(1);
+    let pos = match lua_gettop(l.clone()) {
+        2 => e,
+        3 => {
+            let pos = luaL_checkinteger(l.clone(), 2);
+            // pos 在 [1, e] 之间
+            luaL_argcheck(
+                l.clone(),
+                (pos as u64).wrapping_sub(1) < e as u64,
+                2,
+                "position out of bounds",
+            );
+            // 向后移动元素
+            for i in (pos + 1..=e).rev() {
+                lua_geti(l.clone(), 1, (i - 1) as isize);
+                lua_seti(l.clone(), 1, i as isize);
+            }
+            pos
+        }
+        _ => luaL_error(l, "wrong number of arguments to 'insert'"),
+    };
+    lua_seti(l, 1, pos as isize);
+    0
+}
+
+pub fn tab_remove(l: lua_State) -> usize {
+    let size = aux_getn(l.clone(), 1, TAB_RW);
+    let mut pos = luaL_optinteger(l.clone(), 2, size);
+    if pos != size {
+        // 空表可以移除位置 0 或 1
+        luaL_argcheck(
+            l.clone(),
+            (pos as u64).wrapping_sub(1) <= size as u64,
+            1,
+            "position out of bounds",
+        );
+    }
+    lua_geti(l.clone(), 1, pos as isize);
+    while pos < size {
+        lua_geti(l.clone(), 1, (pos + 1) as isize);
+        lua_seti(l.clone(), 1, pos as isize);
+        pos += 1;
+    }
+    lua_pushnil(l.clone());
+    lua_seti(l, 1, pos as isize);
+    1
+}
+
+// table.move(a1, f, e, t [,a2])：把 a1[f..e] 复制到 a2[t..]，返回 a2
+pub fn tab_move(l: lua_State) -> usize {
+    let f = luaL_checkinteger(l.clone(), 2);
+    let e = luaL_checkinteger(l.clone(), 3);
+    let t = luaL_checkinteger(l.clone(), 4);
+    let tt = if lua_isnoneornil(l.clone(), 5) { 1 } else { 5 };
+    checktab(l.clone(), 1, TAB_R);
+    checktab(l.clone(), tt, TAB_W);
+    if e >= f {
+        luaL_argcheck(
+            l.clone(),
+            f > 0 || e < i64::MAX + f,
+            3,
+            "too many elements to move",
+        );
+        let n = e - f;
+        luaL_argcheck(l.clone(), t <= i64::MAX - n, 4, "destination wrap around");
+        // 目标区间和源区间重叠并且在它后面时要从后往前复制
+        let forward = t > e || t <= f || (tt != 1 && !lua_compare(l.clone(), 1, tt, LUA_OPEQ));
+        let copy = |i: i64| {
+            lua_geti(l.clone(), 1, (f + i) as isize);
+            lua_seti(l.clone(), tt, (t + i) as isize);
+        };
+        if forward {
+            (0..=n).for_each(copy);
+        } else {
+            (0..=n).rev().for_each(copy);
+        }
+    }
+    lua_pushvalue(l, tt);
+    1
+}
+
+pub fn tab_concat(l: lua_State) -> usize {
+    let last = aux_getn(l.clone(), 1, TAB_R);
+    let sep = luaL_optbytes(l.clone(), 2, b"");
+    let first = luaL_optinteger(l.clone(), 3, 1);
+    let last = luaL_optinteger(l.clone(), 4, last);
+    let mut b = Vec::new();
+    let mut i = first;
+    while i <= last {
+        lua_geti(l.clone(), 1, i as isize);
+        if !lua_isstring(l.clone(), -1) {
+            luaL_error(
+                l,
+                &format!("invalid value (at index {}) in table for 'concat'", i),
+            );
+        }
+        b.extend(lua_tobytes(l.clone(), -1).unwrap_or_default());
+        lua_pop(l.clone(), 1);
+        if i == last {
+            break;
+        }
+        b.extend_from_slice(&sep);
+        i += 1;
+    }
+    lua_pushbytes(l, &b);
+    1
+}
+
+// 所有参数放进一个新表，字段 n 是参数个数
+pub fn tab_pack(l: lua_State) -> usize {
+    let n = lua_gettop(l.clone());
+    lua_createtable(l.clone(), n, 1);
+    lua_insert(l.clone(), 1);
+    for i in (1..=n).rev() {
+        lua_seti(l.clone(), 1, i);
+    }
+    lua_pushinteger(l.clone(), n);
+    lua_setfield(l, 1, "n");
+    1
+}
+
+pub fn tab_unpack(l: lua_State) -> usize {
+    let i = luaL_optinteger(l.clone(), 2, 1);
+    let e = if lua_isnoneornil(l.clone(), 3) {
+        luaL_len(l.clone(), 1)
+    } else {
+        luaL_checkinteger(l.clone(), 3)
+    };
+    if i > e {
+        return 0;
+    }
+    let n = (e as u64).wrapping_sub(i as u64);
+    if n >= i32::MAX as u64 || !lua_checkstack(l.clone(), n as isize + 1) {
+        luaL_error(l, "too many results to unpack");
+    }
+    for k in i..=e {
+        lua_geti(l.clone(), 1, k as isize);
+    }
+    n as usize + 1
+}
+
+// table.sort：快速排序，递归过深时剩下的区间改用堆排序，保证最坏情况也是 O(n log n)。
+// 比较函数不一致时分区会越界，这时报错而不是继续循环
+
+// 比较栈上 a、b 两个位置（都是负数下标）的值，第 2 个参数是比较函数
+fn sort_comp(l: lua_State, a: isize, b: isize) -> bool {
+    if lua_isnil(l.clone(), 2) {
+        return lua_compare(l, a, b, LUA_OPLT);
+    }
+    lua_pushvalue(l.clone(), 2);
+    lua_pushvalue(l.clone(), a - 1);
+    lua_pushvalue(l.clone(), b - 2);
+    lua_call(l.clone(), 2, 1);
+    let res = lua_toboolean(l.clone(), -1);
+    lua_pop(l, 1);
+    res
+}
+
+// 栈顶的两个值分别存到 t[i] 和 t[j]
+fn set2(l: lua_State, i: i64, j: i64) {
+    lua_seti(l.clone(), 1, i as isize);
+    lua_seti(l, 1, j as isize);
+}
+
+// a[i] < a[j]
+fn less(l: lua_State, i: i64, j: i64) -> bool {
+    lua_geti(l.clone(), 1, i as isize);
+    lua_geti(l.clone(), 1, j as isize);
+    let res = sort_comp(l.clone(), -2, -1);
+    lua_pop(l, 2);
+    res
+}
+
+fn swap(l: lua_State, i: i64, j: i64) {
+    lua_geti(l.clone(), 1, i as isize);
+    lua_geti(l.clone(), 1, j as isize);
+    set2(l, i, j);
+}
+
+// 主元在栈顶，a[lo] <= P == a[up - 1] <= a[up]
+fn partition(l: lua_State, lo: i64, up: i64) -> i64 {
+    let mut i = lo;
+    let mut j = up - 1;
+    loop {
+        // a[i] < P
+        loop {
+            i += 1;
+            lua_geti(l.clone(), 1, i as isize);
+            if !sort_comp(l.clone(), -1, -2) {
+                break;
+            }
+            if i == up - 1 {
+                luaL_error(l, "invalid order function for sorting");
+            }
+            lua_pop(l.clone(), 1);
+        }
+        // P < a[j]
+        loop {
+            j -= 1;
+            lua_geti(l.clone(), 1, j as isize);
+            if !sort_comp(l.clone(), -3, -1) {
+                break;
+            }
+            if j < i {
+                luaL_error(l, "invalid order function for sorting");
+            }
+            lua_pop(l.clone(), 1);
+        }
+        if j < i {
+            // 交换主元和 a[i]
+            lua_pop(l.clone(), 1);
+            set2(l, up - 1, i);
+            return i;
+        }
+        set2(l.clone(), i, j);
+    }
+}
+
+fn sift_down(l: lua_State, lo: i64, mut root: i64, end: i64) {
+    loop {
+        let mut child = 2 * root + 1;
+        if child > end {
+            return;
+        }
+        if child < end && less(l.clone(), lo + child, lo + child + 1) {
+            child += 1;
+        }
+        if !less(l.clone(), lo + root, lo + child) {
+            return;
+        }
+        swap(l.clone(), lo + root, lo + child);
+        root = child;
+    }
+}
+
+fn heapsort(l: lua_State, lo: i64, up: i64) {
+    let end = up - lo;
+    for root in (0..=end / 2).rev() {
+        sift_down(l.clone(), lo, root, end);
+    }
+    for last in (1..=end).rev() {
+        swap(l.clone(), lo, lo + last);
+        sift_down(l.clone(), lo, 0, last - 1);
+    }
+}
+
+fn auxsort(l: lua_State, mut lo: i64, mut up: i64, mut depth: usize) {
+    while lo < up {
+        // a[lo] <= a[up]
+        lua_geti(l.clone(), 1, lo as isize);
+        lua_geti(l.clone(), 1, up as isize);
+        if sort_comp(l.clone(), -1, -2) {
+            set2(l.clone(), lo, up);
+        } else {
+            lua_pop(l.clone(), 2);
+        }
+        if up - lo == 1 {
+            break;
+        }
+        if depth == 0 {
+            return heapsort(l, lo, up);
+        }
+        depth -= 1;
+        // 三数取中：a[lo] <= a[p] <= a[up]
+        let p = lo + (up - lo) / 2;
+        lua_geti(l.clone(), 1, p as isize);
+        lua_geti(l.clone(), 1, lo as isize);
+        if sort_comp(l.clone(), -2, -1) {
+            set2(l.clone(), p, lo);
+        } else {
+            lua_pop(l.clone(), 1);
+            lua_geti(l.clone(), 1, up as isize);
+            if sort_comp(l.clone(), -1, -2) {
+                set2(l.clone(), p, up);
+            } else {
+                lua_pop(l.clone(), 2);
+            }
+        }
+        if up - lo == 2 {
+            break;
+        }
+        // 主元留在栈顶，并和 a[up - 1] 交换
+        lua_geti(l.clone(), 1, p as isize);
+        lua_pushvalue(l.clone(), -1);
+        lua_geti(l.clone(), 1, (up - 1) as isize);
+        set2(l.clone(), p, up - 1);
+        let p = partition(l.clone(), lo, up);
+        // 递归处理较小的一半，较大的一半继续循环
+        if p - lo < up - p {
+            auxsort(l.clone(), lo, p - 1, depth);
+            lo = p + 1;
+        } else {
+            auxsort(l.clone(), p + 1, up, depth);
+            up = p - 1;
+        }
+    }
+}
+
+pub fn tab_sort(l: lua_State) -> usize {
+    let n = aux_getn(l.clone(), 1, TAB_RW);
+    if n > 1 {
+        luaL_argcheck(l.clone(), n < i32::MAX as i64, 1, "array too big");
+        if !lua_isnoneornil(l.clone(), 2) {
+            luaL_checktype(l.clone(), 2, LUA_TFUNCTION);
+        }
+        lua_settop(l.clone(), 2);
+        let depth = 2 * (64 - (n as u64).leading_zeros()) as usize;
+        auxsort(l, 1, n, depth);
+    }
+    0
+}
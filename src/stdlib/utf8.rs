@@ -0,0 +1,242 @@
+use crate::api::*;
+
+// 参考 lutf8lib.c。字符串按字节处理，位置都是从 1 开始的字节下标
+
+const MAXUNICODE: u32 = 0x10FFFF;
+// 匹配一个 UTF-8 字符序列的模式
+pub const UTF8PATT: &[u8] = b"[\0-\x7F\xC2-\xF4][\x80-\xBF]*";
+
+fn iscont(s: &[u8], i: usize) -> bool {
+    matches!(s.get(i), Some(c) if c & 0xC0 == 0x80)
+}
+
+fn u_posrelat(pos: i64, len: usize) -> i64 {
+    if pos >= 0 {
+        pos
+    } else if pos.unsigned_abs() > len as u64 {
+        0
+    } else {
+        len as i64 + pos + 1
+    }
+}
+
+// 解码 s[i..] 开头的一个字符，返回码点和下一个字符的位置。
+// 不接受过长的编码和超过 MAXUNICODE 的码点
+fn utf8_decode(s: &[u8], i: usize) -> Option<(u32, usize)> {
+    const LIMITS: [u32; 4] = [0xFF, 0x7F, 0x7FF, 0xFFFF];
+    let mut c = s[i] as u32;
+    if c < 0x80 {
+        return Some((c, i + 1));
+    }
+    let mut res = 0;
+    let mut count = 0;
+    while c & 0x40 != 0 {
+        count += 1;
+        let cc = s.get(i + count).copied().unwrap_or(0) as u32;
+        if cc & 0xC0 != 0x80 {
+            return None;
+        }
+        res = (res << 6) | (cc & 0x3F);
+        c <<= 1;
+    }
+    if count > 3 {
+        return None;
+    }
+    res |= (c & 0x7F) << (count * 5);
+    if res > MAXUNICODE || res <= LIMITS[count] {
+        return None;
+    }
+    Some((res, i + count + 1))
+}
+
+fn utf8_encode(b: &mut Vec<u8>, x: u32) {
+    if x < 0x80 {
+        b.push(x as u8);
+        return;
+    }
+    // 从后往前填充后续字节，mfb 是首字节还能容纳的最大值
+    let mut buff = Vec::with_capacity(4);
+    let mut x = x;
+    let mut mfb = 0x3f;
+    loop {
+        buff.push(0x80 | (x & 0x3f) as u8);
+        x >>= 6;
+        mfb >>= 1;
+        if x <= mfb {
+            break;
+        }
+    }
+    buff.push(((!mfb << 1) | x) as u8);
+    b.extend(buff.iter().rev());
+}
+
+// utf8.len(s [, i [, j]])：遇到无效的字节序列时返回 nil 和它的位置
+pub fn utf8_len(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    let len = s.len();
+    let posi = u_posrelat(luaL_optinteger(l.clone(), 2, 1), len);
+    let posj = u_posrelat(luaL_optinteger(l.clone(), 3, -1), len);
+    luaL_argcheck(
+        l.clone(),
+        1 <= posi && posi - 1 <= len as i64,
+        2,
+        "initial position out of string",
+    );
+    luaL_argcheck(
+        l.clone(),
+        posj - 1 < len as i64,
+        3,
+        "final position out of string",
+    );
+    let mut posi = (posi - 1) as usize;
+    let mut n = 0;
+    while (posi as i64) < posj {
+        match utf8_decode(&s, posi) {
+            Some((_, next)) => posi = next,
+            None => {
+                lua_pushnil(l.clone());
+                lua_pushinteger(l, posi as isize + 1);
+                return 2;
+            }
+        }
+        n += 1;
+    }
+    lua_pushinteger(l, n);
+    1
+}
+
+// utf8.codepoint(s [, i [, j]])：返回 s[i..j] 中所有字符的码点
+pub fn utf8_codepoint(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    let len = s.len();
+    let posi = u_posrelat(luaL_optinteger(l.clone(), 2, 1), len);
+    let pose = u_posrelat(luaL_optinteger(l.clone(), 3, posi), len);
+    luaL_argcheck(l.clone(), posi >= 1, 2, "out of range");
+    luaL_argcheck(l.clone(), pose <= len as i64, 3, "out of range");
+    if posi > pose {
+        return 0;
+    }
+    if pose - posi >= i32::MAX as i64 || !lua_checkstack(l.clone(), (pose - posi + 1) as isize) {
+        luaL_error(l, "string slice too long");
+    }
+    let mut n = 0;
+    let mut i = (posi - 1) as usize;
+    while i < pose as usize {
+        match utf8_decode(&s, i) {
+            Some((code, next)) => {
+                lua_pushinteger(l.clone(), code as isize);
+                i = next;
+            }
+            None => luaL_error(l, "invalid UTF-8 code"),
+        }
+        n += 1;
+    }
+    n
+}
+
+// utf8.char(...)：把每个码点编码后连接起来
+pub fn utf8_char(l: lua_State) -> usize {
+    let n = lua_gettop(l.clone());
+    let mut b = Vec::new();
+    for i in 1..=n {
+        let code = luaL_checkinteger(l.clone(), i);
+        luaL_argcheck(
+            l.clone(),
+            code as u64 <= MAXUNICODE as u64,
+            i,
+            "value out of range",
+        );
+        utf8_encode(&mut b, code as u32);
+    }
+    lua_pushbytes(l, &b);
+    1
+}
+
+// utf8.offset(s, n [, i])：从 i 开始第 n 个字符的起始位置，n 为负数时向前找，
+// n 为 0 时返回 i 所在字符的起始位置
+pub fn utf8_offset(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    let len = s.len() as i64;
+    let mut n = luaL_checkinteger(l.clone(), 2);
+    let posi = if n >= 0 { 1 } else { len + 1 };
+    let posi = u_posrelat(luaL_optinteger(l.clone(), 3, posi), s.len());
+    luaL_argcheck(
+        l.clone(),
+        1 <= posi && posi - 1 <= len,
+        3,
+        "position out of range",
+    );
+    let mut posi = posi - 1;
+    if n == 0 {
+        while posi > 0 && iscont(&s, posi as usize) {
+            posi -= 1;
+        }
+    } else {
+        if iscont(&s, posi as usize) {
+            luaL_error(l, "initial position is a continuation byte");
+        }
+        if n < 0 {
+            while n < 0 && posi > 0 {
+                posi -= 1;
+                while posi > 0 && iscont(&s, posi as usize) {
+                    posi -= 1;
+                }
+                n += 1;
+            }
+        } else {
+            // 第一个字符不用移动
+            n -= 1;
+            while n > 0 && posi < len {
+                posi += 1;
+                while iscont(&s, posi as usize) {
+                    posi += 1;
+                }
+                n -= 1;
+            }
+        }
+    }
+    if n == 0 {
+        lua_pushinteger(l, posi as isize + 1);
+    } else {
+        lua_pushnil(l);
+    }
+    1
+}
+
+fn iter_aux(l: lua_State) -> usize {
+    let s = luaL_checkbytes(l.clone(), 1);
+    let len = s.len();
+    let n = lua_tointeger(l.clone(), 2) - 1;
+    let n = if n < 0 {
+        0
+    } else if (n as usize) < len {
+        // 跳过当前字符
+        let mut n = n as usize + 1;
+        while iscont(&s, n) {
+            n += 1;
+        }
+        n
+    } else {
+        n as usize
+    };
+    if n >= len {
+        return 0;
+    }
+    match utf8_decode(&s, n) {
+        Some((code, next)) if !iscont(&s, next) => {
+            lua_pushinteger(l.clone(), n as isize + 1);
+            lua_pushinteger(l, code as isize);
+            2
+        }
+        _ => luaL_error(l, "invalid UTF-8 code"),
+    }
+}
+
+// for p, c in utf8.codes(s) do ... end
+pub fn utf8_codes(l: lua_State) -> usize {
+    luaL_checkbytes(l.clone(), 1);
+    lua_pushcfunction(l.clone(), iter_aux);
+    lua_pushvalue(l.clone(), 1);
+    lua_pushinteger(l, 0);
+    3
+}
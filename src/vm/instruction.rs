@@ -94,55 +94,87 @@ impl Instruction for u32 {
             OP_GETTABLE => {
                 debug!(self.opname());
                 let (a, b, c) = self.abc();
-                let value = l.get_register(b);
-                assert!(value.is_table());
-                if let LuaValue::Table(table) = value {
-                    let v = table.borrow().get(l.get_rk(c));
-                    l.set_register(a, v)
-                }
+                let t = l.get_register(b);
+                let key = l.get_rk(c);
+                let v = l.index_value(t, key);
+                l.set_register(a, v)
             }
             OP_SETTABLE => {
                 debug!(self.opname());
                 let (a, b, c) = self.abc();
-                let value = l.get_register(a);
-                assert!(value.is_table());
-                if let LuaValue::Table(table) = value {
-                    for _i in 1..=b {
-                        table.borrow_mut().set_hash(l.get_rk(b), l.get_rk(c));
-                    }
-                }
+                let t = l.get_register(a);
+                let (key, value) = (l.get_rk(b), l.get_rk(c));
+                l.new_index(t, key, value);
             }
             OP_NEWTABLE => {
                 debug!(self.opname());
                 let (a, b, c) = self.abc();
-                let v = l.create_table(b, c);
+                let v = LuaValue::new_table(b as usize, c as usize);
                 l.set_register(a, v);
             }
-            OP_ADD => {
+            OP_ADD | OP_SUB | OP_MUL | OP_MOD | OP_POW | OP_DIV | OP_IDIV | OP_BAND | OP_BOR
+            | OP_BXOR | OP_SHL | OP_SHR => {
                 debug!(self.opname());
                 let (a, b, c) = self.abc();
-                if let LuaValue::Integer(b_value) = l.get_rk(b) {
-                    if let LuaValue::Integer(c_value) = l.get_rk(c) {
-                        let add = b_value + c_value;
-                        l.set_register(a, LuaValue::Integer(add));
-                    } else {
-                        panic!()
-                    }
-                } else {
-                    panic!()
+                let (x, y) = (l.get_rk(b), l.get_rk(c));
+                let v = l.arith_op((self.opcode() - OP_ADD) as isize, x, y);
+                l.set_register(a, v);
+            }
+            OP_UNM | OP_BNOT => {
+                debug!(self.opname());
+                let (a, b, _) = self.abc();
+                let x = l.get_register(b);
+                let v = l.arith_op((self.opcode() - OP_ADD) as isize, x.clone(), x);
+                l.set_register(a, v);
+            }
+            OP_NOT => {
+                debug!(self.opname());
+                let (a, b, _) = self.abc();
+                let v = !l.get_register(b).to_boolean();
+                l.set_register(a, LuaValue::Boolean(v));
+            }
+            OP_LEN => {
+                debug!(self.opname());
+                let (a, b, _) = self.abc();
+                let x = l.get_register(b);
+                let v = l.len_value(x);
+                l.set_register(a, v);
+            }
+            OP_CONCAT => {
+                debug!(self.opname());
+                let (a, b, c) = self.abc();
+                let values = (b..=c).map(|i| l.get_register(i)).collect();
+                let v = l.concat_values(values);
+                l.set_register(a, v);
+            }
+            OP_JMP => {
+                debug!(self.opname());
+                let (_, sbx) = self.a_sbx();
+                l.add_pc(sbx);
+            }
+            OP_EQ | OP_LT | OP_LE => {
+                debug!(self.opname());
+                let (a, b, c) = self.abc();
+                let (x, y) = (l.get_rk(b), l.get_rk(c));
+                let result = match self.opcode() {
+                    OP_EQ => l.values_equal(&x, &y),
+                    OP_LT => l.less_than(&x, &y),
+                    _ => l.less_equal(&x, &y),
+                };
+                // 比较结果和 A 不一致时跳过下一条 JMP 指令
+                if result != (a != 0) {
+                    l.add_pc(1);
                 }
             }
             OP_CALL => {
                 debug!(self.opname());
                 let (a, b, c) = self.abc();
-                if let LuaValue::Closure(_) = l.get_register(a) {
-                    l.precall(a, b, c);
-                }
+                l.call_register(a, b, c);
             }
             OP_RETURN => {
                 debug!(self.opname());
-                let (a, b, c) = self.abc();
-                l.postcall(a, b, c);
+                let (a, b, _) = self.abc();
+                l.return_register(a, b);
             }
             OP_SETLIST => {
                 debug!(self.opname());
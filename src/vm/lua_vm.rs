@@ -57,7 +57,8 @@ mod tests {
         let mut cls = |l: &LuaState| {
             debug!("hello");
             assert_eq!(
-                l.stack.stack[2], expect[expect_index],
+                l.stack.borrow().stack[2],
+                expect[expect_index],
                 "expect index {}",
                 expect_index
             );
@@ -92,7 +93,7 @@ mod tests {
             debug!("assert local variable");
             let (i, v) = expect[expect_index].clone();
             assert_eq!(
-                l.stack.stack[i + 1],
+                l.stack.borrow().stack[i + 1],
                 v,
                 "register {} with expect index {}",
                 i,
@@ -115,25 +116,25 @@ mod tests {
         // 1	[1]	NEWTABLE 	0 3 0
         expect_closure.push(Box::new(|l: &LuaState| {
             assert_eq!(
-                l.stack.stack[2],
+                l.stack.borrow().stack[2],
                 LuaValue::Table(Rc::new(RefCell::new(LuaTable::new(3, 0))))
             )
         }));
         // 2	[1]	LOADK    	1 -1	; 88
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[3], LuaValue::Integer(88));
+            assert_eq!(l.stack.borrow().stack[3], LuaValue::Integer(88));
         }));
         // 3	[1]	LOADK    	2 -2	; 11
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[4], LuaValue::Integer(11))
+            assert_eq!(l.stack.borrow().stack[4], LuaValue::Integer(11))
         }));
         // 4	[1]	LOADK    	3 -3	; 3
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[5], LuaValue::Integer(03))
+            assert_eq!(l.stack.borrow().stack[5], LuaValue::Integer(03))
         }));
         // 5	[1]	SETLIST  	0 3 1	; 1
         expect_closure.push(Box::new(|l: &LuaState| {
-            if let LuaValue::Table(table) = &l.stack.stack[2] {
+            if let LuaValue::Table(table) = &l.stack.borrow().stack[2] {
                 assert_eq!(table.borrow_mut().len(), 3);
                 assert_eq!(table.borrow_mut().get_array(1), LuaValue::Integer(88));
                 assert_eq!(table.borrow_mut().get_array(2), LuaValue::Integer(11));
@@ -144,12 +145,12 @@ mod tests {
         }));
         // 6	[2]	SETTABLE 	0 -4 -5	; "sweethui" 881103
         expect_closure.push(Box::new(|l: &LuaState| {
-            if let LuaValue::Table(table) = &l.stack.stack[2] {
+            if let LuaValue::Table(table) = &l.stack.borrow().stack[2] {
                 // assert_eq!(table.borrow_mut().len(), 3);
                 assert_eq!(
                     table
                         .borrow_mut()
-                        .get_hash(LuaValue::String("sweethui".to_string())),
+                        .get_hash(LuaValue::String("sweethui".into())),
                     LuaValue::Integer(881103)
                 );
             } else {
@@ -158,15 +159,15 @@ mod tests {
         }));
         // 7	[3]	GETTABLE 	1 0 -4	; "sweethui"
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[3], LuaValue::Integer(881103));
+            assert_eq!(l.stack.borrow().stack[3], LuaValue::Integer(881103));
         }));
         // 8	[4]	GETTABLE 	2 0 -6	; 1
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[4], LuaValue::Integer(88));
+            assert_eq!(l.stack.borrow().stack[4], LuaValue::Integer(88));
         }));
         // 9	[2]	RETURN   	0 1
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[4], LuaValue::Integer(88));
+            assert_eq!(l.stack.borrow().stack[4], LuaValue::Integer(88));
         }));
         let mut expect_fun = |l: &LuaState| {
             debug!("assert table");
@@ -189,7 +190,7 @@ mod tests {
         // 1	[9] 	CLOSURE  	0 0	; 0x7fd20d4063c0
         expect_closure.push(Box::new(|l: &LuaState| {
             debug!("CLOSURE  	0 0");
-            if let LuaValue::Closure(_) = l.stack.stack[2] {
+            if let LuaValue::Closure(_) = l.stack.borrow().stack[2] {
             } else {
                 assert!(false, "expect function")
             }
@@ -197,7 +198,7 @@ mod tests {
         // 2	[11]	MOVE     	1 0
         expect_closure.push(Box::new(|l: &LuaState| {
             debug!("MOVE     	1 0");
-            if let LuaValue::Closure(_) = l.stack.stack[3] {
+            if let LuaValue::Closure(_) = l.stack.borrow().stack[3] {
             } else {
                 assert!(false, "expect function")
             }
@@ -205,37 +206,37 @@ mod tests {
         // 3	[11]	LOADK    	2 -1	; 11
         expect_closure.push(Box::new(|l: &LuaState| {
             debug!("LOADK    	2 -1	; 11");
-            assert_eq!(l.stack.stack[4], LuaValue::Integer(11))
+            assert_eq!(l.stack.borrow().stack[4], LuaValue::Integer(11))
         }));
         // 4	[11]	LOADK    	3 -2	; 3
         expect_closure.push(Box::new(|l: &LuaState| {
             debug!("LOADK    	3 -2	; 3");
-            assert_eq!(l.stack.stack[5], LuaValue::Integer(3))
+            assert_eq!(l.stack.borrow().stack[5], LuaValue::Integer(3))
         }));
         // 5	[11]	CALL     	1 3 2
         expect_closure.push(Box::new(|l: &LuaState| {
             debug!("CALL     	1 3 2");
-            if let LuaValue::Closure(_) = l.stack.stack[3] {
+            if let LuaValue::Closure(_) = l.stack.borrow().stack[3] {
             } else {
                 assert!(false, "expect function")
             }
-            assert_eq!(l.stack.stack[4], LuaValue::Integer(11));
-            assert_eq!(l.stack.stack[5], LuaValue::Integer(3));
+            assert_eq!(l.stack.borrow().stack[4], LuaValue::Integer(11));
+            assert_eq!(l.stack.borrow().stack[5], LuaValue::Integer(3));
         }));
         // 1	[8]	ADD      	2 0 1
         expect_closure.push(Box::new(|l: &LuaState| {
             debug!("ADD      	2 0 1");
-            assert_eq!(l.stack.stack[6], LuaValue::Integer(14))
+            assert_eq!(l.stack.borrow().stack[6], LuaValue::Integer(14))
         }));
         // 2	[8]	RETURN   	2 2
         expect_closure.push(Box::new(|l: &LuaState| {
             debug!("RETURN   	2 2");
-            assert_eq!(l.stack.stack[3], LuaValue::Integer(14))
+            assert_eq!(l.stack.borrow().stack[3], LuaValue::Integer(14))
         }));
         // 6	[11]	RETURN   	0 1
         expect_closure.push(Box::new(|l: &LuaState| {
             debug!("RETURN   	0 1");
-            assert_eq!(l.stack.stack[3], LuaValue::Integer(14))
+            assert_eq!(l.stack.borrow().stack[3], LuaValue::Integer(14))
         }));
 
         let mut expect_fun = |l: &LuaState| {
@@ -257,16 +258,16 @@ mod tests {
         let mut expect_closure: Vec<Box<dyn FnMut(&LuaState)>> = Vec::new();
         // 1	[6] 	LOADK    	0 -1
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[2], LuaValue::Integer(88));
+            assert_eq!(l.stack.borrow().stack[2], LuaValue::Integer(88));
         }));
         // 2	[6] 	LOADK    	1 -2
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[3], LuaValue::Integer(11));
+            assert_eq!(l.stack.borrow().stack[3], LuaValue::Integer(11));
         }));
         // 3	[11]	CLOSURE  	2 0
         expect_closure.push(Box::new(|l: &LuaState| {
             debug!("CLOSURE  	2 0");
-            if let LuaValue::Closure(_) = l.stack.stack[4] {
+            if let LuaValue::Closure(_) = l.stack.borrow().stack[4] {
             } else {
                 assert!(false, "expect function")
             }
@@ -274,7 +275,7 @@ mod tests {
         // 4	[12]	MOVE     	3 2
         expect_closure.push(Box::new(|l: &LuaState| {
             debug!("MOVE     	3 2");
-            if let LuaValue::Closure(_) = l.stack.stack[5] {
+            if let LuaValue::Closure(_) = l.stack.borrow().stack[5] {
             } else {
                 assert!(false, "expect function")
             }
@@ -282,41 +283,41 @@ mod tests {
         // 5	[12]	CALL     	3 1 1
         expect_closure.push(Box::new(|l: &LuaState| {
             debug!("CALL     	3 1 1");
-            if let LuaValue::Closure(_) = l.stack.stack[5] {
+            if let LuaValue::Closure(_) = l.stack.borrow().stack[5] {
             } else {
                 assert!(false, "expect function")
             }
         }));
         // 1	[8] 	GETUPVAL 	0 0
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[6], LuaValue::Integer(88));
+            assert_eq!(l.stack.borrow().stack[6], LuaValue::Integer(88));
         }));
         // 2	[9] 	GETUPVAL 	1 1
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[7], LuaValue::Integer(11));
+            assert_eq!(l.stack.borrow().stack[7], LuaValue::Integer(11));
         }));
         // 3	[10]	MOVE     	2 0
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[8], LuaValue::Integer(88));
+            assert_eq!(l.stack.borrow().stack[8], LuaValue::Integer(88));
         }));
         // 4	[10]	MOVE     	3 1
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[9], LuaValue::Integer(11));
+            assert_eq!(l.stack.borrow().stack[9], LuaValue::Integer(11));
         }));
         // 5	[10]	RETURN   	2 3
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[5], LuaValue::Integer(88));
-            assert_eq!(l.stack.stack[6], LuaValue::Integer(11));
+            assert_eq!(l.stack.borrow().stack[5], LuaValue::Integer(88));
+            assert_eq!(l.stack.borrow().stack[6], LuaValue::Integer(11));
         }));
         // 6	[11]	RETURN   	0 1
         // 6	[13]	ADD      	3 3 4
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[5], LuaValue::Integer(99));
-            assert_eq!(l.stack.stack[6], LuaValue::Integer(11));
+            assert_eq!(l.stack.borrow().stack[5], LuaValue::Integer(99));
+            assert_eq!(l.stack.borrow().stack[6], LuaValue::Integer(11));
         }));
         // 7	[13]	RETURN   	0 1
         expect_closure.push(Box::new(|l: &LuaState| {
-            assert_eq!(l.stack.stack[5], LuaValue::Integer(99));
+            assert_eq!(l.stack.borrow().stack[5], LuaValue::Integer(99));
         }));
 
         let mut expect_fun = |l: &LuaState| {
@@ -22,3 +22,82 @@ fn push_is_test() {
     assert_eq!(lua_gettop(l.clone()), 5);
     assert!(lua_isnumber(l.clone(), -1));
 }
+
+#[test]
+fn to_number_test() {
+    debug!("test lua_tonumberx & lua_tointegerx");
+    let l = luaL_newstate();
+    lua_pushinteger(l.clone(), 1103);
+    lua_pushnumber(l.clone(), 3.0);
+    lua_pushnumber(l.clone(), 3.5);
+    lua_pushstring(l.clone(), " 0x10 ");
+    lua_pushstring(l.clone(), "1e2");
+    lua_pushstring(l.clone(), "sweethui");
+    let mut isnum = false;
+    assert_eq!(lua_tointegerx(l.clone(), 1, Some(&mut isnum)), 1103);
+    assert!(isnum);
+    assert_eq!(lua_tointeger(l.clone(), 2), 3);
+    assert_eq!(lua_tointegerx(l.clone(), 3, Some(&mut isnum)), 0);
+    assert!(!isnum);
+    assert_eq!(lua_tonumber(l.clone(), 3), 3.5);
+    assert_eq!(lua_tointeger(l.clone(), 4), 16);
+    assert_eq!(lua_tointeger(l.clone(), 5), 100);
+    assert_eq!(lua_tonumber(l.clone(), 5), 100.0);
+    assert_eq!(lua_tonumberx(l.clone(), 6, Some(&mut isnum)), 0.0);
+    assert!(!isnum);
+    assert!(lua_isnumber(l.clone(), 4));
+    assert!(!lua_isnumber(l.clone(), 6));
+    assert_eq!(lua_tonumberx(l.clone(), 7, Some(&mut isnum)), 0.0);
+    assert!(!isnum);
+}
+
+#[test]
+fn to_string_test() {
+    debug!("test lua_tolstring & lua_toboolean");
+    let l = luaL_newstate();
+    lua_pushinteger(l.clone(), 88);
+    lua_pushnumber(l.clone(), 11.0);
+    lua_pushnumber(l.clone(), 0.1);
+    lua_pushboolean(l.clone(), false);
+    lua_pushnil(l.clone());
+    assert_eq!(lua_tolstring(l.clone(), 1), Some("88".to_string()));
+    // 数字被原地转换为字符串
+    assert_eq!(lua_type(l.clone(), 1), LUA_TSTRING);
+    assert_eq!(lua_tostring(l.clone(), 2), "11.0".to_string());
+    assert_eq!(lua_tostring(l.clone(), 3), "0.1".to_string());
+    assert_eq!(lua_tolstring(l.clone(), 4), None);
+    assert!(lua_toboolean(l.clone(), 1));
+    assert!(!lua_toboolean(l.clone(), 4));
+    assert!(!lua_toboolean(l.clone(), 5));
+    assert!(!lua_toboolean(l.clone(), 6));
+}
+
+#[test]
+fn rawlen_typename_test() {
+    debug!("test lua_rawlen, lua_stringtonumber & lua_typename");
+    let l = luaL_newstate();
+    lua_pushstring(l.clone(), "sweethui");
+    assert_eq!(lua_rawlen(l.clone(), -1), 8);
+    lua_createtable(l.clone(), 4, 0);
+    for i in 1..=3 {
+        lua_pushinteger(l.clone(), i);
+        lua_rawseti(l.clone(), -2, i);
+    }
+    assert_eq!(lua_rawlen(l.clone(), -1), 3);
+    lua_pushinteger(l.clone(), 4);
+    lua_rawseti(l.clone(), -2, 4);
+    lua_pushinteger(l.clone(), 5);
+    lua_rawseti(l.clone(), -2, 5);
+    assert_eq!(lua_rawlen(l.clone(), -1), 5);
+    assert_eq!(lua_rawlen(l.clone(), 1103), 0);
+
+    assert_eq!(lua_stringtonumber(l.clone(), "0x1p4"), 6);
+    assert_eq!(lua_tonumber(l.clone(), -1), 16.0);
+    assert_eq!(lua_stringtonumber(l.clone(), "12abc"), 0);
+    assert_eq!(lua_gettop(l.clone()), 3);
+
+    assert_eq!(lua_typename(l.clone(), lua_type(l.clone(), 1)), "string");
+    assert_eq!(lua_typename(l.clone(), lua_type(l.clone(), 2)), "table");
+    assert_eq!(lua_type(l.clone(), 10), LUA_TNONE);
+    assert_eq!(lua_typename(l.clone(), LUA_TNONE), "no value");
+}
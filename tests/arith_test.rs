@@ -0,0 +1,214 @@
+use llua::api::*;
+use llua::debug;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+fn error_message<F: FnOnce()>(f: F) -> String {
+    let err = catch_unwind(AssertUnwindSafe(f)).unwrap_err();
+    err.downcast_ref::<LuaError>().unwrap().message.clone()
+}
+
+// 创建一个带有 __add/__eq/__lt/__concat/__len 元方法的表
+fn new_vector(l: lua_State, x: isize) {
+    fn add(l: lua_State) -> usize {
+        lua_getfield(l.clone(), 1, "x");
+        lua_getfield(l.clone(), 2, "x");
+        lua_arith(l.clone(), LUA_OPADD);
+        1
+    }
+    fn lt(l: lua_State) -> usize {
+        lua_getfield(l.clone(), 1, "x");
+        lua_getfield(l.clone(), 2, "x");
+        let r = lua_compare(l.clone(), -2, -1, LUA_OPLT);
+        lua_pushboolean(l, r);
+        1
+    }
+    fn eq(l: lua_State) -> usize {
+        lua_getfield(l.clone(), 1, "x");
+        lua_getfield(l.clone(), 2, "x");
+        let r = lua_rawequal(l.clone(), -2, -1);
+        lua_pushboolean(l, r);
+        1
+    }
+    fn concat(l: lua_State) -> usize {
+        lua_pushstring(l, "vector");
+        1
+    }
+    fn len(l: lua_State) -> usize {
+        lua_pushinteger(l, 1103);
+        1
+    }
+    lua_newtable(l.clone());
+    lua_pushinteger(l.clone(), x);
+    lua_setfield(l.clone(), -2, "x");
+    lua_newtable(l.clone());
+    lua_pushcfunction(l.clone(), add);
+    lua_setfield(l.clone(), -2, "__add");
+    lua_pushcfunction(l.clone(), lt);
+    lua_setfield(l.clone(), -2, "__lt");
+    lua_pushcfunction(l.clone(), eq);
+    lua_setfield(l.clone(), -2, "__eq");
+    lua_pushcfunction(l.clone(), concat);
+    lua_setfield(l.clone(), -2, "__concat");
+    lua_pushcfunction(l.clone(), len);
+    lua_setfield(l.clone(), -2, "__len");
+    lua_setmetatable(l, -2);
+}
+
+#[test]
+fn arith_test() {
+    debug!("test lua_arith");
+    let l = luaL_newstate();
+    let cases: &[(isize, i64, i64, i64)] = &[
+        (LUA_OPADD, 88, 11, 99),
+        (LUA_OPSUB, 88, 11, 77),
+        (LUA_OPMUL, 88, 11, 968),
+        (LUA_OPMOD, -7, 3, 2),
+        (LUA_OPIDIV, -7, 2, -4),
+        (LUA_OPBAND, 6, 3, 2),
+        (LUA_OPBOR, 6, 3, 7),
+        (LUA_OPBXOR, 6, 3, 5),
+        (LUA_OPSHL, 1, 4, 16),
+        (LUA_OPSHR, -1, 60, 15),
+    ];
+    for &(op, a, b, expect) in cases {
+        lua_pushinteger(l.clone(), a as isize);
+        lua_pushinteger(l.clone(), b as isize);
+        lua_arith(l.clone(), op);
+        assert!(lua_isinteger(l.clone(), -1));
+        assert_eq!(lua_tointeger(l.clone(), -1), expect);
+        lua_pop(l.clone(), 1);
+    }
+
+    lua_pushinteger(l.clone(), 7);
+    lua_pushinteger(l.clone(), 2);
+    lua_arith(l.clone(), LUA_OPDIV);
+    assert_eq!(lua_tonumber(l.clone(), -1), 3.5);
+    lua_pushinteger(l.clone(), 2);
+    lua_arith(l.clone(), LUA_OPPOW);
+    assert_eq!(lua_tonumber(l.clone(), -1), 12.25);
+    lua_arith(l.clone(), LUA_OPUNM);
+    assert_eq!(lua_tonumber(l.clone(), -1), -12.25);
+    lua_pushstring(l.clone(), "0x10");
+    lua_pushinteger(l.clone(), 1);
+    lua_arith(l.clone(), LUA_OPADD);
+    assert!(!lua_isinteger(l.clone(), -1));
+    assert_eq!(lua_tonumber(l.clone(), -1), 17.0);
+    lua_pushinteger(l.clone(), 0);
+    lua_arith(l.clone(), LUA_OPBNOT);
+    assert_eq!(lua_tointeger(l.clone(), -1), -1);
+    assert_eq!(lua_gettop(l.clone()), 3);
+}
+
+#[test]
+fn arith_error_test() {
+    debug!("test lua_arith errors");
+    let l = luaL_newstate();
+    let message = error_message(|| {
+        lua_pushinteger(l.clone(), 1);
+        lua_pushinteger(l.clone(), 0);
+        lua_arith(l.clone(), LUA_OPMOD);
+    });
+    assert_eq!(message, "attempt to perform 'n%0'");
+    let message = error_message(|| {
+        lua_pushinteger(l.clone(), 1);
+        lua_newtable(l.clone());
+        lua_arith(l.clone(), LUA_OPADD);
+    });
+    assert_eq!(message, "attempt to perform arithmetic on a table value");
+    let message = error_message(|| {
+        lua_pushnumber(l.clone(), 1.5);
+        lua_pushinteger(l.clone(), 1);
+        lua_arith(l.clone(), LUA_OPBOR);
+    });
+    assert_eq!(message, "number has no integer representation");
+}
+
+#[test]
+fn metamethod_test() {
+    debug!("test arithmetic and comparison metamethods");
+    let l = luaL_newstate();
+    new_vector(l.clone(), 88);
+    new_vector(l.clone(), 11);
+    new_vector(l.clone(), 88);
+
+    lua_pushvalue(l.clone(), 1);
+    lua_pushvalue(l.clone(), 2);
+    lua_arith(l.clone(), LUA_OPADD);
+    assert_eq!(lua_tointeger(l.clone(), -1), 99);
+    lua_pop(l.clone(), 1);
+
+    assert!(lua_compare(l.clone(), 2, 1, LUA_OPLT));
+    assert!(!lua_compare(l.clone(), 1, 2, LUA_OPLT));
+    // 没有 __le 时使用 not (b < a)
+    assert!(lua_compare(l.clone(), 1, 3, LUA_OPLE));
+    assert!(lua_compare(l.clone(), 1, 3, LUA_OPEQ));
+    assert!(!lua_rawequal(l.clone(), 1, 3));
+    assert!(!lua_compare(l.clone(), 1, 2, LUA_OPEQ));
+    assert!(!lua_compare(l.clone(), 1, 10, LUA_OPEQ));
+
+    lua_len(l.clone(), 1);
+    assert_eq!(lua_tointeger(l.clone(), -1), 1103);
+    lua_pop(l.clone(), 1);
+    lua_pushstring(l.clone(), "a ");
+    lua_pushvalue(l.clone(), 1);
+    lua_concat(l.clone(), 2);
+    assert_eq!(lua_tostring(l.clone(), -1), "vector".to_string());
+}
+
+#[test]
+fn compare_test() {
+    debug!("test lua_compare on primitive values");
+    let l = luaL_newstate();
+    lua_pushinteger(l.clone(), 1);
+    lua_pushnumber(l.clone(), 1.0);
+    lua_pushnumber(l.clone(), 1.5);
+    lua_pushstring(l.clone(), "a");
+    lua_pushstring(l.clone(), "b");
+    assert!(lua_compare(l.clone(), 1, 2, LUA_OPEQ));
+    assert!(lua_rawequal(l.clone(), 1, 2));
+    assert!(lua_compare(l.clone(), 1, 3, LUA_OPLT));
+    assert!(lua_compare(l.clone(), 2, 1, LUA_OPLE));
+    assert!(lua_compare(l.clone(), 4, 5, LUA_OPLT));
+    assert!(!lua_compare(l.clone(), 5, 4, LUA_OPLE));
+    let message = error_message(|| {
+        lua_compare(l.clone(), 1, 4, LUA_OPLT);
+    });
+    assert_eq!(message, "attempt to compare number with string");
+}
+
+#[test]
+fn concat_len_test() {
+    debug!("test lua_concat and lua_len");
+    let l = luaL_newstate();
+    lua_pushstring(l.clone(), "sweet");
+    lua_pushstring(l.clone(), "hui");
+    lua_pushinteger(l.clone(), 88);
+    lua_pushnumber(l.clone(), 1.0);
+    lua_concat(l.clone(), 4);
+    assert_eq!(lua_gettop(l.clone()), 1);
+    assert_eq!(lua_tostring(l.clone(), 1), "sweethui881.0".to_string());
+    lua_concat(l.clone(), 0);
+    assert_eq!(lua_tostring(l.clone(), -1), "".to_string());
+    lua_len(l.clone(), 1);
+    assert_eq!(lua_tointeger(l.clone(), -1), 13);
+
+    lua_createtable(l.clone(), 3, 0);
+    for i in 1..=3 {
+        lua_pushinteger(l.clone(), i);
+        lua_rawseti(l.clone(), -2, i);
+    }
+    lua_len(l.clone(), -1);
+    assert_eq!(lua_tointeger(l.clone(), -1), 3);
+
+    let message = error_message(|| {
+        lua_pushboolean(l.clone(), true);
+        lua_len(l.clone(), -1);
+    });
+    assert_eq!(message, "attempt to get length of a boolean value");
+    let message = error_message(|| {
+        lua_pushstring(l.clone(), "x");
+        lua_pushnil(l.clone());
+        lua_concat(l.clone(), 2);
+    });
+    assert_eq!(message, "attempt to concatenate a nil value");
+}
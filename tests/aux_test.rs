@@ -0,0 +1,174 @@
+use llua::api::*;
+use llua::debug;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+fn error_message<F: FnOnce()>(f: F) -> String {
+    let err = catch_unwind(AssertUnwindSafe(f)).unwrap_err();
+    err.downcast_ref::<LuaError>().unwrap().message.clone()
+}
+
+fn foo(l: lua_State) -> usize {
+    let a = luaL_checkinteger(l.clone(), 1);
+    let b = luaL_checknumber(l.clone(), 2);
+    let s = luaL_optstring(l.clone(), 3, "sweet");
+    lua_pushnumber(l.clone(), a as f64 + b);
+    lua_pushstring(l, &s);
+    2
+}
+
+fn call_foo(l: lua_State, args: &[LuaValue]) {
+    lua_getglobal(l.clone(), "foo");
+    for arg in args {
+        l.borrow_mut().push(arg.clone());
+    }
+    lua_call(l, args.len() as isize, 2);
+}
+
+#[test]
+fn check_args_test() {
+    debug!("test luaL_check* and luaL_opt*");
+    let l = luaL_newstate();
+    lua_register(l.clone(), "foo", foo);
+    call_foo(
+        l.clone(),
+        &[LuaValue::Integer(88), LuaValue::String("11.5".into())],
+    );
+    assert_eq!(lua_tonumber(l.clone(), 1), 99.5);
+    assert_eq!(lua_tostring(l.clone(), 2), "sweet".to_string());
+    lua_settop(l.clone(), 0);
+    call_foo(
+        l.clone(),
+        &[
+            LuaValue::Number(3.0),
+            LuaValue::Integer(2),
+            LuaValue::String("hui".into()),
+        ],
+    );
+    assert_eq!(lua_tonumber(l.clone(), 1), 5.0);
+    assert_eq!(lua_tostring(l.clone(), 2), "hui".to_string());
+}
+
+#[test]
+fn arg_error_test() {
+    debug!("test luaL_argerror messages");
+    let l = luaL_newstate();
+    lua_register(l.clone(), "foo", foo);
+    let message = error_message(|| call_foo(l.clone(), &[LuaValue::Integer(1)]));
+    assert_eq!(
+        message,
+        "bad argument #2 to 'foo' (number expected, got no value)"
+    );
+    let message = error_message(|| {
+        call_foo(l.clone(), &[LuaValue::Integer(1), LuaValue::Nil]);
+    });
+    assert_eq!(
+        message,
+        "bad argument #2 to 'foo' (number expected, got nil)"
+    );
+    let message = error_message(|| {
+        call_foo(l.clone(), &[LuaValue::Number(1.5), LuaValue::Integer(1)]);
+    });
+    assert_eq!(
+        message,
+        "bad argument #1 to 'foo' (number has no integer representation)"
+    );
+    let message = error_message(|| {
+        call_foo(
+            l.clone(),
+            &[
+                LuaValue::Integer(1),
+                LuaValue::Integer(1),
+                LuaValue::Boolean(true),
+            ],
+        );
+    });
+    assert_eq!(
+        message,
+        "bad argument #3 to 'foo' (string expected, got boolean)"
+    );
+
+    // 不在任何函数中时没有函数名
+    let l = luaL_newstate();
+    let message = error_message(|| {
+        luaL_checkany(l.clone(), 1);
+    });
+    assert_eq!(message, "bad argument #1 (value expected)");
+}
+
+#[test]
+fn loaded_name_test() {
+    debug!("test function names found in _LOADED");
+    fn bar(l: lua_State) -> usize {
+        luaL_checktype(l, 1, LUA_TTABLE);
+        0
+    }
+    let l = luaL_newstate();
+    lua_newtable(l.clone());
+    lua_newtable(l.clone());
+    lua_pushcfunction(l.clone(), bar);
+    lua_setfield(l.clone(), -2, "bar");
+    lua_setfield(l.clone(), -2, "mod");
+    lua_setfield(l.clone(), LUA_REGISTRYINDEX, "_LOADED");
+
+    let message = error_message(|| {
+        lua_getfield(l.clone(), LUA_REGISTRYINDEX, "_LOADED");
+        lua_getfield(l.clone(), -1, "mod");
+        lua_getfield(l.clone(), -1, "bar");
+        lua_pushinteger(l.clone(), 1);
+        lua_call(l.clone(), 1, 0);
+    });
+    assert_eq!(
+        message,
+        "bad argument #1 to 'mod.bar' (table expected, got number)"
+    );
+}
+
+#[test]
+fn check_option_test() {
+    debug!("test luaL_checkoption");
+    fn mode(l: lua_State) -> usize {
+        let i = luaL_checkoption(l.clone(), 1, Some("read"), &["read", "write", "append"]);
+        lua_pushinteger(l, i as isize);
+        1
+    }
+    let l = luaL_newstate();
+    lua_register(l.clone(), "mode", mode);
+    lua_getglobal(l.clone(), "mode");
+    lua_call(l.clone(), 0, 1);
+    assert_eq!(lua_tointeger(l.clone(), -1), 0);
+    lua_getglobal(l.clone(), "mode");
+    lua_pushstring(l.clone(), "append");
+    lua_call(l.clone(), 1, 1);
+    assert_eq!(lua_tointeger(l.clone(), -1), 2);
+    let message = error_message(|| {
+        lua_getglobal(l.clone(), "mode");
+        lua_pushstring(l.clone(), "sweet");
+        lua_call(l.clone(), 1, 1);
+    });
+    assert_eq!(
+        message,
+        "bad argument #1 to 'mode' (invalid option 'sweet')"
+    );
+}
+
+#[test]
+fn typeerror_name_test() {
+    debug!("test luaL_typeerror with __name");
+    fn check(l: lua_State) -> usize {
+        luaL_checkinteger(l, 1);
+        0
+    }
+    let l = luaL_newstate();
+    lua_register(l.clone(), "check", check);
+    let message = error_message(|| {
+        lua_getglobal(l.clone(), "check");
+        lua_newtable(l.clone());
+        luaL_newmetatable(l.clone(), "Point");
+        lua_setmetatable(l.clone(), -2);
+        lua_call(l.clone(), 1, 0);
+    });
+    assert_eq!(
+        message,
+        "bad argument #1 to 'check' (number expected, got Point)"
+    );
+}
@@ -0,0 +1,134 @@
+use llua::api::*;
+use llua::debug;
+
+fn sum(l: lua_State) -> usize {
+    let n = lua_gettop(l.clone());
+    let mut total = 0;
+    for i in 1..=n {
+        total += lua_tointeger(l.clone(), i);
+    }
+    lua_pushinteger(l.clone(), total as isize);
+    lua_pushinteger(l, n);
+    2
+}
+
+fn three(l: lua_State) -> usize {
+    lua_pushinteger(l.clone(), 1);
+    lua_pushinteger(l.clone(), 2);
+    lua_pushinteger(l, 3);
+    3
+}
+
+fn call_three(l: lua_State, nresults: isize) {
+    lua_pushcfunction(l.clone(), three);
+    lua_call(l, 0, nresults);
+}
+
+#[test]
+fn native_return_test() {
+    debug!("test native return values");
+    let l = luaL_newstate();
+    lua_pushinteger(l.clone(), 1103);
+    lua_pushcfunction(l.clone(), sum);
+    lua_pushinteger(l.clone(), 88);
+    lua_pushinteger(l.clone(), 11);
+    lua_call(l.clone(), 2, 2);
+    assert_eq!(lua_gettop(l.clone()), 3);
+    assert_eq!(lua_tointeger(l.clone(), 1), 1103);
+    assert_eq!(lua_tointeger(l.clone(), 2), 99);
+    assert_eq!(lua_tointeger(l.clone(), 3), 2);
+}
+
+#[test]
+fn nresults_adjust_test() {
+    debug!("test nresults truncation and padding");
+    let l = luaL_newstate();
+    call_three(l.clone(), 1);
+    assert_eq!(lua_gettop(l.clone()), 1);
+    assert_eq!(lua_tointeger(l.clone(), 1), 1);
+    lua_pop(l.clone(), 1);
+
+    call_three(l.clone(), 0);
+    assert_eq!(lua_gettop(l.clone()), 0);
+
+    call_three(l.clone(), 5);
+    assert_eq!(lua_gettop(l.clone()), 5);
+    assert_eq!(lua_tointeger(l.clone(), 3), 3);
+    assert!(lua_isnil(l.clone(), 4));
+    assert!(lua_isnil(l.clone(), 5));
+}
+
+#[test]
+fn multret_test() {
+    debug!("test LUA_MULTRET");
+    let l = luaL_newstate();
+    call_three(l.clone(), LUA_MULTRET);
+    assert_eq!(lua_gettop(l.clone()), 3);
+    assert_eq!(lua_tointeger(l.clone(), -1), 3);
+}
+
+#[test]
+fn nested_call_test() {
+    debug!("test native calling native");
+    fn outer(l: lua_State) -> usize {
+        // 自己的参数从 1 开始，嵌套调用的结果压在参数之后
+        assert_eq!(lua_gettop(l.clone()), 1);
+        call_three(l.clone(), 2);
+        assert_eq!(lua_gettop(l.clone()), 3);
+        assert_eq!(lua_tointeger(l.clone(), 1), 1103);
+        assert_eq!(lua_tointeger(l.clone(), 3), 2);
+        1
+    }
+    let l = luaL_newstate();
+    lua_pushcfunction(l.clone(), outer);
+    lua_pushinteger(l.clone(), 1103);
+    lua_call(l.clone(), 1, LUA_MULTRET);
+    assert_eq!(lua_gettop(l.clone()), 1);
+    assert_eq!(lua_tointeger(l.clone(), 1), 2);
+}
+
+#[test]
+fn pcall_test() {
+    debug!("test lua_pcall and message handlers");
+    fn fail(l: lua_State) -> usize {
+        lua_pushinteger(l.clone(), 1103);
+        luaL_error(l, "sweet")
+    }
+    fn nested(l: lua_State) -> usize {
+        lua_pushcfunction(l.clone(), fail);
+        lua_call(l, 0, 0);
+        0
+    }
+    fn handler(l: lua_State) -> usize {
+        let msg = lua_tostring(l.clone(), 1);
+        lua_pushstring(l, &format!("handled: {}", msg));
+        1
+    }
+    let l = luaL_newstate();
+    lua_pushinteger(l.clone(), 88);
+    lua_pushcfunction(l.clone(), nested);
+    assert_eq!(lua_pcall(l.clone(), 0, 1, 0), LUA_ERRRUN);
+    assert_eq!(lua_gettop(l.clone()), 2);
+    assert_eq!(lua_tostring(l.clone(), -1), "sweet".to_string());
+    lua_pop(l.clone(), 1);
+
+    lua_pushcfunction(l.clone(), handler);
+    lua_pushcfunction(l.clone(), nested);
+    assert_eq!(lua_pcall(l.clone(), 0, 0, 2), LUA_ERRRUN);
+    assert_eq!(lua_tostring(l.clone(), -1), "handled: sweet".to_string());
+    lua_settop(l.clone(), 1);
+
+    // 调用链已经恢复，出错之后还能正常调用
+    lua_pushcfunction(l.clone(), sum);
+    lua_pushinteger(l.clone(), 11);
+    assert_eq!(lua_pcall(l.clone(), 1, 1, 0), LUA_OK);
+    assert_eq!(lua_tointeger(l.clone(), -1), 11);
+
+    lua_pushcfunction(l.clone(), fail);
+    assert_eq!(lua_pcall(l.clone(), 0, 0, -1), LUA_ERRERR);
+    assert_eq!(
+        lua_tostring(l.clone(), -1),
+        "error in error handling".to_string()
+    );
+    assert_eq!(lua_gettop(l), 3);
+}
@@ -0,0 +1,138 @@
+use llua::api::*;
+use llua::debug;
+use std::cell::{Cell, RefCell};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::rc::Rc;
+
+fn call_global(l: lua_State, name: &str) {
+    lua_getglobal(l.clone(), name);
+    lua_call(l, 0, 0);
+}
+
+#[test]
+fn rust_closure_test() {
+    debug!("test native closure with captured state");
+    let l = luaL_newstate();
+    let counter = Rc::new(Cell::new(0));
+    let c = counter.clone();
+    lua_pushrustclosure(
+        l.clone(),
+        move |_| {
+            c.set(c.get() + 1);
+            0
+        },
+        0,
+    );
+    assert!(lua_iscfunction(l.clone(), -1));
+    lua_setglobal(l.clone(), "inc");
+    call_global(l.clone(), "inc");
+    call_global(l.clone(), "inc");
+    assert_eq!(counter.get(), 2);
+}
+
+#[test]
+fn rust_closure_mut_test() {
+    debug!("test FnMut native closure");
+    let l = luaL_newstate();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let out = log.clone();
+    let mut calls = 0;
+    lua_pushrustclosure_mut(
+        l.clone(),
+        move |_| {
+            calls += 1;
+            out.borrow_mut().push(calls);
+            0
+        },
+        0,
+    );
+    lua_setglobal(l.clone(), "next");
+    for _ in 0..3 {
+        call_global(l.clone(), "next");
+    }
+    assert_eq!(*log.borrow(), vec![1, 2, 3]);
+}
+
+#[test]
+fn reentrant_closure_mut_test() {
+    debug!("test re-entering a FnMut native closure");
+    let l = luaL_newstate();
+    lua_pushrustclosure_mut(
+        l.clone(),
+        move |l| {
+            call_global(l, "again");
+            0
+        },
+        0,
+    );
+    lua_setglobal(l.clone(), "again");
+    let result = catch_unwind(AssertUnwindSafe(|| call_global(l.clone(), "again")));
+    let err = result.unwrap_err();
+    let err = err.downcast_ref::<LuaError>().unwrap();
+    assert!(err.message.contains("re-enter"));
+}
+
+#[test]
+fn c_upvalue_test() {
+    debug!("test lua_pushcclosure upvalues");
+    fn upvalues(l: lua_State) -> usize {
+        assert_eq!(lua_tointeger(l.clone(), lua_upvalueindex(1)), 88);
+        assert_eq!(
+            lua_tostring(l.clone(), lua_upvalueindex(2)),
+            "sweethui".to_string()
+        );
+        assert!(lua_isnil(l.clone(), lua_upvalueindex(3)));
+        lua_pushvalue(l.clone(), lua_upvalueindex(1));
+        lua_setglobal(l, "seen");
+        0
+    }
+    let l = luaL_newstate();
+    lua_pushinteger(l.clone(), 88);
+    lua_pushstring(l.clone(), "sweethui");
+    lua_pushcclosure(l.clone(), upvalues, 2);
+    assert_eq!(lua_gettop(l.clone()), 1);
+    lua_setglobal(l.clone(), "f");
+    call_global(l.clone(), "f");
+    lua_getglobal(l.clone(), "seen");
+    assert_eq!(lua_tointeger(l.clone(), -1), 88);
+}
+
+#[test]
+fn setfuncs_shared_upvalue_test() {
+    debug!("test luaL_setfuncs with shared upvalues");
+    fn incr(l: lua_State) -> usize {
+        // 字段不存在时 lua_tointeger 得到 0
+        lua_getfield(l.clone(), lua_upvalueindex(1), "n");
+        let n = lua_tointeger(l.clone(), -1);
+        lua_pushinteger(l.clone(), n as isize + 1);
+        lua_setfield(l, lua_upvalueindex(1), "n");
+        0
+    }
+    fn report(l: lua_State) -> usize {
+        lua_getfield(l.clone(), lua_upvalueindex(1), "n");
+        lua_setglobal(l, "count");
+        0
+    }
+    const FUNCS: &[luaL_Reg] = &[
+        luaL_Reg {
+            name: "incr",
+            func: incr,
+        },
+        luaL_Reg {
+            name: "report",
+            func: report,
+        },
+    ];
+    let l = luaL_newstate();
+    lua_pushglobaltable(l.clone());
+    l.borrow_mut().push(LuaValue::new_table(0, 1));
+    luaL_setfuncs(l.clone(), FUNCS, 1);
+    assert_eq!(lua_gettop(l.clone()), 1);
+    lua_pop(l.clone(), 1);
+
+    call_global(l.clone(), "incr");
+    call_global(l.clone(), "incr");
+    call_global(l.clone(), "report");
+    lua_getglobal(l.clone(), "count");
+    assert_eq!(lua_tointeger(l.clone(), -1), 2);
+}
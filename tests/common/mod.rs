@@ -0,0 +1,73 @@
+// 标准库测试共用的辅助函数，每个测试文件只用到其中的一部分
+#![allow(dead_code)]
+
+use llua::api::*;
+
+pub fn new_state() -> lua_State {
+    let l = luaL_newstate();
+    luaL_openlibs(l.clone());
+    l
+}
+
+// 压入全局表 lib 中的函数 name 和参数，调用前栈上已有的值保持不变
+fn push_call(l: lua_State, lib: &str, name: &str, args: &[LuaValue]) {
+    lua_getglobal(l.clone(), lib);
+    lua_getfield(l.clone(), -1, name);
+    lua_remove(l.clone(), -2);
+    for arg in args {
+        l.borrow_mut().push(arg.clone());
+    }
+}
+
+// 调用 lib 库中的函数，返回所有结果
+pub fn call_lib(l: lua_State, lib: &str, name: &str, args: &[LuaValue]) -> Vec<LuaValue> {
+    let top = lua_gettop(l.clone());
+    push_call(l.clone(), lib, name, args);
+    lua_call(l.clone(), args.len() as isize, LUA_MULTRET);
+    let results = (top + 1..=lua_gettop(l.clone()))
+        .map(|i| l.borrow().get(i))
+        .collect();
+    lua_settop(l, top);
+    results
+}
+
+// 调用全局函数 name，返回所有结果，调用前栈上已有的值保持不变
+pub fn call_global(l: lua_State, name: &str, args: &[LuaValue]) -> Vec<LuaValue> {
+    let top = lua_gettop(l.clone());
+    lua_getglobal(l.clone(), name);
+    for arg in args {
+        l.borrow_mut().push(arg.clone());
+    }
+    lua_call(l.clone(), args.len() as isize, LUA_MULTRET);
+    let results = (top + 1..=lua_gettop(l.clone()))
+        .map(|i| l.borrow().get(i))
+        .collect();
+    lua_settop(l, top);
+    results
+}
+
+// 调用 lib 库中的函数，它必须出错，返回错误信息
+pub fn lib_error(l: lua_State, lib: &str, name: &str, args: &[LuaValue]) -> String {
+    let top = lua_gettop(l.clone());
+    push_call(l.clone(), lib, name, args);
+    assert_ne!(lua_pcall(l.clone(), args.len() as isize, 0, 0), LUA_OK);
+    let msg = lua_tostring(l.clone(), -1);
+    lua_settop(l, top);
+    msg
+}
+
+pub fn s(s: &str) -> LuaValue {
+    LuaValue::String(s.into())
+}
+
+pub fn b(b: &[u8]) -> LuaValue {
+    LuaValue::String(b.to_vec())
+}
+
+pub fn i(i: i64) -> LuaValue {
+    LuaValue::Integer(i)
+}
+
+pub fn f(f: f64) -> LuaValue {
+    LuaValue::Number(f)
+}
@@ -0,0 +1,141 @@
+use llua::debug;
+use llua::lua::{Error, Function, Lua, LuaValue, Table, Variadic};
+use std::collections::HashMap;
+
+#[test]
+fn create_function_test() {
+    debug!("test registering Rust functions");
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals
+        .set(
+            "add",
+            lua.create_function(|_, (a, b): (i64, i64)| Ok(a + b))
+                .unwrap(),
+        )
+        .unwrap();
+    let add: Function = globals.get("add").unwrap();
+    assert_eq!(add.call::<_, i64>((88, 11)).unwrap(), 99);
+    assert_eq!(add.call::<_, i64>(("1", 2.0)).unwrap(), 3);
+    assert_eq!(
+        add.call::<_, i64>(("sweet", 1)),
+        Err(Error::RuntimeError(
+            "error converting Lua string to i64".to_string()
+        ))
+    );
+
+    let fail = lua
+        .create_function(|_, ()| -> Result<(), Error> {
+            Err(Error::RuntimeError("sweethui".to_string()))
+        })
+        .unwrap();
+    assert_eq!(
+        fail.call::<_, ()>(()),
+        Err(Error::RuntimeError("sweethui".to_string()))
+    );
+}
+
+#[test]
+fn callback_test() {
+    debug!("test calling back into Lua from a Rust function");
+    let lua = Lua::new();
+    let apply = lua
+        .create_function(|_, (f, x): (Function, i64)| f.call::<_, i64>(x * 2))
+        .unwrap();
+    let inc = lua.create_function(|_, x: i64| Ok(x + 1)).unwrap();
+    assert_eq!(apply.call::<_, i64>((inc, 20)).unwrap(), 41);
+
+    let mut total = 0;
+    let counter = lua
+        .create_function_mut(move |_, n: i64| {
+            total += n;
+            Ok(total)
+        })
+        .unwrap();
+    counter.call::<_, i64>(1).unwrap();
+    assert_eq!(counter.call::<_, i64>(2).unwrap(), 3);
+
+    // 通过传入的 &Lua 创建新的值
+    let make = lua
+        .create_function(|lua, n: i64| {
+            let t = lua.create_table();
+            t.set("n", n)?;
+            Ok(t)
+        })
+        .unwrap();
+    let t: Table = make.call(1103).unwrap();
+    assert_eq!(t.get::<_, i64>("n").unwrap(), 1103);
+}
+
+#[test]
+fn container_test() {
+    debug!("test Option, Vec and HashMap conversions");
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals.set("list", vec![1, 2, 3]).unwrap();
+    let list: Table = globals.get("list").unwrap();
+    assert_eq!(list.raw_len(), 3);
+    assert_eq!(globals.get::<_, Vec<i64>>("list").unwrap(), vec![1, 2, 3]);
+
+    let mut map = HashMap::new();
+    map.insert("sweet".to_string(), 88u8);
+    map.insert("hui".to_string(), 11u8);
+    globals.set("map", map.clone()).unwrap();
+    let back: HashMap<String, u8> = globals.get("map").unwrap();
+    assert_eq!(back, map);
+
+    globals.set("none", None::<i64>).unwrap();
+    assert_eq!(globals.get::<_, Option<i64>>("none").unwrap(), None);
+    assert!(globals.get::<_, Option<i64>>("list").is_err());
+    globals.set("some", Some(1.5)).unwrap();
+    assert_eq!(globals.get::<_, Option<f64>>("some").unwrap(), Some(1.5));
+}
+
+#[test]
+fn number_test() {
+    debug!("test integer and float conversions");
+    let lua = Lua::new();
+    let t = lua.create_table();
+    t.set(1, 300).unwrap();
+    t.set(2, 2.5f32).unwrap();
+    t.set(3, u64::MAX).unwrap();
+    t.set(4, -1i8).unwrap();
+    assert_eq!(t.get::<_, u16>(1).unwrap(), 300);
+    match t.get::<_, u8>(1) {
+        Err(Error::FromLuaConversionError { to, .. }) => assert_eq!(to, "u8"),
+        _ => panic!("expected a conversion error"),
+    }
+    assert_eq!(t.get::<_, f32>(2).unwrap(), 2.5);
+    assert!(t.get::<_, i64>(2).is_err());
+    assert_eq!(
+        t.get::<_, LuaValue>(3).unwrap(),
+        LuaValue::Number(u64::MAX as f64)
+    );
+    assert_eq!(t.get::<_, i32>(4).unwrap(), -1);
+    assert!(t.get::<_, u32>(4).is_err());
+}
+
+#[test]
+fn variadic_test() {
+    debug!("test variadic arguments and results");
+    let lua = Lua::new();
+    let sum = lua
+        .create_function(|_, (scale, rest): (i64, Variadic<i64>)| {
+            Ok(rest.iter().map(|n| n * scale).sum::<i64>())
+        })
+        .unwrap();
+    assert_eq!(sum.call::<_, i64>((2, 1, 2, 3)).unwrap(), 12);
+    assert_eq!(sum.call::<_, i64>(2).unwrap(), 0);
+
+    let split = lua
+        .create_function(|_, s: String| {
+            let parts: Vec<String> = s.split(',').map(|p| p.to_string()).collect();
+            Ok(Variadic::from(parts))
+        })
+        .unwrap();
+    let (first, rest) = split
+        .call::<_, (String, Variadic<String>)>("a,b,c")
+        .unwrap();
+    assert_eq!(first, "a");
+    assert_eq!(*rest, vec!["b".to_string(), "c".to_string()]);
+}
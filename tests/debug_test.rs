@@ -0,0 +1,478 @@
+use llua::api::*;
+use llua::chunk::binary::*;
+use llua::debug;
+use llua::vm::opcodes::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod common;
+use common::*;
+
+// 还没有编译器，手工汇编下面的代码（@inspect.lua）：
+//   1  local a = 10
+//   2  local b = "x"
+//   3  local r = probe(a)
+//   4  return a, b, r
+fn inspect_chunk() -> Vec<u8> {
+    fn abc(op: u8, a: u32, b: u32, c: u32) -> u32 {
+        op as u32 | a << 6 | c << 14 | b << 23
+    }
+    fn abx(op: u8, a: u32, bx: u32) -> u32 {
+        op as u32 | a << 6 | bx << 14
+    }
+    fn string(buf: &mut Vec<u8>, s: &str) {
+        buf.push(s.len() as u8 + 1);
+        buf.extend_from_slice(s.as_bytes());
+    }
+    fn vector<T>(buf: &mut Vec<u8>, items: &[T], mut f: impl FnMut(&mut Vec<u8>, &T)) {
+        buf.extend_from_slice(&(items.len() as u32).to_le_bytes());
+        for item in items {
+            f(buf, item);
+        }
+    }
+    let code = [
+        abx(OP_LOADK, 0, 0),
+        abx(OP_LOADK, 1, 1),
+        abc(OP_GETTABUP, 2, 0, 0x100 | 2),
+        abc(OP_MOVE, 3, 0, 0),
+        abc(OP_CALL, 2, 2, 2),
+        abc(OP_RETURN, 0, 4, 0),
+        abc(OP_RETURN, 0, 1, 0),
+    ];
+    let lines = [1u32, 2, 3, 3, 3, 4, 4];
+    let locals = [("a", 1u32, 7u32), ("b", 2, 7), ("r", 5, 7)];
+
+    let mut buf = LUA_SIGNATURE.to_vec();
+    buf.extend_from_slice(&[LUAC_VERSION, LUAC_FORMAT]);
+    buf.extend_from_slice(&LUAC_DATA);
+    buf.extend_from_slice(&[4, 8, 4, 8, 8]);
+    buf.extend_from_slice(&LUAC_INT.to_le_bytes());
+    buf.extend_from_slice(&LUAC_NUM.to_le_bytes());
+    buf.push(1);
+    string(&mut buf, "@inspect.lua");
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf.extend_from_slice(&[0, 1, 4]);
+    vector(&mut buf, &code, |b, i| {
+        b.extend_from_slice(&i.to_le_bytes())
+    });
+    buf.extend_from_slice(&3u32.to_le_bytes());
+    buf.push(TAG_INTEGER);
+    buf.extend_from_slice(&10i64.to_le_bytes());
+    buf.push(TAG_SHORT_STR);
+    string(&mut buf, "x");
+    buf.push(TAG_SHORT_STR);
+    string(&mut buf, "probe");
+    vector(&mut buf, &[(1u8, 0u8)], |b, u| {
+        b.extend_from_slice(&[u.0, u.1])
+    });
+    vector::<u8>(&mut buf, &[], |_, _| ());
+    vector(&mut buf, &lines, |b, l| {
+        b.extend_from_slice(&l.to_le_bytes())
+    });
+    vector(&mut buf, &locals, |b, (name, start, end)| {
+        string(b, name);
+        b.extend_from_slice(&start.to_le_bytes());
+        b.extend_from_slice(&end.to_le_bytes());
+    });
+    vector(&mut buf, &["_ENV"], |b, name| string(b, name));
+    buf
+}
+
+fn load_inspect(l: lua_State) {
+    assert_eq!(
+        luaL_loadbuffer(l.clone(), &inspect_chunk(), "=inspect"),
+        LUA_OK
+    );
+}
+
+fn pop_value(l: lua_State) -> LuaValue {
+    let v = l.borrow().get(lua_gettop(l.clone()));
+    lua_pop(l, 1);
+    v
+}
+
+fn field(l: lua_State, t: &LuaValue, k: &str) -> LuaValue {
+    l.borrow_mut().push(t.clone());
+    lua_getfield(l.clone(), -1, k);
+    let v = l.borrow().get(lua_gettop(l.clone()));
+    lua_pop(l, 2);
+    v
+}
+
+#[test]
+fn getinfo_test() {
+    debug!("test lua_getstack and lua_getinfo on Lua and native frames");
+    let l = new_state();
+    lua_pushcfunction(l.clone(), |l| {
+        let mut ar = lua_Debug::default();
+        assert!(lua_getstack(l.clone(), 0, &mut ar));
+        assert!(lua_getinfo(l.clone(), "nSlu", &mut ar));
+        assert_eq!(ar.name.as_deref(), Some("probe"));
+        assert_eq!(ar.namewhat, "global");
+        assert_eq!(ar.what, "C");
+        assert_eq!(ar.short_src, "[C]");
+        assert_eq!(ar.currentline, -1);
+        assert!(ar.isvararg);
+
+        assert!(lua_getstack(l.clone(), 1, &mut ar));
+        assert!(lua_getinfo(l.clone(), "nSltuf", &mut ar));
+        assert_eq!(ar.name, None);
+        assert_eq!(ar.namewhat, "");
+        assert_eq!(ar.what, "main");
+        assert_eq!(ar.source, "@inspect.lua");
+        assert_eq!(ar.short_src, "inspect.lua");
+        assert_eq!(ar.currentline, 3);
+        assert_eq!((ar.linedefined, ar.nups, ar.nparams), (0, 1, 0));
+        assert!(ar.isvararg);
+        assert!(!ar.istailcall);
+        assert!(lua_isfunction(l.clone(), -1));
+        assert!(!lua_getinfo(l.clone(), "x", &mut ar));
+        assert!(!lua_getstack(l.clone(), 2, &mut ar));
+        lua_pushinteger(l, 1);
+        1
+    });
+    lua_setglobal(l.clone(), "probe");
+    load_inspect(l.clone());
+    lua_pushvalue(l.clone(), 1);
+    lua_call(l.clone(), 0, 3);
+    assert_eq!(l.borrow().get(4), i(1));
+
+    // '>' 查询栈顶的函数，'L' 压入有效行号的表
+    let mut ar = lua_Debug::default();
+    lua_pushvalue(l.clone(), 1);
+    assert!(lua_getinfo(l.clone(), ">SL", &mut ar));
+    assert_eq!((ar.what.as_str(), ar.lastlinedefined), ("main", 0));
+    for line in 1..=4 {
+        lua_rawgeti(l.clone(), -1, line);
+        assert!(lua_toboolean(l.clone(), -1));
+        lua_pop(l.clone(), 1);
+    }
+    lua_rawgeti(l.clone(), -1, 5);
+    assert!(lua_isnil(l.clone(), -1));
+    lua_getglobal(l.clone(), "print");
+    assert!(lua_getinfo(l.clone(), ">L", &mut ar));
+    assert!(lua_isnil(l.clone(), -1));
+}
+
+#[test]
+fn locals_test() {
+    debug!("test lua_getlocal and lua_setlocal with active variables and temporaries");
+    let l = new_state();
+    lua_pushcfunction(l.clone(), |l| {
+        let mut ar = lua_Debug::default();
+        assert!(lua_getstack(l.clone(), 1, &mut ar));
+        assert_eq!(lua_getlocal(l.clone(), Some(&ar), 1).as_deref(), Some("a"));
+        assert_eq!(lua_getlocal(l.clone(), Some(&ar), 2).as_deref(), Some("b"));
+        assert_eq!(lua_tostring(l.clone(), -1), "x");
+        assert_eq!(lua_tointeger(l.clone(), -2), 10);
+        // r 还没有进入作用域，它的位置是被调用的函数
+        assert_eq!(lua_getlocal(l.clone(), Some(&ar), 3), None);
+        assert_eq!(lua_getlocal(l.clone(), Some(&ar), 0), None);
+        assert_eq!(lua_getlocal(l.clone(), Some(&ar), -1), None);
+        lua_pushinteger(l.clone(), 20);
+        assert_eq!(lua_setlocal(l.clone(), &ar, 1).as_deref(), Some("a"));
+        lua_pushinteger(l.clone(), 30);
+        assert_eq!(lua_setlocal(l.clone(), &ar, 5), None);
+        lua_pop(l.clone(), 1);
+
+        // 原生函数栈上的值都是临时变量
+        assert!(lua_getstack(l.clone(), 0, &mut ar));
+        assert_eq!(lua_gettop(l.clone()), 3);
+        assert_eq!(
+            lua_getlocal(l.clone(), Some(&ar), 3).as_deref(),
+            Some("(*temporary)")
+        );
+        assert_eq!(lua_getlocal(l.clone(), Some(&ar), 5), None);
+        lua_pushstring(l, "probed");
+        1
+    });
+    lua_setglobal(l.clone(), "probe");
+    load_inspect(l.clone());
+    lua_call(l.clone(), 0, 3);
+    assert_eq!(l.borrow().get(1), i(20));
+    assert_eq!(l.borrow().get(2), s("x"));
+    assert_eq!(l.borrow().get(3), s("probed"));
+
+    // 主函数没有参数
+    load_inspect(l.clone());
+    assert_eq!(lua_getlocal(l.clone(), None, 1), None);
+    assert_eq!(lua_gettop(l.clone()), 4);
+}
+
+#[test]
+fn debug_library_test() {
+    debug!("test debug.getinfo, debug.getlocal, debug.setlocal and debug.traceback");
+    let l = new_state();
+    lua_pushcfunction(l.clone(), |l| {
+        // level 0 是 debug 库的函数，1 是 probe，2 是主函数
+        let info = call_lib(l.clone(), "debug", "getinfo", &[i(2), s("Sl")])[0].clone();
+        assert_eq!(field(l.clone(), &info, "short_src"), s("inspect.lua"));
+        assert_eq!(field(l.clone(), &info, "currentline"), i(3));
+        assert_eq!(field(l.clone(), &info, "what"), s("main"));
+        assert_eq!(field(l.clone(), &info, "name"), LuaValue::Nil);
+        let info = call_lib(l.clone(), "debug", "getinfo", &[i(1)])[0].clone();
+        assert_eq!(field(l.clone(), &info, "name"), s("probe"));
+        assert_eq!(field(l.clone(), &info, "namewhat"), s("global"));
+        let func = field(l.clone(), &info, "func");
+        assert_eq!(func.lua_type(), LUA_TFUNCTION);
+        assert_eq!(
+            call_lib(l.clone(), "debug", "getinfo", &[i(3)]),
+            [LuaValue::Nil]
+        );
+
+        assert_eq!(
+            call_lib(l.clone(), "debug", "getlocal", &[i(2), i(2)]),
+            [s("b"), s("x")]
+        );
+        assert_eq!(
+            call_lib(l.clone(), "debug", "setlocal", &[i(2), i(2), s("y")]),
+            [s("b")]
+        );
+        assert_eq!(
+            call_lib(l.clone(), "debug", "setlocal", &[i(2), i(9), s("y")]),
+            [LuaValue::Nil]
+        );
+        assert_eq!(
+            call_lib(l.clone(), "debug", "traceback", &[s("msg")]),
+            [s("msg\nstack traceback:\n\t[C]: in function 'probe'\
+                \n\tinspect.lua:3: in main chunk")]
+        );
+        let msg = lib_error(l.clone(), "debug", "getlocal", &[i(5), i(1)]);
+        assert!(msg.contains("level out of range"), "{}", msg);
+        let msg = lib_error(l.clone(), "debug", "getinfo", &[i(1), s(">")]);
+        assert!(msg.contains("invalid option"), "{}", msg);
+        lua_pushnil(l);
+        1
+    });
+    lua_setglobal(l.clone(), "probe");
+    load_inspect(l.clone());
+    lua_call(l.clone(), 0, 3);
+    assert_eq!(l.borrow().get(2), s("y"));
+    lua_settop(l.clone(), 0);
+
+    load_inspect(l.clone());
+    let main = l.borrow().get(1);
+    assert_eq!(
+        call_lib(l.clone(), "debug", "getlocal", &[main.clone(), i(1)]),
+        [LuaValue::Nil]
+    );
+    let info = call_lib(l.clone(), "debug", "getinfo", &[main, s("u")])[0].clone();
+    assert_eq!(field(l.clone(), &info, "nups"), i(1));
+    assert_eq!(field(l.clone(), &info, "nparams"), i(0));
+    assert_eq!(field(l.clone(), &info, "isvararg"), LuaValue::Boolean(true));
+    assert_eq!(field(l.clone(), &info, "currentline"), LuaValue::Nil);
+    // 在宿主中调用时 level 1 已经没有函数了，不是字符串的消息原样返回
+    assert_eq!(
+        call_lib(l.clone(), "debug", "traceback", &[s("top")]),
+        [s("top\nstack traceback:")]
+    );
+    lua_newtable(l.clone());
+    let t = pop_value(l.clone());
+    assert_eq!(
+        call_lib(l.clone(), "debug", "traceback", std::slice::from_ref(&t)),
+        [t]
+    );
+}
+
+#[test]
+fn upvalue_test() {
+    debug!("test debug.getupvalue, setupvalue, upvalueid and upvaluejoin");
+    let l = new_state();
+    lua_pushinteger(l.clone(), 1);
+    lua_pushstring(l.clone(), "two");
+    lua_pushcclosure(l.clone(), |_| 0, 2);
+    let f = pop_value(l.clone());
+
+    assert_eq!(
+        call_lib(l.clone(), "debug", "getupvalue", &[f.clone(), i(2)]),
+        [s(""), s("two")]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "debug", "getupvalue", &[f.clone(), i(3)]),
+        []
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "debug",
+            "setupvalue",
+            &[f.clone(), i(1), s("one")]
+        ),
+        [s("")]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "debug", "getupvalue", &[f.clone(), i(1)]),
+        [s(""), s("one")]
+    );
+    let id1 = call_lib(l.clone(), "debug", "upvalueid", &[f.clone(), i(1)]);
+    let id2 = call_lib(l.clone(), "debug", "upvalueid", &[f.clone(), i(2)]);
+    assert_eq!(
+        id1,
+        call_lib(l.clone(), "debug", "upvalueid", &[f.clone(), i(1)])
+    );
+    assert_ne!(id1, id2);
+    let msg = lib_error(l.clone(), "debug", "upvalueid", &[f.clone(), i(3)]);
+    assert!(msg.contains("invalid upvalue index"), "{}", msg);
+    let msg = lib_error(
+        l.clone(),
+        "debug",
+        "upvaluejoin",
+        &[f.clone(), i(1), f, i(2)],
+    );
+    assert!(msg.contains("Lua function expected"), "{}", msg);
+
+    // Lua 函数的上值有名字；两个闭包的上值各自独立，join 之后共享同一个单元
+    load_inspect(l.clone());
+    load_inspect(l.clone());
+    let (m1, m2) = (l.borrow().get(1), l.borrow().get(2));
+    lua_getglobal(l.clone(), "_G");
+    let globals = l.borrow().get(3);
+    assert_eq!(
+        call_lib(l.clone(), "debug", "getupvalue", &[m1.clone(), i(1)]),
+        [s("_ENV"), globals.clone()]
+    );
+    lua_newtable(l.clone());
+    let env = l.borrow().get(4);
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "debug",
+            "setupvalue",
+            &[m2.clone(), i(1), env.clone()]
+        ),
+        [s("_ENV")]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "debug", "getupvalue", &[m1.clone(), i(1)]),
+        [s("_ENV"), globals.clone()]
+    );
+    let id = |m: &LuaValue| call_lib(l.clone(), "debug", "upvalueid", &[m.clone(), i(1)]);
+    assert_ne!(id(&m1), id(&m2));
+
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "debug",
+            "upvaluejoin",
+            &[m1.clone(), i(1), m2.clone(), i(1)]
+        ),
+        []
+    );
+    assert_eq!(
+        call_lib(l.clone(), "debug", "getupvalue", &[m1.clone(), i(1)]),
+        [s("_ENV"), env]
+    );
+    assert_eq!(id(&m1), id(&m2));
+    // 通过一个闭包修改共享的上值，另一个闭包也能看到
+    call_lib(
+        l.clone(),
+        "debug",
+        "setupvalue",
+        &[m1, i(1), globals.clone()],
+    );
+    assert_eq!(
+        call_lib(l.clone(), "debug", "getupvalue", &[m2, i(1)]),
+        [s("_ENV"), globals]
+    );
+}
+
+// lua_Hook 是普通函数，行号记录在注册表的 external_lines 中
+fn external_hook(l: lua_State, ar: &mut lua_Debug) {
+    assert!(lua_getinfo(l.clone(), "Sl", ar));
+    if lua_getfield(l.clone(), LUA_REGISTRYINDEX, "external_lines") != LUA_TTABLE {
+        lua_pop(l.clone(), 1);
+        lua_newtable(l.clone());
+        lua_pushvalue(l.clone(), -1);
+        lua_setfield(l.clone(), LUA_REGISTRYINDEX, "external_lines");
+    }
+    let n = lua_rawlen(l.clone(), -1) as isize;
+    lua_pushinteger(l.clone(), ar.currentline);
+    lua_rawseti(l.clone(), -2, n + 1);
+    lua_pop(l, 1);
+}
+
+fn external_lines(l: lua_State) -> Vec<i64> {
+    lua_getfield(l.clone(), LUA_REGISTRYINDEX, "external_lines");
+    let n = lua_rawlen(l.clone(), -1) as isize;
+    let lines = (1..=n)
+        .map(|k| {
+            lua_rawgeti(l.clone(), -1, k);
+            let line = lua_tointeger(l.clone(), -1);
+            lua_pop(l.clone(), 1);
+            line
+        })
+        .collect();
+    lua_pop(l, 1);
+    lines
+}
+
+#[test]
+fn hook_test() {
+    debug!("test debug.sethook, debug.gethook and lua_sethook");
+    let l = new_state();
+    lua_pushcfunction(l.clone(), |l| {
+        lua_pushinteger(l, 0);
+        1
+    });
+    lua_setglobal(l.clone(), "probe");
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let log = events.clone();
+    lua_pushrustclosure(
+        l.clone(),
+        move |l| {
+            let line = match lua_isnil(l.clone(), 2) {
+                true => String::new(),
+                false => format!(" {}", lua_tointeger(l.clone(), 2)),
+            };
+            log.borrow_mut()
+                .push(format!("{}{}", lua_tostring(l, 1), line));
+            0
+        },
+        0,
+    );
+    let hook = pop_value(l.clone());
+
+    call_lib(l.clone(), "debug", "sethook", &[hook.clone(), s("crl")]);
+    assert_eq!(
+        call_lib(l.clone(), "debug", "gethook", &[]),
+        [hook.clone(), s("crl"), i(0)]
+    );
+    events.borrow_mut().clear();
+    load_inspect(l.clone());
+    lua_call(l.clone(), 0, 0);
+    assert_eq!(
+        *events.borrow(),
+        ["call", "line 1", "line 2", "line 3", "call", "return", "line 4", "return",]
+    );
+
+    // 每执行一条指令触发一次计数钩子
+    call_lib(l.clone(), "debug", "sethook", &[hook.clone(), s(""), i(1)]);
+    events.borrow_mut().clear();
+    load_inspect(l.clone());
+    lua_call(l.clone(), 0, 0);
+    assert_eq!(*events.borrow(), vec!["count"; 6]);
+    assert_eq!(
+        call_lib(l.clone(), "debug", "gethook", &[]),
+        [hook, s(""), i(1)]
+    );
+
+    call_lib(l.clone(), "debug", "sethook", &[]);
+    assert_eq!(
+        call_lib(l.clone(), "debug", "gethook", &[]),
+        [LuaValue::Nil, s(""), i(0)]
+    );
+    assert!(lua_gethook(l.clone()).is_none());
+
+    lua_sethook(l.clone(), Some(external_hook), LUA_MASKLINE, 0);
+    assert_eq!(lua_gethookmask(l.clone()), LUA_MASKLINE);
+    load_inspect(l.clone());
+    lua_call(l.clone(), 0, 0);
+    assert_eq!(external_lines(l.clone()), [1, 2, 3, 4]);
+    assert_eq!(
+        call_lib(l.clone(), "debug", "gethook", &[])[..2],
+        [s("external hook"), s("l")]
+    );
+    lua_sethook(l.clone(), None, LUA_MASKLINE, 0);
+    assert_eq!(lua_gethookmask(l), 0);
+}
@@ -0,0 +1,216 @@
+use llua::api::*;
+use llua::debug;
+use std::cell::RefCell;
+
+thread_local! {
+    static FINALIZED: RefCell<Vec<i64>> = const { RefCell::new(Vec::new()) };
+}
+
+fn new_table(l: lua_State) {
+    l.borrow_mut().push(LuaValue::new_table(0, 0));
+}
+
+fn field(l: lua_State, idx: isize, name: &str) -> LuaValue {
+    let index = lua_absindex(l.clone(), idx);
+    if let LuaValue::Table(t) = l.borrow().get(index) {
+        t.borrow().get(LuaValue::String(name.into()))
+    } else {
+        LuaValue::Nil
+    }
+}
+
+fn record_gc(l: lua_State) -> usize {
+    if let LuaValue::Integer(id) = field(l, 1, "id") {
+        FINALIZED.with(|f| f.borrow_mut().push(id));
+    }
+    0
+}
+
+fn resurrect(l: lua_State) -> usize {
+    record_gc(l.clone());
+    lua_pushvalue(l.clone(), 1);
+    lua_setglobal(l, "saved");
+    0
+}
+
+fn failing_gc(l: lua_State) -> usize {
+    record_gc(l.clone());
+    luaL_error(l, "error in finalizer")
+}
+
+// 创建一个带 __gc 元方法的对象并留在栈顶
+fn new_finalizable(l: lua_State, id: isize, gc: lua_CFunction) {
+    new_table(l.clone());
+    lua_pushinteger(l.clone(), id);
+    lua_setfield(l.clone(), -2, "id");
+    new_table(l.clone());
+    lua_pushcfunction(l.clone(), gc);
+    lua_setfield(l.clone(), -2, "__gc");
+    lua_setmetatable(l, -2);
+}
+
+fn new_weak_table(l: lua_State, mode: &str) {
+    new_table(l.clone());
+    new_table(l.clone());
+    lua_pushstring(l.clone(), mode);
+    lua_setfield(l.clone(), -2, "__mode");
+    lua_setmetatable(l, -2);
+}
+
+fn finalized() -> Vec<i64> {
+    FINALIZED.with(|f| f.borrow_mut().drain(..).collect())
+}
+
+#[test]
+fn weak_values_test() {
+    debug!("test weak values");
+    let l = luaL_newstate();
+    new_weak_table(l.clone(), "v");
+    new_table(l.clone());
+    lua_setfield(l.clone(), 1, "garbage");
+    new_table(l.clone());
+    lua_pushvalue(l.clone(), -1);
+    lua_setglobal(l.clone(), "alive");
+    lua_setfield(l.clone(), 1, "alive");
+    lua_pushstring(l.clone(), "sweethui");
+    lua_setfield(l.clone(), 1, "name");
+    assert!(field(l.clone(), 1, "garbage").is_table());
+
+    assert_eq!(lua_gc(l.clone(), LUA_GCCOLLECT, 0), 0);
+    assert_eq!(field(l.clone(), 1, "garbage"), LuaValue::Nil);
+    assert!(field(l.clone(), 1, "alive").is_table());
+    assert_eq!(
+        field(l.clone(), 1, "name"),
+        LuaValue::String("sweethui".into())
+    );
+}
+
+#[test]
+fn ephemeron_test() {
+    debug!("test weak keys");
+    let l = luaL_newstate();
+    new_weak_table(l.clone(), "k");
+    let cache = l.borrow().get(1);
+    // 值引用了键，键不可达时整项仍然要被回收
+    let key = LuaValue::new_table(0, 0);
+    let value = LuaValue::new_table(0, 0);
+    if let (LuaValue::Table(c), LuaValue::Table(v)) = (&cache, &value) {
+        v.borrow_mut()
+            .set(LuaValue::String("key".into()), key.clone());
+        c.borrow_mut().set(key.clone(), value.clone());
+    }
+    let alive = LuaValue::new_table(0, 0);
+    l.borrow_mut().push(alive.clone());
+    lua_setglobal(l.clone(), "alive");
+    if let LuaValue::Table(c) = &cache {
+        c.borrow_mut().set(alive.clone(), LuaValue::Integer(1103));
+    }
+    drop(key);
+    drop(value);
+
+    lua_gc(l.clone(), LUA_GCCOLLECT, 0);
+    if let LuaValue::Table(c) = &cache {
+        let c = c.borrow();
+        assert_eq!(c.entries().len(), 1);
+        assert_eq!(c.get(alive), LuaValue::Integer(1103));
+    } else {
+        unreachable!()
+    }
+}
+
+#[test]
+fn finalizer_order_test() {
+    debug!("test __gc order");
+    let l = luaL_newstate();
+    for id in 1..=3 {
+        new_finalizable(l.clone(), id, record_gc);
+    }
+    lua_gc(l.clone(), LUA_GCCOLLECT, 0);
+    assert!(finalized().is_empty());
+
+    lua_pop(l.clone(), 1);
+    lua_pop(l.clone(), 1);
+    lua_pop(l.clone(), 1);
+    lua_gc(l.clone(), LUA_GCCOLLECT, 0);
+    assert_eq!(finalized(), vec![3, 2, 1]);
+    lua_gc(l.clone(), LUA_GCCOLLECT, 0);
+    assert!(finalized().is_empty());
+}
+
+#[test]
+fn resurrection_test() {
+    debug!("test __gc resurrection");
+    let l = luaL_newstate();
+    new_weak_table(l.clone(), "v");
+    new_weak_table(l.clone(), "k");
+    new_finalizable(l.clone(), 7, resurrect);
+    lua_pushvalue(l.clone(), -1);
+    lua_setfield(l.clone(), 1, "obj");
+    let obj = l.borrow().get(3);
+    let keys = l.borrow().get(2);
+    if let LuaValue::Table(keys) = keys {
+        keys.borrow_mut().set(obj.clone(), LuaValue::Boolean(true));
+    }
+    drop(obj);
+    lua_pop(l.clone(), 1);
+
+    lua_gc(l.clone(), LUA_GCCOLLECT, 0);
+    assert_eq!(finalized(), vec![7]);
+    lua_getglobal(l.clone(), "saved");
+    assert!(lua_istable(l.clone(), -1));
+    assert_eq!(field(l.clone(), -1, "id"), LuaValue::Integer(7));
+    // 复活的对象在调用 __gc 前已从弱值表移除，但仍保留在弱键表中
+    assert_eq!(field(l.clone(), 1, "obj"), LuaValue::Nil);
+    let saved = l.borrow().get(3);
+    let keys = l.borrow().get(2);
+    if let LuaValue::Table(keys) = keys {
+        assert_eq!(keys.borrow().get(saved), LuaValue::Boolean(true));
+    }
+
+    // 终结器只会运行一次
+    lua_pop(l.clone(), 1);
+    lua_pushnil(l.clone());
+    lua_setglobal(l.clone(), "saved");
+    lua_gc(l.clone(), LUA_GCCOLLECT, 0);
+    assert!(finalized().is_empty());
+    let keys = l.borrow().get(2);
+    if let LuaValue::Table(keys) = keys {
+        assert!(keys.borrow().entries().is_empty());
+    }
+}
+
+#[test]
+fn close_test() {
+    debug!("test lua_close");
+    let l = luaL_newstate();
+    new_finalizable(l.clone(), 1, record_gc);
+    lua_setglobal(l.clone(), "global");
+    new_finalizable(l.clone(), 2, record_gc);
+    new_finalizable(l.clone(), 3, record_gc);
+    lua_close(l.clone());
+    assert_eq!(finalized(), vec![3, 2, 1]);
+}
+
+#[test]
+fn finalizer_error_test() {
+    debug!("test errors raised by __gc");
+    // 终结器中的错误被吞掉，不需要打印它们的 panic 信息
+    llua::install_panic_hook();
+    let l = luaL_newstate();
+    new_finalizable(l.clone(), 1, record_gc);
+    new_finalizable(l.clone(), 2, failing_gc);
+    new_finalizable(l.clone(), 3, record_gc);
+    lua_pushinteger(l.clone(), 1103);
+    lua_rotate(l.clone(), 1, 1);
+    lua_settop(l.clone(), 1);
+    // 出错的终结器不影响其他终结器，也不会让 lua_gc 出错
+    lua_gc(l.clone(), LUA_GCCOLLECT, 0);
+    assert_eq!(finalized(), vec![3, 2, 1]);
+    assert_eq!(lua_gettop(l.clone()), 1);
+    assert_eq!(lua_tointeger(l.clone(), 1), 1103);
+
+    new_finalizable(l.clone(), 4, failing_gc);
+    new_finalizable(l.clone(), 5, record_gc);
+    lua_close(l);
+    assert_eq!(finalized(), vec![5, 4]);
+}
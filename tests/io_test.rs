@@ -0,0 +1,339 @@
+use llua::api::*;
+use llua::debug;
+use std::path::{Path, PathBuf};
+
+mod common;
+use common::*;
+
+// 每个测试使用自己的临时文件
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("llua_io_{}_{}", std::process::id(), name))
+}
+
+// 调用栈顶的函数，它下面没有其他值，返回所有结果
+fn call(l: lua_State, args: &[LuaValue]) -> Vec<LuaValue> {
+    for arg in args {
+        l.borrow_mut().push(arg.clone());
+    }
+    lua_call(l.clone(), args.len() as isize, LUA_MULTRET);
+    (1..=lua_gettop(l.clone()))
+        .map(|i| l.borrow().get(i))
+        .collect()
+}
+
+fn pcall_error(l: lua_State, args: &[LuaValue]) -> String {
+    for arg in args {
+        l.borrow_mut().push(arg.clone());
+    }
+    assert_ne!(lua_pcall(l.clone(), args.len() as isize, 0, 0), LUA_OK);
+    lua_tostring(l, -1)
+}
+
+// 以 file:name(...) 的形式调用方法，返回包括文件在内的参数
+fn push_method(l: lua_State, file: &LuaValue, name: &str, args: &[LuaValue]) -> Vec<LuaValue> {
+    lua_settop(l.clone(), 0);
+    l.borrow_mut().push(file.clone());
+    lua_getfield(l.clone(), 1, name);
+    lua_remove(l, 1);
+    let mut all = vec![file.clone()];
+    all.extend_from_slice(args);
+    all
+}
+
+fn call_method(l: lua_State, file: &LuaValue, name: &str, args: &[LuaValue]) -> Vec<LuaValue> {
+    let args = push_method(l.clone(), file, name, args);
+    call(l, &args)
+}
+
+fn method_error(l: lua_State, file: &LuaValue, name: &str, args: &[LuaValue]) -> String {
+    let args = push_method(l.clone(), file, name, args);
+    pcall_error(l, &args)
+}
+
+fn open(l: lua_State, path: &Path, mode: &str) -> LuaValue {
+    let path = s(&path.to_string_lossy());
+    call_lib(l, "io", "open", &[path, s(mode)]).remove(0)
+}
+
+// 调用迭代器直到它返回 nil
+fn collect_lines(l: lua_State, iter: &LuaValue) -> Vec<Vec<LuaValue>> {
+    let mut lines = Vec::new();
+    loop {
+        lua_settop(l.clone(), 0);
+        l.borrow_mut().push(iter.clone());
+        let values = call(l.clone(), &[]);
+        match values.first() {
+            None | Some(LuaValue::Nil) => return lines,
+            _ => lines.push(values),
+        }
+    }
+}
+
+#[test]
+fn read_formats_test() {
+    debug!("test file:read with n, l, L, a and byte counts");
+    let l = new_state();
+    let path = temp_path("read");
+    std::fs::write(&path, "first line\nsecond\n  42 0x1F -3.5e2 nope\nrest").unwrap();
+
+    let f = open(l.clone(), &path, "r");
+    assert_eq!(
+        call_method(l.clone(), &f, "read", &[s("l"), s("L")]),
+        [s("first line"), s("second\n")]
+    );
+    assert_eq!(
+        call_method(l.clone(), &f, "read", &[s("n"), s("*n"), s("n")]),
+        [i(42), i(31), LuaValue::Number(-350.0)]
+    );
+    // 读取失败的格式返回 nil，后面的格式不再读取
+    assert_eq!(
+        call_method(l.clone(), &f, "read", &[s("n"), s("l")]),
+        [LuaValue::Nil]
+    );
+    assert_eq!(call_method(l.clone(), &f, "read", &[i(2)]), [s("no")]);
+    assert_eq!(call_method(l.clone(), &f, "read", &[i(0)]), [s("")]);
+    assert_eq!(
+        call_method(l.clone(), &f, "read", &[s("a")]),
+        [s("pe\nrest")]
+    );
+    assert_eq!(
+        call_method(l.clone(), &f, "read", &[s("a"), s("l"), i(0)]),
+        [s(""), LuaValue::Nil]
+    );
+    assert_eq!(call_method(l.clone(), &f, "read", &[i(0)]), [LuaValue::Nil]);
+    let msg = method_error(l.clone(), &f, "read", &[s("x")]);
+    assert!(msg.contains("(invalid format)"), "{}", msg);
+    call_method(l, &f, "close", &[]);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn write_and_lines_test() {
+    debug!("test file:write chaining, io.lines and file:lines");
+    let l = new_state();
+    let path = temp_path("lines");
+
+    let f = open(l.clone(), &path, "w");
+    let args = [s("a"), i(1), s(" "), LuaValue::Number(2.5), s(" ")];
+    assert_eq!(
+        call_method(l.clone(), &f, "write", &args),
+        std::slice::from_ref(&f)
+    );
+    let args = [LuaValue::Number(1e100), s("\nb\nc")];
+    assert_eq!(
+        call_method(l.clone(), &f, "write", &args),
+        std::slice::from_ref(&f)
+    );
+    assert_eq!(
+        call_method(l.clone(), &f, "close", &[]),
+        [LuaValue::Boolean(true)]
+    );
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "a1 2.5 1e+100\nb\nc"
+    );
+
+    let iter = call_lib(l.clone(), "io", "lines", &[s(&path.to_string_lossy())]).remove(0);
+    assert_eq!(
+        collect_lines(l.clone(), &iter),
+        [[s("a1 2.5 1e+100")], [s("b")], [s("c")]]
+    );
+    // 读完之后 io.lines 打开的文件已经关闭
+    let msg = {
+        lua_settop(l.clone(), 0);
+        l.borrow_mut().push(iter);
+        pcall_error(l.clone(), &[])
+    };
+    assert!(msg.contains("file is already closed"), "{}", msg);
+
+    let f = open(l.clone(), &path, "r");
+    let iter = call_method(l.clone(), &f, "lines", &[i(1), s("L")]).remove(0);
+    assert_eq!(
+        collect_lines(l.clone(), &iter),
+        [
+            vec![s("a"), s("1 2.5 1e+100\n")],
+            vec![s("b"), s("\n")],
+            vec![s("c"), LuaValue::Nil],
+        ]
+    );
+    assert_eq!(call_lib(l.clone(), "io", "type", &[f]), [s("file")]);
+
+    let msg = lib_error(l, "io", "lines", &[s("/nonexistent/llua")]);
+    assert!(
+        msg.contains("cannot open file '/nonexistent/llua' (No such file or directory)"),
+        "{}",
+        msg
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn seek_and_buffer_test() {
+    debug!("test file:seek, file:setvbuf and mixing reads with writes");
+    let l = new_state();
+    let path = temp_path("seek");
+
+    let f = open(l.clone(), &path, "w+");
+    assert_eq!(
+        call_method(l.clone(), &f, "setvbuf", &[s("full"), i(1024)]),
+        [LuaValue::Boolean(true)]
+    );
+    call_method(l.clone(), &f, "write", &[s("hello world")]);
+    // 缓冲中的内容还没有写入文件
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    assert_eq!(call_method(l.clone(), &f, "seek", &[s("end")]), [i(11)]);
+    assert_eq!(call_method(l.clone(), &f, "seek", &[s("set")]), [i(0)]);
+    assert_eq!(call_method(l.clone(), &f, "read", &[i(5)]), [s("hello")]);
+    assert_eq!(call_method(l.clone(), &f, "seek", &[]), [i(5)]);
+    call_method(l.clone(), &f, "write", &[s("_")]);
+    call_method(l.clone(), &f, "seek", &[s("set"), i(0)]);
+    assert_eq!(
+        call_method(l.clone(), &f, "read", &[s("a")]),
+        [s("hello_world")]
+    );
+    assert_eq!(
+        call_method(l.clone(), &f, "seek", &[s("cur"), i(-5)]),
+        [i(6)]
+    );
+    let result = call_method(l.clone(), &f, "seek", &[s("set"), i(-1)]);
+    assert_eq!(result[0], LuaValue::Nil);
+
+    call_method(l.clone(), &f, "setvbuf", &[s("no")]);
+    call_method(l.clone(), &f, "seek", &[s("end")]);
+    call_method(l.clone(), &f, "write", &[s("!")]);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello_world!");
+    let msg = method_error(l.clone(), &f, "setvbuf", &[s("some")]);
+    assert!(msg.contains("invalid option 'some'"), "{}", msg);
+    call_method(l, &f, "close", &[]);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn open_errors_test() {
+    debug!("test io.open failures, closed files and io.type");
+    let l = new_state();
+    let path = temp_path("errors");
+
+    assert_eq!(
+        call_lib(l.clone(), "io", "open", &[s("/nonexistent/llua/file")]),
+        [
+            LuaValue::Nil,
+            s("/nonexistent/llua/file: No such file or directory"),
+            i(2),
+        ]
+    );
+    let msg = lib_error(l.clone(), "io", "open", &[s("file"), s("rw")]);
+    assert!(
+        msg.contains("bad argument #2 to 'io.open' (invalid mode)"),
+        "{}",
+        msg
+    );
+
+    let f = open(l.clone(), &path, "wb");
+    lua_settop(l.clone(), 0);
+    l.borrow_mut().push(f.clone());
+    assert!(luaL_tolstring(l.clone(), 1).starts_with("file (0x"));
+    call_method(l.clone(), &f, "close", &[]);
+    assert_eq!(
+        call_lib(l.clone(), "io", "type", std::slice::from_ref(&f)),
+        [s("closed file")]
+    );
+    assert_eq!(call_lib(l.clone(), "io", "type", &[i(42)]), [LuaValue::Nil]);
+    lua_settop(l.clone(), 0);
+    l.borrow_mut().push(f.clone());
+    assert_eq!(luaL_tolstring(l.clone(), 1), "file (closed)");
+
+    let msg = method_error(l.clone(), &f, "read", &[]);
+    assert!(msg.contains("attempt to use a closed file"), "{}", msg);
+    let msg = lib_error(l.clone(), "io", "type", &[]);
+    assert!(msg.contains("bad argument #1 to 'io.type'"), "{}", msg);
+
+    lua_settop(l.clone(), 0);
+    lua_getglobal(l.clone(), "io");
+    lua_getfield(l.clone(), 1, "stdout");
+    let stdout = l.borrow().get(2);
+    assert_eq!(
+        call_method(l, &stdout, "close", &[]),
+        [LuaValue::Nil, s("cannot close standard file")]
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn default_files_test() {
+    debug!("test io.input, io.output, io.read, io.write and io.tmpfile");
+    let l = new_state();
+    let path = temp_path("default");
+    let name = s(&path.to_string_lossy());
+
+    let stdout = call_lib(l.clone(), "io", "output", &[]).remove(0);
+    let out = call_lib(l.clone(), "io", "output", std::slice::from_ref(&name)).remove(0);
+    assert_ne!(out, stdout);
+    let args = [s("x = "), i(10), s("\n"), s("y")];
+    assert_eq!(
+        call_lib(l.clone(), "io", "write", &args),
+        std::slice::from_ref(&out)
+    );
+    assert_eq!(
+        call_lib(l.clone(), "io", "close", &[]),
+        [LuaValue::Boolean(true)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "io", "type", &[out]),
+        [s("closed file")]
+    );
+    let msg = lib_error(l.clone(), "io", "write", &[s("z")]);
+    assert!(msg.contains("standard output file is closed"), "{}", msg);
+    call_lib(l.clone(), "io", "output", std::slice::from_ref(&stdout));
+    assert_eq!(call_lib(l.clone(), "io", "output", &[]), [stdout]);
+
+    let input = call_lib(l.clone(), "io", "input", &[name]).remove(0);
+    assert_eq!(
+        call_lib(l.clone(), "io", "read", &[s("L"), s("a")]),
+        [s("x = 10\n"), s("y")]
+    );
+    assert_eq!(call_lib(l.clone(), "io", "read", &[]), [LuaValue::Nil]);
+    call_method(l.clone(), &input, "close", &[]);
+    let msg = lib_error(l.clone(), "io", "read", &[]);
+    assert!(msg.contains("standard input file is closed"), "{}", msg);
+
+    let t = call_lib(l.clone(), "io", "tmpfile", &[]).remove(0);
+    call_method(l.clone(), &t, "write", &[s("temp")]);
+    call_method(l.clone(), &t, "seek", &[s("set")]);
+    assert_eq!(call_method(l.clone(), &t, "read", &[s("a")]), [s("temp")]);
+    call_method(l, &t, "close", &[]);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn popen_test() {
+    debug!("test io.popen for reading and writing");
+    let l = new_state();
+    let path = temp_path("popen");
+
+    let p = call_lib(l.clone(), "io", "popen", &[s("echo hello; echo world")]).remove(0);
+    assert_eq!(
+        call_method(l.clone(), &p, "read", &[s("a")]),
+        [s("hello\nworld\n")]
+    );
+    assert_eq!(
+        call_method(l.clone(), &p, "close", &[]),
+        [LuaValue::Boolean(true), s("exit"), i(0)]
+    );
+
+    let command = format!("cat > {}", path.to_string_lossy());
+    let w = call_lib(l.clone(), "io", "popen", &[s(&command), s("w")]).remove(0);
+    call_method(l.clone(), &w, "write", &[s("piped")]);
+    call_method(l.clone(), &w, "close", &[]);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "piped");
+
+    let p = call_lib(l.clone(), "io", "popen", &[s("exit 3")]).remove(0);
+    assert_eq!(
+        call_method(l.clone(), &p, "close", &[]),
+        [LuaValue::Nil, s("exit"), i(3)]
+    );
+    let msg = lib_error(l, "io", "popen", &[s("ls"), s("rw")]);
+    assert!(msg.contains("invalid mode"), "{}", msg);
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,186 @@
+use llua::api::*;
+use llua::debug;
+use llua::lua::{Error, Function, Lua, LuaString, LuaValue, Table};
+
+fn add(l: lua_State) -> usize {
+    let a = luaL_checkinteger(l.clone(), 1);
+    let b = luaL_checkinteger(l.clone(), 2);
+    lua_pushinteger(l.clone(), (a + b) as isize);
+    lua_pushboolean(l, a < b);
+    2
+}
+
+#[test]
+fn globals_test() {
+    debug!("test reading and writing globals");
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals.set("x", 1103i64).unwrap();
+    globals.set("name", "sweethui").unwrap();
+    assert_eq!(globals.get::<_, i64>("x").unwrap(), 1103);
+    assert_eq!(globals.get::<_, String>("x").unwrap(), "1103");
+    assert_eq!(globals.get::<_, String>("name").unwrap(), "sweethui");
+    assert_eq!(
+        globals.get::<_, LuaValue>("missing").unwrap(),
+        LuaValue::Nil
+    );
+    assert!(globals.contains_key("print").unwrap());
+
+    let err = globals.get::<_, i64>("name").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "error converting Lua string to i64".to_string()
+    );
+}
+
+#[test]
+fn table_test() {
+    debug!("test Table handles");
+    let lua = Lua::new();
+    let t = lua.create_table();
+    for i in 1i64..=3 {
+        t.set(i, i * 11).unwrap();
+    }
+    t.raw_set("key", true).unwrap();
+    assert_eq!(t.len().unwrap(), 3);
+    assert_eq!(t.raw_len(), 3);
+    assert!(t.raw_get::<_, bool>("key").unwrap());
+    let values = t
+        .sequence_values::<i64>()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(values, vec![11, 22, 33]);
+    let mut pairs = t
+        .pairs::<LuaValue, LuaValue>()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(pairs.len(), 4);
+    pairs.retain(|(k, _)| k == &LuaValue::String("key".into()));
+    assert_eq!(pairs[0].1, LuaValue::Boolean(true));
+
+    match t.raw_set(LuaValue::Nil, 1i64) {
+        Err(Error::RuntimeError(msg)) => assert_eq!(msg, "index is nil"),
+        _ => panic!("expected a runtime error"),
+    }
+
+    // 表作为值存入另一个表后仍然是同一个对象
+    lua.globals().set("t", t.clone()).unwrap();
+    let same: Table = lua.globals().get("t").unwrap();
+    same.set("key", false).unwrap();
+    assert!(!t.get::<_, bool>("key").unwrap());
+}
+
+#[test]
+fn metatable_test() {
+    debug!("test metamethods through Table handles");
+    fn index(l: lua_State) -> usize {
+        let key = luaL_checkstring(l.clone(), 2);
+        lua_pushstring(l, &format!("missing {}", key));
+        1
+    }
+    fn len(l: lua_State) -> usize {
+        lua_pushinteger(l, 88);
+        1
+    }
+    fn fail(l: lua_State) -> usize {
+        luaL_error(l, "read only")
+    }
+    let lua = Lua::new();
+    let l = lua.lua_state();
+    lua_register(l.clone(), "index", index);
+    lua_register(l.clone(), "len", len);
+    lua_register(l.clone(), "fail", fail);
+
+    let globals = lua.globals();
+    let mt = lua.create_table();
+    mt.set("__index", globals.get::<_, Function>("index").unwrap())
+        .unwrap();
+    mt.set("__len", globals.get::<_, Function>("len").unwrap())
+        .unwrap();
+    mt.set("__newindex", globals.get::<_, Function>("fail").unwrap())
+        .unwrap();
+    let t = lua.create_table();
+    t.set_metatable(Some(mt));
+    assert!(t.get_metatable().is_some());
+
+    assert_eq!(t.get::<_, String>("x").unwrap(), "missing x");
+    assert_eq!(t.raw_get::<_, LuaValue>("x").unwrap(), LuaValue::Nil);
+    assert_eq!(t.len().unwrap(), 88);
+    assert_eq!(
+        t.set("x", 1i64),
+        Err(Error::RuntimeError("read only".to_string()))
+    );
+    t.raw_set("x", 1i64).unwrap();
+    assert_eq!(t.get::<_, i64>("x").unwrap(), 1);
+
+    t.set_metatable(None);
+    assert!(t.get_metatable().is_none());
+    assert_eq!(lua_gettop(l), 0);
+}
+
+#[test]
+fn function_test() {
+    debug!("test calling functions");
+    let lua = Lua::new();
+    let l = lua.lua_state();
+    lua_register(l.clone(), "add", add);
+    let f: Function = lua.globals().get("add").unwrap();
+    let (sum, less) = f.call::<_, (i64, bool)>((88i64, 11i64)).unwrap();
+    assert_eq!(sum, 99);
+    assert!(!less);
+    assert_eq!(f.call::<_, i64>(("1", 2.0)).unwrap(), 3);
+    let (sum, less, extra) = f.call::<_, (i64, bool, LuaValue)>((1i64, 2i64)).unwrap();
+    assert_eq!((sum, less, extra), (3, true, LuaValue::Nil));
+
+    let err = f.call::<_, i64>(1i64).unwrap_err();
+    assert_eq!(
+        err,
+        Error::RuntimeError("bad argument #2 to 'add' (number expected, got no value)".to_string())
+    );
+    // 出错后栈和调用链都已经恢复，可以继续调用
+    assert_eq!(f.call::<_, i64>((1i64, 1i64)).unwrap(), 2);
+    assert_eq!(lua_gettop(l), 0);
+}
+
+#[test]
+fn load_error_test() {
+    debug!("test loading a text chunk");
+    let lua = Lua::new();
+    let err = lua.load("return 1").set_name("main").exec().unwrap_err();
+    match err {
+        Error::SyntaxError(msg) => assert!(msg.starts_with("main: attempt to load a text chunk")),
+        _ => panic!("expected a syntax error"),
+    }
+    let s: LuaString = lua.create_string("sweet");
+    assert_eq!(s.to_str().unwrap(), "sweet");
+    assert_eq!(s.len(), 5);
+}
+
+#[test]
+fn load_binary_test() {
+    debug!("test loading and running a binary chunk");
+    let lua = Lua::new();
+    let func = include_bytes!("func.out");
+    let (a, b, r) = lua.load(&func[..]).eval::<(i64, i64, i64)>().unwrap();
+    assert_eq!((a, b, r), (11, 3, 14));
+
+    let f = lua
+        .load(&func[..])
+        .set_name("func")
+        .into_function()
+        .unwrap();
+    assert_eq!(f.call::<_, i64>(()).unwrap(), 11);
+    assert_eq!(f.call::<_, (i64, i64, i64)>(()).unwrap(), (11, 3, 14));
+
+    lua.load(&include_bytes!("sample.out")[..]).exec().unwrap();
+
+    // global.out 读取全局变量 hui
+    let global = include_bytes!("global.out");
+    let (n, hui) = lua.load(&global[..]).eval::<(i64, LuaValue)>().unwrap();
+    assert_eq!((n, hui), (88, LuaValue::Nil));
+    lua.globals().set("hui", 1103i64).unwrap();
+    assert_eq!(
+        lua.load(&global[..]).eval::<(i64, i64)>().unwrap(),
+        (88, 1103)
+    );
+}
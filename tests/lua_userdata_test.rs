@@ -0,0 +1,128 @@
+use llua::api::*;
+use llua::debug;
+use llua::lua::{AnyUserData, Error, Function, Lua, UserData, UserDataFields, UserDataMethods};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Vec2 {
+    x: f64,
+    y: f64,
+}
+
+impl UserData for Vec2 {
+    fn add_fields(fields: &mut UserDataFields<Self>) {
+        fields.add_field_method_get("x", |_, v| Ok(v.x));
+        fields.add_field_method_get("y", |_, v| Ok(v.y));
+        fields.add_field_method_set("x", |_, v, x: f64| {
+            v.x = x;
+            Ok(())
+        });
+    }
+
+    fn add_methods(methods: &mut UserDataMethods<Self>) {
+        methods.add_method("length", |_, v, ()| Ok((v.x * v.x + v.y * v.y).sqrt()));
+        methods.add_method_mut("scale", |_, v, k: f64| {
+            v.x *= k;
+            v.y *= k;
+            Ok(())
+        });
+        // 调用传入的函数时仍然持有 self 的可变借用
+        methods.add_method_mut("visit", |_, v, (f, this): (Function, AnyUserData)| {
+            v.x += 1.0;
+            f.call::<_, ()>((this, 2))
+        });
+        methods.add_meta_function("__add", |lua, (a, b): (AnyUserData, AnyUserData)| {
+            let (a, b) = (a.borrow::<Vec2>()?, b.borrow::<Vec2>()?);
+            lua.create_userdata(Vec2 {
+                x: a.x + b.x,
+                y: a.y + b.y,
+            })
+        });
+        methods.add_meta_method("__tostring", |_, v, ()| Ok(format!("({}, {})", v.x, v.y)));
+    }
+}
+
+struct Tag;
+
+impl UserData for Tag {}
+
+#[test]
+fn userdata_test() {
+    debug!("test fields, methods and meta-methods of userdata");
+    let lua = Lua::new();
+    let v = lua.create_userdata(Vec2 { x: 3.0, y: 4.0 }).unwrap();
+    assert!(v.is::<Vec2>());
+    assert!(!v.is::<Tag>());
+
+    let mt = v.get_metatable().unwrap();
+    let index: Function = mt.get("__index").unwrap();
+    let newindex: Function = mt.get("__newindex").unwrap();
+    assert_eq!(index.call::<_, f64>((v.clone(), "y")).unwrap(), 4.0);
+    let length: Function = index.call((v.clone(), "length")).unwrap();
+    assert_eq!(length.call::<_, f64>(v.clone()).unwrap(), 5.0);
+    let scale: Function = index.call((v.clone(), "scale")).unwrap();
+    scale.call::<_, ()>((v.clone(), 2)).unwrap();
+    assert_eq!(*v.borrow::<Vec2>().unwrap(), Vec2 { x: 6.0, y: 8.0 });
+
+    newindex.call::<_, ()>((v.clone(), "x", 1)).unwrap();
+    assert_eq!(v.borrow::<Vec2>().unwrap().x, 1.0);
+    match newindex.call::<_, ()>((v.clone(), "y", 1)) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.ends_with("attempt to set unknown field 'y'"))
+        }
+        r => panic!("unexpected result {:?}", r),
+    }
+    // 用点号而不是冒号调用方法时 self 类型不对
+    assert!(length.call::<_, f64>(1).is_err());
+
+    let tostring: Function = mt.get("__tostring").unwrap();
+    assert_eq!(tostring.call::<_, String>(v.clone()).unwrap(), "(1, 8)");
+
+    // 同一类型的所有值共享一个元表
+    let w = lua.create_userdata(Vec2 { x: 1.0, y: 1.0 }).unwrap();
+    let l = lua.lua_state();
+    lua.globals().set("v", v).unwrap();
+    lua.globals().set("w", w).unwrap();
+    lua_getglobal(l.clone(), "v");
+    lua_getglobal(l.clone(), "w");
+    assert!(lua_getmetatable(l.clone(), -1));
+    assert!(lua_getmetatable(l.clone(), -3));
+    assert!(lua_rawequal(l.clone(), -1, -2));
+    lua_pop(l.clone(), 2);
+    lua_arith(l.clone(), LUA_OPADD);
+    lua_setglobal(l, "sum");
+    let sum: AnyUserData = lua.globals().get("sum").unwrap();
+    assert_eq!(*sum.borrow::<Vec2>().unwrap(), Vec2 { x: 2.0, y: 9.0 });
+}
+
+#[test]
+fn borrow_test() {
+    debug!("test borrow checking of userdata");
+    let lua = Lua::new();
+    let v = lua.create_userdata(Vec2 { x: 0.0, y: 0.0 }).unwrap();
+    let index: Function = v.get_metatable().unwrap().get("__index").unwrap();
+    let visit: Function = index.call((v.clone(), "visit")).unwrap();
+    let scale: Function = index.call((v.clone(), "scale")).unwrap();
+    let length: Function = index.call((v.clone(), "length")).unwrap();
+
+    // 重入时的可变借用作为 Lua 错误返回，而不是 panic
+    match visit.call::<_, ()>((v.clone(), scale, v.clone())) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.ends_with("userdata already borrowed")),
+        r => panic!("unexpected result {:?}", r),
+    }
+    match visit.call::<_, ()>((v.clone(), length, v.clone())) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.ends_with("userdata already mutably borrowed"))
+        }
+        r => panic!("unexpected result {:?}", r),
+    }
+    assert_eq!(v.borrow::<Vec2>().unwrap().x, 2.0);
+
+    let guard = v.borrow::<Vec2>().unwrap();
+    assert_eq!(
+        v.borrow_mut::<Vec2>().err(),
+        Some(Error::UserDataBorrowMutError)
+    );
+    drop(guard);
+    v.borrow_mut::<Vec2>().unwrap().y = 1.0;
+    assert_eq!(v.borrow::<Tag>().err(), Some(Error::UserDataTypeMismatch));
+}
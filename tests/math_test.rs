@@ -0,0 +1,133 @@
+use llua::api::*;
+use llua::debug;
+
+mod common;
+use common::*;
+
+#[test]
+fn integer_functions_test() {
+    debug!("test the math functions that distinguish integers from floats");
+    let l = new_state();
+    let cases: &[(&str, &[LuaValue], &[LuaValue])] = &[
+        ("floor", &[f(3.7)], &[i(3)]),
+        ("floor", &[f(-3.5)], &[i(-4)]),
+        ("floor", &[i(5)], &[i(5)]),
+        ("floor", &[f(1e100)], &[f(1e100)]),
+        ("ceil", &[f(3.2)], &[i(4)]),
+        ("ceil", &[f(-0.5)], &[i(0)]),
+        ("tointeger", &[f(3.0)], &[i(3)]),
+        ("tointeger", &[f(3.5)], &[LuaValue::Nil]),
+        ("type", &[i(1)], &[LuaValue::String("integer".into())]),
+        ("type", &[f(1.0)], &[LuaValue::String("float".into())]),
+        ("type", &[LuaValue::String("1".into())], &[LuaValue::Nil]),
+        ("ult", &[i(1), i(-1)], &[LuaValue::Boolean(true)]),
+        ("ult", &[i(-1), i(1)], &[LuaValue::Boolean(false)]),
+        ("abs", &[i(-3)], &[i(3)]),
+        ("abs", &[i(i64::MIN)], &[i(i64::MIN)]),
+        ("abs", &[f(-2.5)], &[f(2.5)]),
+        ("fmod", &[i(7), i(3)], &[i(1)]),
+        ("fmod", &[i(-7), i(3)], &[i(-1)]),
+        ("fmod", &[i(i64::MIN), i(-1)], &[i(0)]),
+        ("fmod", &[f(7.5), i(2)], &[f(1.5)]),
+        ("modf", &[f(3.5)], &[f(3.0), f(0.5)]),
+        ("modf", &[f(-3.5)], &[f(-3.0), f(-0.5)]),
+        (
+            "modf",
+            &[f(f64::NEG_INFINITY)],
+            &[f(f64::NEG_INFINITY), f(0.0)],
+        ),
+        ("modf", &[i(4)], &[i(4), f(0.0)]),
+        ("max", &[i(1), f(2.5), i(2)], &[f(2.5)]),
+        ("min", &[i(3), i(1), f(2.0)], &[i(1)]),
+        ("sqrt", &[i(16)], &[f(4.0)]),
+        ("log", &[i(8), i(2)], &[f(3.0)]),
+        ("log", &[i(100), i(10)], &[f(2.0)]),
+    ];
+    for (name, args, results) in cases {
+        assert_eq!(
+            call_lib(l.clone(), "math", name, args),
+            *results,
+            "{}",
+            name
+        );
+    }
+
+    let errors: &[(&str, &[LuaValue], &str)] = &[
+        (
+            "fmod",
+            &[i(1), i(0)],
+            "bad argument #2 to 'math.fmod' (zero)",
+        ),
+        ("max", &[], "bad argument #1 to 'math.max' (value expected)"),
+        ("floor", &[LuaValue::Nil], "(number expected, got nil)"),
+        ("tointeger", &[], "(value expected)"),
+    ];
+    for (name, args, message) in errors {
+        let err = lib_error(l.clone(), "math", name, args);
+        assert!(err.ends_with(message), "{}: {}", name, err);
+    }
+}
+
+#[test]
+fn constants_test() {
+    debug!("test math.pi, math.huge, math.maxinteger and math.mininteger");
+    let l = new_state();
+    lua_getglobal(l.clone(), "math");
+    lua_getfield(l.clone(), 1, "pi");
+    assert_eq!(lua_tonumber(l.clone(), -1), std::f64::consts::PI);
+    lua_getfield(l.clone(), 1, "huge");
+    assert_eq!(lua_tonumber(l.clone(), -1), f64::INFINITY);
+    lua_getfield(l.clone(), 1, "maxinteger");
+    assert_eq!(lua_tointeger(l.clone(), -1), i64::MAX);
+    lua_getfield(l.clone(), 1, "mininteger");
+    assert_eq!(lua_tointeger(l, -1), i64::MIN);
+}
+
+fn sequence(l: lua_State, n: usize) -> Vec<LuaValue> {
+    (0..n)
+        .map(|_| call_lib(l.clone(), "math", "random", &[i(1), i(1000)])[0].clone())
+        .collect()
+}
+
+#[test]
+fn random_test() {
+    debug!("test that math.random is deterministic for a given seed");
+    let (l1, l2) = (new_state(), new_state());
+    // 默认的种子是固定的
+    assert_eq!(sequence(l1.clone(), 20), sequence(l2.clone(), 20));
+
+    call_lib(l1.clone(), "math", "randomseed", &[i(42)]);
+    let first = sequence(l1.clone(), 20);
+    call_lib(l1.clone(), "math", "randomseed", &[i(42)]);
+    assert_eq!(sequence(l1.clone(), 20), first);
+    call_lib(l2.clone(), "math", "randomseed", &[f(42.0)]);
+    assert_eq!(sequence(l2.clone(), 20), first);
+    call_lib(l2.clone(), "math", "randomseed", &[i(43)]);
+    assert_ne!(sequence(l2, 20), first);
+
+    for _ in 0..100 {
+        match call_lib(l1.clone(), "math", "random", &[])[0] {
+            LuaValue::Number(n) => assert!((0.0..1.0).contains(&n)),
+            ref v => panic!("{:?}", v),
+        }
+        match call_lib(l1.clone(), "math", "random", &[i(-3), i(3)])[0] {
+            LuaValue::Integer(n) => assert!((-3..=3).contains(&n)),
+            ref v => panic!("{:?}", v),
+        }
+        match call_lib(l1.clone(), "math", "random", &[i(6)])[0] {
+            LuaValue::Integer(n) => assert!((1..=6).contains(&n)),
+            ref v => panic!("{:?}", v),
+        }
+    }
+    assert_eq!(
+        call_lib(l1.clone(), "math", "random", &[i(7), i(7)]),
+        [i(7)]
+    );
+    let full = call_lib(l1.clone(), "math", "random", &[i(i64::MIN), i(i64::MAX)]);
+    assert!(matches!(full[0], LuaValue::Integer(_)));
+
+    let err = lib_error(l1.clone(), "math", "random", &[i(5), i(1)]);
+    assert!(err.ends_with("(interval is empty)"), "{}", err);
+    let err = lib_error(l1, "math", "random", &[i(1), i(2), i(3)]);
+    assert!(err.ends_with("wrong number of arguments"), "{}", err);
+}
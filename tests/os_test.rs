@@ -0,0 +1,340 @@
+use llua::api::*;
+use llua::debug;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+mod common;
+use common::*;
+
+type ExitLog = Rc<RefCell<Vec<(i32, bool)>>>;
+
+// 固定时间的宿主，记录 os.exit 的调用而不是结束进程
+struct FrozenHost {
+    now: i64,
+    offset: i64,
+    env: HashMap<String, String>,
+    exits: ExitLog,
+}
+
+impl OsHost for FrozenHost {
+    fn time(&self) -> i64 {
+        self.now
+    }
+
+    fn clock(&self) -> f64 {
+        1.5
+    }
+
+    fn getenv(&self, name: &str) -> Option<String> {
+        self.env.get(name).cloned()
+    }
+
+    fn utc_offset(&self, _time: i64) -> i64 {
+        self.offset
+    }
+
+    // 从当前时刻开始进入夏令时
+    fn is_dst(&self, time: i64) -> bool {
+        time >= self.now
+    }
+
+    fn exit(&self, code: i32, close: bool) {
+        self.exits.borrow_mut().push((code, close));
+    }
+}
+
+fn frozen_state(offset: i64) -> (lua_State, ExitLog) {
+    let l = luaL_newstate();
+    luaL_openlibs(l.clone());
+    let exits = Rc::new(RefCell::new(Vec::new()));
+    let mut env = HashMap::new();
+    env.insert("HOME".to_string(), "/home/lua".to_string());
+    let host = FrozenHost {
+        now: 1_000_000_000,
+        offset,
+        env,
+        exits: exits.clone(),
+    };
+    luaL_setoshost(l.clone(), Rc::new(host));
+    (l, exits)
+}
+
+fn field(l: lua_State, t: &LuaValue, key: &str) -> i64 {
+    l.borrow_mut().push(t.clone());
+    lua_getfield(l.clone(), -1, key);
+    let n = lua_tointeger(l.clone(), -1);
+    lua_pop(l, 2);
+    n
+}
+
+fn isdst(l: lua_State, t: &LuaValue) -> bool {
+    l.borrow_mut().push(t.clone());
+    lua_getfield(l.clone(), -1, "isdst");
+    let b = lua_toboolean(l.clone(), -1);
+    lua_pop(l, 2);
+    b
+}
+
+#[test]
+fn date_test() {
+    debug!("test os.date with strftime formats and date tables");
+    let (l, _) = frozen_state(0);
+    let cases = [
+        ("%c", "Sun Sep  9 01:46:40 2001"),
+        ("%a %A %b %B %h", "Sun Sunday Sep September Sep"),
+        ("%C %d %D %e %F", "20 09 09/09/01  9 2001-09-09"),
+        ("%g %G %V %U %W %j", "01 2001 36 36 36 252"),
+        (
+            "%H %I %M %S %p %r %R %T",
+            "01 01 46 40 AM 01:46:40 AM 01:46 01:46:40",
+        ),
+        ("%u %w %x %X %y %Y", "7 0 09/09/01 01:46:40 01 2001"),
+        ("%z %Z %% %n%t", "+0000 UTC % \n\t"),
+        ("%Ec|%Oy", "Sun Sep  9 01:46:40 2001|01"),
+    ];
+    for (fmt, expected) in cases.iter() {
+        assert_eq!(
+            call_lib(l.clone(), "os", "date", &[s(fmt)]),
+            [s(expected)],
+            "{}",
+            fmt
+        );
+    }
+    assert_eq!(
+        call_lib(l.clone(), "os", "date", &[s("!%c"), i(-86400000)]),
+        [s("Fri Apr  7 00:00:00 1967")]
+    );
+    // 2021-01-03 属于 ISO 8601 中 2020 年的第 53 周
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "os",
+            "date",
+            &[s("%G %V %U %W %j"), i(1609632000)]
+        ),
+        [s("2020 53 01 00 003")]
+    );
+
+    let t = call_lib(l.clone(), "os", "date", &[s("*t")]).remove(0);
+    let fields = [
+        ("year", 2001),
+        ("month", 9),
+        ("day", 9),
+        ("hour", 1),
+        ("min", 46),
+        ("sec", 40),
+        ("wday", 1),
+        ("yday", 252),
+    ];
+    for (key, value) in fields.iter() {
+        assert_eq!(field(l.clone(), &t, key), *value, "{}", key);
+    }
+
+    let err = lib_error(l.clone(), "os", "date", &[s("%Ez")]);
+    assert!(
+        err.ends_with("(invalid conversion specifier '%Ez')"),
+        "{}",
+        err
+    );
+    let err = lib_error(l, "os", "date", &[s("%Q")]);
+    assert!(
+        err.ends_with("(invalid conversion specifier '%Q')"),
+        "{}",
+        err
+    );
+}
+
+fn date_table(l: lua_State, fields: &[(&str, i64)]) -> LuaValue {
+    lua_newtable(l.clone());
+    for (key, value) in fields {
+        lua_pushinteger(l.clone(), *value as isize);
+        lua_setfield(l.clone(), -2, key);
+    }
+    let top = lua_gettop(l.clone());
+    let t = l.borrow().get(top);
+    lua_pop(l, 1);
+    t
+}
+
+#[test]
+fn time_test() {
+    debug!("test os.time, os.clock and os.difftime with a frozen clock");
+    let (l, _) = frozen_state(0);
+    assert_eq!(call_lib(l.clone(), "os", "time", &[]), [i(1_000_000_000)]);
+    assert_eq!(
+        call_lib(l.clone(), "os", "clock", &[]),
+        [LuaValue::Number(1.5)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "os", "difftime", &[i(10), i(4)]),
+        [LuaValue::Number(6.0)]
+    );
+
+    let t = date_table(
+        l.clone(),
+        &[
+            ("year", 2001),
+            ("month", 9),
+            ("day", 9),
+            ("hour", 1),
+            ("min", 46),
+            ("sec", 40),
+        ],
+    );
+    assert_eq!(call_lib(l.clone(), "os", "time", &[t]), [i(1_000_000_000)]);
+    // hour 默认是 12
+    let t = date_table(l.clone(), &[("year", 1970), ("month", 1), ("day", 1)]);
+    assert_eq!(call_lib(l.clone(), "os", "time", &[t]), [i(12 * 3600)]);
+
+    // 超出范围的字段被规范化，表也被更新
+    let t = date_table(
+        l.clone(),
+        &[
+            ("year", 2000),
+            ("month", 14),
+            ("day", 31),
+            ("hour", 0),
+            ("sec", -1),
+        ],
+    );
+    let res = call_lib(l.clone(), "os", "time", std::slice::from_ref(&t));
+    assert_eq!(
+        call_lib(l.clone(), "os", "date", &[s("!%F %T"), res[0].clone()]),
+        [s("2001-03-02 23:59:59")]
+    );
+    assert_eq!(field(l.clone(), &t, "year"), 2001);
+    assert_eq!(field(l.clone(), &t, "month"), 3);
+    assert_eq!(field(l.clone(), &t, "day"), 2);
+    assert_eq!(field(l.clone(), &t, "sec"), 59);
+
+    let t = date_table(l.clone(), &[("year", 2001), ("day", 1)]);
+    let err = lib_error(l.clone(), "os", "time", &[t]);
+    assert!(
+        err.ends_with("field 'month' missing in date table"),
+        "{}",
+        err
+    );
+    let t = date_table(l.clone(), &[("year", 2001), ("month", 1), ("day", 1 << 40)]);
+    let err = lib_error(l, "os", "time", &[t]);
+    assert!(err.ends_with("field 'day' is out-of-bound"), "{}", err);
+}
+
+#[test]
+fn local_time_test() {
+    debug!("test that os.date and os.time use the host's UTC offset and daylight saving time");
+    let (l, _) = frozen_state(8 * 3600);
+    assert_eq!(
+        call_lib(l.clone(), "os", "date", &[s("%H:%M %z"), i(0)]),
+        [s("08:00 +0800")]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "os", "date", &[s("!%H:%M %Z"), i(0)]),
+        [s("00:00 UTC")]
+    );
+    let t = date_table(
+        l.clone(),
+        &[("year", 1970), ("month", 1), ("day", 1), ("hour", 8)],
+    );
+    assert_eq!(
+        call_lib(l.clone(), "os", "time", std::slice::from_ref(&t)),
+        [i(0)]
+    );
+    assert!(!isdst(l.clone(), &t));
+
+    let t = call_lib(l.clone(), "os", "date", &[s("*t")]).remove(0);
+    assert!(isdst(l.clone(), &t));
+    let t = call_lib(l.clone(), "os", "date", &[s("*t"), i(0)]).remove(0);
+    assert!(!isdst(l.clone(), &t));
+    // UTC 时间没有夏令时
+    let t = call_lib(l.clone(), "os", "date", &[s("!*t")]).remove(0);
+    assert!(!isdst(l.clone(), &t));
+    let t = date_table(
+        l.clone(),
+        &[("year", 2001), ("month", 9), ("day", 9), ("hour", 10)],
+    );
+    assert_eq!(
+        call_lib(l.clone(), "os", "time", std::slice::from_ref(&t)),
+        [i(1_000_000_800)]
+    );
+    assert!(isdst(l, &t));
+}
+
+#[test]
+fn host_test() {
+    debug!("test os.getenv and os.exit through the host");
+    let (l, exits) = frozen_state(0);
+    assert_eq!(
+        call_lib(l.clone(), "os", "getenv", &[s("HOME")]),
+        [s("/home/lua")]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "os", "getenv", &[s("PATH")]),
+        [LuaValue::Nil]
+    );
+
+    call_lib(l.clone(), "os", "exit", &[]);
+    call_lib(l.clone(), "os", "exit", &[i(3)]);
+    call_lib(l.clone(), "os", "exit", &[LuaValue::Boolean(false)]);
+    call_lib(
+        l,
+        "os",
+        "exit",
+        &[LuaValue::Boolean(true), LuaValue::Boolean(true)],
+    );
+    assert_eq!(
+        *exits.borrow(),
+        [(0, false), (3, false), (1, false), (0, true)]
+    );
+}
+
+#[test]
+fn system_host_test() {
+    debug!("test os.clock and local time with the default host");
+    let l = new_state();
+    let start = call_lib(l.clone(), "os", "clock", &[]).remove(0);
+    let mut n = 0u64;
+    for k in 0..1_000_000u64 {
+        n = n.wrapping_mul(31).wrapping_add(k);
+    }
+    assert_ne!(n, 1);
+    let end = call_lib(l.clone(), "os", "clock", &[]).remove(0);
+    match (start, end) {
+        (LuaValue::Number(start), LuaValue::Number(end)) => assert!(0.0 <= start && start <= end),
+        r => panic!("{:?}", r),
+    }
+    // 本地时间的换算前后一致
+    let t = call_lib(l.clone(), "os", "date", &[s("*t"), i(1_000_000_000)]).remove(0);
+    assert_eq!(call_lib(l, "os", "time", &[t]), [i(1_000_000_000)]);
+}
+
+#[test]
+fn file_test() {
+    debug!("test os.tmpname, os.rename and os.remove");
+    let (l, _) = frozen_state(0);
+    let name = match call_lib(l.clone(), "os", "tmpname", &[]).remove(0) {
+        LuaValue::String(name) => String::from_utf8(name).unwrap(),
+        v => panic!("{:?}", v),
+    };
+    assert!(std::path::Path::new(&name).exists());
+    let renamed = format!("{}.renamed", name);
+    assert_eq!(
+        call_lib(l.clone(), "os", "rename", &[s(&name), s(&renamed)]),
+        [LuaValue::Boolean(true)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "os", "remove", &[s(&renamed)]),
+        [LuaValue::Boolean(true)]
+    );
+    assert!(!std::path::Path::new(&renamed).exists());
+
+    let res = call_lib(l, "os", "remove", &[s(&renamed)]);
+    assert_eq!(
+        res,
+        [
+            LuaValue::Nil,
+            s(&format!("{}: No such file or directory", renamed)),
+            i(2)
+        ]
+    );
+}
@@ -0,0 +1,234 @@
+use llua::api::*;
+use llua::debug;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+mod common;
+use common::*;
+
+fn require(l: lua_State, name: &str) -> LuaValue {
+    lua_settop(l.clone(), 0);
+    lua_getglobal(l.clone(), "require");
+    lua_pushstring(l.clone(), name);
+    lua_call(l.clone(), 1, 1);
+    l.borrow().get(1)
+}
+
+fn require_error(l: lua_State, name: &str) -> String {
+    lua_settop(l.clone(), 0);
+    lua_getglobal(l.clone(), "require");
+    lua_pushstring(l.clone(), name);
+    assert_ne!(lua_pcall(l.clone(), 1, 1, 0), LUA_OK);
+    lua_tostring(l, -1)
+}
+
+// 设置 package 表的字段
+fn set_package_field(l: lua_State, key: &str, value: &str) {
+    lua_getglobal(l.clone(), "package");
+    lua_pushstring(l.clone(), value);
+    lua_setfield(l.clone(), -2, key);
+    lua_pop(l, 1);
+}
+
+// 返回一个表，记录加载函数收到的两个参数
+fn record_loader(l: lua_State) -> usize {
+    lua_createtable(l.clone(), 2, 0);
+    lua_pushvalue(l.clone(), 1);
+    lua_rawseti(l.clone(), -2, 1);
+    lua_pushvalue(l.clone(), 2);
+    lua_rawseti(l.clone(), -2, 2);
+    1
+}
+
+fn field(l: lua_State, t: &LuaValue, n: isize) -> LuaValue {
+    l.borrow_mut().push(t.clone());
+    lua_rawgeti(l.clone(), -1, n);
+    let v = l.borrow().get(lua_gettop(l.clone()));
+    lua_pop(l, 2);
+    v
+}
+
+#[test]
+fn preload_test() {
+    debug!("test require with package.preload and package.loaded");
+    let l = new_state();
+    let calls = Rc::new(Cell::new(0));
+    lua_getglobal(l.clone(), "package");
+    lua_getfield(l.clone(), -1, "preload");
+    let counter = calls.clone();
+    lua_pushrustclosure(
+        l.clone(),
+        move |l| {
+            counter.set(counter.get() + 1);
+            lua_pushstring(l, "counted");
+            1
+        },
+        0,
+    );
+    lua_setfield(l.clone(), -2, "counted");
+    lua_pushcfunction(l.clone(), |_| 0);
+    lua_setfield(l.clone(), -2, "empty");
+    lua_pushcfunction(l.clone(), record_loader);
+    lua_setfield(l.clone(), -2, "record");
+
+    assert_eq!(require(l.clone(), "counted"), s("counted"));
+    assert_eq!(require(l.clone(), "counted"), s("counted"));
+    assert_eq!(calls.get(), 1);
+    // 加载函数没有返回值时记录为 true
+    assert_eq!(require(l.clone(), "empty"), LuaValue::Boolean(true));
+    let args = require(l.clone(), "record");
+    assert_eq!(field(l.clone(), &args, 1), s("record"));
+    assert_eq!(field(l.clone(), &args, 2), LuaValue::Nil);
+
+    // package.loaded 就是注册表中的 _LOADED
+    lua_settop(l.clone(), 0);
+    lua_getglobal(l.clone(), "package");
+    lua_getfield(l.clone(), 1, "loaded");
+    lua_getfield(l.clone(), 2, "string");
+    lua_getglobal(l.clone(), "string");
+    assert!(lua_rawequal(l.clone(), 3, 4));
+    lua_getfield(l.clone(), 2, "counted");
+    assert_eq!(lua_tostring(l.clone(), -1), "counted");
+    assert_eq!(require(l.clone(), "table"), {
+        lua_getglobal(l.clone(), "table");
+        l.borrow().get(2)
+    });
+}
+
+#[test]
+fn searchpath_test() {
+    debug!("test package.searchpath and the Lua file searcher");
+    let l = new_state();
+    let dir = std::env::temp_dir().join(format!("llua_package_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("a")).unwrap();
+    let file = dir.join("a").join("b.lua");
+    std::fs::write(&file, "return 1").unwrap();
+    let dir = dir.to_string_lossy().to_string();
+    let path = format!("{}/?.luac;{}/?.lua", dir, dir);
+
+    assert_eq!(
+        call_lib(l.clone(), "package", "searchpath", &[s("a.b"), s(&path)]),
+        [s(&file.to_string_lossy())]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "package",
+            "searchpath",
+            &[s("a_c"), s(&path), s("_"), s("/")]
+        ),
+        [
+            LuaValue::Nil,
+            s(&format!(
+                "\n\tno file '{}/a/c.luac'\n\tno file '{}/a/c.lua'",
+                dir, dir
+            )),
+        ]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "package",
+            "searchpath",
+            &[s("a.b"), s(&path), s("")]
+        ),
+        [
+            LuaValue::Nil,
+            s(&format!(
+                "\n\tno file '{}/a.b.luac'\n\tno file '{}/a.b.lua'",
+                dir, dir
+            )),
+        ]
+    );
+
+    set_package_field(l.clone(), "path", &path);
+    let msg = require_error(l.clone(), "x.y");
+    assert!(
+        msg.contains(&format!(
+            "module 'x.y' not found:\n\tno field package.preload['x.y']\
+             \n\tno file '{}/x/y.luac'\n\tno file '{}/x/y.lua'",
+            dir, dir
+        )),
+        "{}",
+        msg
+    );
+    // 还没有编译器，源代码文件可以找到但是不能加载
+    let msg = require_error(l.clone(), "a.b");
+    assert!(
+        msg.contains(&format!(
+            "error loading module 'a.b' from file '{}':\n\t@{}: attempt to load a text chunk",
+            file.to_string_lossy(),
+            file.to_string_lossy()
+        )),
+        "{}",
+        msg
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn custom_searcher_test() {
+    debug!("test a host searcher serving modules from memory");
+    let l = new_state();
+    let mut modules: HashMap<&str, lua_CFunction> = HashMap::new();
+    modules.insert("mem.record", record_loader);
+    luaL_addsearcher(l.clone(), move |l| {
+        let name = luaL_checkstring(l.clone(), 1);
+        match modules.get(name.as_str()) {
+            Some(loader) => {
+                lua_pushcfunction(l.clone(), *loader);
+                lua_pushstring(l, &format!("memory:{}", name));
+                2
+            }
+            None => {
+                lua_pushstring(l, &format!("\n\tno module '{}' in memory", name));
+                1
+            }
+        }
+    });
+    set_package_field(l.clone(), "path", "");
+
+    let args = require(l.clone(), "mem.record");
+    assert_eq!(field(l.clone(), &args, 1), s("mem.record"));
+    assert_eq!(field(l.clone(), &args, 2), s("memory:mem.record"));
+    let msg = require_error(l.clone(), "mem.other");
+    assert!(
+        msg.contains(
+            "module 'mem.other' not found:\n\tno field package.preload['mem.other']\
+             \n\tno module 'mem.other' in memory"
+        ),
+        "{}",
+        msg
+    );
+
+    // package.searchers 被替换成非表的值
+    set_package_field(l.clone(), "searchers", "none");
+    let msg = require_error(l.clone(), "mem.other");
+    assert!(
+        msg.contains("'package.searchers' must be a table"),
+        "{}",
+        msg
+    );
+}
+
+#[test]
+fn package_fields_test() {
+    debug!("test package.config, package.path and package.loadlib");
+    let l = new_state();
+    lua_getglobal(l.clone(), "package");
+    lua_getfield(l.clone(), 1, "config");
+    assert_eq!(lua_tostring(l.clone(), -1), "/\n;\n?\n!\n-\n");
+    lua_getfield(l.clone(), 1, "path");
+    assert!(lua_tostring(l.clone(), -1).contains("./?.lua;./?/init.lua"));
+    lua_getfield(l.clone(), 1, "cpath");
+    assert!(lua_isstring(l.clone(), -1));
+    assert_eq!(
+        call_lib(l.clone(), "package", "loadlib", &[s("lib.so"), s("*")]),
+        [
+            LuaValue::Nil,
+            s("dynamic libraries not enabled; check your Lua installation"),
+            s("absent"),
+        ]
+    );
+}
@@ -8,7 +8,7 @@ fn sample_lua() {
     luaL_loadfile(l.clone(), "tests/sample.out");
     assert_eq!(lua_gettop(l.clone()), 1);
     assert!(lua_isfunction(l.clone(), lua_gettop(l.clone())));
-    lua_call(l.clone(), 0, 0);
+    lua_call(l.clone(), 0, LUA_MULTRET);
     assert!(lua_isnumber(l.clone(), lua_gettop(l.clone())));
 }
 
@@ -21,14 +21,14 @@ fn function_test() {
     luaL_loadfile(l.clone(), "tests/func.out");
     assert_eq!(lua_gettop(l.clone()), 1);
     assert!(lua_isfunction(l.clone(), -1));
-    lua_call(l.clone(), 0, 0);
+    lua_call(l.clone(), 0, LUA_MULTRET);
     assert_eq!(l.borrow().get_top(), 3);
     assert!(lua_isnumber(l.clone(), -1));
     assert!(lua_isnumber(l.clone(), -2));
     assert!(lua_isnumber(l.clone(), -3));
-    assert_eq!(lua_tointeger(l.clone(), -1), LuaValue::Integer(14));
-    assert_eq!(lua_tointeger(l.clone(), -2), LuaValue::Integer(3));
-    assert_eq!(lua_tointeger(l.clone(), -3), LuaValue::Integer(11));
+    assert_eq!(lua_tointeger(l.clone(), -1), 14);
+    assert_eq!(lua_tointeger(l.clone(), -2), 3);
+    assert_eq!(lua_tointeger(l.clone(), -3), 11);
 }
 
 #[test]
@@ -42,12 +42,12 @@ fn global_test() {
     luaL_loadfile(l.clone(), "tests/global.out");
     assert_eq!(lua_gettop(l.clone()), 1);
     assert!(lua_isfunction(l.clone(), -1));
-    lua_call(l.clone(), 0, 0);
+    lua_call(l.clone(), 0, LUA_MULTRET);
     assert_eq!(lua_gettop(l.clone()), 2);
     assert!(lua_isnumber(l.clone(), -1));
     assert!(lua_isnumber(l.clone(), -2));
-    assert_eq!(lua_tointeger(l.clone(), -1), LuaValue::Integer(1103));
-    assert_eq!(lua_tointeger(l.clone(), -2), LuaValue::Integer(88));
+    assert_eq!(lua_tointeger(l.clone(), -1), 1103);
+    assert_eq!(lua_tointeger(l.clone(), -2), 88);
 }
 
 #[test]
@@ -69,7 +69,7 @@ fn print_test() {
     luaL_loadfile(l.clone(), "tests/print.out");
     assert_eq!(lua_gettop(l.clone()), 1);
     assert!(lua_isfunction(l.clone(), -1));
-    lua_call(l.clone(), 0, 0);
+    lua_call(l.clone(), 0, LUA_MULTRET);
     assert!(lua_isnumber(l.clone(), -1));
-    assert_eq!(lua_tointeger(l.clone(), -1), LuaValue::Integer(881103));
+    assert_eq!(lua_tointeger(l.clone(), -1), 881103);
 }
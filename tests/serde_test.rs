@@ -0,0 +1,130 @@
+#![cfg(feature = "serde")]
+
+use llua::debug;
+use llua::lua::{Error, FromLua, Lua, LuaValue, Table, ToLua};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Mode {
+    Fast,
+    Limit(u32),
+    Range { from: i64, to: i64 },
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Config {
+    name: String,
+    level: u8,
+    ratio: f64,
+    tags: Vec<String>,
+    extra: Option<String>,
+    modes: Vec<Mode>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+enum Shape {
+    List(Vec<i64>),
+    Map(HashMap<String, i64>),
+}
+
+#[derive(Deserialize, Debug)]
+struct Node {
+    #[allow(dead_code)]
+    next: Option<Box<Node>>,
+}
+
+#[test]
+fn round_trip_test() {
+    debug!("test serializing a struct into Lua and back");
+    let lua = Lua::new();
+    let config = Config {
+        name: "sweet".to_string(),
+        level: 3,
+        ratio: 0.5,
+        tags: vec!["a".to_string(), "b".to_string()],
+        extra: None,
+        modes: vec![Mode::Fast, Mode::Limit(10), Mode::Range { from: 1, to: 2 }],
+    };
+    let value = lua.to_value(&config).unwrap();
+    let table = Table::from_lua(value.clone(), &lua).unwrap();
+    assert_eq!(table.get::<_, String>("name").unwrap(), "sweet");
+    assert_eq!(table.get::<_, LuaValue>("extra").unwrap(), lua.null());
+    let tags: Table = table.get("tags").unwrap();
+    assert_eq!(tags.raw_len(), 2);
+    let modes: Table = table.get("modes").unwrap();
+    assert_eq!(modes.get::<_, String>(1).unwrap(), "Fast");
+    let limit: Table = modes.get(2).unwrap();
+    assert_eq!(limit.get::<_, i64>("Limit").unwrap(), 10);
+
+    let back: Config = lua.from_value(value).unwrap();
+    assert_eq!(back, config);
+}
+
+#[test]
+fn array_test() {
+    debug!("test the array metatable and the null sentinel");
+    let lua = Lua::new();
+    let empty = lua.to_value(&Vec::<i64>::new()).unwrap();
+    assert_eq!(lua.from_value::<Shape>(empty).unwrap(), Shape::List(vec![]));
+
+    // 空表默认当作映射，设置了数组元表之后当作序列
+    let t = lua.create_table();
+    assert_eq!(
+        lua.from_value::<Shape>(t.clone().to_lua(&lua).unwrap())
+            .unwrap(),
+        Shape::Map(HashMap::new())
+    );
+    t.set_metatable(Some(lua.array_metatable()));
+    assert_eq!(
+        lua.from_value::<Shape>(t.to_lua(&lua).unwrap()).unwrap(),
+        Shape::List(vec![])
+    );
+
+    // nil 会在表中留下空洞，None 和 () 用 null 哨兵表示
+    let list = lua.to_value(&vec![Some(1), None, Some(3)]).unwrap();
+    let t = Table::from_lua(list.clone(), &lua).unwrap();
+    assert_eq!(t.raw_len(), 3);
+    assert_eq!(t.raw_get::<_, LuaValue>(2).unwrap(), lua.null());
+    let back: Vec<Option<i64>> = lua.from_value(list).unwrap();
+    assert_eq!(back, vec![Some(1), None, Some(3)]);
+    assert_eq!(lua.to_value(&()).unwrap(), lua.null());
+}
+
+#[test]
+fn error_test() {
+    debug!("test errors for cycles, non-string keys and integer ranges");
+    let lua = Lua::new();
+    match lua.to_value(&u64::MAX) {
+        Err(Error::ToLuaConversionError { from, .. }) => assert_eq!(from, "u64"),
+        _ => panic!("expected a conversion error"),
+    }
+
+    let t = lua.create_table();
+    t.set("name", "sweet").unwrap();
+    t.set("level", 300).unwrap();
+    t.set("ratio", 1).unwrap();
+    t.set("tags", lua.create_table()).unwrap();
+    t.set("modes", lua.create_table()).unwrap();
+    match lua.from_value::<Config>(t.clone().to_lua(&lua).unwrap()) {
+        Err(Error::FromLuaConversionError { to, .. }) => assert_eq!(to, "u8"),
+        r => panic!("expected a conversion error, got {:?}", r),
+    }
+
+    t.set("level", 1).unwrap();
+    t.set(1, true).unwrap();
+    assert_eq!(
+        lua.from_value::<Config>(t.to_lua(&lua).unwrap())
+            .unwrap_err(),
+        Error::DeserializeError("non-string key of type 'number' in table".to_string())
+    );
+
+    let node = lua.create_table();
+    node.set("next", node.clone()).unwrap();
+    assert_eq!(
+        lua.from_value::<Node>(node.to_lua(&lua).unwrap())
+            .unwrap_err(),
+        Error::DeserializeError("recursive table detected".to_string())
+    );
+}
@@ -0,0 +1,114 @@
+use llua::api::*;
+use llua::debug;
+
+fn push_range(l: lua_State, n: isize) {
+    for i in 1..=n {
+        lua_pushinteger(l.clone(), i);
+    }
+}
+
+fn stack_values(l: lua_State) -> Vec<i64> {
+    let mut values = Vec::new();
+    for i in 1..=lua_gettop(l.clone()) {
+        values.push(lua_tointeger(l.clone(), i));
+    }
+    values
+}
+
+#[test]
+fn settop_pop_test() {
+    debug!("test lua_settop and lua_pop");
+    let l = luaL_newstate();
+    push_range(l.clone(), 5);
+    lua_pop(l.clone(), 2);
+    assert_eq!(stack_values(l.clone()), vec![1, 2, 3]);
+    lua_settop(l.clone(), 5);
+    assert_eq!(lua_gettop(l.clone()), 5);
+    assert!(lua_isnil(l.clone(), 4));
+    assert!(lua_isnil(l.clone(), 5));
+    lua_settop(l.clone(), -3);
+    assert_eq!(stack_values(l.clone()), vec![1, 2, 3]);
+    lua_settop(l.clone(), 0);
+    assert_eq!(lua_gettop(l.clone()), 0);
+}
+
+#[test]
+fn rotate_test() {
+    debug!("test lua_rotate, lua_insert and lua_remove");
+    let l = luaL_newstate();
+    push_range(l.clone(), 5);
+    lua_rotate(l.clone(), 2, 1);
+    assert_eq!(stack_values(l.clone()), vec![1, 5, 2, 3, 4]);
+    lua_rotate(l.clone(), -4, -2);
+    assert_eq!(stack_values(l.clone()), vec![1, 3, 4, 5, 2]);
+
+    lua_settop(l.clone(), 0);
+    push_range(l.clone(), 4);
+    lua_insert(l.clone(), 1);
+    assert_eq!(stack_values(l.clone()), vec![4, 1, 2, 3]);
+    lua_remove(l.clone(), -2);
+    assert_eq!(stack_values(l.clone()), vec![4, 1, 3]);
+    lua_remove(l.clone(), 1);
+    assert_eq!(stack_values(l.clone()), vec![1, 3]);
+}
+
+#[test]
+fn copy_replace_test() {
+    debug!("test lua_copy and lua_replace");
+    let l = luaL_newstate();
+    push_range(l.clone(), 3);
+    lua_copy(l.clone(), 1, -1);
+    assert_eq!(stack_values(l.clone()), vec![1, 2, 1]);
+    lua_pushinteger(l.clone(), 1103);
+    lua_replace(l.clone(), 2);
+    assert_eq!(stack_values(l.clone()), vec![1, 1103, 1]);
+}
+
+#[test]
+fn native_frame_test() {
+    debug!("test stack operations inside a native function");
+    fn swap(l: lua_State) -> usize {
+        lua_settop(l.clone(), 2);
+        lua_insert(l.clone(), 1);
+        2
+    }
+    let l = luaL_newstate();
+    lua_pushinteger(l.clone(), 1103);
+    lua_pushcfunction(l.clone(), swap);
+    push_range(l.clone(), 3);
+    lua_call(l.clone(), 3, LUA_MULTRET);
+    assert_eq!(stack_values(l.clone()), vec![1103, 2, 1]);
+}
+
+#[test]
+fn copy_upvalue_test() {
+    debug!("test lua_copy into an upvalue");
+    fn counter(l: lua_State) -> usize {
+        let n = lua_tointeger(l.clone(), lua_upvalueindex(1));
+        lua_pushinteger(l.clone(), n as isize + 1);
+        lua_replace(l.clone(), lua_upvalueindex(1));
+        lua_pushvalue(l, lua_upvalueindex(1));
+        1
+    }
+    let l = luaL_newstate();
+    lua_pushinteger(l.clone(), 0);
+    lua_pushcclosure(l.clone(), counter, 1);
+    for _ in 0..3 {
+        lua_pushvalue(l.clone(), 1);
+        lua_call(l.clone(), 0, 1);
+    }
+    assert_eq!(stack_values(l.clone())[1..], [1, 2, 3]);
+}
+
+#[test]
+fn checkstack_test() {
+    debug!("test lua_checkstack");
+    let l = luaL_newstate();
+    assert!(lua_checkstack(l.clone(), 100));
+    for i in 0..100 {
+        lua_pushinteger(l.clone(), i);
+    }
+    assert_eq!(lua_gettop(l.clone()), 100);
+    assert!(!lua_checkstack(l.clone(), LUAI_MAXSTACK as isize));
+    assert!(!lua_checkstack(l.clone(), -1));
+}
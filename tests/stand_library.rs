@@ -1,6 +1,9 @@
 use llua::api::*;
 use llua::debug;
 
+mod common;
+use common::*;
+
 #[test]
 fn base_library() {
     debug!("test base library function");
@@ -12,3 +15,177 @@ fn base_library() {
     lua_getglobal(l.clone(), "_G");
     assert!(lua_istable(l.clone(), -1));
 }
+
+fn top_value(l: lua_State) -> LuaValue {
+    let top = lua_gettop(l.clone());
+    l.borrow().get(top)
+}
+
+#[test]
+fn conversion_functions() {
+    debug!("test type, tonumber, tostring and select");
+    let l = luaL_newstate();
+    luaopen_base(l.clone());
+    assert_eq!(call_global(l.clone(), "type", &[LuaValue::Nil]), [s("nil")]);
+    assert_eq!(call_global(l.clone(), "tonumber", &[s(" 0x10 ")]), [i(16)]);
+    assert_eq!(call_global(l.clone(), "tonumber", &[s("1e2")]), [f(100.0)]);
+    assert_eq!(
+        call_global(l.clone(), "tonumber", &[s("zz"), i(36)]),
+        [i(1295)]
+    );
+    assert_eq!(
+        call_global(l.clone(), "tonumber", &[s("-ff"), i(16)]),
+        [i(-255)]
+    );
+    assert_eq!(
+        call_global(l.clone(), "tonumber", &[s("8"), i(8)]),
+        [LuaValue::Nil]
+    );
+    assert_eq!(call_global(l.clone(), "tostring", &[f(1e15)]), [s("1e+15")]);
+    assert_eq!(call_global(l.clone(), "tostring", &[f(3.0)]), [s("3.0")]);
+    assert_eq!(call_global(l.clone(), "tostring", &[f(0.1)]), [s("0.1")]);
+
+    let args = [s("#"), s("a"), s("b"), s("c")];
+    assert_eq!(call_global(l.clone(), "select", &args), [i(3)]);
+    let mut args = args.to_vec();
+    args[0] = i(2);
+    assert_eq!(call_global(l.clone(), "select", &args), [s("b"), s("c")]);
+    args[0] = i(-1);
+    assert_eq!(call_global(l.clone(), "select", &args), [s("c")]);
+    args[0] = i(5);
+    assert_eq!(call_global(l.clone(), "select", &args), []);
+
+    lua_getglobal(l.clone(), "_VERSION");
+    assert_eq!(lua_tostring(l, -1), "Lua 5.3");
+}
+
+#[test]
+fn protected_calls() {
+    debug!("test pcall with assert, error, rawlen and setmetatable");
+    let l = luaL_newstate();
+    luaopen_base(l.clone());
+    let pcall = |f: &str, args: &[LuaValue]| {
+        lua_getglobal(l.clone(), f);
+        let mut all = vec![top_value(l.clone())];
+        lua_pop(l.clone(), 1);
+        all.extend_from_slice(args);
+        call_global(l.clone(), "pcall", &all)
+    };
+    let failed = |msg: &str| vec![LuaValue::Boolean(false), s(msg)];
+
+    assert_eq!(
+        pcall("assert", &[i(1), s("x")]),
+        [LuaValue::Boolean(true), i(1), s("x")]
+    );
+    assert_eq!(
+        pcall("assert", &[LuaValue::Nil]),
+        failed("assertion failed!")
+    );
+    assert_eq!(
+        pcall("assert", &[LuaValue::Boolean(false), s("oops")]),
+        failed("oops")
+    );
+    assert_eq!(pcall("error", &[s("boom")]), failed("boom"));
+    assert_eq!(
+        pcall("rawlen", &[i(1)]),
+        failed("bad argument #1 to 'rawlen' (table or string expected)")
+    );
+
+    lua_newtable(l.clone());
+    let t = top_value(l.clone());
+    lua_newtable(l.clone());
+    lua_pushstring(l.clone(), "locked");
+    lua_setfield(l.clone(), -2, "__metatable");
+    let mt = top_value(l.clone());
+    call_global(l.clone(), "setmetatable", &[t.clone(), mt]);
+    assert_eq!(
+        call_global(l.clone(), "getmetatable", std::slice::from_ref(&t)),
+        [s("locked")]
+    );
+    assert_eq!(
+        pcall("setmetatable", &[t, LuaValue::Nil]),
+        failed("cannot change a protected metatable")
+    );
+}
+
+#[test]
+fn unpack_alias() {
+    debug!("test the global unpack alias of table.unpack");
+    let l = luaL_newstate();
+    luaL_openlibs(l.clone());
+    lua_newtable(l.clone());
+    for i in 1..=3 {
+        lua_pushinteger(l.clone(), i);
+        lua_rawseti(l.clone(), -2, i);
+    }
+    let t = top_value(l.clone());
+    assert_eq!(call_global(l.clone(), "unpack", &[t]), [i(1), i(2), i(3)]);
+    lua_settop(l.clone(), 0);
+    lua_getglobal(l.clone(), "unpack");
+    lua_getglobal(l.clone(), "table");
+    lua_getfield(l.clone(), -1, "unpack");
+    assert!(lua_rawequal(l.clone(), 1, 3));
+}
+
+#[test]
+fn traversal_functions() {
+    debug!("test next, pairs and ipairs");
+    let l = luaL_newstate();
+    luaopen_base(l.clone());
+    lua_newtable(l.clone());
+    for i in 1..=3 {
+        lua_pushinteger(l.clone(), i * 10);
+        lua_rawseti(l.clone(), -2, i);
+    }
+    for key in &["a", "b", "c"] {
+        lua_pushboolean(l.clone(), true);
+        lua_setfield(l.clone(), -2, key);
+    }
+    let t = top_value(l.clone());
+
+    let r = call_global(l.clone(), "ipairs", std::slice::from_ref(&t));
+    let (iter, mut index) = (r[0].clone(), r[2].clone());
+    let mut values = vec![];
+    loop {
+        lua_settop(l.clone(), 0);
+        for v in &[iter.clone(), t.clone(), index] {
+            l.borrow_mut().push(v.clone());
+        }
+        lua_call(l.clone(), 2, 2);
+        if lua_isnil(l.clone(), 2) {
+            break;
+        }
+        index = l.borrow().get(1);
+        values.push(l.borrow().get(2));
+    }
+    assert_eq!(values, [i(10), i(20), i(30)]);
+
+    // 遍历时可以清除已有的字段
+    let mut key = LuaValue::Nil;
+    let mut count = 0;
+    loop {
+        let r = call_global(l.clone(), "next", &[t.clone(), key]);
+        if r[0] == LuaValue::Nil {
+            break;
+        }
+        count += 1;
+        key = r[0].clone();
+        call_global(
+            l.clone(),
+            "rawset",
+            &[t.clone(), key.clone(), LuaValue::Nil],
+        );
+    }
+    assert_eq!(count, 6);
+    assert_eq!(
+        call_global(l.clone(), "next", std::slice::from_ref(&t)),
+        [LuaValue::Nil]
+    );
+
+    let r = call_global(l.clone(), "pcall", &{
+        lua_getglobal(l.clone(), "next");
+        let next = top_value(l.clone());
+        [next, t, s("missing")]
+    });
+    assert_eq!(r[1], s("invalid key to 'next'"));
+}
@@ -0,0 +1,612 @@
+use llua::api::*;
+use llua::debug;
+
+mod common;
+use common::*;
+
+#[test]
+fn find_test() {
+    debug!("test string.find with plain and pattern searches");
+    let l = new_state();
+    assert_eq!(
+        call_lib(l.clone(), "string", "find", &[s("hello world"), s("o w")]),
+        [i(5), i(7)]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "string",
+            "find",
+            &[s("a.b"), s("."), i(1), LuaValue::Boolean(true)]
+        ),
+        [i(2), i(2)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "find", &[s("hello"), s("l+")]),
+        [i(3), i(4)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "find", &[s("hello"), s("l"), i(-2)]),
+        [i(4), i(4)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "find", &[s("hello"), s("^l")]),
+        [LuaValue::Nil]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "string",
+            "find",
+            &[s("key = value"), s("(%w+)%s*=%s*(%w+)")]
+        ),
+        [i(1), i(11), s("key"), s("value")]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "find", &[s("abc"), s(""), i(10)]),
+        [LuaValue::Nil]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "find", &[s("abc"), s(""), i(4)]),
+        [i(4), i(3)]
+    );
+}
+
+#[test]
+fn match_test() {
+    debug!("test string.match with classes, sets, %b, %f and back references");
+    let l = new_state();
+    let cases: &[(&str, &str, &[LuaValue])] = &[
+        ("  trim  ", "^%s*(.-)%s*$", &[s("trim")]),
+        ("x = f(a(b)c) + 1", "%b()", &[s("(a(b)c)")]),
+        ("THE (quick) fox", "%f[%a]%a+%f[%A]", &[s("THE")]),
+        ("the anthem", "%f[%w]an%w*", &[s("anthem")]),
+        ("hello", "()ll()", &[i(3), i(5)]),
+        ("say 'hi' now", "(['\"])(.-)%1", &[s("'"), s("hi")]),
+        (
+            "2024-10-19",
+            "(%d+)-(%d+)-(%d+)",
+            &[s("2024"), s("10"), s("19")],
+        ),
+        ("a]b", "[]]", &[s("]")]),
+        ("x-y", "[%a-]+", &[s("x-y")]),
+        ("F00d", "%x+$", &[s("F00d")]),
+        ("abc", "[^%l]", &[LuaValue::Nil]),
+        ("aaab", "a-b", &[s("aaab")]),
+        ("ab", "a?ab", &[s("ab")]),
+    ];
+    for (src, pat, expected) in cases {
+        assert_eq!(
+            call_lib(l.clone(), "string", "match", &[s(src), s(pat)]),
+            *expected,
+            "match({:?}, {:?})",
+            src,
+            pat
+        );
+    }
+}
+
+#[test]
+fn gmatch_test() {
+    debug!("test string.gmatch iteration");
+    let l = new_state();
+    let iterate = |src: &str, pat: &str| {
+        let f = call_lib(l.clone(), "string", "gmatch", &[s(src), s(pat)]).remove(0);
+        let mut results = vec![];
+        loop {
+            lua_settop(l.clone(), 0);
+            l.borrow_mut().push(f.clone());
+            lua_call(l.clone(), 0, LUA_MULTRET);
+            if lua_gettop(l.clone()) == 0 || lua_isnil(l.clone(), 1) {
+                return results;
+            }
+            let values: Vec<LuaValue> = (1..=lua_gettop(l.clone()))
+                .map(|i| l.borrow().get(i))
+                .collect();
+            results.push(values);
+        }
+    };
+    assert_eq!(
+        iterate("one two  three", "%a+"),
+        [[s("one")], [s("two")], [s("three")]]
+    );
+    assert_eq!(
+        iterate("a=1, b=2", "(%w+)=(%w+)"),
+        [[s("a"), s("1")], [s("b"), s("2")]]
+    );
+    // 空匹配不会紧接着上一次匹配的结尾出现
+    assert_eq!(iterate("abc", "a*"), [[s("a")], [s("")], [s("")]]);
+}
+
+#[test]
+fn gsub_test() {
+    debug!("test string.gsub with string, table and function replacements");
+    let l = new_state();
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "string",
+            "gsub",
+            &[s("hello world"), s("o"), s("0")]
+        ),
+        [s("hell0 w0rld"), i(2)]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "string",
+            "gsub",
+            &[s("hello world"), s("(%w+)"), s("<%1>")]
+        ),
+        [s("<hello> <world>"), i(2)]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "string",
+            "gsub",
+            &[s("abc"), s("%w"), s("%0%0"), i(2)]
+        ),
+        [s("aabbc"), i(2)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "gsub", &[s("abc"), s(""), s("-")]),
+        [s("-a-b-c-"), i(4)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "gsub", &[s("hello"), s("^h"), s("H")]),
+        [s("Hello"), i(1)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "gsub", &[s("50%"), s("%%"), s(" %%")]),
+        [s("50 %"), i(1)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "gsub", &[s("abc"), s("()b"), s("%1")]),
+        [s("a2c"), i(1)]
+    );
+
+    lua_newtable(l.clone());
+    lua_pushstring(l.clone(), "Lua");
+    lua_setfield(l.clone(), -2, "name");
+    lua_pushboolean(l.clone(), false);
+    lua_setfield(l.clone(), -2, "skip");
+    let top = lua_gettop(l.clone());
+    let vars = l.borrow().get(top);
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "string",
+            "gsub",
+            &[s("$name is $skip $none"), s("%$(%w+)"), vars.clone()]
+        ),
+        [s("Lua is $skip $none"), i(3)]
+    );
+
+    fn upper(l: lua_State) -> usize {
+        let word = lua_tostring(l.clone(), 1);
+        if word == "keep" {
+            return 0;
+        }
+        lua_pushstring(l, &word.to_uppercase());
+        1
+    }
+    lua_pushcfunction(l.clone(), upper);
+    let top = lua_gettop(l.clone());
+    let f = l.borrow().get(top);
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "string",
+            "gsub",
+            &[s("make keep loud"), s("%a+"), f]
+        ),
+        [s("MAKE keep LOUD"), i(3)]
+    );
+
+    lua_newtable(l.clone());
+    lua_newtable(l.clone());
+    lua_setfield(l.clone(), -2, "x");
+    let top = lua_gettop(l.clone());
+    let bad = l.borrow().get(top);
+    assert!(
+        lib_error(l.clone(), "string", "gsub", &[s("x"), s("x"), bad])
+            .ends_with("invalid replacement value (a table)")
+    );
+    assert!(
+        lib_error(l.clone(), "string", "gsub", &[s("x"), s("x"), s("%2")])
+            .ends_with("invalid capture index %2")
+    );
+    assert!(
+        lib_error(l.clone(), "string", "gsub", &[s("x"), s("x"), s("%z")])
+            .ends_with("invalid use of '%' in replacement string")
+    );
+    assert!(lib_error(
+        l,
+        "string",
+        "gsub",
+        &[s("x"), s("x"), LuaValue::Boolean(true)]
+    )
+    .ends_with("bad argument #3 to 'string.gsub' (string/function/table expected)"));
+}
+
+#[test]
+fn pattern_error_test() {
+    debug!("test malformed patterns and the matching limits");
+    let l = new_state();
+    let cases = &[
+        ("%", "malformed pattern (ends with '%')"),
+        ("[a", "malformed pattern (missing ']')"),
+        ("(a", "unfinished capture"),
+        ("a)", "invalid pattern capture"),
+        ("%b", "malformed pattern (missing arguments to '%b')"),
+        ("%fa", "missing '[' after '%f' in pattern"),
+        ("(a)%2", "invalid capture index %2"),
+    ];
+    for (pat, message) in cases {
+        let err = lib_error(l.clone(), "string", "match", &[s("a"), s(pat)]);
+        assert!(err.ends_with(message), "{:?}: {}", pat, err);
+    }
+    let pat = "(".repeat(33) + &")".repeat(33);
+    assert!(
+        lib_error(l.clone(), "string", "match", &[s("a"), s(&pat)]).ends_with("too many captures")
+    );
+
+    // 递归太深或回溯次数过多时报错而不是一直运行下去
+    let src = "a".repeat(300);
+    let err = lib_error(
+        l.clone(),
+        "string",
+        "find",
+        &[s(&src), s(&"a?".repeat(300))],
+    );
+    assert!(err.ends_with("pattern too complex"), "{}", err);
+    let src = "a".repeat(64);
+    let err = lib_error(
+        l.clone(),
+        "string",
+        "find",
+        &[s(&src), s("^a*a*a*a*a*a*a*b")],
+    );
+    assert!(err.ends_with("pattern too complex"), "{}", err);
+    // 上限随源字符串变长，线性的扫描不受影响
+    let src = "a".repeat(5_000_000) + "b";
+    assert_eq!(
+        call_lib(l, "string", "find", &[s(&src), s(".-b")]),
+        [i(1), i(5_000_001)]
+    );
+}
+
+#[test]
+fn basic_string_test() {
+    debug!("test sub, rep, byte, char, upper, lower, reverse and len");
+    let l = new_state();
+    let cases: &[(&str, &[LuaValue], &[LuaValue])] = &[
+        ("sub", &[s("hello"), i(2), i(-2)], &[s("ell")]),
+        ("sub", &[s("hello"), i(-3)], &[s("llo")]),
+        ("sub", &[s("hello"), i(0)], &[s("hello")]),
+        ("sub", &[s("hello"), i(10)], &[s("")]),
+        ("rep", &[s("ab"), i(3), s(",")], &[s("ab,ab,ab")]),
+        ("rep", &[s("ab"), i(0)], &[s("")]),
+        ("rep", &[s(""), f(3e9)], &[s("")]),
+        ("rep", &[s(""), i(i64::MAX), s("")], &[s("")]),
+        ("byte", &[s("ABC"), i(1), i(-1)], &[i(65), i(66), i(67)]),
+        ("byte", &[s("ABC")], &[i(65)]),
+        ("byte", &[s(""), i(1)], &[]),
+        ("char", &[i(72), i(105)], &[s("Hi")]),
+        ("char", &[], &[s("")]),
+        ("upper", &[s("Hello, 世界")], &[s("HELLO, 世界")]),
+        ("lower", &[s("HeLLo")], &[s("hello")]),
+        ("reverse", &[s("abc")], &[s("cba")]),
+        ("len", &[s("世界")], &[i(6)]),
+    ];
+    for (name, args, expected) in cases {
+        assert_eq!(
+            call_lib(l.clone(), "string", name, args),
+            *expected,
+            "{}({:?})",
+            name,
+            args
+        );
+    }
+    assert!(lib_error(l.clone(), "string", "char", &[i(256)]).ends_with("(value out of range)"));
+    assert!(lib_error(l, "string", "rep", &[s("x"), i(1 << 40)])
+        .ends_with("resulting string too large"));
+}
+
+#[test]
+fn format_test() {
+    debug!("test string.format conversions, flags, width and precision");
+    let l = new_state();
+    let cases: &[(&str, &[LuaValue], &str)] = &[
+        (
+            "%d|%5d|%-5d|%05d",
+            &[i(1), i(42), i(42), i(-42)],
+            "1|   42|42   |-0042",
+        ),
+        ("%+d % d %.3d %.0d", &[i(5), i(5), i(7), i(0)], "+5  5 007 "),
+        ("%i", &[LuaValue::Number(3.0)], "3"),
+        (
+            "%x %X %#x %#o %o",
+            &[i(255), i(255), i(255), i(8), i(0)],
+            "ff FF 0xff 010 0",
+        ),
+        ("%x", &[i(-1)], "ffffffffffffffff"),
+        ("%u", &[i(-1)], "18446744073709551615"),
+        ("%c%c%3c", &[i(72), i(105), i(33)], "Hi  !"),
+        (
+            "%5.2f|%.0f|%#.0f",
+            &[
+                LuaValue::Number(3.14259),
+                LuaValue::Number(2.7),
+                LuaValue::Number(3.0),
+            ],
+            " 3.14|3|3.",
+        ),
+        (
+            "%05.1f|%-8.3f|",
+            &[LuaValue::Number(-2.25), LuaValue::Number(1.0)],
+            "-02.2|1.000   |",
+        ),
+        (
+            "%e|%.2E",
+            &[LuaValue::Number(12345.678), LuaValue::Number(0.0)],
+            "1.234568e+04|0.00E+00",
+        ),
+        ("%.1e", &[LuaValue::Number(1e-300)], "1.0e-300"),
+        (
+            "%g %g %g %g",
+            &[
+                LuaValue::Number(100000.0),
+                LuaValue::Number(1e6),
+                LuaValue::Number(0.0001),
+                LuaValue::Number(0.00001),
+            ],
+            "100000 1e+06 0.0001 1e-05",
+        ),
+        (
+            "%.3g|%#g|%G|%g",
+            &[
+                LuaValue::Number(3.14259),
+                LuaValue::Number(1.0),
+                LuaValue::Number(1e-10),
+                LuaValue::Number(0.0),
+            ],
+            "3.14|1.00000|1E-10|0",
+        ),
+        (
+            "%f %F %5.1f",
+            &[
+                LuaValue::Number(f64::INFINITY),
+                LuaValue::Number(f64::NEG_INFINITY),
+                LuaValue::Number(f64::NAN),
+            ],
+            "inf -INF   nan",
+        ),
+        (
+            "%a %a %A %a",
+            &[
+                LuaValue::Number(1.0),
+                LuaValue::Number(0.5),
+                LuaValue::Number(3.0),
+                LuaValue::Number(0.0),
+            ],
+            "0x1p+0 0x1p-1 0X1.8P+1 0x0p+0",
+        ),
+        (
+            "%.1a %.0a %a",
+            &[
+                LuaValue::Number(1.0),
+                LuaValue::Number(1.5),
+                LuaValue::Number(-0.1),
+            ],
+            "0x1.0p+0 0x2p+0 -0x1.999999999999ap-4",
+        ),
+        (
+            "%5s|%-5s|%.2s|%s",
+            &[s("ab"), s("cd"), s("xyz"), LuaValue::Boolean(true)],
+            "   ab|cd   |xy|true",
+        ),
+        (
+            "%q",
+            &[s("a\nb\"c\\\r\u{1}9\u{1}")],
+            "\"a\\\nb\\\"c\\\\\\r\\0019\\1\"",
+        ),
+        (
+            "%q %q %q",
+            &[
+                i(i64::MIN),
+                LuaValue::Number(f64::INFINITY),
+                LuaValue::Number(0.5),
+            ],
+            "0x8000000000000000 1e9999 0x1p-1",
+        ),
+        (
+            "%q %q",
+            &[LuaValue::Nil, LuaValue::Number(f64::NAN)],
+            "nil (0/0)",
+        ),
+        ("100%%", &[], "100%"),
+    ];
+    for (fmt, args, expected) in cases {
+        let mut all = vec![s(fmt)];
+        all.extend_from_slice(args);
+        assert_eq!(
+            call_lib(l.clone(), "string", "format", &all),
+            [s(expected)],
+            "format({:?})",
+            fmt
+        );
+    }
+
+    let errors: &[(&[LuaValue], &str)] = &[
+        (&[s("%d")], "bad argument #2 to 'string.format' (no value)"),
+        (
+            &[s("%d"), LuaValue::Number(1.5)],
+            "(number has no integer representation)",
+        ),
+        (&[s("%y"), i(1)], "invalid option '%y' to 'format'"),
+        (
+            &[s("%123d"), i(1)],
+            "invalid format (width or precision too long)",
+        ),
+        (&[s("%------d"), i(1)], "invalid format (repeated flags)"),
+    ];
+    for (args, message) in errors {
+        let err = lib_error(l.clone(), "string", "format", args);
+        assert!(err.ends_with(message), "{:?}: {}", args, err);
+    }
+    lua_newtable(l.clone());
+    let top = lua_gettop(l.clone());
+    let t = l.borrow().get(top);
+    assert!(
+        lib_error(l, "string", "format", &[s("%q"), t]).ends_with("(value has no literal form)")
+    );
+}
+
+#[test]
+fn string_metatable_test() {
+    debug!("test the shared metatable of strings");
+    let l = new_state();
+    lua_pushstring(l.clone(), "abc");
+    assert!(lua_getmetatable(l.clone(), -1));
+    lua_getfield(l.clone(), -1, "__index");
+    lua_getglobal(l.clone(), "string");
+    assert!(lua_rawequal(l.clone(), -1, -2));
+    lua_settop(l.clone(), 1);
+
+    // ("abc"):rep(2)
+    lua_getfield(l.clone(), 1, "rep");
+    lua_pushvalue(l.clone(), 1);
+    lua_pushinteger(l.clone(), 2);
+    lua_call(l.clone(), 2, 1);
+    assert_eq!(lua_tostring(l.clone(), -1), "abcabc");
+    lua_pushinteger(l.clone(), 1);
+    assert!(!lua_getmetatable(l, -1));
+}
+
+#[test]
+fn pack_test() {
+    debug!("test string.pack, string.unpack and string.packsize");
+    let l = new_state();
+    assert_eq!(
+        call_lib(l.clone(), "string", "pack", &[s("<i4"), i(1)]),
+        [b(&[1, 0, 0, 0])]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "pack", &[s(">i2 B"), i(-2), i(255)]),
+        [b(&[0xff, 0xfe, 0xff])]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "pack", &[s("<i16"), i(-1)]),
+        [b(&[0xff; 16])]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "pack", &[s("<!4 b i4"), i(1), i(2)]),
+        [b(&[1, 0, 0, 0, 2, 0, 0, 0])]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "pack", &[s("s1 z"), s("ab"), s("cd")]),
+        [b(b"\x02abcd\0")]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "string",
+            "pack",
+            &[s(">d"), LuaValue::Number(1.0)]
+        ),
+        [b(&1.0f64.to_be_bytes())]
+    );
+
+    assert_eq!(
+        call_lib(l.clone(), "string", "unpack", &[s("<i4"), b(&[1, 0, 0, 0])]),
+        [i(1), i(5)]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "string",
+            "unpack",
+            &[s("<i3 I2"), b(&[0xff, 0xff, 0xff, 0xff, 0xff])]
+        ),
+        [i(-1), i(65535), i(6)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "unpack", &[s("<i16"), b(&[0xff; 16])]),
+        [i(-1), i(17)]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "string",
+            "unpack",
+            &[s("s1 z"), b(b"\x02abcd\0")]
+        ),
+        [s("ab"), s("cd"), i(7)]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "string",
+            "unpack",
+            &[s("<f"), b(&1.5f32.to_le_bytes())]
+        ),
+        [LuaValue::Number(1.5), i(5)]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "string",
+            "unpack",
+            &[s("b"), b(b"\x01\x02"), i(-1)]
+        ),
+        [i(2), i(3)]
+    );
+
+    assert_eq!(
+        call_lib(l.clone(), "string", "packsize", &[s("i4 i8 d")]),
+        [i(20)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "string", "packsize", &[s("!8 b Xi8 j")]),
+        [i(16)]
+    );
+
+    let errors: &[(&str, &[LuaValue], &str)] = &[
+        (
+            "pack",
+            &[s("i17"), i(1)],
+            "integral size (17) out of limits [1,16]",
+        ),
+        ("pack", &[s("i1"), i(128)], "(integer overflow)"),
+        ("pack", &[s("y")], "invalid format option 'y'"),
+        (
+            "pack",
+            &[s("s1"), s(&"x".repeat(256))],
+            "(string length does not fit in given size)",
+        ),
+        ("pack", &[s("z"), b(b"a\0b")], "(string contains zeros)"),
+        (
+            "pack",
+            &[s("!3 i4"), i(1)],
+            "(format asks for alignment not power of 2)",
+        ),
+        ("unpack", &[s("i4"), s("abc")], "(data string too short)"),
+        (
+            "unpack",
+            &[s("z"), s("abc")],
+            "(unfinished string for format 'z')",
+        ),
+        (
+            "unpack",
+            &[s("<i9"), b(&[0, 0, 0, 0, 0, 0, 0, 0, 1])],
+            "9-byte integer does not fit into Lua Integer",
+        ),
+        ("packsize", &[s("s")], "(variable-length format)"),
+    ];
+    for (name, args, message) in errors {
+        let err = lib_error(l.clone(), "string", name, args);
+        assert!(err.ends_with(message), "{}: {}", name, err);
+    }
+}
@@ -0,0 +1,299 @@
+use llua::api::*;
+use llua::debug;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+fn index_fallback(l: lua_State) -> usize {
+    // __index(t, k) 返回 "missing " .. k
+    let key = lua_tostring(l.clone(), 2);
+    lua_pushstring(l, &format!("missing {}", key));
+    1
+}
+
+fn newindex_log(l: lua_State) -> usize {
+    // __newindex(t, k, v) 把赋值记录到 log 表里，不写入 t
+    lua_getglobal(l.clone(), "log");
+    lua_pushvalue(l.clone(), 2);
+    lua_pushvalue(l.clone(), 3);
+    lua_rawset(l, -3);
+    0
+}
+
+#[test]
+fn get_set_test() {
+    debug!("test lua_gettable/settable/geti/seti");
+    let l = luaL_newstate();
+    lua_createtable(l.clone(), 2, 1);
+    lua_pushstring(l.clone(), "name");
+    lua_pushstring(l.clone(), "sweethui");
+    lua_settable(l.clone(), 1);
+    lua_pushinteger(l.clone(), 88);
+    lua_seti(l.clone(), 1, 1);
+    lua_pushinteger(l.clone(), 11);
+    lua_seti(l.clone(), -2, 10);
+    assert_eq!(lua_gettop(l.clone()), 1);
+
+    lua_pushstring(l.clone(), "name");
+    assert_eq!(lua_gettable(l.clone(), 1), LUA_TSTRING);
+    assert_eq!(lua_tostring(l.clone(), -1), "sweethui".to_string());
+    assert_eq!(lua_geti(l.clone(), 1, 1), LUA_TNUMBER);
+    assert_eq!(lua_tointeger(l.clone(), -1), 88);
+    assert_eq!(lua_geti(l.clone(), 1, 10), LUA_TNUMBER);
+    assert_eq!(lua_tointeger(l.clone(), -1), 11);
+    assert_eq!(lua_geti(l.clone(), 1, 2), LUA_TNIL);
+    assert_eq!(lua_getfield(l.clone(), 1, "nothing"), LUA_TNIL);
+    assert_eq!(lua_gettop(l.clone()), 6);
+}
+
+#[test]
+fn raw_access_test() {
+    debug!("test lua_rawget/rawset/rawgeti/rawseti");
+    let l = luaL_newstate();
+    lua_newtable(l.clone());
+    lua_newtable(l.clone());
+    lua_pushcfunction(l.clone(), index_fallback);
+    lua_setfield(l.clone(), -2, "__index");
+    lua_setmetatable(l.clone(), 1);
+
+    lua_pushstring(l.clone(), "key");
+    lua_pushinteger(l.clone(), 1103);
+    lua_rawset(l.clone(), 1);
+    lua_pushboolean(l.clone(), true);
+    lua_rawseti(l.clone(), 1, 3);
+
+    lua_pushstring(l.clone(), "key");
+    assert_eq!(lua_rawget(l.clone(), 1), LUA_TNUMBER);
+    assert_eq!(lua_tointeger(l.clone(), -1), 1103);
+    assert_eq!(lua_rawgeti(l.clone(), 1, 3), LUA_TBOOLEAN);
+    // 原始访问不触发 __index
+    lua_pushstring(l.clone(), "other");
+    assert_eq!(lua_rawget(l.clone(), 1), LUA_TNIL);
+    assert_eq!(lua_getfield(l.clone(), 1, "other"), LUA_TSTRING);
+    assert_eq!(lua_tostring(l.clone(), -1), "missing other".to_string());
+}
+
+#[test]
+fn index_chain_test() {
+    debug!("test __index table chain");
+    let l = luaL_newstate();
+    lua_newtable(l.clone());
+    lua_pushinteger(l.clone(), 88);
+    lua_setfield(l.clone(), 1, "base");
+
+    lua_newtable(l.clone());
+    lua_newtable(l.clone());
+    lua_pushvalue(l.clone(), 1);
+    lua_setfield(l.clone(), -2, "__index");
+    lua_setmetatable(l.clone(), 2);
+
+    assert_eq!(lua_getfield(l.clone(), 2, "base"), LUA_TNUMBER);
+    assert_eq!(lua_tointeger(l.clone(), -1), 88);
+    lua_pushstring(l.clone(), "base");
+    assert_eq!(lua_rawget(l.clone(), 2), LUA_TNIL);
+}
+
+#[test]
+fn newindex_test() {
+    debug!("test __newindex function");
+    let l = luaL_newstate();
+    lua_newtable(l.clone());
+    lua_setglobal(l.clone(), "log");
+
+    lua_newtable(l.clone());
+    lua_pushinteger(l.clone(), 1);
+    lua_setfield(l.clone(), 1, "exists");
+    lua_newtable(l.clone());
+    lua_pushcfunction(l.clone(), newindex_log);
+    lua_setfield(l.clone(), -2, "__newindex");
+    lua_setmetatable(l.clone(), 1);
+
+    lua_pushinteger(l.clone(), 2);
+    lua_setfield(l.clone(), 1, "exists");
+    lua_pushinteger(l.clone(), 3);
+    lua_setfield(l.clone(), 1, "fresh");
+
+    assert_eq!(lua_getfield(l.clone(), 1, "exists"), LUA_TNUMBER);
+    assert_eq!(lua_tointeger(l.clone(), -1), 2);
+    lua_pushstring(l.clone(), "fresh");
+    assert_eq!(lua_rawget(l.clone(), 1), LUA_TNIL);
+    lua_getglobal(l.clone(), "log");
+    assert_eq!(lua_getfield(l.clone(), -1, "fresh"), LUA_TNUMBER);
+    assert_eq!(lua_tointeger(l.clone(), -1), 3);
+}
+
+#[test]
+fn global_metamethod_test() {
+    debug!("test lua_getglobal and lua_setglobal with metamethods on _G");
+    let l = luaL_newstate();
+    lua_newtable(l.clone());
+    lua_setglobal(l.clone(), "log");
+    lua_pushglobaltable(l.clone());
+    lua_newtable(l.clone());
+    lua_pushcfunction(l.clone(), index_fallback);
+    lua_setfield(l.clone(), -2, "__index");
+    lua_pushcfunction(l.clone(), newindex_log);
+    lua_setfield(l.clone(), -2, "__newindex");
+    lua_setmetatable(l.clone(), -2);
+    lua_pop(l.clone(), 1);
+
+    assert_eq!(lua_getglobal(l.clone(), "log"), LUA_TTABLE);
+    assert_eq!(lua_getglobal(l.clone(), "undefined"), LUA_TSTRING);
+    assert_eq!(lua_tostring(l.clone(), -1), "missing undefined");
+    lua_settop(l.clone(), 0);
+
+    lua_pushinteger(l.clone(), 7);
+    lua_setglobal(l.clone(), "fresh");
+    lua_pushglobaltable(l.clone());
+    lua_pushstring(l.clone(), "fresh");
+    assert_eq!(lua_rawget(l.clone(), 1), LUA_TNIL);
+    lua_getglobal(l.clone(), "log");
+    assert_eq!(lua_getfield(l.clone(), -1, "fresh"), LUA_TNUMBER);
+    assert_eq!(lua_tointeger(l.clone(), -1), 7);
+}
+
+#[test]
+fn border_test() {
+    debug!("test the length of tables filled through the hash part");
+    let l = luaL_newstate();
+    lua_newtable(l.clone());
+    // 先设置后面的键，补上前面的键之后它们要算进长度
+    for i in &[3, 2, 5, 1] {
+        lua_pushinteger(l.clone(), *i);
+        lua_rawseti(l.clone(), 1, *i);
+    }
+    assert_eq!(lua_rawlen(l.clone(), 1), 3);
+    lua_pushinteger(l.clone(), 4);
+    lua_rawseti(l.clone(), 1, 4);
+    assert_eq!(lua_rawlen(l.clone(), 1), 5);
+
+    // t[#t + 1] = v 不应该随着长度变慢
+    for _ in 0..200_000 {
+        let n = lua_rawlen(l.clone(), 1) as isize;
+        lua_pushinteger(l.clone(), n + 1);
+        lua_rawseti(l.clone(), 1, n + 1);
+    }
+    assert_eq!(lua_rawlen(l.clone(), 1), 200_005);
+    lua_pushnil(l.clone());
+    lua_rawseti(l.clone(), 1, 200_005);
+    assert_eq!(lua_rawlen(l.clone(), 1), 200_004);
+}
+
+#[test]
+fn next_test() {
+    debug!("test traversing a large hash part with lua_next");
+    let l = luaL_newstate();
+    lua_newtable(l.clone());
+    for i in 0..100_000 {
+        lua_pushinteger(l.clone(), i);
+        lua_setfield(l.clone(), 1, &format!("k{}", i));
+    }
+    // 遍历过程中清除已有的键不影响后面的遍历
+    let mut sum = 0;
+    let mut count = 0;
+    lua_pushnil(l.clone());
+    while lua_next(l.clone(), 1) {
+        sum += lua_tointeger(l.clone(), 3);
+        count += 1;
+        lua_pop(l.clone(), 1);
+        lua_pushvalue(l.clone(), 2);
+        lua_pushnil(l.clone());
+        lua_rawset(l.clone(), 1);
+    }
+    assert_eq!(count, 100_000);
+    assert_eq!(sum, (0..100_000).sum());
+    lua_pushnil(l.clone());
+    assert!(!lua_next(l.clone(), 1));
+    assert_eq!(lua_gettop(l), 1);
+}
+
+#[test]
+fn registry_test() {
+    debug!("test registry pseudo-index");
+    let l = luaL_newstate();
+    lua_pushstring(l.clone(), "sweethui");
+    lua_setfield(l.clone(), LUA_REGISTRYINDEX, "owner");
+    assert_eq!(lua_gettop(l.clone()), 0);
+    assert_eq!(
+        lua_getfield(l.clone(), LUA_REGISTRYINDEX, "owner"),
+        LUA_TSTRING
+    );
+    assert_eq!(lua_tostring(l.clone(), -1), "sweethui".to_string());
+    assert_eq!(
+        lua_rawgeti(l.clone(), LUA_REGISTRYINDEX, LUA_RIDX_GLOBALS),
+        LUA_TTABLE
+    );
+    assert_eq!(
+        lua_rawgeti(l.clone(), LUA_REGISTRYINDEX, LUA_RIDX_MAINTHREAD),
+        LUA_TLIGHTUSERDATA
+    );
+}
+
+#[test]
+fn ref_test() {
+    debug!("test luaL_ref and luaL_unref");
+    fn callback(l: lua_State) -> usize {
+        lua_pushinteger(l, 1103);
+        1
+    }
+    let l = luaL_newstate();
+    lua_pushnil(l.clone());
+    assert_eq!(luaL_ref(l.clone(), LUA_REGISTRYINDEX), LUA_REFNIL);
+    lua_pushcfunction(l.clone(), callback);
+    let r1 = luaL_ref(l.clone(), LUA_REGISTRYINDEX);
+    lua_pushstring(l.clone(), "sweet");
+    let r2 = luaL_ref(l.clone(), LUA_REGISTRYINDEX);
+    assert!(r1 > LUA_RIDX_LAST);
+    assert_ne!(r1, r2);
+    assert_eq!(lua_gettop(l.clone()), 0);
+
+    lua_rawgeti(l.clone(), LUA_REGISTRYINDEX, r1);
+    lua_call(l.clone(), 0, 1);
+    assert_eq!(lua_tointeger(l.clone(), -1), 1103);
+    lua_rawgeti(l.clone(), LUA_REGISTRYINDEX, r2);
+    assert_eq!(lua_tostring(l.clone(), -1), "sweet".to_string());
+    lua_settop(l.clone(), 0);
+
+    // 释放的引用会被复用
+    luaL_unref(l.clone(), LUA_REGISTRYINDEX, r1);
+    luaL_unref(l.clone(), LUA_REGISTRYINDEX, LUA_NOREF);
+    lua_pushboolean(l.clone(), true);
+    assert_eq!(luaL_ref(l.clone(), LUA_REGISTRYINDEX), r1);
+    lua_pushboolean(l.clone(), false);
+    let r3 = luaL_ref(l.clone(), LUA_REGISTRYINDEX);
+    assert!(r3 != r1 && r3 != r2);
+}
+
+#[test]
+fn rawp_test() {
+    debug!("test lua_rawgetp and lua_rawsetp");
+    let l = luaL_newstate();
+    let key = 0x1103;
+    lua_pushstring(l.clone(), "sweethui");
+    lua_rawsetp(l.clone(), LUA_REGISTRYINDEX, key);
+    assert_eq!(lua_gettop(l.clone()), 0);
+    assert_eq!(lua_rawgetp(l.clone(), LUA_REGISTRYINDEX, key), LUA_TSTRING);
+    assert_eq!(lua_tostring(l.clone(), -1), "sweethui".to_string());
+    assert_eq!(lua_rawgetp(l.clone(), LUA_REGISTRYINDEX, key + 1), LUA_TNIL);
+}
+
+#[test]
+fn index_error_test() {
+    debug!("test indexing a non-table value");
+    let l = luaL_newstate();
+    lua_pushinteger(l.clone(), 1103);
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        lua_getfield(l.clone(), 1, "x");
+    }));
+    let err = result.unwrap_err();
+    let err = err.downcast_ref::<LuaError>().unwrap();
+    assert_eq!(err.message, "attempt to index a number value");
+
+    lua_newtable(l.clone());
+    lua_pushnil(l.clone());
+    lua_pushinteger(l.clone(), 1);
+    let result = catch_unwind(AssertUnwindSafe(|| lua_rawset(l.clone(), -3)));
+    let err = result.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<LuaError>().unwrap().message,
+        "index is nil"
+    );
+}
@@ -0,0 +1,293 @@
+use llua::api::*;
+use llua::debug;
+
+mod common;
+use common::*;
+
+fn new_list(l: lua_State, items: &[LuaValue]) -> LuaValue {
+    lua_createtable(l.clone(), items.len() as isize, 0);
+    for (n, item) in items.iter().enumerate() {
+        l.borrow_mut().push(item.clone());
+        lua_rawseti(l.clone(), -2, n as isize + 1);
+    }
+    let top = lua_gettop(l.clone());
+    let t = l.borrow().get(top);
+    lua_pop(l, 1);
+    t
+}
+
+// 表的 1..#t 部分
+fn contents(l: lua_State, t: &LuaValue) -> Vec<LuaValue> {
+    l.borrow_mut().push(t.clone());
+    let n = lua_rawlen(l.clone(), -1) as isize;
+    let items = (1..=n)
+        .map(|i| {
+            lua_rawgeti(l.clone(), -1, i);
+            let top = lua_gettop(l.clone());
+            let v = l.borrow().get(top);
+            lua_pop(l.clone(), 1);
+            v
+        })
+        .collect();
+    lua_pop(l, 1);
+    items
+}
+
+fn ints(items: &[i64]) -> Vec<LuaValue> {
+    items.iter().map(|&n| i(n)).collect()
+}
+
+#[test]
+fn insert_remove_test() {
+    debug!("test table.insert and table.remove");
+    let l = new_state();
+    let t = new_list(l.clone(), &ints(&[1, 2, 3]));
+    call_lib(l.clone(), "table", "insert", &[t.clone(), i(4)]);
+    call_lib(l.clone(), "table", "insert", &[t.clone(), i(1), i(0)]);
+    call_lib(l.clone(), "table", "insert", &[t.clone(), i(3), s("x")]);
+    assert_eq!(
+        contents(l.clone(), &t),
+        [i(0), i(1), s("x"), i(2), i(3), i(4)]
+    );
+
+    assert_eq!(
+        call_lib(l.clone(), "table", "remove", std::slice::from_ref(&t)),
+        [i(4)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "table", "remove", &[t.clone(), i(3)]),
+        [s("x")]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "table", "remove", &[t.clone(), i(1)]),
+        [i(0)]
+    );
+    assert_eq!(contents(l.clone(), &t), ints(&[1, 2, 3]));
+
+    let empty = new_list(l.clone(), &[]);
+    assert_eq!(
+        call_lib(l.clone(), "table", "remove", std::slice::from_ref(&empty)),
+        [LuaValue::Nil]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "table", "remove", &[empty.clone(), i(0)]),
+        [LuaValue::Nil]
+    );
+
+    let errors: &[(&str, &[LuaValue], &str)] = &[
+        (
+            "insert",
+            &[t.clone(), i(5), i(1)],
+            "(position out of bounds)",
+        ),
+        (
+            "insert",
+            &[t.clone(), i(0), i(1)],
+            "(position out of bounds)",
+        ),
+        (
+            "insert",
+            &[t.clone(), i(1), i(2), i(3)],
+            "wrong number of arguments to 'insert'",
+        ),
+        ("remove", &[t.clone(), i(5)], "(position out of bounds)"),
+        ("insert", &[i(1), i(1)], "(table expected, got number)"),
+    ];
+    for (name, args, message) in errors {
+        let err = lib_error(l.clone(), "table", name, args);
+        assert!(err.ends_with(message), "{}: {}", name, err);
+    }
+}
+
+#[test]
+fn concat_pack_unpack_test() {
+    debug!("test table.concat, table.pack, table.unpack and table.move");
+    let l = new_state();
+    let t = new_list(l.clone(), &[s("a"), i(1), LuaValue::Number(2.5), s("b")]);
+    assert_eq!(
+        call_lib(l.clone(), "table", "concat", std::slice::from_ref(&t)),
+        [s("a12.5b")]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "table",
+            "concat",
+            &[t.clone(), s(", "), i(2), i(3)]
+        ),
+        [s("1, 2.5")]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "table",
+            "concat",
+            &[t.clone(), s(","), i(3), i(2)]
+        ),
+        [s("")]
+    );
+    let bad = new_list(l.clone(), &[s("a"), LuaValue::Boolean(true)]);
+    assert!(lib_error(l.clone(), "table", "concat", &[bad])
+        .ends_with("invalid value (at index 2) in table for 'concat'"));
+
+    let packed = call_lib(l.clone(), "table", "pack", &[i(1), LuaValue::Nil, i(3)]);
+    l.borrow_mut().push(packed[0].clone());
+    lua_getfield(l.clone(), -1, "n");
+    assert_eq!(lua_tointeger(l.clone(), -1), 3);
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "table",
+            "unpack",
+            &[packed[0].clone(), i(1), i(3)]
+        ),
+        [i(1), LuaValue::Nil, i(3)]
+    );
+    let t = new_list(l.clone(), &ints(&[1, 2, 3]));
+    assert_eq!(
+        call_lib(l.clone(), "table", "unpack", std::slice::from_ref(&t)),
+        ints(&[1, 2, 3])
+    );
+    assert_eq!(
+        call_lib(l.clone(), "table", "unpack", &[t.clone(), i(2)]),
+        ints(&[2, 3])
+    );
+    assert!(call_lib(l.clone(), "table", "unpack", &[t.clone(), i(3), i(2)]).is_empty());
+    assert!(
+        lib_error(l.clone(), "table", "unpack", &[t, i(1), i(i64::MAX)])
+            .ends_with("too many results to unpack")
+    );
+
+    // 重叠的区间
+    let t = new_list(l.clone(), &ints(&[1, 2, 3, 4, 5]));
+    call_lib(l.clone(), "table", "move", &[t.clone(), i(1), i(3), i(2)]);
+    assert_eq!(contents(l.clone(), &t), ints(&[1, 1, 2, 3, 5]));
+    call_lib(l.clone(), "table", "move", &[t.clone(), i(2), i(5), i(1)]);
+    assert_eq!(contents(l.clone(), &t), ints(&[1, 2, 3, 5, 5]));
+    let dst = new_list(l.clone(), &[]);
+    let res = call_lib(
+        l.clone(),
+        "table",
+        "move",
+        &[t, i(1), i(3), i(1), dst.clone()],
+    );
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0], dst);
+    assert_eq!(contents(l, &dst), ints(&[1, 2, 3]));
+}
+
+fn greater(l: lua_State) -> usize {
+    let res = lua_compare(l.clone(), 2, 1, LUA_OPLT);
+    lua_pushboolean(l, res);
+    1
+}
+
+fn always_less(l: lua_State) -> usize {
+    lua_pushboolean(l, true);
+    1
+}
+
+#[test]
+fn sort_test() {
+    debug!("test table.sort with and without an order function");
+    let l = new_state();
+    // 线性同余生成的伪随机数，包含重复的值
+    let mut seed = 12345u64;
+    let mut numbers: Vec<i64> = (0..500)
+        .map(|_| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as i64 % 100
+        })
+        .collect();
+    let t = new_list(l.clone(), &ints(&numbers));
+    call_lib(l.clone(), "table", "sort", std::slice::from_ref(&t));
+    numbers.sort_unstable();
+    assert_eq!(contents(l.clone(), &t), ints(&numbers));
+
+    lua_pushcfunction(l.clone(), greater);
+    let top = lua_gettop(l.clone());
+    let greater = l.borrow().get(top);
+    call_lib(l.clone(), "table", "sort", &[t.clone(), greater]);
+    numbers.reverse();
+    assert_eq!(contents(l.clone(), &t), ints(&numbers));
+
+    let words = new_list(l.clone(), &[s("pear"), s("apple"), s("fig"), s("banana")]);
+    call_lib(l.clone(), "table", "sort", std::slice::from_ref(&words));
+    assert_eq!(
+        contents(l.clone(), &words),
+        [s("apple"), s("banana"), s("fig"), s("pear")]
+    );
+
+    lua_pushcfunction(l.clone(), always_less);
+    let top = lua_gettop(l.clone());
+    let always_less = l.borrow().get(top);
+    let t = new_list(l.clone(), &ints(&[3, 1, 4, 1, 5, 9, 2, 6]));
+    assert!(lib_error(l.clone(), "table", "sort", &[t, always_less])
+        .ends_with("invalid order function for sorting"));
+    let mixed = new_list(l.clone(), &[i(1), s("x"), i(2)]);
+    assert!(
+        lib_error(l, "table", "sort", &[mixed]).ends_with("attempt to compare string with number")
+    );
+}
+
+// 代理表：所有读写和取长度都转到全局的 backing 表
+fn proxy_index(l: lua_State) -> usize {
+    lua_getglobal(l.clone(), "backing");
+    lua_pushvalue(l.clone(), 2);
+    lua_rawget(l, -2);
+    1
+}
+
+fn proxy_newindex(l: lua_State) -> usize {
+    lua_getglobal(l.clone(), "backing");
+    lua_pushvalue(l.clone(), 2);
+    lua_pushvalue(l.clone(), 3);
+    lua_rawset(l, -3);
+    0
+}
+
+fn proxy_len(l: lua_State) -> usize {
+    lua_getglobal(l.clone(), "backing");
+    let n = lua_rawlen(l.clone(), -1);
+    lua_pushinteger(l, n as isize);
+    1
+}
+
+#[test]
+fn metamethod_test() {
+    debug!("test that the table library goes through __index, __newindex and __len");
+    let l = new_state();
+    let backing = new_list(l.clone(), &ints(&[5, 3, 8]));
+    l.borrow_mut().push(backing.clone());
+    lua_setglobal(l.clone(), "backing");
+    lua_newtable(l.clone());
+    lua_newtable(l.clone());
+    lua_pushcfunction(l.clone(), proxy_index);
+    lua_setfield(l.clone(), -2, "__index");
+    lua_pushcfunction(l.clone(), proxy_newindex);
+    lua_setfield(l.clone(), -2, "__newindex");
+    lua_pushcfunction(l.clone(), proxy_len);
+    lua_setfield(l.clone(), -2, "__len");
+    lua_setmetatable(l.clone(), -2);
+    let top = lua_gettop(l.clone());
+    let proxy = l.borrow().get(top);
+
+    call_lib(l.clone(), "table", "insert", &[proxy.clone(), i(1)]);
+    call_lib(l.clone(), "table", "sort", std::slice::from_ref(&proxy));
+    assert_eq!(contents(l.clone(), &backing), ints(&[1, 3, 5, 8]));
+    assert!(contents(l.clone(), &proxy).is_empty());
+    assert_eq!(
+        call_lib(l.clone(), "table", "concat", &[proxy.clone(), s("-")]),
+        [s("1-3-5-8")]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "table", "remove", &[proxy.clone(), i(1)]),
+        [i(1)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "table", "unpack", &[proxy]),
+        ints(&[3, 5, 8])
+    );
+}
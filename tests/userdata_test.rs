@@ -0,0 +1,132 @@
+use llua::api::*;
+use llua::debug;
+use std::cell::{Cell, RefCell};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::rc::Rc;
+
+struct Point {
+    x: i64,
+    y: i64,
+}
+
+struct Handle {
+    closed: Rc<Cell<bool>>,
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.closed.set(true);
+    }
+}
+
+thread_local! {
+    static COLLECTED: RefCell<Vec<i64>> = const { RefCell::new(Vec::new()) };
+}
+
+fn handle_gc(l: lua_State) -> usize {
+    let ud = luaL_checkudata(l, 1, "Handle");
+    assert!(ud.borrow().downcast_ref::<Handle>().is_some());
+    COLLECTED.with(|c| c.borrow_mut().push(1));
+    0
+}
+
+#[test]
+fn userdata_payload_test() {
+    debug!("test full userdata");
+    let l = luaL_newstate();
+    let ud = lua_newuserdata(l.clone(), Box::new(Point { x: 88, y: 11 }));
+    assert_eq!(lua_type(l.clone(), -1), LUA_TUSERDATA);
+    assert!(lua_isuserdata(l.clone(), -1));
+    assert!(!lua_islightuserdata(l.clone(), -1));
+
+    let u = lua_touserdata(l.clone(), -1).unwrap();
+    assert!(Rc::ptr_eq(&u, &ud));
+    u.borrow_mut().downcast_mut::<Point>().unwrap().x = 1103;
+    let point = ud.borrow();
+    let point = point.downcast_ref::<Point>().unwrap();
+    assert_eq!((point.x, point.y), (1103, 11));
+    assert!(ud.borrow().downcast_ref::<String>().is_none());
+    assert!(lua_touserdata(l.clone(), 0).is_none());
+}
+
+#[test]
+fn light_userdata_test() {
+    debug!("test light userdata");
+    let l = luaL_newstate();
+    lua_pushlightuserdata(l.clone(), 0x1103);
+    assert_eq!(lua_type(l.clone(), -1), LUA_TLIGHTUSERDATA);
+    assert!(lua_isuserdata(l.clone(), -1));
+    assert!(lua_islightuserdata(l.clone(), -1));
+    assert!(lua_touserdata(l.clone(), -1).is_none());
+    assert_eq!(lua_topointer(l.clone(), -1), 0x1103);
+    assert!(!lua_getmetatable(l.clone(), -1));
+}
+
+#[test]
+fn metatable_test() {
+    debug!("test luaL_newmetatable and luaL_checkudata");
+    let l = luaL_newstate();
+    assert!(luaL_newmetatable(l.clone(), "Point"));
+    assert_eq!(lua_getfield(l.clone(), -1, "__name"), LUA_TSTRING);
+    assert_eq!(lua_tostring(l.clone(), -1), "Point".to_string());
+    lua_pop(l.clone(), 1);
+    lua_pop(l.clone(), 1);
+    assert!(!luaL_newmetatable(l.clone(), "Point"));
+    lua_pop(l.clone(), 1);
+    assert_eq!(lua_gettop(l.clone()), 0);
+
+    lua_newuserdata(l.clone(), Box::new(Point { x: 1, y: 2 }));
+    luaL_setmetatable(l.clone(), "Point");
+    assert_eq!(lua_gettop(l.clone()), 1);
+    let ud = luaL_checkudata(l.clone(), 1, "Point");
+    assert_eq!(ud.borrow().downcast_ref::<Point>().unwrap().y, 2);
+    assert!(luaL_testudata(l.clone(), 1, "Handle").is_none());
+    assert_eq!(lua_gettop(l.clone()), 1);
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        luaL_checkudata(l.clone(), 1, "Handle");
+    }));
+    let err = result.unwrap_err();
+    let err = err.downcast_ref::<LuaError>().unwrap();
+    assert_eq!(err.status, LUA_ERRRUN);
+    assert!(err.message.contains("Handle expected"));
+}
+
+#[test]
+fn uservalue_test() {
+    debug!("test user value");
+    let l = luaL_newstate();
+    lua_newuserdata(l.clone(), Box::new(0));
+    assert_eq!(lua_getuservalue(l.clone(), -1), LUA_TNIL);
+    lua_pop(l.clone(), 1);
+    lua_pushstring(l.clone(), "sweethui");
+    lua_setuservalue(l.clone(), -2);
+    assert_eq!(lua_getuservalue(l.clone(), -1), LUA_TSTRING);
+    assert_eq!(lua_tostring(l.clone(), -1), "sweethui".to_string());
+}
+
+#[test]
+fn userdata_gc_test() {
+    debug!("test userdata __gc");
+    let l = luaL_newstate();
+    luaL_newmetatable(l.clone(), "Handle");
+    lua_pushcfunction(l.clone(), handle_gc);
+    lua_setfield(l.clone(), -2, "__gc");
+    lua_pop(l.clone(), 1);
+
+    let closed = Rc::new(Cell::new(false));
+    lua_newuserdata(
+        l.clone(),
+        Box::new(Handle {
+            closed: closed.clone(),
+        }),
+    );
+    luaL_setmetatable(l.clone(), "Handle");
+    lua_gc(l.clone(), LUA_GCCOLLECT, 0);
+    assert!(COLLECTED.with(|c| c.borrow().is_empty()));
+
+    lua_pop(l.clone(), 1);
+    lua_gc(l.clone(), LUA_GCCOLLECT, 0);
+    assert_eq!(COLLECTED.with(|c| c.borrow().clone()), vec![1]);
+    assert!(closed.get());
+}
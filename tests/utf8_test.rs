@@ -0,0 +1,196 @@
+use llua::api::*;
+use llua::debug;
+
+mod common;
+use common::*;
+
+#[test]
+fn char_codepoint_test() {
+    debug!("test utf8.char, utf8.codepoint and utf8.charpattern");
+    let l = new_state();
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "utf8",
+            "char",
+            &[i(72), i(0xE9), i(0x4E2D), i(0x1F600)]
+        ),
+        [b("Hé中😀".as_bytes())]
+    );
+    assert_eq!(call_lib(l.clone(), "utf8", "char", &[]), [b(b"")]);
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "utf8",
+            "codepoint",
+            &[b("Hé中😀".as_bytes()), i(1), i(-1)]
+        ),
+        [i(72), i(0xE9), i(0x4E2D), i(0x1F600)]
+    );
+    assert_eq!(
+        call_lib(
+            l.clone(),
+            "utf8",
+            "codepoint",
+            &[b("Hé中".as_bytes()), i(2)]
+        ),
+        [i(0xE9)]
+    );
+    assert!(call_lib(l.clone(), "utf8", "codepoint", &[b(b"abc"), i(3), i(2)]).is_empty());
+
+    lua_getglobal(l.clone(), "utf8");
+    lua_getfield(l.clone(), -1, "charpattern");
+    assert_eq!(
+        lua_tobytes(l.clone(), -1).unwrap(),
+        b"[\0-\x7F\xC2-\xF4][\x80-\xBF]*"
+    );
+
+    let errors: &[(&str, &[LuaValue], &str)] = &[
+        ("char", &[i(0x110000)], "(value out of range)"),
+        ("char", &[i(-1)], "(value out of range)"),
+        ("codepoint", &[b(b"\xff")], "invalid UTF-8 code"),
+        ("codepoint", &[b(b"\xc0\x80")], "invalid UTF-8 code"),
+        (
+            "codepoint",
+            &[b(b"abc"), i(1), i(4)],
+            "bad argument #3 to 'utf8.codepoint' (out of range)",
+        ),
+    ];
+    for (name, args, message) in errors {
+        let err = lib_error(l.clone(), "utf8", name, args);
+        assert!(err.ends_with(message), "{}: {}", name, err);
+    }
+}
+
+#[test]
+fn len_offset_test() {
+    debug!("test utf8.len and utf8.offset");
+    let l = new_state();
+    let s = b("aé中😀".as_bytes());
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "len", std::slice::from_ref(&s)),
+        [i(4)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "len", &[s.clone(), i(2)]),
+        [i(3)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "len", &[s.clone(), i(-4)]),
+        [i(1)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "len", &[s.clone(), i(1), i(1)]),
+        [i(1)]
+    );
+    assert_eq!(call_lib(l.clone(), "utf8", "len", &[b(b"")]), [i(0)]);
+    // 第一个无效字节的位置
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "len", &[b(b"ab\xe4\xb8cd")]),
+        [LuaValue::Nil, i(3)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "len", &[s.clone(), i(3)]),
+        [LuaValue::Nil, i(3)]
+    );
+
+    // 字节位置：a=1 é=2 中=4 😀=7，总长 10
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "offset", &[s.clone(), i(3)]),
+        [i(4)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "offset", &[s.clone(), i(5)]),
+        [i(11)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "offset", &[s.clone(), i(6)]),
+        [LuaValue::Nil]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "offset", &[s.clone(), i(-1)]),
+        [i(7)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "offset", &[s.clone(), i(-4)]),
+        [i(1)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "offset", &[s.clone(), i(-5)]),
+        [LuaValue::Nil]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "offset", &[s.clone(), i(-1), i(-4)]),
+        [i(4)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "offset", &[s.clone(), i(0), i(5)]),
+        [i(4)]
+    );
+    assert_eq!(
+        call_lib(l.clone(), "utf8", "offset", &[s.clone(), i(2), i(4)]),
+        [i(7)]
+    );
+
+    let errors: &[(&str, &[LuaValue], &str)] = &[
+        (
+            "offset",
+            &[s.clone(), i(1), i(3)],
+            "initial position is a continuation byte",
+        ),
+        (
+            "offset",
+            &[s.clone(), i(1), i(12)],
+            "(position out of range)",
+        ),
+        (
+            "len",
+            &[s.clone(), i(12)],
+            "(initial position out of string)",
+        ),
+        ("len", &[s, i(1), i(11)], "(final position out of string)"),
+    ];
+    for (name, args, message) in errors {
+        let err = lib_error(l.clone(), "utf8", name, args);
+        assert!(err.ends_with(message), "{}: {}", name, err);
+    }
+}
+
+// 手动驱动 utf8.codes 返回的迭代器，收集所有 (位置, 码点)
+fn collect_codes(l: lua_State, s: &[u8]) -> Vec<(i64, i64)> {
+    let res = call_lib(l.clone(), "utf8", "codes", &[b(s)]);
+    let mut control = res[2].clone();
+    let mut codes = Vec::new();
+    loop {
+        lua_settop(l.clone(), 0);
+        l.borrow_mut().push(res[0].clone());
+        l.borrow_mut().push(res[1].clone());
+        l.borrow_mut().push(control);
+        lua_call(l.clone(), 2, 2);
+        if lua_isnil(l.clone(), 1) {
+            return codes;
+        }
+        codes.push((lua_tointeger(l.clone(), 1), lua_tointeger(l.clone(), 2)));
+        control = l.borrow().get(1);
+    }
+}
+
+#[test]
+fn codes_test() {
+    debug!("test the utf8.codes iterator");
+    let l = new_state();
+    assert_eq!(
+        collect_codes(l.clone(), "aé中😀".as_bytes()),
+        [(1, 0x61), (2, 0xE9), (4, 0x4E2D), (7, 0x1F600)]
+    );
+    assert!(collect_codes(l.clone(), b"").is_empty());
+
+    // 第一个字符之后是不完整的字节序列
+    let res = call_lib(l.clone(), "utf8", "codes", &[b(b"a\xe4\xb8b")]);
+    lua_settop(l.clone(), 0);
+    l.borrow_mut().push(res[0].clone());
+    l.borrow_mut().push(res[1].clone());
+    lua_pushinteger(l.clone(), 1);
+    assert_ne!(lua_pcall(l.clone(), 2, 2, 0), LUA_OK);
+    assert!(lua_tostring(l, -1).ends_with("invalid UTF-8 code"));
+}